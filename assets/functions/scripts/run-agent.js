@@ -0,0 +1,140 @@
+#!/usr/bin/env node
+
+// Usage: ./{agent_name}.js <agent-func> <agent-data>
+
+const fs = require("fs");
+const path = require("path");
+const { pathToFileURL } = require("url");
+
+async function main() {
+  const [agentFunc, rawData] = parseArgv();
+  const agentData = parseRawData(rawData);
+
+  const rootDir = "{config_dir}";
+  setupEnv(rootDir, agentFunc);
+
+  const agentToolsPath = path.join(rootDir, "agents/{agent_name}/tools.{tool_extension}");
+  await run(agentToolsPath, agentFunc, agentData);
+}
+
+function parseRawData(data) {
+  if (!data) {
+    throw new Error("No JSON data");
+  }
+
+  try {
+    return JSON.parse(data);
+  } catch {
+    throw new Error("Invalid JSON data");
+  }
+}
+
+function parseArgv() {
+  const agentFunc = process.argv[2];
+  const agentData = process.argv[3];
+
+  if (!agentFunc || !agentData) {
+    console.error("Usage: ./{agent_name}.js <agent-func> <agent-data>");
+    process.exit(1);
+  }
+
+  return [agentFunc, agentData];
+}
+
+function setupEnv(rootDir, agentFunc) {
+  loadEnv(path.join(rootDir, ".env"));
+  process.env["LLM_ROOT_DIR"] = rootDir;
+  process.env["LLM_AGENT_NAME"] = "{agent_name}";
+  process.env["LLM_AGENT_FUNC"] = agentFunc;
+  process.env["LLM_AGENT_ROOT_DIR"] = path.join(rootDir, "agents", "{agent_name}");
+  process.env["LLM_AGENT_CACHE_DIR"] = path.join(rootDir, "cache", "{agent_name}");
+}
+
+function loadEnv(filePath) {
+  let lines;
+  try {
+    lines = fs.readFileSync(filePath, "utf8").split("\n");
+  } catch {
+    return;
+  }
+
+  for (let line of lines) {
+    line = line.trim();
+    if (line.startsWith("#") || !line) {
+      continue;
+    }
+
+    const [key, ...valueParts] = line.split("=");
+    const envName = key.trim();
+
+    if (!(envName in process.env)) {
+      let envValue = valueParts.join("=").trim();
+      if (
+        (envValue.startsWith('"') && envValue.endsWith('"')) ||
+        (envValue.startsWith("'") && envValue.endsWith("'"))
+      ) {
+        envValue = envValue.slice(1, -1);
+      }
+      process.env[envName] = envValue;
+    }
+  }
+}
+
+async function run(agentPath, agentFunc, agentData) {
+  const mod = await import(pathToFileURL(agentPath).href);
+  const func = mod[agentFunc] ?? mod.default?.[agentFunc];
+
+  if (typeof func !== "function") {
+    throw new Error(`Not module function '${agentFunc}' at '${agentPath}'`);
+  }
+
+  const value = await func(agentData);
+  returnToLlm(value);
+  dumpResult("{agent_name}" + `:${agentFunc}`);
+}
+
+function returnToLlm(value) {
+  if (value === null || value === undefined) {
+    return;
+  }
+
+  const output =
+    typeof value === "object" ? JSON.stringify(value, null, 2) : String(value);
+
+  if (process.env["LLM_OUTPUT"]) {
+    fs.writeFileSync(process.env["LLM_OUTPUT"], output);
+  } else {
+    process.stdout.write(output);
+  }
+}
+
+function dumpResult(name) {
+  if (!process.env["LLM_DUMP_RESULTS"] || !process.env["LLM_OUTPUT"] || !process.stdout.isTTY) {
+    return;
+  }
+
+  let showResult = false;
+  try {
+    if (new RegExp(`\\b(${process.env["LLM_DUMP_RESULTS"]})\\b`).test(name)) {
+      showResult = true;
+    }
+  } catch {}
+
+  if (!showResult) {
+    return;
+  }
+
+  let data;
+  try {
+    data = fs.readFileSync(process.env["LLM_OUTPUT"], "utf8");
+  } catch {
+    return;
+  }
+
+  console.log(`\x1b[2m----------------------\n${data}\n----------------------\x1b[0m`);
+}
+
+main().catch((err) => {
+  console.error(err instanceof Error ? err.message : err);
+  process.exit(1);
+});
@@ -0,0 +1,145 @@
+#!/usr/bin/env -S pwsh -NoProfile
+
+# Usage: ./{agent_name}.ps1 <agent-func> <agent-data>
+
+$ErrorActionPreference = "Stop"
+
+function Main {
+    $agentFunc, $rawData = Parse-Argv
+    $agentData = Parse-RawData $rawData
+
+    $rootDir = "{config_dir}"
+    Setup-Env $rootDir $agentFunc
+
+    $agentToolsPath = Join-Path $rootDir "agents/{agent_name}/tools.ps1"
+    Run-Agent $agentToolsPath $agentFunc $agentData
+}
+
+function Parse-RawData($data) {
+    if (-not $data) {
+        throw "No JSON data"
+    }
+
+    try {
+        return ConvertFrom-Json $data
+    } catch {
+        throw "Invalid JSON data"
+    }
+}
+
+function Parse-Argv {
+    $agentFunc = $script:Argv[0]
+    $agentData = $script:Argv[1]
+
+    if (-not $agentFunc -or -not $agentData) {
+        [Console]::Error.WriteLine("Usage: ./{agent_name}.ps1 <agent-func> <agent-data>")
+        exit 1
+    }
+
+    return $agentFunc, $agentData
+}
+
+function Setup-Env($rootDir, $agentFunc) {
+    Load-Env (Join-Path $rootDir ".env")
+    $env:LLM_ROOT_DIR = $rootDir
+    $env:LLM_AGENT_NAME = "{agent_name}"
+    $env:LLM_AGENT_FUNC = $agentFunc
+    $env:LLM_AGENT_ROOT_DIR = Join-Path (Join-Path $rootDir "agents") "{agent_name}"
+    $env:LLM_AGENT_CACHE_DIR = Join-Path (Join-Path $rootDir "cache") "{agent_name}"
+}
+
+function Load-Env($filePath) {
+    if (-not (Test-Path -LiteralPath $filePath)) {
+        return
+    }
+
+    foreach ($line in Get-Content -LiteralPath $filePath) {
+        $line = $line.Trim()
+        if ($line.StartsWith("#") -or -not $line) {
+            continue
+        }
+
+        $key, $valueParts = $line -split "=", 2
+        $envName = $key.Trim()
+
+        if (-not (Test-Path "env:$envName")) {
+            $envValue = "$valueParts".Trim()
+            if (($envValue.StartsWith('"') -and $envValue.EndsWith('"')) -or
+                ($envValue.StartsWith("'") -and $envValue.EndsWith("'"))) {
+                $envValue = $envValue.Substring(1, $envValue.Length - 2)
+            }
+            Set-Item "env:$envName" $envValue
+        }
+    }
+}
+
+function Run-Agent($agentPath, $agentFunc, $agentData) {
+    . $agentPath
+
+    if (-not (Get-Command $agentFunc -CommandType Function -ErrorAction SilentlyContinue)) {
+        throw "Not module function '$agentFunc' at '$agentPath'"
+    }
+
+    $params = @{}
+    foreach ($property in $agentData.PSObject.Properties) {
+        $params[$property.Name] = $property.Value
+    }
+
+    $value = & $agentFunc @params
+    Return-ToLlm $value
+    Dump-Result "{agent_name}:$agentFunc"
+}
+
+function Return-ToLlm($value) {
+    if ($null -eq $value) {
+        return
+    }
+
+    if ($value -is [string] -or $value -is [ValueType]) {
+        $output = "$value"
+    } elseif ($value -is [array] -and -not ($value | Where-Object { $_ -isnot [string] })) {
+        $output = $value -join "`n"
+    } else {
+        $output = ConvertTo-Json $value -Depth 20
+    }
+
+    if ($env:LLM_OUTPUT) {
+        [IO.File]::WriteAllText($env:LLM_OUTPUT, $output)
+    } else {
+        [Console]::Out.Write($output)
+    }
+}
+
+function Dump-Result($name) {
+    if (-not $env:LLM_DUMP_RESULTS -or -not $env:LLM_OUTPUT -or [Console]::IsOutputRedirected) {
+        return
+    }
+
+    $showResult = $false
+    try {
+        if ($name -match "\b($($env:LLM_DUMP_RESULTS))\b") {
+            $showResult = $true
+        }
+    } catch {}
+
+    if (-not $showResult) {
+        return
+    }
+
+    try {
+        $data = [IO.File]::ReadAllText($env:LLM_OUTPUT)
+    } catch {
+        return
+    }
+
+    Write-Host "$([char]27)[2m----------------------`n$data`n----------------------$([char]27)[0m"
+}
+
+$script:Argv = $args
+
+try {
+    Main
+} catch {
+    [Console]::Error.WriteLine($_.Exception.Message)
+    exit 1
+}
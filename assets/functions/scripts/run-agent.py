@@ -9,6 +9,57 @@ import sys
 import importlib.util
 from pathlib import Path
 
+def _ensure_requirements_venv():
+    requirements_file = r"{requirements_file}"
+    if not requirements_file:
+        return False
+
+    venv_dir = Path(r"{venv_dir}")
+    if Path(sys.prefix).resolve() == venv_dir.resolve():
+        return True
+
+    py = venv_dir / ("Scripts/python.exe" if os.name == "nt" else "bin/python")
+    ready_file = venv_dir / ".loki-ready"
+    if not ready_file.exists():
+        _install_requirements(venv_dir, py, requirements_file, ready_file)
+
+    os.execv(str(py), [str(py)] + sys.argv)
+
+
+def _install_requirements(venv, py, requirements, ready_file):
+    import subprocess
+    import time
+
+    # Tools can run concurrently, so only one of them installs while the others wait
+    lock_dir = venv.with_name(venv.name + ".lock")
+    venv.parent.mkdir(parents=True, exist_ok=True)
+    while True:
+        try:
+            lock_dir.mkdir()
+            break
+        except FileExistsError:
+            if ready_file.exists():
+                return
+            # A lock left behind by an install that was killed
+            try:
+                if time.time() - lock_dir.stat().st_mtime > 600:
+                    lock_dir.rmdir()
+                    continue
+            except OSError:
+                continue
+            time.sleep(0.5)
+
+    try:
+        if ready_file.exists():
+            return
+        print(f"Installing the Python requirements in {requirements} into {venv}", file=sys.stderr)
+        subprocess.run([sys.executable, "-m", "venv", "--clear", str(venv)], check=True, stdout=sys.stderr)
+        subprocess.run([str(py), "-m", "pip", "install", "--quiet", "-r", requirements], check=True, stdout=sys.stderr)
+        ready_file.touch()
+    finally:
+        lock_dir.rmdir()
+
+
 def _ensure_cwd_venv():
     cwd = Path.cwd()
     venv_dir = cwd / ".venv"
@@ -24,7 +75,9 @@ def _ensure_cwd_venv():
 
     os.execv(str(py), [str(py)] + sys.argv)
 
-_ensure_cwd_venv()
+# A dedicated virtualenv for the tools' requirements takes precedence over one in the current directory
+if not _ensure_requirements_venv():
+    _ensure_cwd_venv()
 
 
 def main():
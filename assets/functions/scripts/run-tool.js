@@ -0,0 +1,137 @@
+#!/usr/bin/env node
+
+// Usage: ./{function_name}.js <tool-data>
+
+const fs = require("fs");
+const path = require("path");
+const { pathToFileURL } = require("url");
+
+async function main() {
+  const rawData = parseArgv();
+  const toolData = parseRawData(rawData);
+
+  const rootDir = "{root_dir}";
+  setupEnv(rootDir);
+
+  const toolPath = "{tool_path}.{tool_extension}";
+  await run(toolPath, "run", toolData);
+}
+
+function parseRawData(data) {
+  if (!data) {
+    throw new Error("No JSON data");
+  }
+
+  try {
+    return JSON.parse(data);
+  } catch {
+    throw new Error("Invalid JSON data");
+  }
+}
+
+function parseArgv() {
+  const toolData = process.argv[2];
+
+  if (!toolData) {
+    console.error("Usage: ./{function_name}.js <tool-data>");
+    process.exit(1);
+  }
+
+  return toolData;
+}
+
+function setupEnv(rootDir) {
+  loadEnv(path.join(rootDir, ".env"));
+  process.env["LLM_ROOT_DIR"] = rootDir;
+  process.env["LLM_TOOL_NAME"] = "{function_name}";
+  process.env["LLM_TOOL_CACHE_DIR"] = path.join(rootDir, "cache", "{function_name}");
+}
+
+function loadEnv(filePath) {
+  let lines;
+  try {
+    lines = fs.readFileSync(filePath, "utf8").split("\n");
+  } catch {
+    return;
+  }
+
+  for (let line of lines) {
+    line = line.trim();
+    if (line.startsWith("#") || !line) {
+      continue;
+    }
+
+    const [key, ...valueParts] = line.split("=");
+    const envName = key.trim();
+
+    if (!(envName in process.env)) {
+      let envValue = valueParts.join("=").trim();
+      if (
+        (envValue.startsWith('"') && envValue.endsWith('"')) ||
+        (envValue.startsWith("'") && envValue.endsWith("'"))
+      ) {
+        envValue = envValue.slice(1, -1);
+      }
+      process.env[envName] = envValue;
+    }
+  }
+}
+
+async function run(toolPath, toolFunc, toolData) {
+  const mod = await import(pathToFileURL(toolPath).href);
+  const func = mod[toolFunc] ?? mod.default?.[toolFunc];
+
+  if (typeof func !== "function") {
+    throw new Error(`No module function '${toolFunc}' at '${toolPath}'`);
+  }
+
+  const value = await func(toolData);
+  returnToLlm(value);
+  dumpResult("{function_name}");
+}
+
+function returnToLlm(value) {
+  if (value === null || value === undefined) {
+    return;
+  }
+
+  const output =
+    typeof value === "object" ? JSON.stringify(value, null, 2) : String(value);
+
+  if (process.env["LLM_OUTPUT"]) {
+    fs.writeFileSync(process.env["LLM_OUTPUT"], output);
+  } else {
+    process.stdout.write(output);
+  }
+}
+
+function dumpResult(name) {
+  if (!process.env["LLM_DUMP_RESULTS"] || !process.env["LLM_OUTPUT"] || !process.stdout.isTTY) {
+    return;
+  }
+
+  let showResult = false;
+  try {
+    if (new RegExp(`\\b(${process.env["LLM_DUMP_RESULTS"]})\\b`).test(name)) {
+      showResult = true;
+    }
+  } catch {}
+
+  if (!showResult) {
+    return;
+  }
+
+  let data;
+  try {
+    data = fs.readFileSync(process.env["LLM_OUTPUT"], "utf8");
+  } catch {
+    return;
+  }
+
+  console.log(`\x1b[2m----------------------\n${data}\n----------------------\x1b[0m`);
+}
+
+main().catch((err) => {
+  console.error(err instanceof Error ? err.message : err);
+  process.exit(1);
+});
@@ -0,0 +1,142 @@
+#!/usr/bin/env -S pwsh -NoProfile
+
+# Usage: ./{function_name}.ps1 <tool-data>
+
+$ErrorActionPreference = "Stop"
+
+function Main {
+    $rawData = Parse-Argv
+    $toolData = Parse-RawData $rawData
+
+    $rootDir = "{root_dir}"
+    Setup-Env $rootDir
+
+    $toolPath = "{tool_path}.ps1"
+    Run-Tool $toolPath "run" $toolData
+}
+
+function Parse-RawData($data) {
+    if (-not $data) {
+        throw "No JSON data"
+    }
+
+    try {
+        return ConvertFrom-Json $data
+    } catch {
+        throw "Invalid JSON data"
+    }
+}
+
+function Parse-Argv {
+    $toolData = $script:Argv[0]
+
+    if (-not $toolData) {
+        [Console]::Error.WriteLine("Usage: ./{function_name}.ps1 <tool-data>")
+        exit 1
+    }
+
+    return $toolData
+}
+
+function Setup-Env($rootDir) {
+    Load-Env (Join-Path $rootDir ".env")
+    $env:LLM_ROOT_DIR = $rootDir
+    $env:LLM_TOOL_NAME = "{function_name}"
+    $env:LLM_TOOL_CACHE_DIR = Join-Path (Join-Path $rootDir "cache") "{function_name}"
+}
+
+function Load-Env($filePath) {
+    if (-not (Test-Path -LiteralPath $filePath)) {
+        return
+    }
+
+    foreach ($line in Get-Content -LiteralPath $filePath) {
+        $line = $line.Trim()
+        if ($line.StartsWith("#") -or -not $line) {
+            continue
+        }
+
+        $key, $valueParts = $line -split "=", 2
+        $envName = $key.Trim()
+
+        if (-not (Test-Path "env:$envName")) {
+            $envValue = "$valueParts".Trim()
+            if (($envValue.StartsWith('"') -and $envValue.EndsWith('"')) -or
+                ($envValue.StartsWith("'") -and $envValue.EndsWith("'"))) {
+                $envValue = $envValue.Substring(1, $envValue.Length - 2)
+            }
+            Set-Item "env:$envName" $envValue
+        }
+    }
+}
+
+function Run-Tool($toolPath, $toolFunc, $toolData) {
+    . $toolPath
+
+    if (-not (Get-Command $toolFunc -CommandType Function -ErrorAction SilentlyContinue)) {
+        throw "No module function '$toolFunc' at '$toolPath'"
+    }
+
+    $params = @{}
+    foreach ($property in $toolData.PSObject.Properties) {
+        $params[$property.Name] = $property.Value
+    }
+
+    $value = & $toolFunc @params
+    Return-ToLlm $value
+    Dump-Result "{function_name}"
+}
+
+function Return-ToLlm($value) {
+    if ($null -eq $value) {
+        return
+    }
+
+    if ($value -is [string] -or $value -is [ValueType]) {
+        $output = "$value"
+    } elseif ($value -is [array] -and -not ($value | Where-Object { $_ -isnot [string] })) {
+        $output = $value -join "`n"
+    } else {
+        $output = ConvertTo-Json $value -Depth 20
+    }
+
+    if ($env:LLM_OUTPUT) {
+        [IO.File]::WriteAllText($env:LLM_OUTPUT, $output)
+    } else {
+        [Console]::Out.Write($output)
+    }
+}
+
+function Dump-Result($name) {
+    if (-not $env:LLM_DUMP_RESULTS -or -not $env:LLM_OUTPUT -or [Console]::IsOutputRedirected) {
+        return
+    }
+
+    $showResult = $false
+    try {
+        if ($name -match "\b($($env:LLM_DUMP_RESULTS))\b") {
+            $showResult = $true
+        }
+    } catch {}
+
+    if (-not $showResult) {
+        return
+    }
+
+    try {
+        $data = [IO.File]::ReadAllText($env:LLM_OUTPUT)
+    } catch {
+        return
+    }
+
+    Write-Host "$([char]27)[2m----------------------`n$data`n----------------------$([char]27)[0m"
+}
+
+$script:Argv = $args
+
+try {
+    Main
+} catch {
+    [Console]::Error.WriteLine($_.Exception.Message)
+    exit 1
+}
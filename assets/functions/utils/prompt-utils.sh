@@ -24,6 +24,7 @@
 # - guard_path        Prompt for permission to perform path operations
 # - patch_file        Patch a file
 # - error             Log an error
+# - tool_error        Report a structured error back to the LLM
 # - warn              Log a warning
 # - info              Log info
 # - debug             Log a debug message
@@ -55,6 +56,13 @@ error() {
   echo -e "${red}${bold}ERROR:${default}${red} $1${default}"
 }
 
+tool_error() {
+  local hint="${2:-}" retryable="${3:-false}"
+  jq -n --arg error "$1" --arg hint "$hint" --argjson retryable "$retryable" \
+    '{error: $error, retryable: $retryable} + (if $hint == "" then {} else {hint: $hint} end)' \
+    > "${LLM_ERROR:-/dev/stderr}"
+}
+
 warn() {
   echo -e "${gold}${bold}WARN:${default}${gold} $1${default}"
 }
@@ -4,12 +4,37 @@ use crate::cli::completer::{
     ShellCompletion, agent_completer, macro_completer, model_completer, rag_completer,
     role_completer, secrets_completer, session_completer,
 };
+use crate::config::{DEFAULT_BATCH_CONCURRENCY, DEFAULT_BATCH_RETRIES};
 use anyhow::{Context, Result};
 use clap::ValueHint;
 use clap::{Parser, crate_authors, crate_description, crate_name, crate_version};
 use clap_complete::ArgValueCompleter;
 use is_terminal::IsTerminal;
 use std::io::{Read, stdin};
+use std::path::PathBuf;
+
+/// What a `--dry-run` skips.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DryRun {
+    /// Display the message without sending it
+    Messages,
+    /// Send the message, but preview the tool calls the model makes instead of running them
+    Tools,
+}
+
+/// How `--output` prints the answer to a one-off query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Print the answer as it comes in, rendered on a terminal
+    #[default]
+    Text,
+    /// Print the answer's raw Markdown once it's complete
+    Md,
+    /// Print a JSON object with the answer, model, token usage, tool calls, RAG sources, and timing
+    Json,
+    /// Print a standalone HTML page with the answer, its tool calls, and RAG sources
+    Html,
+}
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -40,6 +65,21 @@ pub struct Cli {
     /// Start or join a session
     #[arg(short = 's', long, add = ArgValueCompleter::new(session_completer))]
     pub session: Option<Option<String>>,
+    /// Continue the most recently used session, saving the new messages to it
+    #[arg(short = 'C', long = "continue", conflicts_with_all = ["session", "resume"])]
+    pub continue_session: bool,
+    /// Resume a saved session, saving the new messages to it
+    #[arg(long, value_name = "SESSION", conflicts_with = "session", add = ArgValueCompleter::new(session_completer))]
+    pub resume: Option<String>,
+    /// Print a saved session as a Markdown, HTML, or JSON document
+    #[arg(long, value_name = "SESSION", conflicts_with = "import_session", add = ArgValueCompleter::new(session_completer))]
+    pub export_session: Option<String>,
+    /// The format of --export-session
+    #[arg(long, value_name = "FORMAT", requires = "export_session", default_value = "md", value_parser = ["md", "html", "json"])]
+    pub format: String,
+    /// Save a session exported with --format json, or a copy of a session file, to the sessions directory
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub import_session: Option<PathBuf>,
     /// Ensure the session is empty
     #[arg(long)]
     pub empty_session: bool,
@@ -70,15 +110,69 @@ pub struct Cli {
     /// Include files, directories, or URLs
     #[arg(short = 'f', long, value_name = "FILE|URL", value_hint = ValueHint::AnyPath)]
     pub file: Vec<String>,
+    /// Put the text in the clipboard in front of the prompt, or include the image in it
+    #[arg(long, conflicts_with_all = ["macro_name", "batch_file"])]
+    pub paste: bool,
+    /// Print only the answer and errors, without spinners, tool call summaries, or progress bars
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
     /// Turn off stream mode
     #[arg(short = 'S', long)]
     pub no_stream: bool,
-    /// Display the message without sending it
-    #[arg(long)]
-    pub dry_run: bool,
+    /// Give up on a model that sends nothing for this many seconds
+    #[arg(long, value_name = "SECS")]
+    pub timeout: Option<u64>,
+    /// Give up connecting to a model's API after this many seconds
+    #[arg(long, value_name = "SECS")]
+    pub connect_timeout: Option<u64>,
+    /// How to print the answer to a one-off query
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    pub output: Option<OutputFormat>,
+    /// Write the answer to a file instead of stdout, naming it with any of the {date}, {time},
+    /// {session}, and {model} placeholders
+    #[arg(long, value_name = "PATH", conflicts_with = "batch_file", value_hint = ValueHint::FilePath)]
+    pub output_file: Option<String>,
+    /// Add the answer to the end of --output-file instead of replacing it
+    #[arg(long, requires = "output_file")]
+    pub append: bool,
+    /// Run each prompt in a file of JSON lines and print a JSON result per line
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, conflicts_with_all = ["session", "continue_session", "resume", "agent"])]
+    pub batch_file: Option<PathBuf>,
+    /// Write the results of --batch-file to a file instead of stdout
+    #[arg(long, value_name = "FILE", requires = "batch_file", value_hint = ValueHint::FilePath)]
+    pub batch_output: Option<PathBuf>,
+    /// How many prompts of --batch-file run at the same time
+    #[arg(long, value_name = "N", requires = "batch_file", default_value_t = DEFAULT_BATCH_CONCURRENCY)]
+    pub batch_concurrency: usize,
+    /// How many times a failed prompt of --batch-file is retried
+    #[arg(long, value_name = "N", requires = "batch_file", default_value_t = DEFAULT_BATCH_RETRIES)]
+    pub batch_retries: usize,
+    /// Display the message without sending it, or with `--dry-run=tools`, preview the tool calls
+    /// the model makes instead of running them
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "messages"
+    )]
+    pub dry_run: Option<DryRun>,
     /// Display information
     #[arg(long)]
     pub info: bool,
+    /// Check the config, roles, agents, macros, and MCP servers for problems, without starting a chat
+    #[arg(long, exclusive = true)]
+    pub check_config: bool,
+    /// Run the test cases defined in an agent's tests.yaml
+    #[arg(long, value_name = "AGENT", add = ArgValueCompleter::new(agent_completer))]
+    pub test_agent: Option<String>,
+    /// Package an agent into a signed .tar.gz archive for distribution
+    #[arg(long, value_name = "AGENT", exclusive = true, add = ArgValueCompleter::new(agent_completer))]
+    pub package_agent: Option<String>,
+    /// Install an agent from a .tar.gz archive created with --package-agent
+    #[arg(long, value_name = "FILE", exclusive = true, value_hint = ValueHint::FilePath)]
+    pub install_agent: Option<String>,
     /// Build all configured Bash tool scripts
     #[arg(long)]
     pub build_tools: bool,
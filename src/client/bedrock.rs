@@ -520,7 +520,12 @@ fn extract_chat_completions(data: &Value) -> Result<ChatCompletionsOutput> {
         bail!("Invalid response data: {data}");
     }
 
-    let output = ChatCompletionsOutput { text, tool_calls };
+    let output = ChatCompletionsOutput {
+        text,
+        tool_calls,
+        input_tokens: data["usage"]["inputTokens"].as_u64().map(|v| v as usize),
+        output_tokens: data["usage"]["outputTokens"].as_u64().map(|v| v as usize),
+    };
     Ok(output)
 }
 
@@ -174,6 +174,11 @@ pub async fn claude_chat_completions_streaming(
         debug!("stream-data: {data}");
         if let Some(typ) = data["type"].as_str() {
             match typ {
+                "message_start" => handler.set_usage(
+                    data["message"]["usage"]["input_tokens"].as_u64(),
+                    data["message"]["usage"]["output_tokens"].as_u64(),
+                ),
+                "message_delta" => handler.set_usage(None, data["usage"]["output_tokens"].as_u64()),
                 "content_block_start" => {
                     if let (Some("tool_use"), Some(name), Some(id)) = (
                         data["content_block"]["type"].as_str(),
@@ -444,6 +449,8 @@ pub fn claude_extract_chat_completions(data: &Value) -> Result<ChatCompletionsOu
     let output = ChatCompletionsOutput {
         text: text.to_string(),
         tool_calls,
+        input_tokens: data["usage"]["input_tokens"].as_u64().map(|v| v as usize),
+        output_tokens: data["usage"]["output_tokens"].as_u64().map(|v| v as usize),
     };
     Ok(output)
 }
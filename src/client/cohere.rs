@@ -244,6 +244,15 @@ fn extract_chat_completions(data: &Value) -> Result<ChatCompletionsOutput> {
     if text.is_empty() && tool_calls.is_empty() {
         bail!("Invalid response data: {data}");
     }
-    let output = ChatCompletionsOutput { text, tool_calls };
+    let output = ChatCompletionsOutput {
+        text,
+        tool_calls,
+        input_tokens: data["usage"]["tokens"]["input_tokens"]
+            .as_u64()
+            .map(|v| v as usize),
+        output_tokens: data["usage"]["tokens"]["output_tokens"]
+            .as_u64()
+            .map(|v| v as usize),
+    };
     Ok(output)
 }
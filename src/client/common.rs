@@ -1,14 +1,14 @@
 use super::*;
 
 use crate::{
-    config::{Config, GlobalConfig, Input},
+    config::{Config, GlobalConfig, Input, budget::Usage},
     function::{FunctionDeclaration, ToolCall, ToolResult, eval_tool_calls},
-    render::render_stream,
+    render::{print_inline_images, print_references, render_stream},
     utils::*,
 };
 
 use crate::vault::Vault;
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use fancy_regex::Regex;
 use indexmap::IndexMap;
 use inquire::{
@@ -18,7 +18,7 @@ use reqwest::{Client as ReqwestClient, RequestBuilder};
 use serde::Deserialize;
 use serde_json::{Value, json};
 use std::sync::LazyLock;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::unbounded_channel;
 
 pub const MODELS_YAML: &str = include_str!("../../models.yaml");
@@ -54,15 +54,27 @@ pub trait Client: Sync + Send {
     fn build_client(&self) -> Result<ReqwestClient> {
         let mut builder = ReqwestClient::builder();
         let extra = self.extra_config();
-        let timeout = extra.and_then(|v| v.connect_timeout).unwrap_or(10);
+        let config = self.global_config().read();
+        let connect_timeout = extra
+            .and_then(|v| v.connect_timeout)
+            .unwrap_or(config.connect_timeout_secs);
+        // A stalled response is given up on once nothing arrives for this long, rather than
+        // bounding the whole request, which would cut long streamed answers short
+        let read_timeout = extra
+            .and_then(|v| v.read_timeout)
+            .or(config.read_timeout_secs)
+            .filter(|v| *v > 0);
         if let Some(proxy) = extra.and_then(|v| v.proxy.as_deref()) {
             builder = set_proxy(builder, proxy)?;
         }
-        if let Some(user_agent) = self.global_config().read().user_agent.as_ref() {
+        if let Some(user_agent) = config.user_agent.as_ref() {
             builder = builder.user_agent(user_agent);
         }
+        if let Some(read_timeout) = read_timeout {
+            builder = builder.read_timeout(Duration::from_secs(read_timeout));
+        }
         let client = builder
-            .connect_timeout(Duration::from_secs(timeout))
+            .connect_timeout(Duration::from_secs(connect_timeout))
             .build()
             .with_context(|| "Failed to build client")?;
         Ok(client)
@@ -204,6 +216,7 @@ impl Default for ClientConfig {
 pub struct ExtraConfig {
     pub proxy: Option<String>,
     pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -293,6 +306,9 @@ pub struct ChatCompletionsData {
 pub struct ChatCompletionsOutput {
     pub text: String,
     pub tool_calls: Vec<ToolCall>,
+    /// The token counts the provider reported, if it did.
+    pub input_tokens: Option<usize>,
+    pub output_tokens: Option<usize>,
 }
 
 impl ChatCompletionsOutput {
@@ -415,12 +431,14 @@ pub async fn call_chat_completions(
     client: &dyn Client,
     abort_signal: AbortSignal,
 ) -> Result<(String, Vec<ToolResult>)> {
+    client.global_config().read().guard_agent_budget()?;
     let is_child_agent = client.global_config().read().current_depth > 0;
     let spinner_message = if is_child_agent { "" } else { "Generating" };
+    let started_at = Instant::now();
     let ret = abortable_run_with_spinner(
         client.chat_completions(input.clone()),
         spinner_message,
-        abort_signal,
+        abort_signal.clone(),
     )
     .await;
 
@@ -429,17 +447,32 @@ pub async fn call_chat_completions(
             let ChatCompletionsOutput {
                 mut text,
                 tool_calls,
-                ..
+                input_tokens,
+                output_tokens,
             } = ret;
+            let reported = (input_tokens, output_tokens);
+            record_usage(
+                input,
+                client,
+                &text,
+                &tool_calls,
+                reported,
+                started_at.elapsed(),
+            );
             if !text.is_empty() {
                 if extract_code {
                     text = extract_code_block(&strip_think_tag(&text)).to_string();
                 }
                 if print {
                     client.global_config().read().print_markdown(&text)?;
+                    print_references(client.global_config(), &text)?;
                 }
             }
-            let tool_results = eval_tool_calls(client.global_config(), tool_calls).await?;
+            let tool_results =
+                eval_tool_calls(client.global_config(), tool_calls, abort_signal.clone()).await?;
+            if print {
+                print_inline_images(client.global_config(), &text, &tool_results)?;
+            }
             if let Some(tracker) = client.global_config().write().tool_call_tracker.as_mut() {
                 tool_results
                     .iter()
@@ -456,9 +489,19 @@ pub async fn call_chat_completions_streaming(
     client: &dyn Client,
     abort_signal: AbortSignal,
 ) -> Result<(String, Vec<ToolResult>)> {
+    let (max_call_repeats, call_repeat_chain_len) = {
+        let config = client.global_config().read();
+        config.guard_agent_budget()?;
+        (
+            config.max_tool_call_repeats,
+            config.tool_call_repeat_chain_len,
+        )
+    };
     let (tx, rx) = unbounded_channel();
-    let mut handler = SseHandler::new(tx, abort_signal.clone());
+    let mut handler = SseHandler::new(tx, abort_signal.clone())
+        .with_loop_detection(max_call_repeats, call_repeat_chain_len);
 
+    let started_at = Instant::now();
     let (send_ret, render_ret) = tokio::join!(
         client.chat_completions_streaming(input, &mut handler),
         render_stream(rx, client.global_config(), abort_signal.clone()),
@@ -470,13 +513,25 @@ pub async fn call_chat_completions_streaming(
 
     render_ret?;
 
+    let reported = handler.reported_usage();
     let (text, tool_calls) = handler.take();
     match send_ret {
         Ok(_) => {
+            record_usage(
+                input,
+                client,
+                &text,
+                &tool_calls,
+                reported,
+                started_at.elapsed(),
+            );
             if !text.is_empty() && !text.ends_with('\n') {
                 println!();
             }
-            let tool_results = eval_tool_calls(client.global_config(), tool_calls).await?;
+            print_references(client.global_config(), &text)?;
+            let tool_results =
+                eval_tool_calls(client.global_config(), tool_calls, abort_signal.clone()).await?;
+            print_inline_images(client.global_config(), &text, &tool_results)?;
             if let Some(tracker) = client.global_config().write().tool_call_tracker.as_mut() {
                 tool_results
                     .iter()
@@ -493,6 +548,59 @@ pub async fn call_chat_completions_streaming(
     }
 }
 
+/// Whether a completion failed because the model couldn't be reached at all (e.g. no network
+/// or the server is down), as opposed to the API rejecting the request.
+pub fn is_connection_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|v| v.is_connect() || v.is_timeout())
+            || matches!(
+                cause.downcast_ref::<reqwest_eventsource::Error>(),
+                Some(reqwest_eventsource::Error::Transport(v)) if v.is_connect() || v.is_timeout()
+            )
+    })
+}
+
+/// Records the usage of a completion for the session and the agent, preferring the token counts
+/// the provider reported and estimating the rest. Nothing is counted when neither needs it.
+fn record_usage(
+    input: &Input,
+    client: &dyn Client,
+    text: &str,
+    tool_calls: &[ToolCall],
+    (input_tokens, output_tokens): (Option<usize>, Option<usize>),
+    latency: Duration,
+) {
+    {
+        let config = client.global_config().read();
+        if config.agent.is_none() && input.session(&config.session).is_none() {
+            return;
+        }
+    }
+    let model = client.model();
+    let input_tokens = input_tokens.unwrap_or_else(|| {
+        input
+            .build_messages()
+            .map(|messages| model.total_tokens(&messages))
+            .unwrap_or_default()
+    });
+    let output_tokens = output_tokens.unwrap_or_else(|| {
+        estimate_token_length(text)
+            + serde_json::to_string(tool_calls)
+                .map(|v| estimate_token_length(&v))
+                .unwrap_or_default()
+    });
+    let usage = Usage::new(model, input_tokens, output_tokens);
+    let mut config = client.global_config().write();
+    if let Some(session) = input.session_mut(&mut config.session) {
+        session.add_pending_usage(input, &model.id(), &usage, latency);
+    }
+    if let Err(err) = config.record_agent_usage(&usage, tool_calls) {
+        warn!("Failed to record agent usage: {err}");
+    }
+}
+
 pub fn noop_prepare_rerank<T>(_client: &T, _data: &RerankData) -> Result<RequestData> {
     bail!("The client doesn't support rerank api")
 }
@@ -501,46 +609,52 @@ pub async fn noop_rerank(_builder: RequestBuilder, _model: &Model) -> Result<Rer
     bail!("The client doesn't support rerank api")
 }
 
+/// Turns an error response from a model provider into an error, tagged as a network error for
+/// `loki`'s exit code.
 pub fn catch_error(data: &Value, status: u16) -> Result<()> {
     if (200..300).contains(&status) {
         return Ok(());
     }
+    Err(ExitCode::Network.tag(response_error(data, status)))
+}
+
+fn response_error(data: &Value, status: u16) -> anyhow::Error {
     debug!("Invalid response, status: {status}, data: {data}");
     if let Some(error) = data["error"].as_object() {
         if let (Some(typ), Some(message)) = (
             json_str_from_map(error, "type"),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (type: {typ})");
+            return anyhow!("{message} (type: {typ})");
         } else if let (Some(typ), Some(message)) = (
             json_str_from_map(error, "code"),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (code: {typ})");
+            return anyhow!("{message} (code: {typ})");
         }
     } else if let Some(error) = data["errors"][0].as_object() {
         if let (Some(code), Some(message)) = (
             error.get("code").and_then(|v| v.as_u64()),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (status: {code})")
+            return anyhow!("{message} (status: {code})");
         }
     } else if let Some(error) = data[0]["error"].as_object() {
         if let (Some(status), Some(message)) = (
             json_str_from_map(error, "status"),
             json_str_from_map(error, "message"),
         ) {
-            bail!("{message} (status: {status})")
+            return anyhow!("{message} (status: {status})");
         }
     } else if let (Some(detail), Some(status)) = (data["detail"].as_str(), data["status"].as_i64())
     {
-        bail!("{detail} (status: {status})");
+        return anyhow!("{detail} (status: {status})");
     } else if let Some(error) = data["error"].as_str() {
-        bail!("{error}");
+        return anyhow!("{error}");
     } else if let Some(message) = data["message"].as_str() {
-        bail!("{message}");
+        return anyhow!("{message}");
     }
-    bail!("Invalid response data: {data} (status: {status})");
+    anyhow!("Invalid response data: {data} (status: {status})")
 }
 
 pub fn json_str_from_map<'a>(
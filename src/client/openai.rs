@@ -128,6 +128,10 @@ pub async fn openai_chat_completions_streaming(
         }
         let data: Value = serde_json::from_str(&message.data)?;
         debug!("stream-data: {data}");
+        handler.set_usage(
+            data["usage"]["prompt_tokens"].as_u64(),
+            data["usage"]["completion_tokens"].as_u64(),
+        );
         if let Some(text) = data["choices"][0]["delta"]["content"]
             .as_str()
             .filter(|v| !v.is_empty())
@@ -391,7 +395,14 @@ pub fn openai_extract_chat_completions(data: &Value) -> Result<ChatCompletionsOu
     } else {
         text.to_string()
     };
-    let output = ChatCompletionsOutput { text, tool_calls };
+    let output = ChatCompletionsOutput {
+        text,
+        tool_calls,
+        input_tokens: data["usage"]["prompt_tokens"].as_u64().map(|v| v as usize),
+        output_tokens: data["usage"]["completion_tokens"]
+            .as_u64()
+            .map(|v| v as usize),
+    };
     Ok(output)
 }
 
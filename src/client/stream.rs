@@ -16,6 +16,8 @@ pub struct SseHandler {
     last_tool_calls: Vec<ToolCall>,
     max_call_repeats: usize,
     call_repeat_chain_len: usize,
+    input_tokens: Option<usize>,
+    output_tokens: Option<usize>,
 }
 
 impl SseHandler {
@@ -28,9 +30,23 @@ impl SseHandler {
             last_tool_calls: Vec::new(),
             max_call_repeats: 2,
             call_repeat_chain_len: 3,
+            input_tokens: None,
+            output_tokens: None,
         }
     }
 
+    /// Sets how many times a chain of calls can repeat before it's treated as a loop.
+    /// A `max_call_repeats` of 0 turns loop detection off.
+    pub fn with_loop_detection(
+        mut self,
+        max_call_repeats: usize,
+        call_repeat_chain_len: usize,
+    ) -> Self {
+        self.max_call_repeats = max_call_repeats;
+        self.call_repeat_chain_len = call_repeat_chain_len.max(1);
+        self
+    }
+
     pub fn text(&mut self, text: &str) -> Result<()> {
         if text.is_empty() {
             return Ok(());
@@ -49,6 +65,21 @@ impl SseHandler {
         Ok(())
     }
 
+    /// Records the token counts the provider reported, which may come in over several events.
+    pub fn set_usage(&mut self, input_tokens: Option<u64>, output_tokens: Option<u64>) {
+        if let Some(v) = input_tokens {
+            self.input_tokens = Some(v as usize);
+        }
+        if let Some(v) = output_tokens {
+            self.output_tokens = Some(v as usize);
+        }
+    }
+
+    /// The input and output token counts the provider reported, if it did.
+    pub fn reported_usage(&self) -> (Option<usize>, Option<usize>) {
+        (self.input_tokens, self.output_tokens)
+    }
+
     pub fn done(&mut self) {
         let ret = self.sender.send(SseEvent::Done);
         if ret.is_err() {
@@ -60,6 +91,11 @@ impl SseHandler {
     }
 
     pub fn tool_call(&mut self, call: ToolCall) -> Result<()> {
+        if self.max_call_repeats == 0 {
+            self.tool_calls.push(call);
+            return Ok(());
+        }
+
         if self.is_call_loop(&call) {
             let loop_message = self.create_loop_detection_message(&call);
             return Err(anyhow!(loop_message));
@@ -230,7 +266,7 @@ where
                         );
                     }
                     _ => {
-                        bail!("{}", err);
+                        return Err(err.into());
                     }
                 }
                 es.close();
@@ -311,10 +347,8 @@ impl JsonStreamParser {
                     }
                     self.balances.push(ch);
                 }
-                '[' => {
-                    if self.start.is_some() {
-                        self.balances.push(ch);
-                    }
+                '[' if self.start.is_some() => {
+                    self.balances.push(ch);
                 }
                 '}' => {
                     self.balances.pop();
@@ -391,6 +425,20 @@ mod tests {
         assert!(error_message.contains("test_function_loop"));
     }
 
+    #[test]
+    fn test_call_loop_detection_disabled() {
+        let (sender, _) = tokio::sync::mpsc::unbounded_channel();
+        let abort_signal = crate::utils::create_abort_signal();
+        let mut handler = SseHandler::new(sender, abort_signal).with_loop_detection(0, 3);
+
+        let call = ToolCall::new("test_function_loop".to_string(), json!({"param": 1}), None);
+
+        for _ in 0..10 {
+            handler.tool_call(call.clone()).unwrap();
+        }
+        assert_eq!(handler.take().1.len(), 10);
+    }
+
     fn split_chunks(text: &str) -> Vec<Vec<u8>> {
         let mut rng = rand::rng();
         let len = text.len();
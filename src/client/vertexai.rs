@@ -216,6 +216,10 @@ pub async fn gemini_chat_completions_streaming(
         let handle = |value: &str| -> Result<()> {
             let data: Value = serde_json::from_str(value)?;
             debug!("stream-data: {data}");
+            handler.set_usage(
+                data["usageMetadata"]["promptTokenCount"].as_u64(),
+                data["usageMetadata"]["candidatesTokenCount"].as_u64(),
+            );
             if let Some(parts) = data["candidates"][0]["content"]["parts"].as_array() {
                 for (i, part) in parts.iter().enumerate() {
                     if let Some(text) = part["text"].as_str() {
@@ -318,7 +322,16 @@ fn gemini_extract_chat_completions_text(data: &Value) -> Result<ChatCompletionsO
             bail!("Invalid response data: {data}");
         }
     }
-    let output = ChatCompletionsOutput { text, tool_calls };
+    let output = ChatCompletionsOutput {
+        text,
+        tool_calls,
+        input_tokens: data["usageMetadata"]["promptTokenCount"]
+            .as_u64()
+            .map(|v| v as usize),
+        output_tokens: data["usageMetadata"]["candidatesTokenCount"]
+            .as_u64()
+            .map(|v| v as usize),
+    };
     Ok(output)
 }
 
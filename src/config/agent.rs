@@ -1,24 +1,43 @@
+use super::budget::{AgentBudget, Usage};
+use super::history::ActiveRun;
+use super::macros::{Macro, MacroVariable, resolve_variables, variables_usage};
+use super::memory::MemoryStore;
+use super::skill::{SKILLS_DIR_NAME, Skill};
+use super::template::render_template;
 use super::todo::TodoList;
 use super::*;
 
 use crate::{
     client::Model,
-    function::{Functions, run_llm_function},
+    function::{
+        Functions,
+        permissions::ToolPermissions,
+        run_llm_function,
+        sandbox::{DEFAULT_SANDBOX_IMAGE, DockerSandbox, SandboxType},
+    },
+    repl::split_args_text,
 };
 
 use crate::config::prompts::{
-    DEFAULT_SPAWN_INSTRUCTIONS, DEFAULT_TEAMMATE_INSTRUCTIONS, DEFAULT_TODO_INSTRUCTIONS,
+    DEFAULT_MEMORY_INSTRUCTIONS, DEFAULT_SKILLS_INSTRUCTIONS, DEFAULT_SPAWN_INSTRUCTIONS,
+    DEFAULT_TEAMMATE_INSTRUCTIONS, DEFAULT_TODO_INSTRUCTIONS,
     DEFAULT_USER_INTERACTION_INSTRUCTIONS,
 };
-use crate::vault::SECRET_RE;
+use crate::vault::{SECRET_RE, Vault};
 use anyhow::{Context, Result};
-use fancy_regex::Captures;
-use inquire::{Text, validator::Validation};
+use fancy_regex::{Captures, Regex};
+use inquire::{Password, PasswordDisplayMode, Select, Text, validator::Validation};
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
-use std::{ffi::OsStr, path::Path};
+use std::{ffi::OsStr, path::Path, time::Duration};
 
-const DEFAULT_AGENT_NAME: &str = "rag";
+pub const DEFAULT_AGENT_NAME: &str = "rag";
+pub const AGENT_VERSION_FILE_NAME: &str = ".version";
+const MIGRATE_SCRIPTS: [(&str, &str); 3] = [
+    ("migrate.sh", "bash"),
+    ("migrate.py", "python"),
+    ("migrate.js", "node"),
+];
 
 pub type AgentVariables = IndexMap<String, String>;
 
@@ -39,8 +58,14 @@ pub struct Agent {
     model: Model,
     vault: GlobalVault,
     todo_list: TodoList,
+    todo_file: Option<PathBuf>,
     continuation_count: usize,
     last_continuation_response: Option<String>,
+    memory: Option<MemoryStore>,
+    skills: Vec<Skill>,
+    session_usage: Usage,
+    active_run: Option<ActiveRun>,
+    fallbacks_used: usize,
 }
 
 impl Agent {
@@ -62,7 +87,10 @@ impl Agent {
                 .and_then(OsStr::to_str)
                 .map(|s| s.to_lowercase());
             #[cfg_attr(not(unix), expect(unused))]
-            let is_script = matches!(file_extension.as_deref(), Some("sh") | Some("py"));
+            let is_script = matches!(
+                file_extension.as_deref(),
+                Some("sh") | Some("py") | Some("js")
+            );
 
             if file_path.exists() {
                 debug!(
@@ -87,6 +115,26 @@ impl Agent {
         Ok(())
     }
 
+    /// Re-indexes the documents of an agent's existing RAG, only embedding the files that
+    /// were added or changed since it was last built.
+    pub async fn sync_rag(
+        config: &GlobalConfig,
+        name: &str,
+        abort_signal: AbortSignal,
+    ) -> Result<()> {
+        let rag_path = Config::agent_rag_file(name, DEFAULT_AGENT_NAME);
+        let config_path = Config::agent_config_file(name);
+        if !rag_path.exists() || !config_path.exists() {
+            return Ok(());
+        }
+        let agent_config = AgentConfig::load(&config_path)?;
+        if agent_config.documents.is_empty() {
+            return Ok(());
+        }
+        refresh_rag(config, name, &agent_config, &rag_path, abort_signal).await?;
+        Ok(())
+    }
+
     pub async fn init(
         config: &GlobalConfig,
         name: &str,
@@ -101,6 +149,9 @@ impl Agent {
         } else {
             bail!("Agent config file not found at '{}'", config_path.display())
         };
+        migrate_agent_data(name, &agent_config.version)?;
+        render_template(&agent_config.instructions, &AgentVariables::new())
+            .with_context(|| format!("Invalid instructions for agent '{name}'"))?;
         let mut functions = Functions::init_agent(name, &agent_config.global_tools)?;
 
         config.write().functions.clear_mcp_meta_functions();
@@ -125,10 +176,10 @@ impl Agent {
             .take()
             .with_context(|| "MCP registry should be populated")?;
         let new_mcp_registry =
-            McpRegistry::reinit(registry, mcp_servers, abort_signal.clone()).await?;
+            McpRegistry::reinit_deferred(registry, mcp_servers, abort_signal.clone()).await?;
 
         if !new_mcp_registry.is_empty() {
-            functions.append_mcp_meta_functions(new_mcp_registry.list_started_servers());
+            functions.append_mcp_meta_functions(new_mcp_registry.list_enabled_servers());
         }
 
         config.write().mcp_registry = Some(new_mcp_registry);
@@ -152,7 +203,15 @@ impl Agent {
         };
 
         let rag = if rag_path.exists() {
-            Some(Arc::new(Rag::load(config, DEFAULT_AGENT_NAME, &rag_path)?))
+            let rag = if !agent_config.documents.is_empty()
+                && !config.read().info_flag
+                && agent_config.rag_refresh.is_due(&rag_path)
+            {
+                refresh_rag(config, name, &agent_config, &rag_path, abort_signal.clone()).await?
+            } else {
+                Rag::load(config, DEFAULT_AGENT_NAME, &rag_path)?
+            };
+            Some(Arc::new(rag))
         } else if !agent_config.documents.is_empty() && !config.read().info_flag {
             let mut ans = false;
             if *IS_STDOUT_TERMINAL {
@@ -161,32 +220,18 @@ impl Agent {
                     .prompt()?;
             }
             if ans {
-                let mut document_paths = vec![];
-                for path in &agent_config.documents {
-                    if is_url(path) {
-                        document_paths.push(path.to_string());
-                    } else if is_loader_protocol(&loaders, path) {
-                        let (protocol, document_path) = path
-                            .split_once(':')
-                            .with_context(|| "Invalid loader protocol path")?;
-                        let resolved_path = resolve_home_dir(document_path);
-                        let new_path = if Path::new(&resolved_path).is_relative() {
-                            safe_join_path(&agent_data_dir, resolved_path)
-                                .ok_or_else(|| anyhow!("Invalid document path: '{path}'"))?
-                        } else {
-                            PathBuf::from(&resolved_path)
-                        };
-                        document_paths.push(format!("{}:{}", protocol, new_path.display()));
-                    } else if Path::new(&resolve_home_dir(path)).is_relative() {
-                        let new_path = safe_join_path(&agent_data_dir, path)
-                            .ok_or_else(|| anyhow!("Invalid document path: '{path}'"))?;
-                        document_paths.push(new_path.display().to_string())
-                    } else {
-                        document_paths.push(path.to_string())
-                    }
-                }
-                let rag =
-                    Rag::init(config, "rag", &rag_path, &document_paths, abort_signal).await?;
+                let document_paths =
+                    resolve_document_paths(&agent_data_dir, &loaders, &agent_config.documents)?;
+                let exclude_paths = resolve_exclude_paths(&agent_data_dir, &agent_config.exclude)?;
+                let rag = Rag::init(
+                    config,
+                    "rag",
+                    &rag_path,
+                    &document_paths,
+                    &exclude_paths,
+                    abort_signal,
+                )
+                .await?;
                 Some(Arc::new(rag))
             } else {
                 None
@@ -203,6 +248,21 @@ impl Agent {
             functions.append_supervisor_functions();
         }
 
+        let memory = if agent_config.memory {
+            functions.append_memory_functions();
+            Some(MemoryStore::load(
+                &config.read(),
+                &Config::agent_memory_file(name),
+            )?)
+        } else {
+            None
+        };
+
+        let skills = Skill::list(&agent_data_dir.join(SKILLS_DIR_NAME))?;
+        if !skills.is_empty() {
+            functions.append_skill_functions();
+        }
+
         functions.append_teammate_functions();
         functions.append_user_interaction_functions();
 
@@ -220,8 +280,14 @@ impl Agent {
             model,
             vault: Arc::clone(&config.read().vault),
             todo_list: TodoList::default(),
+            todo_file: None,
             continuation_count: 0,
             last_continuation_response: None,
+            memory,
+            skills,
+            session_usage: Usage::default(),
+            active_run: None,
+            fallbacks_used: 0,
         })
     }
 
@@ -229,6 +295,7 @@ impl Agent {
         agent_variables: &[AgentVariable],
         pre_set_variables: Option<&AgentVariables>,
         no_interaction: bool,
+        vault: &Vault,
     ) -> Result<AgentVariables> {
         let mut output = IndexMap::new();
         if agent_variables.is_empty() {
@@ -239,13 +306,25 @@ impl Agent {
         for agent_variable in agent_variables {
             let key = agent_variable.name.clone();
             if let Some(value) = pre_set_variables.and_then(|v| v.get(&key)) {
+                agent_variable
+                    .validate(value)
+                    .map_err(|err| anyhow!("Invalid value for agent variable '{key}': {err}"))?;
                 output.insert(key, value.clone());
                 continue;
             }
             if let Some(value) = agent_variable.default.clone() {
+                agent_variable.validate(&value).map_err(|err| {
+                    anyhow!("Invalid default value for agent variable '{key}': {err}")
+                })?;
                 output.insert(key, value);
                 continue;
             }
+            if agent_variable.variable_type == AgentVariableType::Secret
+                && vault.get_secret(&key, false).is_ok()
+            {
+                output.insert(key.clone(), format!("{{{{{key}}}}}"));
+                continue;
+            }
             if no_interaction {
                 continue;
             }
@@ -254,18 +333,7 @@ impl Agent {
                     println!("⚙ Init agent variables...");
                     printed = true;
                 }
-                let value = Text::new(&format!(
-                    "{} ({}):",
-                    agent_variable.name, agent_variable.description
-                ))
-                .with_validator(|input: &str| {
-                    if input.trim().is_empty() {
-                        Ok(Validation::Invalid("This field is required".into()))
-                    } else {
-                        Ok(Validation::Valid)
-                    }
-                })
-                .prompt()?;
+                let value = agent_variable.prompt()?;
                 output.insert(key, value);
             } else {
                 unset_variables.push(agent_variable)
@@ -287,7 +355,7 @@ impl Agent {
     pub fn export(&self) -> Result<String> {
         let mut value = json!({});
         value["name"] = json!(self.name());
-        let variables = self.variables();
+        let variables = self.masked_variables();
         if !variables.is_empty() {
             value["variables"] = serde_json::to_value(variables)?;
         }
@@ -303,12 +371,23 @@ impl Agent {
             .display()
             .to_string()
             .into();
+        if let Some(memory) = &self.memory {
+            value["memory_file"] = Config::agent_memory_file(&self.name)
+                .display()
+                .to_string()
+                .into();
+            value["memories"] = memory.len().into();
+        }
+        if !self.skills.is_empty() {
+            value["skills"] = self.skills.iter().map(|v| v.name.clone()).collect();
+        }
         let data = serde_yaml::to_string(&value)?;
         Ok(data)
     }
 
     pub fn banner(&self) -> String {
-        self.config.banner(&self.conversation_starters())
+        self.config
+            .banner(&self.conversation_starters(), &self.config.commands)
     }
 
     pub fn name(&self) -> &str {
@@ -331,6 +410,34 @@ impl Agent {
             .collect()
     }
 
+    pub fn commands(&self) -> &[AgentCommand] {
+        &self.config.commands
+    }
+
+    /// Expands a slash command (e.g. `/deploy prod`) into its prompt. Returns `None` when
+    /// the line doesn't name one of the agent's commands.
+    pub fn expand_command(&self, line: &str) -> Option<Result<String>> {
+        let (name, args) = match line
+            .trim()
+            .strip_prefix('/')?
+            .split_once(char::is_whitespace)
+        {
+            Some((name, args)) => (name, args.trim()),
+            None => (line.trim().strip_prefix('/')?, ""),
+        };
+        let command = self.config.commands.iter().find(|v| v.name == name)?;
+        let (mut args, text) = split_args_text(args, cfg!(windows));
+        if !text.is_empty() {
+            args.push(text.to_string());
+        }
+        let prompt = resolve_variables(&command.variables, &args)
+            .map(|variables| {
+                self.interpolate_text(&Macro::interpolate_command(&command.prompt, &variables))
+            })
+            .map_err(|err| anyhow!("{err}. Usage: {}", command.usage()));
+        Some(prompt)
+    }
+
     pub fn interpolated_instructions(&self) -> String {
         let mut output = self
             .session_dynamic_instructions
@@ -346,6 +453,18 @@ impl Agent {
             output.push_str(DEFAULT_SPAWN_INSTRUCTIONS);
         }
 
+        if self.config.memory && self.config.inject_memory_instructions {
+            output.push_str(DEFAULT_MEMORY_INSTRUCTIONS);
+        }
+
+        if !self.skills.is_empty() {
+            output.push_str(DEFAULT_SKILLS_INSTRUCTIONS);
+            for skill in &self.skills {
+                output.push_str(&format!("    - `{}`: {}\n", skill.name, skill.description));
+            }
+            output.push('\n');
+        }
+
         output.push_str(DEFAULT_TEAMMATE_INSTRUCTIONS);
         output.push_str(DEFAULT_USER_INTERACTION_INSTRUCTIONS);
 
@@ -353,7 +472,13 @@ impl Agent {
     }
 
     fn interpolate_text(&self, text: &str) -> String {
-        let mut output = text.to_string();
+        let mut output = render_template(text, self.variables()).unwrap_or_else(|err| {
+            warn!(
+                "Failed to render the template for agent '{}': {err}",
+                self.name
+            );
+            text.to_string()
+        });
         for (k, v) in self.variables() {
             output = output.replace(&format!("{{{{{k}}}}}"), v)
         }
@@ -361,6 +486,15 @@ impl Agent {
         output
     }
 
+    /// Loads the prelude of an agent without initializing it.
+    pub fn load_prelude(name: &str) -> Result<AgentPrelude> {
+        let config_path = Config::agent_config_file(name);
+        if !config_path.exists() {
+            return Ok(AgentPrelude::default());
+        }
+        Ok(AgentConfig::load(&config_path)?.prelude)
+    }
+
     pub fn agent_session(&self) -> Option<&str> {
         self.config.agent_session.as_deref()
     }
@@ -372,6 +506,20 @@ impl Agent {
         }
     }
 
+    /// The agent's variables with the values of `secret` variables masked, for display.
+    pub fn masked_variables(&self) -> AgentVariables {
+        self.variables()
+            .iter()
+            .map(|(k, v)| {
+                let is_secret = self.config.variables.iter().any(|variable| {
+                    variable.name == *k && variable.variable_type == AgentVariableType::Secret
+                });
+                let value = if is_secret { "********" } else { v.as_str() };
+                (k.clone(), value.to_string())
+            })
+            .collect()
+    }
+
     pub fn variable_envs(&self) -> HashMap<String, String> {
         self.variables()
             .iter()
@@ -466,19 +614,126 @@ impl Agent {
 
     pub fn init_todo_list(&mut self, goal: &str) {
         self.todo_list = TodoList::new(goal);
+        self.save_todo_list();
     }
 
     pub fn add_todo(&mut self, task: &str) -> usize {
-        self.todo_list.add(task)
+        let id = self.todo_list.add(task);
+        self.save_todo_list();
+        id
     }
 
     pub fn mark_todo_done(&mut self, id: usize) -> bool {
-        self.todo_list.mark_done(id)
+        let found = self.todo_list.mark_done(id);
+        if found {
+            self.save_todo_list();
+        }
+        found
     }
 
     pub fn clear_todo_list(&mut self) {
         self.todo_list.clear();
         self.reset_continuation();
+        self.save_todo_list();
+    }
+
+    /// Switches the todo list to the one persisted for the given session (or the
+    /// agent's sessionless list when `None`).
+    pub fn load_todo_list(&mut self, session_name: Option<&str>) -> Result<()> {
+        if !self.config.auto_continue {
+            return Ok(());
+        }
+        let path = Config::agent_todo_file(&self.name, session_name);
+        self.todo_list = TodoList::load(&path)?;
+        self.todo_file = Some(path);
+        self.reset_continuation();
+        Ok(())
+    }
+
+    fn save_todo_list(&self) {
+        if let Some(path) = &self.todo_file
+            && let Err(err) = self.todo_list.save(path)
+        {
+            warn!("Failed to persist the todo list: {err}");
+        }
+    }
+
+    pub fn permissions(&self) -> &ToolPermissions {
+        &self.config.permissions
+    }
+
+    /// The container the agent's tools run in, if the agent is sandboxed.
+    pub fn sandbox(&self) -> Option<DockerSandbox> {
+        match self.config.sandbox {
+            SandboxType::None => None,
+            SandboxType::Docker => Some(DockerSandbox {
+                image: self
+                    .config
+                    .sandbox_image
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_SANDBOX_IMAGE.to_string()),
+                network: self.config.sandbox_network,
+                work_dir: self.config.sandbox_work_dir.as_ref().map(PathBuf::from),
+            }),
+        }
+    }
+
+    pub fn budget(&self) -> &AgentBudget {
+        &self.config.budget
+    }
+
+    /// Usage outside of a named session, which is only tracked for the lifetime of the agent.
+    pub fn session_usage(&self) -> &Usage {
+        &self.session_usage
+    }
+
+    pub fn add_session_usage(&mut self, usage: &Usage) {
+        self.session_usage.add(usage);
+    }
+
+    /// Starts recording a run, unless one is already in progress (e.g. while the agent is
+    /// still working through tool calls for the previous prompt).
+    pub fn begin_run(&mut self, prompt: &str, session: Option<&str>) {
+        if self.active_run.is_none() {
+            self.active_run = Some(ActiveRun::new(prompt, session));
+        }
+    }
+
+    pub fn active_run_mut(&mut self) -> Option<&mut ActiveRun> {
+        self.active_run.as_mut()
+    }
+
+    pub fn take_run(&mut self) -> Option<ActiveRun> {
+        self.active_run.take()
+    }
+
+    /// The next of the agent's `model_fallbacks` that it hasn't switched to yet.
+    pub fn next_fallback_model_id(&mut self) -> Option<String> {
+        let model_id = self
+            .config
+            .model_fallbacks
+            .get(self.fallbacks_used)
+            .cloned();
+        if model_id.is_some() {
+            self.fallbacks_used += 1;
+        }
+        model_id
+    }
+
+    pub fn skills(&self) -> &[Skill] {
+        &self.skills
+    }
+
+    pub fn memory(&self) -> Option<&MemoryStore> {
+        self.memory.as_ref()
+    }
+
+    pub fn memory_mut(&mut self) -> Option<&mut MemoryStore> {
+        self.memory.as_mut()
+    }
+
+    pub fn memory_top_k(&self) -> usize {
+        self.config.memory_top_k
     }
 
     pub fn continuation_prompt(&self) -> String {
@@ -525,7 +780,11 @@ impl Agent {
             vec!["_instructions".into(), "{}".into()],
             self.variable_envs(),
             Some(self.name().to_string()),
-        )?;
+            self.sandbox().as_ref(),
+            None,
+            None,
+        )?
+        .output;
         match value {
             Some(v) => Ok(v),
             _ => bail!("No return value from '_instructions' function"),
@@ -612,6 +871,8 @@ pub struct AgentConfig {
     pub name: String,
     #[serde(rename(serialize = "model", deserialize = "model"))]
     pub model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_fallbacks: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -632,6 +893,16 @@ pub struct AgentConfig {
     pub inject_todo_instructions: bool,
     #[serde(default = "default_true")]
     pub inject_spawn_instructions: bool,
+    #[serde(default)]
+    pub memory: bool,
+    #[serde(default = "default_memory_top_k")]
+    pub memory_top_k: usize,
+    #[serde(default = "default_true")]
+    pub inject_memory_instructions: bool,
+    #[serde(default, skip_serializing_if = "AgentBudget::is_empty")]
+    pub budget: AgentBudget,
+    #[serde(default, skip_serializing_if = "AgentPrelude::is_empty")]
+    pub prelude: AgentPrelude,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub compression_threshold: Option<usize>,
     #[serde(default)]
@@ -642,6 +913,16 @@ pub struct AgentConfig {
     pub mcp_servers: Vec<String>,
     #[serde(default)]
     pub global_tools: Vec<String>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub permissions: ToolPermissions,
+    #[serde(default)]
+    pub sandbox: SandboxType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox_image: Option<String>,
+    #[serde(default)]
+    pub sandbox_network: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox_work_dir: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub continuation_prompt: Option<String>,
     #[serde(default)]
@@ -652,8 +933,14 @@ pub struct AgentConfig {
     pub variables: Vec<AgentVariable>,
     #[serde(default)]
     pub conversation_starters: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<AgentCommand>,
     #[serde(default)]
     pub documents: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub rag_refresh: RagRefresh,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub summarization_model: Option<String>,
     #[serde(default = "default_summarization_threshold")]
@@ -662,6 +949,50 @@ pub struct AgentConfig {
     pub escalation_timeout: u64,
 }
 
+/// When an agent's existing RAG is re-synced with its documents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RagRefresh {
+    OnStart,
+    Daily,
+    #[default]
+    Never,
+}
+
+impl RagRefresh {
+    /// Whether the RAG at `rag_path` is stale. The RAG file is rewritten on every sync, so
+    /// its modification time is when the RAG was last synced.
+    fn is_due(&self, rag_path: &Path) -> bool {
+        match self {
+            Self::OnStart => true,
+            Self::Daily => rag_path
+                .metadata()
+                .and_then(|v| v.modified())
+                .ok()
+                .and_then(|v| v.elapsed().ok())
+                .is_none_or(|v| v >= Duration::from_secs(24 * 60 * 60)),
+            Self::Never => false,
+        }
+    }
+}
+
+/// Actions run when the agent is started with `--agent`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct AgentPrelude {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+}
+
+impl AgentPrelude {
+    pub fn is_empty(&self) -> bool {
+        self.session.is_none() && self.rag.is_none() && self.prompt.is_none()
+    }
+}
+
 fn default_max_auto_continues() -> usize {
     10
 }
@@ -674,6 +1005,10 @@ fn default_max_agent_depth() -> usize {
     3
 }
 
+fn default_memory_top_k() -> usize {
+    5
+}
+
 fn default_true() -> bool {
     true
 }
@@ -713,6 +1048,11 @@ impl AgentConfig {
         if let Some(v) = read_env_value::<f64>(&with_prefix("top_p")) {
             self.top_p = v;
         }
+        if let Ok(v) = env::var(with_prefix("model_fallbacks"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.model_fallbacks = v;
+        }
         if let Ok(v) = env::var(with_prefix("global_tools"))
             && let Ok(v) = serde_json::from_str(&v)
         {
@@ -723,6 +1063,16 @@ impl AgentConfig {
         {
             self.mcp_servers = v;
         }
+        if let Ok(v) = env::var(with_prefix("permissions"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.permissions = v;
+        }
+        if let Ok(v) = env::var(with_prefix("budget"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.budget = v;
+        }
         if let Some(v) = read_env_value::<String>(&with_prefix("agent_session")) {
             self.agent_session = v;
         }
@@ -733,7 +1083,7 @@ impl AgentConfig {
         }
     }
 
-    fn banner(&self, conversation_starters: &[String]) -> String {
+    fn banner(&self, conversation_starters: &[String], commands: &[AgentCommand]) -> String {
         let AgentConfig {
             name,
             description,
@@ -755,9 +1105,24 @@ impl AgentConfig {
 {starters}"#
             )
         };
+        let commands = if commands.is_empty() {
+            String::new()
+        } else {
+            let commands = commands
+                .iter()
+                .map(|v| format!("- `{}`: {}", v.usage(), v.description))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!(
+                r#"
+
+## Commands
+{commands}"#
+            )
+        };
         format!(
             r#"# {name} {version}
-{description}{starters}"#
+{description}{starters}{commands}"#
         )
     }
 
@@ -782,16 +1147,238 @@ impl AgentConfig {
     }
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AgentCommand {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables: Vec<MacroVariable>,
+    pub prompt: String,
+}
+
+impl AgentCommand {
+    pub fn usage(&self) -> String {
+        variables_usage(&format!("/{}", self.name), &self.variables)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentVariableType {
+    #[default]
+    String,
+    Int,
+    Bool,
+    Enum,
+    Secret,
+}
+
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct AgentVariable {
     pub name: String,
     pub description: String,
+    #[serde(rename = "type", default)]
+    pub variable_type: AgentVariableType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub default: Option<String>,
     #[serde(skip_deserializing, default)]
     pub value: String,
 }
 
+impl AgentVariable {
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        match self.variable_type {
+            AgentVariableType::Int if value.trim().parse::<i64>().is_err() => {
+                return Err("must be an integer".into());
+            }
+            AgentVariableType::Bool if value.trim().parse::<bool>().is_err() => {
+                return Err("must be 'true' or 'false'".into());
+            }
+            AgentVariableType::Enum if !self.choices.iter().any(|v| v == value) => {
+                return Err(format!("must be one of: {}", self.choices.join(", ")));
+            }
+            AgentVariableType::Secret if SECRET_RE.is_match(value).unwrap_or_default() => {
+                return Ok(());
+            }
+            _ => {}
+        }
+        if let Some(pattern) = &self.pattern {
+            let re = Regex::new(pattern).map_err(|err| format!("invalid pattern: {err}"))?;
+            if !re.is_match(value).unwrap_or_default() {
+                return Err(format!("must match the pattern '{pattern}'"));
+            }
+        }
+        Ok(())
+    }
+
+    fn prompt(&self) -> Result<String> {
+        let message = format!("{} ({}):", self.name, self.description);
+        let value = match self.variable_type {
+            AgentVariableType::Bool => Select::new(&message, vec!["true", "false"])
+                .prompt()?
+                .to_string(),
+            AgentVariableType::Enum => Select::new(&message, self.choices.clone()).prompt()?,
+            AgentVariableType::Secret => {
+                let variable = self.clone();
+                Password::new(&message)
+                    .with_display_mode(PasswordDisplayMode::Masked)
+                    .without_confirmation()
+                    .with_validator(move |input: &str| Ok(variable.validation(input)))
+                    .prompt()?
+            }
+            AgentVariableType::String | AgentVariableType::Int => {
+                let variable = self.clone();
+                Text::new(&message)
+                    .with_validator(move |input: &str| Ok(variable.validation(input)))
+                    .prompt()?
+            }
+        };
+        Ok(value)
+    }
+
+    fn validation(&self, input: &str) -> Validation {
+        if input.trim().is_empty() {
+            return Validation::Invalid("This field is required".into());
+        }
+        match self.validate(input) {
+            Ok(()) => Validation::Valid,
+            Err(err) => Validation::Invalid(format!("The value {err}").into()),
+        }
+    }
+}
+
+/// Incrementally syncs an agent's existing RAG with its documents and exclusions.
+async fn refresh_rag(
+    config: &GlobalConfig,
+    name: &str,
+    agent_config: &AgentConfig,
+    rag_path: &Path,
+    abort_signal: AbortSignal,
+) -> Result<Rag> {
+    let agent_data_dir = Config::agent_data_dir(name);
+    let loaders = config.read().document_loaders.clone();
+    let document_paths =
+        resolve_document_paths(&agent_data_dir, &loaders, &agent_config.documents)?;
+    let exclude_paths = resolve_exclude_paths(&agent_data_dir, &agent_config.exclude)?;
+
+    let mut rag = Rag::load(config, DEFAULT_AGENT_NAME, rag_path)?;
+    rag.set_exclude_paths(&exclude_paths);
+    rag.refresh_document_paths(&document_paths, true, config, abort_signal)
+        .await?;
+    Ok(rag)
+}
+
+/// Resolves the agent's documents, making local paths relative to the agent's data dir.
+fn resolve_document_paths(
+    agent_data_dir: &Path,
+    loaders: &HashMap<String, String>,
+    documents: &[String],
+) -> Result<Vec<String>> {
+    let mut document_paths = vec![];
+    for path in documents {
+        if is_url(path) {
+            document_paths.push(path.to_string());
+        } else if is_loader_protocol(loaders, path) {
+            let (protocol, document_path) = path
+                .split_once(':')
+                .with_context(|| "Invalid loader protocol path")?;
+            let resolved_path = resolve_home_dir(document_path);
+            let new_path = if Path::new(&resolved_path).is_relative() {
+                safe_join_path(agent_data_dir, resolved_path)
+                    .ok_or_else(|| anyhow!("Invalid document path: '{path}'"))?
+            } else {
+                PathBuf::from(&resolved_path)
+            };
+            document_paths.push(format!("{}:{}", protocol, new_path.display()));
+        } else if Path::new(&resolve_home_dir(path)).is_relative() {
+            let new_path = safe_join_path(agent_data_dir, path)
+                .ok_or_else(|| anyhow!("Invalid document path: '{path}'"))?;
+            document_paths.push(new_path.display().to_string())
+        } else {
+            document_paths.push(path.to_string())
+        }
+    }
+    Ok(document_paths)
+}
+
+/// Resolves the agent's exclusion globs against its data dir so they match the absolute
+/// paths of the documents they should leave out.
+fn resolve_exclude_paths(agent_data_dir: &Path, exclude: &[String]) -> Result<Vec<String>> {
+    exclude
+        .iter()
+        .map(|pattern| {
+            let resolved_pattern = resolve_home_dir(pattern);
+            if Path::new(&resolved_pattern).is_relative() {
+                safe_join_path(agent_data_dir, &resolved_pattern)
+                    .map(|v| v.display().to_string())
+                    .ok_or_else(|| anyhow!("Invalid exclude pattern: '{pattern}'"))
+            } else {
+                Ok(resolved_pattern)
+            }
+        })
+        .collect()
+}
+
+/// Runs the agent's migrate script (if any) when its declared version differs from the
+/// version its data dir was last used with, then records the new version.
+fn migrate_agent_data(name: &str, version: &str) -> Result<()> {
+    if version.is_empty() {
+        return Ok(());
+    }
+    let version_file = Config::agent_version_file(name);
+    let previous_version = match read_to_string(&version_file) {
+        Ok(contents) => Some(contents.trim().to_string()),
+        Err(_) => None,
+    };
+    if previous_version.as_deref() == Some(version) {
+        return Ok(());
+    }
+
+    if let Some(previous_version) = &previous_version {
+        let agent_data_dir = Config::agent_data_dir(name);
+        if let Some((script, cmd)) = MIGRATE_SCRIPTS
+            .iter()
+            .map(|(script, cmd)| (agent_data_dir.join(script), *cmd))
+            .find(|(script, _)| script.exists())
+        {
+            eprintln!("Migrating agent '{name}' from version {previous_version} to {version}...");
+            let envs = HashMap::from([
+                ("LLM_AGENT_NAME".into(), name.to_string()),
+                (
+                    "LLM_AGENT_ROOT_DIR".into(),
+                    agent_data_dir.display().to_string(),
+                ),
+                (
+                    "LLM_AGENT_PREVIOUS_VERSION".into(),
+                    previous_version.clone(),
+                ),
+                ("LLM_AGENT_VERSION".into(), version.to_string()),
+            ]);
+            let status = run_command(cmd, &[&script], Some(envs))
+                .with_context(|| format!("Failed to run '{}'", script.display()))?;
+            if status != 0 {
+                bail!(
+                    "Migration script '{}' exited with status {status}; agent '{name}' is still at version {previous_version}",
+                    script.display()
+                );
+            }
+        }
+    }
+
+    ensure_parent_exists(&version_file)?;
+    std::fs::write(&version_file, version).with_context(|| {
+        format!(
+            "Failed to write agent version to '{}'",
+            version_file.display()
+        )
+    })
+}
+
 pub fn list_agents() -> Vec<String> {
     let agents_data_dir = Config::agents_data_dir();
     if !agents_data_dir.exists() {
@@ -0,0 +1,340 @@
+use super::agent::{AgentConfig, DEFAULT_AGENT_NAME};
+use super::*;
+
+use crate::utils::{base64_decode, base64_encode, hex_encode};
+
+use anyhow::{Context, Result, bail};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use inquire::Confirm;
+use ring::{
+    rand::SystemRandom,
+    signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, read, read_dir, read_to_string, write},
+    io::Read,
+    path::Path,
+};
+
+const MANIFEST_FILE_NAME: &str = "manifest.yaml";
+const SIGNATURE_FILE_NAME: &str = "manifest.sig";
+const FILES_DIR_NAME: &str = "files";
+const SIGNING_KEY_FILE_NAME: &str = "agent-signing.key";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AgentManifest {
+    name: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    documents: Vec<String>,
+    /// The SHA-256 checksum of every packaged file, keyed by its path relative to the agent dir
+    files: IndexMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AgentSignature {
+    public_key: String,
+    signature: String,
+}
+
+/// Packages an agent's definition into a signed `<name>-<version>.tar.gz` archive in the
+/// current directory.
+pub fn package_agent(name: &str) -> Result<PathBuf> {
+    let agent_dir = Config::agent_data_dir(name);
+    let config_path = Config::agent_config_file(name);
+    if !config_path.exists() {
+        bail!("Agent config file not found at '{}'", config_path.display())
+    }
+    let agent_config = AgentConfig::load(&config_path)?;
+
+    let mut files = IndexMap::new();
+    files.insert("config.yaml".to_string(), read(&config_path)?);
+    let runtime_paths = runtime_paths(name);
+    collect_files(&agent_dir, &agent_dir, &runtime_paths, &mut files)?;
+
+    let manifest = AgentManifest {
+        name: name.to_string(),
+        version: agent_config.version.clone(),
+        description: agent_config.description.clone(),
+        documents: agent_config.documents.clone(),
+        files: files
+            .iter()
+            .map(|(path, contents)| (path.clone(), checksum(contents)))
+            .collect(),
+    };
+    let manifest = serde_yaml::to_string(&manifest)?;
+    let key_pair = load_signing_key()?;
+    let signature = AgentSignature {
+        public_key: base64_encode(key_pair.public_key().as_ref()),
+        signature: base64_encode(key_pair.sign(manifest.as_bytes()).as_ref()),
+    };
+
+    let file_name = if agent_config.version.is_empty() {
+        format!("{name}.tar.gz")
+    } else {
+        format!("{name}-{}.tar.gz", agent_config.version)
+    };
+    let package_path = env::current_dir()?.join(file_name);
+    let package_file = File::create(&package_path)
+        .with_context(|| format!("Failed to create '{}'", package_path.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(package_file, Compression::default()));
+    append_file(&mut builder, MANIFEST_FILE_NAME, manifest.as_bytes())?;
+    append_file(
+        &mut builder,
+        SIGNATURE_FILE_NAME,
+        serde_yaml::to_string(&signature)?.as_bytes(),
+    )?;
+    for (path, contents) in &files {
+        append_file(&mut builder, &format!("{FILES_DIR_NAME}/{path}"), contents)?;
+    }
+    builder.into_inner()?.finish()?;
+
+    println!(
+        "✓ Packaged agent '{name}' to '{}'\nSigned with key: {}",
+        package_path.display(),
+        signature.public_key
+    );
+    Ok(package_path)
+}
+
+/// Installs an agent from a package created by `--package-agent`, after verifying its
+/// checksums and signature.
+pub fn install_agent(config: &Config, package_path: &Path) -> Result<()> {
+    let file_name = package_path.to_string_lossy();
+    if !file_name.ends_with(".tar.gz") && !file_name.ends_with(".tgz") {
+        bail!("Agent packages must be .tar.gz archives");
+    }
+    let package_file = File::open(package_path)
+        .with_context(|| format!("Failed to open '{}'", package_path.display()))?;
+    let mut entries: IndexMap<String, Vec<u8>> = IndexMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(package_file));
+    for entry in archive
+        .entries()
+        .with_context(|| format!("Invalid agent package '{}'", package_path.display()))?
+    {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().replace('\\', "/");
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+        entries.insert(path, contents);
+    }
+
+    let manifest_contents = entries
+        .shift_remove(MANIFEST_FILE_NAME)
+        .with_context(|| format!("Agent package is missing its {MANIFEST_FILE_NAME}"))?;
+    let signature = entries
+        .shift_remove(SIGNATURE_FILE_NAME)
+        .with_context(|| format!("Agent package is missing its {SIGNATURE_FILE_NAME}"))?;
+    let signature: AgentSignature = serde_yaml::from_slice(&signature)
+        .with_context(|| format!("Invalid {SIGNATURE_FILE_NAME}"))?;
+    verify_signature(&signature, &manifest_contents)?;
+    let manifest: AgentManifest = serde_yaml::from_slice(&manifest_contents)
+        .with_context(|| format!("Invalid {MANIFEST_FILE_NAME}"))?;
+
+    let mut files = IndexMap::new();
+    for (path, contents) in entries {
+        let Some(relative_path) = path.strip_prefix(&format!("{FILES_DIR_NAME}/")) else {
+            bail!("Unexpected file '{path}' in agent package");
+        };
+        match manifest.files.get(relative_path) {
+            Some(expected) if *expected == checksum(&contents) => {}
+            Some(_) => bail!("Checksum mismatch for '{relative_path}' in agent package"),
+            None => bail!("File '{relative_path}' is not listed in the agent package manifest"),
+        }
+        files.insert(relative_path.to_string(), contents);
+    }
+    if let Some(missing) = manifest.files.keys().find(|v| !files.contains_key(*v)) {
+        bail!("File '{missing}' is missing from the agent package");
+    }
+
+    let name = &manifest.name;
+    let agent_dir = safe_join_path(Config::agents_data_dir(), name)
+        .filter(|_| !name.is_empty() && !name.contains(['/', '\\']))
+        .ok_or_else(|| anyhow!("Invalid agent name '{name}' in agent package"))?;
+
+    if !config.trusted_agent_keys.contains(&signature.public_key) {
+        let message = format!(
+            "Agent '{name}' is signed by an untrusted key ({}).",
+            signature.public_key
+        );
+        if !*IS_STDOUT_TERMINAL {
+            bail!("{message} Add it to 'trusted_agent_keys' to install the agent.");
+        }
+        if !Confirm::new(&format!("{message} Install it anyway?"))
+            .with_default(false)
+            .prompt()?
+        {
+            bail!("Aborted");
+        }
+    }
+
+    let existing_config = agent_dir.join("config.yaml");
+    if existing_config.exists() && *IS_STDOUT_TERMINAL {
+        let installed_version = AgentConfig::load(&existing_config)
+            .map(|v| v.version)
+            .unwrap_or_default();
+        let ans = Confirm::new(&format!(
+            "Agent '{name}' {installed_version} is already installed. Replace it with {}?",
+            manifest.version
+        ))
+        .with_default(true)
+        .prompt()?;
+        if !ans {
+            bail!("Aborted");
+        }
+    }
+
+    for (relative_path, contents) in &files {
+        let path = safe_join_path(&agent_dir, relative_path)
+            .ok_or_else(|| anyhow!("Invalid path '{relative_path}' in agent package"))?;
+        ensure_parent_exists(&path)?;
+        write(&path, contents).with_context(|| format!("Failed to write '{}'", path.display()))?;
+        #[cfg(unix)]
+        if relative_path.starts_with("tools.") {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        }
+    }
+
+    println!(
+        "✓ Installed agent '{name}' {} to '{}'",
+        manifest.version,
+        agent_dir.display()
+    );
+    if !manifest.documents.is_empty() {
+        println!(
+            "The agent loads the following documents:\n{}",
+            manifest
+                .documents
+                .iter()
+                .map(|v| format!("  - {v}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+    Ok(())
+}
+
+/// The files and directories in an agent's data dir that hold the user's runtime data rather
+/// than the agent's definition, so they're left out of packages.
+fn runtime_paths(name: &str) -> Vec<PathBuf> {
+    let mut paths = vec![
+        Config::agent_sessions_dir(name),
+        Config::agent_bin_dir(name),
+        Config::agent_memory_file(name),
+        Config::agent_usage_file(name),
+        Config::agent_history_file(name),
+        Config::agent_version_file(name),
+        Config::agent_rag_file(name, DEFAULT_AGENT_NAME),
+        Config::agent_data_dir(name).join(ENV_FILE_NAME),
+    ];
+    if let Some(todos_dir) = Config::agent_todo_file(name, None).parent() {
+        paths.push(todos_dir.to_path_buf());
+    }
+    paths
+}
+
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    runtime_paths: &[PathBuf],
+    files: &mut IndexMap<String, Vec<u8>>,
+) -> Result<()> {
+    let mut entries = read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|v| v.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let relative_path = path
+            .strip_prefix(root)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if runtime_paths.contains(&path)
+            || entry.file_name() == "__pycache__"
+            || relative_path.ends_with(".tar.gz")
+        {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(root, &path, runtime_paths, files)?;
+        } else if !files.contains_key(&relative_path) {
+            files.insert(relative_path, read(&path)?);
+        }
+    }
+    Ok(())
+}
+
+fn append_file<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    contents: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, contents)?;
+    Ok(())
+}
+
+fn checksum(contents: &[u8]) -> String {
+    hex_encode(&Sha256::digest(contents))
+}
+
+fn load_signing_key() -> Result<Ed25519KeyPair> {
+    let path = Config::local_path(SIGNING_KEY_FILE_NAME);
+    let pkcs8 = if path.exists() {
+        let contents = read_to_string(&path)
+            .with_context(|| format!("Failed to read signing key at '{}'", path.display()))?;
+        base64_decode(contents.trim())
+            .with_context(|| format!("Invalid signing key at '{}'", path.display()))?
+    } else {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate a signing key"))?;
+        ensure_parent_exists(&path)?;
+        write(&path, base64_encode(pkcs8.as_ref()))
+            .with_context(|| format!("Failed to write signing key to '{}'", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        println!("Generated a new signing key at '{}'", path.display());
+        pkcs8.as_ref().to_vec()
+    };
+    Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map_err(|_| anyhow!("Invalid signing key at '{}'", path.display()))
+}
+
+fn verify_signature(signature: &AgentSignature, manifest: &[u8]) -> Result<()> {
+    let public_key = base64_decode(&signature.public_key).context("Invalid public key")?;
+    let signature_bytes = base64_decode(&signature.signature).context("Invalid signature")?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(manifest, &signature_bytes)
+        .map_err(|_| anyhow!("The agent package signature is invalid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_signature() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let manifest = b"name: demo\nfiles: {}\n";
+        let signature = AgentSignature {
+            public_key: base64_encode(key_pair.public_key().as_ref()),
+            signature: base64_encode(key_pair.sign(manifest).as_ref()),
+        };
+        assert!(verify_signature(&signature, manifest).is_ok());
+        assert!(verify_signature(&signature, b"name: evil\nfiles: {}\n").is_err());
+    }
+}
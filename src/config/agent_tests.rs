@@ -0,0 +1,446 @@
+use super::*;
+
+use crate::client::ChatCompletionsOutput;
+use crate::function::{ToolCall, ToolResult, eval_tool_calls};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::fs::read_to_string;
+
+pub const AGENT_TESTS_FILE_NAME: &str = "tests.yaml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AgentTests {
+    /// Model to run the tests against; defaults to the agent's model
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub tests: Vec<AgentTestCase>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AgentTestCase {
+    pub name: String,
+    pub prompt: String,
+    /// Scripted model responses that are replayed instead of calling the model
+    #[serde(default)]
+    pub mock: Option<Vec<MockResponse>>,
+    #[serde(default)]
+    pub expect: AgentTestExpectations,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockResponse {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub tool_calls: Vec<MockToolCall>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockToolCall {
+    pub name: String,
+    #[serde(default = "default_arguments")]
+    pub arguments: Value,
+    /// The result the tool returns, since tools aren't executed in mock mode
+    #[serde(default)]
+    pub output: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AgentTestExpectations {
+    /// Tools that must be called, in this order (other calls may happen in between)
+    #[serde(default)]
+    pub tool_calls: Vec<String>,
+    /// Tools that must not be called
+    #[serde(default)]
+    pub no_tool_calls: Vec<String>,
+    /// Regex the final output must match
+    #[serde(default)]
+    pub output: Option<String>,
+    /// JSON schema the final output must satisfy
+    #[serde(default)]
+    pub output_schema: Option<Value>,
+    /// JSON schemas the results of the named tools must satisfy
+    #[serde(default)]
+    pub tool_results: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Default)]
+struct AgentTestRun {
+    output: String,
+    tool_calls: Vec<String>,
+    tool_results: Vec<ToolResult>,
+}
+
+impl AgentTests {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = read_to_string(path)
+            .with_context(|| format!("Failed to read agent tests at '{}'", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to load agent tests at '{}'", path.display()))
+    }
+}
+
+/// Runs the agent's `tests.yaml` and prints a pass/fail report. Fails if any
+/// test case fails. `model_id` (from `--model`) takes precedence over the model in the tests.
+pub async fn run_agent_tests(
+    config: &GlobalConfig,
+    agent_name: &str,
+    model_id: Option<&str>,
+    abort_signal: AbortSignal,
+) -> Result<()> {
+    let tests_file = Config::agent_tests_file(agent_name);
+    if !tests_file.exists() {
+        bail!(
+            "No tests found for agent '{agent_name}'. Define them in '{}'",
+            tests_file.display()
+        );
+    }
+    let tests = AgentTests::load(&tests_file)?;
+    if tests.tests.is_empty() {
+        bail!("No test cases defined in '{}'", tests_file.display());
+    }
+
+    Config::use_agent(config, agent_name, None, abort_signal.clone()).await?;
+    if let Some(model_id) = model_id.or(tests.model.as_deref()) {
+        config
+            .write()
+            .set_model(model_id)
+            .map_err(|err| ExitCode::Config.tag(err))?;
+    }
+
+    let total = tests.tests.len();
+    let mut failed = 0;
+    for case in &tests.tests {
+        let failures = match run_test_case(config, case, abort_signal.clone()).await {
+            Ok(run) => check_expectations(&case.expect, &run),
+            Err(err) => vec![format!("Error: {err}")],
+        };
+        if failures.is_empty() {
+            println!(
+                "{} {}",
+                color_text("✓", nu_ansi_term::Color::Green),
+                case.name
+            );
+        } else {
+            failed += 1;
+            println!(
+                "{} {}",
+                color_text("✗", nu_ansi_term::Color::Red),
+                case.name
+            );
+            for failure in failures {
+                println!("    {}", dimmed_text(&failure));
+            }
+        }
+    }
+
+    println!("\n{} passed, {failed} failed", total - failed);
+    if failed > 0 {
+        bail!("{failed} of {total} agent tests failed");
+    }
+    Ok(())
+}
+
+async fn run_test_case(
+    config: &GlobalConfig,
+    case: &AgentTestCase,
+    abort_signal: AbortSignal,
+) -> Result<AgentTestRun> {
+    {
+        let mut config = config.write();
+        config.reset_tool_call_tracker();
+        config.reset_turn_tool_counters();
+    }
+
+    let mut run = AgentTestRun::default();
+    let mut input = Input::from_str(config, &case.prompt, None);
+    input.use_embeddings(abort_signal.clone()).await?;
+    let mut mock_responses = case.mock.clone().map(|v| v.into_iter());
+    let client = input.create_client()?;
+
+    loop {
+        let (text, tool_results) = match mock_responses.as_mut() {
+            Some(responses) => match responses.next() {
+                Some(response) => mock_tool_results(response),
+                None => break,
+            },
+            None => {
+                let ChatCompletionsOutput {
+                    text, tool_calls, ..
+                } = client.chat_completions(input.clone()).await?;
+                run.tool_calls
+                    .extend(tool_calls.iter().map(|call| call.name.clone()));
+                (
+                    text,
+                    eval_tool_calls(config, tool_calls, abort_signal.clone()).await?,
+                )
+            }
+        };
+        run.output = text.clone();
+        if mock_responses.is_some() {
+            run.tool_calls
+                .extend(tool_results.iter().map(|res| res.call.name.clone()));
+        }
+
+        if tool_results.is_empty() {
+            if mock_responses.is_none() {
+                break;
+            }
+            continue;
+        }
+        if let Some(tracker) = config.write().tool_call_tracker.as_mut() {
+            tool_results
+                .iter()
+                .for_each(|res| tracker.record_call(res.call.clone()));
+        }
+        run.tool_results.extend(tool_results.iter().cloned());
+        input = input.merge_tool_results(text, tool_results);
+    }
+
+    Ok(run)
+}
+
+/// Replays a scripted response. Its tool calls are answered with their mocked `output` rather
+/// than executed, so mock mode never has side effects.
+fn mock_tool_results(response: MockResponse) -> (String, Vec<ToolResult>) {
+    let tool_results = response
+        .tool_calls
+        .into_iter()
+        .enumerate()
+        .map(|(i, call)| {
+            let output = call.output.unwrap_or_else(|| json!({"mocked": true}));
+            let call = ToolCall::new(call.name, call.arguments, Some(format!("mock_{i}")));
+            ToolResult::new(call, output)
+        })
+        .collect();
+    (response.text, tool_results)
+}
+
+fn check_expectations(expect: &AgentTestExpectations, run: &AgentTestRun) -> Vec<String> {
+    let mut failures = vec![];
+    let called: Vec<&str> = run.tool_calls.iter().map(String::as_str).collect();
+
+    let mut remaining = called.iter();
+    for name in &expect.tool_calls {
+        if !remaining.any(|v| v == name) {
+            failures.push(format!(
+                "Expected a call to '{name}' (calls made: {})",
+                format_calls(&called)
+            ));
+            break;
+        }
+    }
+    for name in &expect.no_tool_calls {
+        if called.contains(&name.as_str()) {
+            failures.push(format!("Expected no calls to '{name}'"));
+        }
+    }
+
+    if let Some(pattern) = &expect.output {
+        match Regex::new(pattern) {
+            Ok(re) if re.is_match(&run.output).unwrap_or(false) => {}
+            Ok(_) => failures.push(format!(
+                "Output did not match /{pattern}/: {}",
+                run.output.trim()
+            )),
+            Err(err) => failures.push(format!("Invalid output regex /{pattern}/: {err}")),
+        }
+    }
+
+    if let Some(schema) = &expect.output_schema {
+        match serde_json::from_str::<Value>(strip_code_fence(&run.output)) {
+            Ok(value) => failures.extend(
+                validate_json_schema(schema, &value, "$")
+                    .into_iter()
+                    .map(|err| format!("Output schema: {err}")),
+            ),
+            Err(err) => failures.push(format!("Output is not valid JSON: {err}")),
+        }
+    }
+
+    for (name, schema) in &expect.tool_results {
+        let results: Vec<&ToolResult> = run
+            .tool_results
+            .iter()
+            .filter(|res| &res.call.name == name)
+            .collect();
+        if results.is_empty() {
+            failures.push(format!("No results from '{name}' to validate"));
+        }
+        for result in results {
+            failures.extend(
+                validate_json_schema(schema, &result.output, "$")
+                    .into_iter()
+                    .map(|err| format!("Result of '{name}': {err}")),
+            );
+        }
+    }
+
+    failures
+}
+
+fn format_calls(called: &[&str]) -> String {
+    if called.is_empty() {
+        "none".to_string()
+    } else {
+        called.join(", ")
+    }
+}
+
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    match text.strip_prefix("```") {
+        Some(rest) => rest
+            .split_once('\n')
+            .map(|(_, body)| body)
+            .unwrap_or(rest)
+            .trim_end()
+            .trim_end_matches("```")
+            .trim(),
+        None => text,
+    }
+}
+
+/// Validates a value against the commonly used subset of JSON schema:
+/// `type`, `enum`, `required`, `properties`, and `items`.
+fn validate_json_schema(schema: &Value, value: &Value, path: &str) -> Vec<String> {
+    let mut errors = vec![];
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(v) => vec![v.as_str()],
+            Value::Array(v) => v.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|ty| matches_type(ty, value)) {
+            errors.push(format!("{path} is not of type '{}'", types.join("|")));
+            return errors;
+        }
+    }
+
+    if let Some(Value::Array(values)) = schema.get("enum")
+        && !values.contains(value)
+    {
+        errors.push(format!(
+            "{path} is not one of {}",
+            Value::Array(values.clone())
+        ));
+    }
+
+    if let Value::Object(map) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !map.contains_key(key) {
+                    errors.push(format!("{path} is missing required property '{key}'"));
+                }
+            }
+        }
+        if let Some(Value::Object(properties)) = schema.get("properties") {
+            for (key, property_schema) in properties {
+                if let Some(v) = map.get(key) {
+                    errors.extend(validate_json_schema(
+                        property_schema,
+                        v,
+                        &format!("{path}.{key}"),
+                    ));
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            errors.extend(validate_json_schema(
+                item_schema,
+                item,
+                &format!("{path}[{i}]"),
+            ));
+        }
+    }
+
+    errors
+}
+
+fn matches_type(ty: &str, value: &Value) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn default_arguments() -> Value {
+    json!({})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_result(name: &str, output: Value) -> ToolResult {
+        ToolResult::new(ToolCall::new(name.into(), json!({}), None), output)
+    }
+
+    #[test]
+    fn test_validate_json_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": {
+                "name": {"type": "string"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}}
+            }
+        });
+        assert!(
+            validate_json_schema(&schema, &json!({"name": "x", "tags": ["a"]}), "$").is_empty()
+        );
+        let errors = validate_json_schema(&schema, &json!({"name": 1, "tags": ["c"]}), "$");
+        assert_eq!(
+            errors,
+            vec![
+                "$.name is not of type 'string'".to_string(),
+                "$.tags[0] is not one of [\"a\",\"b\"]".to_string(),
+            ]
+        );
+        assert_eq!(validate_json_schema(&schema, &json!({}), "$").len(), 2);
+    }
+
+    #[test]
+    fn test_check_expectations() {
+        let run = AgentTestRun {
+            output: "```json\n{\"city\": \"Paris\"}\n```".into(),
+            tool_calls: vec!["search".into(), "get_weather".into()],
+            tool_results: vec![
+                tool_result("search", json!({"hits": 3})),
+                tool_result("get_weather", json!({"temp": 20})),
+            ],
+        };
+        let expect = AgentTestExpectations {
+            tool_calls: vec!["search".into(), "get_weather".into()],
+            no_tool_calls: vec!["fs_rm".into()],
+            output: Some("Paris".into()),
+            output_schema: Some(json!({"type": "object", "required": ["city"]})),
+            tool_results: IndexMap::from([(
+                "get_weather".to_string(),
+                json!({"type": "object", "required": ["temp"]}),
+            )]),
+        };
+        assert!(check_expectations(&expect, &run).is_empty());
+
+        let expect = AgentTestExpectations {
+            tool_calls: vec!["get_weather".into(), "search".into()],
+            no_tool_calls: vec!["search".into()],
+            output: Some("London".into()),
+            ..Default::default()
+        };
+        assert_eq!(check_expectations(&expect, &run).len(), 3);
+    }
+}
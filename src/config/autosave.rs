@@ -0,0 +1,251 @@
+use super::*;
+
+use anyhow::{Context, Result};
+use std::{
+    fs::{File, OpenOptions, create_dir_all, remove_file, rename, write},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    time::Instant,
+};
+
+const AUTOSAVE_DIR_NAME: &str = ".autosave";
+
+/// An autosaved session, with the name it was autosaved under, since sessions don't hold their
+/// own names.
+#[derive(Debug, Serialize, Deserialize)]
+struct AutosavedSession<T> {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    autoname: Option<String>,
+    saved_at: String,
+    session: T,
+}
+
+/// Writes the active REPL session to `<sessions dir>/.autosave/<pid>.yaml` every so often, so it
+/// can be recovered if Loki crashes or its terminal is killed before the session is saved.
+///
+/// A `<pid>.lock` file next to it stays locked for as long as Loki runs, which is how a later
+/// Loki tells an autosave that was left behind from one that's still in use.
+#[derive(Debug, Clone)]
+pub struct SessionAutosave {
+    path: PathBuf,
+    _lock: Arc<File>,
+    pending_messages: usize,
+    saved_at: Instant,
+}
+
+impl SessionAutosave {
+    pub fn new(sessions_dir: &Path) -> Result<Self> {
+        let dir = sessions_dir.join(AUTOSAVE_DIR_NAME);
+        create_dir_all(&dir)
+            .with_context(|| format!("Failed to create directory '{}'", dir.display()))?;
+        let pid = process::id();
+        let lock_path = dir.join(format!("{pid}.lock"));
+        let lock = File::create(&lock_path)
+            .with_context(|| format!("Failed to create '{}'", lock_path.display()))?;
+        lock.try_lock()
+            .with_context(|| format!("Failed to lock '{}'", lock_path.display()))?;
+        Ok(Self {
+            path: dir.join(format!("{pid}.yaml")),
+            _lock: Arc::new(lock),
+            pending_messages: 0,
+            saved_at: Instant::now(),
+        })
+    }
+
+    /// Counts a message added to the session, and returns whether it's time to autosave: once
+    /// `every_messages` messages have been added, or `every_secs` seconds have passed, since the
+    /// last autosave. Either is off when it's 0.
+    pub fn is_due(&mut self, every_messages: usize, every_secs: u64) -> bool {
+        self.pending_messages += 1;
+        (every_messages > 0 && self.pending_messages >= every_messages)
+            || (every_secs > 0 && self.saved_at.elapsed().as_secs() >= every_secs)
+    }
+
+    /// Writes the session to a temporary file first, so a crash mid-write can't leave a
+    /// half-written autosave behind.
+    pub fn save(&mut self, session: &Session, vault: Option<&Vault>) -> Result<()> {
+        let autosaved = AutosavedSession {
+            name: session.name().to_string(),
+            autoname: session.autoname().map(|v| v.to_string()),
+            saved_at: now(),
+            session,
+        };
+        let mut content =
+            serde_yaml::to_string(&autosaved).context("Failed to serde the session")?;
+        if let Some(vault) = vault {
+            content = format!("{}\n", vault.encrypt_text(&content)?);
+        }
+        let temp_path = self.path.with_extension("yaml.tmp");
+        write(&temp_path, content)
+            .and_then(|_| rename(&temp_path, &self.path))
+            .with_context(|| format!("Failed to write '{}'", self.path.display()))?;
+        self.pending_messages = 0;
+        self.saved_at = Instant::now();
+        Ok(())
+    }
+
+    /// Removes the autosave once its session has been exited, saved or not.
+    pub fn remove(&self) {
+        let _ = remove_file(&self.path);
+    }
+
+    /// The autosaves in a sessions directory that were left behind by a Loki that's no longer
+    /// running. Lock files that were left without an autosave are cleaned up along the way.
+    pub fn left_behind(sessions_dir: &Path) -> Vec<PathBuf> {
+        let dir = sessions_dir.join(AUTOSAVE_DIR_NAME);
+        let mut paths = vec![];
+        for pid in list_file_names(&dir, ".lock") {
+            let lock_path = dir.join(format!("{pid}.lock"));
+            let is_locked = match OpenOptions::new().write(true).open(&lock_path) {
+                Ok(lock) => lock.try_lock().is_err(),
+                Err(_) => continue,
+            };
+            if is_locked {
+                continue;
+            }
+            let path = dir.join(format!("{pid}.yaml"));
+            if path.exists() {
+                paths.push(path);
+            } else {
+                let _ = remove_file(&lock_path);
+            }
+        }
+        paths
+    }
+
+    /// Removes an autosave that was left behind, along with its lock file.
+    pub fn discard(path: &Path) {
+        let _ = remove_file(path);
+        let _ = remove_file(path.with_extension("lock"));
+    }
+}
+
+impl Config {
+    /// Autosaves the active session, when it's time to. Only sessions in the REPL are
+    /// autosaved, as that's where they're recovered, and never ones set not to be saved.
+    pub(super) fn autosave_session(&mut self) -> Result<()> {
+        let (every_messages, every_secs) =
+            (self.session_autosave_messages, self.session_autosave_secs);
+        if !self.working_mode.is_repl()
+            || self.current_depth > 0
+            || (every_messages == 0 && every_secs == 0)
+        {
+            return Ok(());
+        }
+        let Some(session) = &self.session else {
+            return Ok(());
+        };
+        if session.save_session() == Some(false) {
+            return Ok(());
+        }
+        let autosave = match self.session_autosave.take() {
+            Some(autosave) => autosave,
+            None => SessionAutosave::new(&self.sessions_dir())?,
+        };
+        let autosave = self.session_autosave.insert(autosave);
+        if autosave.is_due(every_messages, every_secs) {
+            let vault = self.encrypt_sessions.then_some(self.vault.as_ref());
+            autosave
+                .save(session, vault)
+                .context("Failed to autosave the session")?;
+        }
+        Ok(())
+    }
+
+    /// Offers to recover each session in a sessions directory that was autosaved by a Loki that
+    /// crashed or was killed before it was saved. A recovered session is saved under its own
+    /// name, or an autoname like `_/20250101T120000` if it was a temporary session.
+    pub fn recover_sessions(&self, sessions_dir: &Path) {
+        for path in SessionAutosave::left_behind(sessions_dir) {
+            match self.recover_session(sessions_dir, &path) {
+                Ok(Some(name)) => {
+                    SessionAutosave::discard(&path);
+                    println!(
+                        "✓ Recovered the session '{name}'. Use `.session {name}` to continue it."
+                    );
+                }
+                Ok(None) => SessionAutosave::discard(&path),
+                Err(err) => eprintln!(
+                    "{}",
+                    warning_text(&format!(
+                        "Failed to recover the session autosaved at '{}': {err:#}",
+                        path.display()
+                    ))
+                ),
+            }
+        }
+    }
+
+    fn recover_session(&self, sessions_dir: &Path, path: &Path) -> Result<Option<String>> {
+        let content = self.read_session_file(path)?;
+        let autosaved: AutosavedSession<serde_yaml::Value> =
+            serde_yaml::from_str(&content).context("Invalid autosave")?;
+        let messages = autosaved
+            .session
+            .get("messages")
+            .and_then(|v| v.as_sequence())
+            .map_or(0, |v| v.len());
+        let recover = Confirm::new(&format!(
+            "Recover the unsaved session '{}' ({messages} messages, autosaved at {})?",
+            autosaved.name, autosaved.saved_at
+        ))
+        .with_default(true)
+        .prompt()?;
+        if !recover {
+            return Ok(None);
+        }
+        let name = match autosaved.name.as_str() {
+            TEMP_SESSION_NAME => {
+                let timestamp = chrono::Local::now().format("%Y%m%dT%H%M%S");
+                match &autosaved.autoname {
+                    Some(autoname) => format!("_/{timestamp}-{autoname}"),
+                    None => format!("_/{timestamp}"),
+                }
+            }
+            name => name.to_string(),
+        };
+        let session_path = sessions_dir.join(format!("{name}.yaml"));
+        let mut content = serde_yaml::to_string(&autosaved.session)
+            .with_context(|| format!("Failed to serde session '{name}'"))?;
+        if self.encrypt_sessions {
+            content = format!("{}\n", self.vault.encrypt_text(&content)?);
+        }
+        ensure_parent_exists(&session_path)?;
+        write(&session_path, content).with_context(|| {
+            format!(
+                "Failed to write session '{name}' to '{}'",
+                session_path.display()
+            )
+        })?;
+        Ok(Some(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_autosave_is_due() {
+        let dir = env::temp_dir().join(format!("loki-test-autosave-{}", process::id()));
+        let mut autosave = SessionAutosave::new(&dir).unwrap();
+        assert!(!autosave.is_due(2, 0));
+        assert!(autosave.is_due(2, 0));
+        autosave.pending_messages = 0;
+        assert!(!autosave.is_due(0, 60));
+        autosave.saved_at = Instant::now() - Duration::from_secs(61);
+        assert!(autosave.is_due(0, 60));
+
+        autosave.save(&Session::default(), None).unwrap();
+        assert!(autosave.path.exists());
+        assert!(SessionAutosave::left_behind(&dir).is_empty());
+        drop(autosave);
+        let left_behind = SessionAutosave::left_behind(&dir);
+        assert_eq!(left_behind.len(), 1);
+        SessionAutosave::discard(&left_behind[0]);
+        assert!(SessionAutosave::left_behind(&dir).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
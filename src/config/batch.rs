@@ -0,0 +1,279 @@
+use super::*;
+
+use crate::client::ChatCompletionsOutput;
+use crate::function::eval_tool_calls;
+use crate::utils::wait_abort_signal;
+
+use anyhow::{Context, Result, anyhow, bail};
+use futures_util::{StreamExt, future, stream};
+use is_terminal::IsTerminal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs::{File, read_to_string};
+use std::io::{Write, stderr, stdout};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+pub const DEFAULT_BATCH_RETRIES: usize = 2;
+
+const PROGRESS_BAR_WIDTH: usize = 20;
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A line of a batch file: a prompt, with the role and model to run it with when they differ
+/// from the ones on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct BatchItem {
+    /// Identifies the prompt in the results; defaults to its line number
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub prompt: String,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug)]
+struct BatchResult {
+    id: Value,
+    output: Result<(String, String)>,
+    attempts: usize,
+    elapsed: Duration,
+}
+
+impl BatchResult {
+    fn to_json(&self) -> Value {
+        let mut data = json!({ "id": self.id });
+        match &self.output {
+            Ok((output, model)) => {
+                data["output"] = json!(output);
+                data["model"] = json!(model);
+            }
+            Err(err) => data["error"] = json!(format!("{err:#}")),
+        }
+        data["attempts"] = json!(self.attempts);
+        data["elapsed_ms"] = json!(self.elapsed.as_millis() as u64);
+        data
+    }
+}
+
+/// Reads the prompts of a batch file, one JSON object per line, skipping blank lines.
+fn parse_batch_file(contents: &str) -> Result<Vec<BatchItem>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let mut item: BatchItem = serde_json::from_str(line)
+                .with_context(|| format!("Invalid batch item on line {}", i + 1))?;
+            item.id.get_or_insert_with(|| json!(i + 1));
+            Ok(item)
+        })
+        .collect()
+}
+
+/// Runs every prompt in a batch file, `concurrency` at a time, and writes one JSON result per
+/// line to `output` (or stdout) as each finishes. A prompt that couldn't reach the model before
+/// any of its tools ran is retried `retries` times before its error is written in place of an
+/// output. Fails if any prompt failed, or the batch was aborted with Ctrl-C.
+pub async fn run_batch(
+    config: &GlobalConfig,
+    path: &Path,
+    output: Option<&Path>,
+    concurrency: usize,
+    retries: usize,
+    abort_signal: AbortSignal,
+) -> Result<()> {
+    let contents = read_to_string(path)
+        .with_context(|| format!("Failed to read batch file '{}'", path.display()))?;
+    let items = parse_batch_file(&contents)?;
+    if items.is_empty() {
+        bail!("No prompts found in '{}'", path.display());
+    }
+    let mut writer: Box<dyn Write> = match output {
+        Some(output) => {
+            ensure_parent_exists(output)?;
+            let file = File::create(output)
+                .with_context(|| format!("Failed to create '{}'", output.display()))?;
+            Box::new(file)
+        }
+        None => Box::new(stdout()),
+    };
+
+    let ctrlc_signal = abort_signal.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            ctrlc_signal.set_ctrlc();
+        }
+    });
+
+    let total = items.len();
+    let show_progress = stderr().is_terminal() && !is_quiet();
+    let mut done = 0;
+    let mut failed = 0;
+    let mut results = stream::iter(items)
+        .take_while(|_| future::ready(!abort_signal.aborted()))
+        .map(|item| run_batch_item(config, item, retries, abort_signal.clone()))
+        .buffer_unordered(concurrency.max(1));
+    if show_progress {
+        print_progress(done, failed, total);
+    }
+    while let Some(result) = results.next().await {
+        done += 1;
+        if result.output.is_err() {
+            failed += 1;
+        }
+        if show_progress {
+            eprint!("\r\x1b[K");
+        }
+        writeln!(writer, "{}", result.to_json())?;
+        writer.flush()?;
+        if show_progress {
+            print_progress(done, failed, total);
+        }
+    }
+    if show_progress {
+        eprintln!();
+    }
+
+    if abort_signal.aborted() {
+        bail!("Aborted after {done} of {total} prompts");
+    }
+    if failed > 0 {
+        bail!("{failed} of {total} prompts failed");
+    }
+    Ok(())
+}
+
+async fn run_batch_item(
+    config: &GlobalConfig,
+    item: BatchItem,
+    retries: usize,
+    abort_signal: AbortSignal,
+) -> BatchResult {
+    let id = item.id.clone().unwrap_or_default();
+    let start = Instant::now();
+    // Each prompt gets its own copy of the config, so that their tool-call limits and loop
+    // detection don't add up, and tools never stop to ask the user something, which several
+    // prompts would be doing at once
+    let config: GlobalConfig = {
+        let mut config = config.read().clone();
+        config.batch_flag = true;
+        config.reset_turn_tool_counters();
+        config.reset_tool_call_tracker();
+        Arc::new(RwLock::new(config))
+    };
+    let mut attempts = 0;
+    let mut tools_ran = false;
+    let output = loop {
+        attempts += 1;
+        let ret = tokio::select! {
+            ret = run_prompt(&config, &item, &mut tools_ran, abort_signal.clone()) => ret,
+            _ = wait_abort_signal(&abort_signal) => Err(anyhow!("Aborted.")),
+        };
+        // Sending the prompt again after its tools ran would run them again too
+        let retryable = matches!(&ret, Err(err) if !tools_ran && is_connection_error(err));
+        if !retryable || attempts > retries || abort_signal.aborted() {
+            break ret;
+        }
+        if let Err(err) = &ret {
+            debug!("Retrying batch item {id}: {err}");
+        }
+        tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempts as u32 - 1)).await;
+    };
+    BatchResult {
+        id,
+        output,
+        attempts,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Sends a prompt and runs the tools the model calls until it answers, returning the answer and
+/// the ID of the model that gave it. `tools_ran` is set once any tool has been called.
+async fn run_prompt(
+    config: &GlobalConfig,
+    item: &BatchItem,
+    tools_ran: &mut bool,
+    abort_signal: AbortSignal,
+) -> Result<(String, String)> {
+    let role = match &item.role {
+        Some(name) => Some(config.read().retrieve_role(name)?),
+        None => None,
+    };
+    let mut input = Input::from_str(config, &item.prompt, role);
+    if let Some(model_id) = &item.model {
+        let model = Model::retrieve_model(&config.read(), model_id, ModelType::Chat)?;
+        input.set_model(model);
+    }
+    let client = input.create_client()?;
+    loop {
+        let ChatCompletionsOutput {
+            text, tool_calls, ..
+        } = client.chat_completions(input.clone()).await?;
+        *tools_ran |= !tool_calls.is_empty();
+        let tool_results = eval_tool_calls(config, tool_calls, abort_signal.clone()).await?;
+        if tool_results.is_empty() {
+            return Ok((text, client.model().id()));
+        }
+        input = input.merge_tool_results(text, tool_results);
+    }
+}
+
+fn print_progress(done: usize, failed: usize, total: usize) {
+    let filled = done * PROGRESS_BAR_WIDTH / total;
+    let mut line = format!(
+        "[{}{}] {done}/{total}",
+        "█".repeat(filled),
+        "░".repeat(PROGRESS_BAR_WIDTH - filled)
+    );
+    if failed > 0 {
+        line.push_str(&format!(" ({failed} failed)"));
+    }
+    eprint!("\r\x1b[K{line}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch_file() {
+        let contents = r#"{"prompt": "Hello"}
+
+{"id": "b", "prompt": "Translate to French: cat", "role": "translator", "model": "openai:gpt-4o"}"#;
+        let items = parse_batch_file(contents).unwrap();
+        assert_eq!(
+            items,
+            vec![
+                BatchItem {
+                    id: Some(json!(1)),
+                    prompt: "Hello".into(),
+                    ..Default::default()
+                },
+                BatchItem {
+                    id: Some(json!("b")),
+                    prompt: "Translate to French: cat".into(),
+                    role: Some("translator".into()),
+                    model: Some("openai:gpt-4o".into()),
+                },
+            ]
+        );
+        let err = parse_batch_file("{\"prompt\": \"a\"}\n{\"role\": \"b\"}").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid batch item on line 2");
+    }
+
+    #[test]
+    fn test_batch_result_to_json() {
+        let result = BatchResult {
+            id: json!(3),
+            output: Err(anyhow!("rate limited")),
+            attempts: 3,
+            elapsed: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            result.to_json(),
+            json!({"id": 3, "error": "rate limited", "attempts": 3, "elapsed_ms": 1500})
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use super::*;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+pub const USAGE_FILE_NAME: &str = "usage.yaml";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentBudget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_per_session: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost_per_day: Option<f64>,
+}
+
+impl AgentBudget {
+    pub fn is_empty(&self) -> bool {
+        self.max_tokens_per_session.is_none() && self.max_cost_per_day.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub cost: f64,
+}
+
+impl Usage {
+    /// Estimates the usage of a completion from the token counts and the model's
+    /// pricing (in $ per million tokens). Models without pricing cost nothing.
+    pub fn new(model: &Model, input_tokens: usize, output_tokens: usize) -> Self {
+        let cost = (input_tokens as f64 * model.input_price().unwrap_or_default()
+            + output_tokens as f64 * model.output_price().unwrap_or_default())
+            / 1_000_000.0;
+        Self {
+            input_tokens,
+            output_tokens,
+            cost,
+        }
+    }
+
+    pub fn total_tokens(&self) -> usize {
+        self.input_tokens + self.output_tokens
+    }
+
+    pub fn add(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost += other.cost;
+    }
+}
+
+/// The usage of an agent, persisted per day and per session in the agent's data
+/// directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageLedger {
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub days: IndexMap<String, Usage>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub sessions: IndexMap<String, Usage>,
+}
+
+impl UsageLedger {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = read_to_string(path)
+            .with_context(|| format!("Failed to read usage file at '{}'", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to load usage file at '{}'", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        ensure_parent_exists(path)?;
+        let contents = serde_yaml::to_string(self)?;
+        write(path, contents)
+            .with_context(|| format!("Failed to write usage file at '{}'", path.display()))
+    }
+
+    pub fn today(&self) -> Usage {
+        self.days.get(&today()).copied().unwrap_or_default()
+    }
+
+    pub fn session(&self, name: &str) -> Usage {
+        self.sessions.get(name).copied().unwrap_or_default()
+    }
+
+    /// Clears the usage recorded for a session, e.g. when a temp session is restarted.
+    pub fn reset_session(path: &Path, name: &str) -> Result<()> {
+        let mut ledger = Self::load(path)?;
+        if ledger.sessions.shift_remove(name).is_some() {
+            ledger.save(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, session_name: Option<&str>, usage: &Usage) {
+        self.days.entry(today()).or_default().add(usage);
+        if let Some(name) = session_name {
+            self.sessions
+                .entry(name.to_string())
+                .or_default()
+                .add(usage);
+        }
+    }
+}
+
+fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_usage() {
+        let mut ledger = UsageLedger::default();
+        let usage = Usage {
+            input_tokens: 100,
+            output_tokens: 20,
+            cost: 0.5,
+        };
+        ledger.record(Some("work"), &usage);
+        ledger.record(None, &usage);
+        assert_eq!(ledger.session("work").total_tokens(), 120);
+        assert_eq!(ledger.session("other").total_tokens(), 0);
+        assert_eq!(ledger.today().total_tokens(), 240);
+        assert_eq!(ledger.today().cost, 1.0);
+    }
+}
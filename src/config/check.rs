@@ -0,0 +1,413 @@
+use super::agent::{AgentConfig, AgentVariableType};
+use super::role::{ROLE_METADATA_KEYS, split_metadata};
+use super::*;
+
+use crate::mcp::{McpServer, McpServersConfig};
+use crate::vault::SECRET_RE;
+
+use serde::de::{self, DeserializeOwned, Visitor};
+
+/// What `--check-config` found wrong with one file. Errors keep Loki from working as configured;
+/// warnings, like unknown settings, are ignored by Loki but are likely mistakes.
+#[derive(Debug, Default)]
+struct FileReport {
+    path: PathBuf,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl FileReport {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    /// Reads a YAML file, reporting it if it can't be read or parsed, and warns about the keys
+    /// in it that aren't in `fields`.
+    fn read_yaml(&mut self, fields: &[&str]) -> Option<String> {
+        let content = match read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) => {
+                self.errors.push(format!("Failed to read it: {err}"));
+                return None;
+            }
+        };
+        match serde_yaml::from_str::<Value>(&content) {
+            Ok(value) => {
+                self.check_keys("", &value, fields);
+                Some(content)
+            }
+            Err(err) => {
+                self.errors.push(format!("Invalid YAML: {err}"));
+                None
+            }
+        }
+    }
+
+    fn check_keys(&mut self, prefix: &str, value: &Value, fields: &[&str]) {
+        self.warnings.extend(unknown_keys(prefix, value, fields));
+    }
+
+    fn check_secrets(&mut self, missing_secrets: &[String]) {
+        for secret in missing_secrets {
+            self.errors.push(format!(
+                "The secret '{secret}' isn't in the vault; add it with `loki --add-secret {secret}`"
+            ));
+        }
+    }
+
+    fn check_model(&mut self, config: &Config, key: &str, model_id: &str, model_type: ModelType) {
+        if let Err(err) = Model::retrieve_model(config, model_id, model_type) {
+            self.errors.push(format!(
+                "{key}: {err}; run `loki --list-models` to see the available models"
+            ));
+        }
+    }
+}
+
+/// Loads the config file, the project config, roles, agents, macros, and MCP config, printing the
+/// problems found in each without starting a chat. Fails if any file has errors.
+pub fn check_config() -> Result<()> {
+    let mut reports = vec![];
+    let (config, vault) = check_global_config(&mut reports);
+    let mcp_servers = check_mcp_config(&vault, &mut reports);
+    check_roles(&config, &mut reports);
+    check_agents(&config, &vault, &mcp_servers, &mut reports);
+    check_macros(&mut reports);
+
+    let mut errors = 0;
+    let mut warnings = 0;
+    for report in &reports {
+        errors += report.errors.len();
+        warnings += report.warnings.len();
+        let mark = match (report.errors.is_empty(), report.warnings.is_empty()) {
+            (false, _) => error_text("✗"),
+            (true, false) => warning_text("!"),
+            (true, true) => color_text("✓", nu_ansi_term::Color::Green),
+        };
+        println!("{mark} {}", report.path.display());
+        for error in &report.errors {
+            println!("    {}", error_text(error));
+        }
+        for warning in &report.warnings {
+            println!("    {}", warning_text(warning));
+        }
+    }
+
+    if errors > 0 {
+        bail!("Found {errors} error(s) and {warnings} warning(s)");
+    }
+    if warnings > 0 {
+        println!("\nFound {warnings} warning(s)");
+    } else {
+        println!("\nNo problems found");
+    }
+    Ok(())
+}
+
+/// Checks `config.yaml`, overlaid with the project's, as `Config::init` would load it. Returns the
+/// config, or the default one if it can't be loaded, so the other files can still be checked.
+fn check_global_config(reports: &mut Vec<FileReport>) -> (Config, Vault) {
+    let config_path = Config::config_file();
+    let mut report = FileReport::new(&config_path);
+    let fields = struct_fields::<Config>();
+
+    let mut config = None;
+    if config_path.exists() {
+        if report.read_yaml(fields).is_some() {
+            if let Some(project_config_path) = Config::project_path(CONFIG_FILE_NAME) {
+                let mut project_report = FileReport::new(&project_config_path);
+                project_report.read_yaml(fields);
+                reports.push(project_report);
+            }
+            match Config::load_from_file(&config_path) {
+                Ok(v) => config = Some(v),
+                Err(err) => report.errors.push(format!("{err:#}")),
+            }
+        }
+    } else {
+        match env::var(get_env_name("provider"))
+            .ok()
+            .or_else(|| env::var(get_env_name("platform")).ok())
+        {
+            Some(v) => match Config::load_dynamic(&v) {
+                Ok(v) => config = Some((v, String::new())),
+                Err(err) => report.errors.push(format!("{err:#}")),
+            },
+            None => report
+                .errors
+                .push("Not found; run `loki` to create it".into()),
+        }
+    }
+
+    let (mut config, content) = config.unwrap_or_default();
+    let vault = Vault::init(&config);
+    if !content.is_empty() {
+        let (parsed_content, missing_secrets) = interpolate_secrets(&content, &vault);
+        report.check_secrets(&missing_secrets);
+        if missing_secrets.is_empty() {
+            match Config::load_from_str(&parsed_content) {
+                Ok(v) => config = v,
+                Err(err) => report
+                    .errors
+                    .push(format!("Invalid once secrets are injected: {err:#}")),
+            }
+        }
+    }
+    config.load_envs();
+
+    if config.model_id.is_empty() {
+        if list_models(&config, ModelType::Chat).is_empty() {
+            report
+                .errors
+                .push("model: not set, and no client provides a chat model".into());
+        }
+    } else {
+        let model_id = config.model_id.clone();
+        report.check_model(&config, "model", &model_id, ModelType::Chat);
+    }
+    if let Some(model_id) = &config.rag_embedding_model {
+        report.check_model(
+            &config,
+            "rag_embedding_model",
+            model_id,
+            ModelType::Embedding,
+        );
+    }
+    if let Some(model_id) = &config.rag_reranker_model {
+        report.check_model(&config, "rag_reranker_model", model_id, ModelType::Reranker);
+    }
+
+    reports.insert(0, report);
+    (config, vault)
+}
+
+/// Checks the global and project `mcp.json`, returning the names of the servers they define.
+fn check_mcp_config(vault: &Vault, reports: &mut Vec<FileReport>) -> Vec<String> {
+    let mut server_names = vec![];
+    let paths = [
+        Some(Config::mcp_config_file()),
+        Config::project_mcp_config_file(),
+    ];
+    for path in paths.into_iter().flatten().filter(|v| v.exists()) {
+        let mut report = FileReport::new(&path);
+        match read_to_string(&path) {
+            Ok(content) if content.trim().is_empty() => {}
+            Ok(content) => {
+                let (parsed_content, missing_secrets) = interpolate_secrets(&content, vault);
+                report.check_secrets(&missing_secrets);
+                match serde_json::from_str::<Value>(&parsed_content) {
+                    Ok(value) => {
+                        report.check_keys("", &value, &["mcpServers"]);
+                        if let Some(servers) = value.get("mcpServers").and_then(Value::as_object) {
+                            for (name, server) in servers {
+                                let prefix = format!("mcpServers.{name}.");
+                                report.check_keys(&prefix, server, struct_fields::<McpServer>());
+                            }
+                        }
+                        match serde_json::from_value::<McpServersConfig>(value) {
+                            Ok(v) => server_names.extend(v.mcp_servers.into_keys()),
+                            Err(err) => report.errors.push(err.to_string()),
+                        }
+                    }
+                    Err(err) => report.errors.push(format!("Invalid JSON: {err}")),
+                }
+            }
+            Err(err) => report.errors.push(format!("Failed to read it: {err}")),
+        }
+        reports.push(report);
+    }
+    server_names
+}
+
+fn check_roles(config: &Config, reports: &mut Vec<FileReport>) {
+    for name in Config::list_roles(false) {
+        let mut report = FileReport::new(&Config::role_file(&name));
+        match read_to_string(&report.path) {
+            Ok(content) => {
+                let (metadata, _) = split_metadata(&content);
+                if !metadata.is_empty() {
+                    match serde_yaml::from_str::<Value>(metadata) {
+                        Ok(value) => {
+                            report.check_keys("", &value, &ROLE_METADATA_KEYS);
+                            for (key, value) in value.as_object().into_iter().flatten() {
+                                let is_valid = match key.as_str() {
+                                    "temperature" | "top_p" => value.is_number(),
+                                    _ => value.is_string() || value.is_null(),
+                                };
+                                if !is_valid {
+                                    report.errors.push(format!("{key}: invalid value {value}"));
+                                }
+                            }
+                            if let Some(model_id) = value.get("model").and_then(Value::as_str) {
+                                report.check_model(config, "model", model_id, ModelType::Chat);
+                            }
+                        }
+                        Err(err) => report.errors.push(format!("Invalid front matter: {err}")),
+                    }
+                }
+            }
+            Err(err) => report.errors.push(format!("Failed to read it: {err}")),
+        }
+        reports.push(report);
+    }
+}
+
+fn check_agents(
+    config: &Config,
+    vault: &Vault,
+    mcp_servers: &[String],
+    reports: &mut Vec<FileReport>,
+) {
+    for name in list_agents() {
+        let mut report = FileReport::new(&Config::agent_config_file(&name));
+        if !report.path.exists() {
+            report.errors.push("Not found".into());
+            reports.push(report);
+            continue;
+        }
+        let Some(content) = report.read_yaml(struct_fields::<AgentConfig>()) else {
+            reports.push(report);
+            continue;
+        };
+        let agent_config = match serde_yaml::from_str::<AgentConfig>(&content) {
+            Ok(v) => v,
+            Err(err) => {
+                report.errors.push(err.to_string());
+                reports.push(report);
+                continue;
+            }
+        };
+
+        let models = [
+            ("model", &agent_config.model_id),
+            ("summarization_model", &agent_config.summarization_model),
+        ];
+        for (key, model_id) in models {
+            if let Some(model_id) = model_id {
+                report.check_model(config, key, model_id, ModelType::Chat);
+            }
+        }
+        for model_id in &agent_config.model_fallbacks {
+            report.check_model(config, "model_fallbacks", model_id, ModelType::Chat);
+        }
+        for server in &agent_config.mcp_servers {
+            if !mcp_servers.contains(server) {
+                report
+                    .errors
+                    .push(format!("mcp_servers: '{server}' isn't defined in mcp.json"));
+            }
+        }
+        for variable in &agent_config.variables {
+            let Some(default) = &variable.default else {
+                continue;
+            };
+            if variable.variable_type == AgentVariableType::Secret
+                && SECRET_RE.is_match(default).unwrap_or_default()
+            {
+                let (_, missing_secrets) = interpolate_secrets(default, vault);
+                report.check_secrets(&missing_secrets);
+            } else if let Err(err) = variable.validate(default) {
+                report.errors.push(format!(
+                    "variables: the default of '{}' {err}",
+                    variable.name
+                ));
+            }
+        }
+        reports.push(report);
+    }
+}
+
+fn check_macros(reports: &mut Vec<FileReport>) {
+    for name in Config::list_macros() {
+        let mut report = FileReport::new(&Config::macro_file(&name));
+        if let Some(content) = report.read_yaml(struct_fields::<Macro>()) {
+            match serde_yaml::from_str::<Macro>(&content) {
+                Ok(v) if v.steps.is_empty() => report.errors.push("steps: no steps".into()),
+                Ok(_) => {}
+                Err(err) => report.errors.push(err.to_string()),
+            }
+        }
+        reports.push(report);
+    }
+}
+
+/// The keys of a map that aren't in `fields`, each with the closest field, if any.
+fn unknown_keys(prefix: &str, value: &Value, fields: &[&str]) -> Vec<String> {
+    let Some(map) = value.as_object() else {
+        return vec![];
+    };
+    map.keys()
+        .filter(|key| !fields.contains(&key.as_str()))
+        .map(
+            |key| match fuzzy_filter(fields.to_vec(), |v| *v, key).first() {
+                Some(field) => {
+                    format!("Unknown setting '{prefix}{key}'; did you mean '{prefix}{field}'?")
+                }
+                None => format!("Unknown setting '{prefix}{key}'"),
+            },
+        )
+        .collect()
+}
+
+/// The keys serde reads for a struct, found by asking it to deserialize itself from a
+/// deserializer that only records them.
+fn struct_fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> de::Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("fields recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_struct_fields() {
+        assert_eq!(struct_fields::<Macro>(), ["variables", "steps"]);
+        let fields = struct_fields::<Config>();
+        assert!(fields.contains(&"model") && fields.contains(&"clients"));
+        assert!(!fields.contains(&"model_id") && !fields.contains(&"vault"));
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let value = json!({"model": "openai:gpt-4o", "temprature": 0.2, "foo": true});
+        assert_eq!(
+            unknown_keys("", &value, &["model", "temperature", "top_p"]),
+            vec![
+                "Unknown setting 'temprature'; did you mean 'temperature'?",
+                "Unknown setting 'foo'",
+            ]
+        );
+        assert!(unknown_keys("", &json!("text"), &["model"]).is_empty());
+    }
+}
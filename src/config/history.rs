@@ -0,0 +1,182 @@
+use super::budget::Usage;
+use super::*;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{OpenOptions, read_to_string},
+    io::Write,
+    path::Path,
+    time::Instant,
+};
+
+pub const HISTORY_FILE_NAME: &str = "history.jsonl";
+const PROMPT_SUMMARY_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Ok,
+    Error,
+}
+
+/// A single run of an agent: a prompt and every completion and tool call it took to answer
+/// it. Runs are appended to the agent's `history.jsonl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRun {
+    pub started_at: String,
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub duration_ms: u64,
+    pub status: RunStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An agent run that's still in progress.
+#[derive(Debug, Clone)]
+pub struct ActiveRun {
+    run: AgentRun,
+    started: Instant,
+}
+
+impl ActiveRun {
+    pub fn new(prompt: &str, session: Option<&str>) -> Self {
+        let prompt = prompt.split_whitespace().collect::<Vec<_>>().join(" ");
+        let prompt = match prompt.char_indices().nth(PROMPT_SUMMARY_LEN) {
+            Some((i, _)) => format!("{}…", &prompt[..i]),
+            None => prompt,
+        };
+        Self {
+            run: AgentRun {
+                started_at: now(),
+                prompt,
+                session: session.map(|v| v.to_string()),
+                tools: vec![],
+                usage: Usage::default(),
+                duration_ms: 0,
+                status: RunStatus::Ok,
+                error: None,
+            },
+            started: Instant::now(),
+        }
+    }
+
+    pub fn add_usage(&mut self, usage: &Usage) {
+        self.run.usage.add(usage);
+    }
+
+    pub fn add_tool(&mut self, name: &str) {
+        self.run.tools.push(name.to_string());
+    }
+
+    pub fn finish(self, error: Option<String>) -> AgentRun {
+        AgentRun {
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            status: match error {
+                Some(_) => RunStatus::Error,
+                None => RunStatus::Ok,
+            },
+            error,
+            ..self.run
+        }
+    }
+}
+
+impl AgentRun {
+    /// Appends a line to the log, encrypted with `vault` if it's given.
+    pub fn append(&self, path: &Path, vault: Option<&Vault>) -> Result<()> {
+        let mut line = serde_json::to_string(self)?;
+        if let Some(vault) = vault {
+            line = vault.encrypt_text(&format!("{line}\n"))?;
+        }
+        ensure_parent_exists(path)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open history file at '{}'", path.display()))?;
+        writeln!(file, "{line}")
+            .with_context(|| format!("Failed to write history file at '{}'", path.display()))
+    }
+
+    /// Loads the last `limit` runs, oldest first, decrypting any that were encrypted. Lines that
+    /// can't be parsed are skipped.
+    pub fn load_recent(path: &Path, limit: usize, vault: &Vault) -> Result<Vec<Self>> {
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let contents = read_to_string(path)
+            .with_context(|| format!("Failed to read history file at '{}'", path.display()))?;
+        let contents = vault
+            .decrypt_text(&contents)
+            .with_context(|| format!("Failed to decrypt history file at '{}'", path.display()))?;
+        let runs: Vec<Self> = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let skip = runs.len().saturating_sub(limit);
+        Ok(runs.into_iter().skip(skip).collect())
+    }
+
+    pub fn render(&self) -> String {
+        let status = match &self.error {
+            Some(error) => format!("error: {error}"),
+            None => "ok".to_string(),
+        };
+        let tools = if self.tools.is_empty() {
+            String::new()
+        } else {
+            format!("\n    tools: {}", self.tools.join(", "))
+        };
+        format!(
+            "{} ({:.1}s, {} tokens, ${:.4}) {status}\n    {}{tools}",
+            self.started_at,
+            self.duration_ms as f64 / 1000.0,
+            self.usage.total_tokens(),
+            self.usage.cost,
+            self.prompt,
+        )
+    }
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_run_history() {
+        let path =
+            std::env::temp_dir().join(format!("loki-test-history-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for i in 0..3 {
+            let mut run = ActiveRun::new(&format!("prompt {i}\n{}", "x".repeat(200)), None);
+            run.add_tool("fs_cat");
+            run.add_usage(&Usage {
+                input_tokens: 10,
+                output_tokens: 5,
+                cost: 0.0,
+            });
+            let error = (i == 2).then(|| "Aborted.".to_string());
+            run.finish(error).append(&path, None).unwrap();
+        }
+        let runs = AgentRun::load_recent(&path, 2, &Vault::default()).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert!(runs[0].prompt.starts_with("prompt 1 xxx"));
+        assert!(runs[0].prompt.ends_with('…'));
+        assert_eq!(runs[0].status, RunStatus::Ok);
+        assert_eq!(runs[1].status, RunStatus::Error);
+        assert_eq!(runs[1].tools, ["fs_cat"]);
+        assert_eq!(runs[1].usage.total_tokens(), 15);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -4,8 +4,9 @@ use crate::client::{
     ChatCompletionsData, Client, ImageUrl, Message, MessageContent, MessageContentPart,
     MessageContentToolCalls, MessageRole, Model, init_client, patch_messages,
 };
+use crate::config::memory::recall_memories;
 use crate::function::ToolResult;
-use crate::utils::{AbortSignal, base64_encode, is_loader_protocol, sha256};
+use crate::utils::{AbortSignal, base64_encode, estimate_token_length, is_loader_protocol, sha256};
 
 use anyhow::{Context, Result, bail};
 use indexmap::IndexSet;
@@ -29,6 +30,7 @@ pub struct Input {
     tool_calls: Option<MessageContentToolCalls>,
     role: Role,
     rag_name: Option<String>,
+    memories: Option<String>,
     with_session: bool,
     with_agent: bool,
 }
@@ -49,6 +51,7 @@ impl Input {
             tool_calls: None,
             role,
             rag_name: None,
+            memories: None,
             with_session,
             with_agent,
         }
@@ -116,6 +119,7 @@ impl Input {
             tool_calls: Default::default(),
             role,
             rag_name: None,
+            memories: None,
             with_session,
             with_agent,
         })
@@ -155,6 +159,15 @@ impl Input {
         }
     }
 
+    /// Appends the output of a shell command, the same way `.file` includes a `` `command` ``.
+    pub fn attach_command_output(&mut self, cmd: &str, output: &str) {
+        self.text = format!(
+            "{}\n\n============ CMD: {cmd} ============\n{output}",
+            self.text
+        );
+        self.raw.1.push(format!("`{cmd}`"));
+    }
+
     pub fn clear_patch(&mut self) {
         self.patched_text = None;
     }
@@ -202,6 +215,7 @@ impl Input {
             self.patched_text = Some(result);
             self.rag_name = Some(rag.name().to_string());
         }
+        self.memories = recall_memories(&self.config, &self.text, self.with_agent).await;
         Ok(())
     }
 
@@ -219,6 +233,14 @@ impl Input {
         self
     }
 
+    pub fn set_model(&mut self, model: Model) {
+        self.role.set_model(model);
+    }
+
+    pub fn set_temperature(&mut self, value: Option<f64>) {
+        self.role.set_temperature(value);
+    }
+
     pub fn create_client(&self) -> Result<Box<dyn Client>> {
         init_client(&self.config, Some(self.role().model().clone()))
     }
@@ -265,6 +287,41 @@ impl Input {
         } else {
             self.role().build_messages(self)
         };
+        let mut system_context = vec![];
+        if let Some(prompt) = self
+            .session(&self.config.read().session)
+            .and_then(|session| session.extra_prompt())
+        {
+            system_context.push(prompt.to_string());
+        }
+        if let Some(memories) = &self.memories {
+            system_context.push(memories.clone());
+        }
+        if self.with_agent
+            && let Some(agent) = self.config.read().agent.as_ref()
+            && agent.todo_list().has_incomplete()
+        {
+            system_context.push(format!(
+                "## Active Todo List\n{}",
+                agent.todo_list().render_for_model()
+            ));
+        }
+        if !system_context.is_empty() {
+            let system_context = system_context.join("\n\n");
+            match messages.first_mut() {
+                Some(Message {
+                    role: MessageRole::System,
+                    content: MessageContent::Text(text),
+                }) => {
+                    text.push_str("\n\n");
+                    text.push_str(&system_context);
+                }
+                _ => messages.insert(
+                    0,
+                    Message::new(MessageRole::System, MessageContent::Text(system_context)),
+                ),
+            }
+        }
         if let Some(tool_calls) = &self.tool_calls {
             messages.push(Message::new(
                 MessageRole::Assistant,
@@ -274,6 +331,61 @@ impl Input {
         Ok(messages)
     }
 
+    /// How many tokens each part of the context sent with this input takes up, skipping the parts
+    /// that are empty. The RAG template is counted without the retrieved documents it wraps, since
+    /// those aren't known until the input is sent.
+    pub fn context_breakdown(&self) -> Result<Vec<(&'static str, usize)>> {
+        let model = self.role().model();
+        let messages = self.build_messages()?;
+        let formatting = model.total_tokens(&messages) - model.messages_tokens(&messages);
+        let (system, mut history): (Vec<_>, Vec<_>) =
+            messages.into_iter().partition(|v| v.role.is_system());
+        let pending: Vec<Message> = if history.last().is_some_and(|v| v.role.is_user()) {
+            history.pop().into_iter().collect()
+        } else {
+            vec![]
+        };
+        let config = self.config.read();
+        let system_label = if self.with_agent && config.agent.is_some() {
+            "Agent instructions"
+        } else {
+            "System prompt"
+        };
+        let rag_template = if config.rag.is_some() {
+            estimate_token_length(&config.rag_template("", "", ""))
+        } else {
+            0
+        };
+        let tools = if model.supports_function_calling() {
+            config
+                .select_functions(self.role())
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|v| serde_json::to_string(v).ok())
+                .map(|v| estimate_token_length(&v))
+                .sum()
+        } else {
+            0
+        };
+        let history_label = if self.session(&config.session).is_some() {
+            "Session history"
+        } else {
+            "Role examples"
+        };
+        let parts = vec![
+            (system_label, model.messages_tokens(&system)),
+            ("RAG template", rag_template),
+            ("Tool declarations", tools),
+            (history_label, model.messages_tokens(&history)),
+            ("Pending input", model.messages_tokens(pending.as_slice())),
+            ("Message formatting", formatting),
+        ];
+        Ok(parts
+            .into_iter()
+            .filter(|(_, tokens)| *tokens > 0)
+            .collect())
+    }
+
     pub fn echo_messages(&self) -> String {
         if let Some(session) = self.session(&self.config.read().session) {
             session.echo_messages(self)
@@ -330,6 +442,11 @@ impl Input {
         }
     }
 
+    /// The text as it was entered, without any of the files it included.
+    pub fn raw_text(&self) -> &str {
+        &self.raw.0
+    }
+
     pub fn raw(&self) -> String {
         let (text, files) = &self.raw;
         let mut segments = files.to_vec();
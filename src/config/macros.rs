@@ -5,7 +5,7 @@ use anyhow::{Result, anyhow};
 use indexmap::IndexMap;
 use parking_lot::RwLock;
 use rust_embed::Embed;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Write;
 use std::sync::Arc;
@@ -91,41 +91,11 @@ impl Macro {
     }
 
     pub fn resolve_variables(&self, args: &[String]) -> Result<IndexMap<String, String>> {
-        let mut output = IndexMap::new();
-        for (i, variable) in self.variables.iter().enumerate() {
-            let value = if variable.rest && i == self.variables.len() - 1 {
-                if args.len() > i {
-                    Some(args[i..].join(" "))
-                } else {
-                    variable.default.clone()
-                }
-            } else {
-                args.get(i)
-                    .map(|v| v.to_string())
-                    .or_else(|| variable.default.clone())
-            };
-            let value =
-                value.ok_or_else(|| anyhow!("Missing value for variable '{}'", variable.name))?;
-            output.insert(variable.name.clone(), value);
-        }
-        Ok(output)
+        resolve_variables(&self.variables, args)
     }
 
     pub fn usage(&self, name: &str) -> String {
-        let mut parts = vec![name.to_string()];
-        for (i, variable) in self.variables.iter().enumerate() {
-            let part = match (
-                variable.rest && i == self.variables.len() - 1,
-                variable.default.is_some(),
-            ) {
-                (true, true) => format!("[{}]...", variable.name),
-                (true, false) => format!("<{}>...", variable.name),
-                (false, true) => format!("[{}]", variable.name),
-                (false, false) => format!("<{}>", variable.name),
-            };
-            parts.push(part);
-        }
-        parts.join(" ")
+        variables_usage(name, &self.variables)
     }
 
     pub fn interpolate_command(command: &str, variables: &IndexMap<String, String>) -> String {
@@ -137,10 +107,53 @@ impl Macro {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MacroVariable {
     pub name: String,
     #[serde(default)]
     pub rest: bool,
     pub default: Option<String>,
 }
+
+/// Binds positional arguments to variables, where the last variable may be marked `rest`
+/// to collect all remaining arguments.
+pub fn resolve_variables(
+    variables: &[MacroVariable],
+    args: &[String],
+) -> Result<IndexMap<String, String>> {
+    let mut output = IndexMap::new();
+    for (i, variable) in variables.iter().enumerate() {
+        let value = if variable.rest && i == variables.len() - 1 {
+            if args.len() > i {
+                Some(args[i..].join(" "))
+            } else {
+                variable.default.clone()
+            }
+        } else {
+            args.get(i)
+                .map(|v| v.to_string())
+                .or_else(|| variable.default.clone())
+        };
+        let value =
+            value.ok_or_else(|| anyhow!("Missing value for variable '{}'", variable.name))?;
+        output.insert(variable.name.clone(), value);
+    }
+    Ok(output)
+}
+
+pub fn variables_usage(name: &str, variables: &[MacroVariable]) -> String {
+    let mut parts = vec![name.to_string()];
+    for (i, variable) in variables.iter().enumerate() {
+        let part = match (
+            variable.rest && i == variables.len() - 1,
+            variable.default.is_some(),
+        ) {
+            (true, true) => format!("[{}]...", variable.name),
+            (true, false) => format!("<{}>...", variable.name),
+            (false, true) => format!("[{}]", variable.name),
+            (false, false) => format!("<{}>", variable.name),
+        };
+        parts.push(part);
+    }
+    parts.join(" ")
+}
@@ -0,0 +1,460 @@
+use super::role::EXTRACT_MEMORIES_ROLE;
+use super::*;
+
+use crate::client::{EmbeddingsData, EmbeddingsOutput, ModelType, init_client};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+pub const MEMORY_FILE_NAME: &str = "memory.yaml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub id: usize,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryStore {
+    #[serde(skip)]
+    path: PathBuf,
+    /// The vault to encrypt the store with when it's saved, if `encrypt_sessions` is on.
+    #[serde(skip)]
+    vault: Option<GlobalVault>,
+    #[serde(default)]
+    memories: Vec<MemoryEntry>,
+}
+
+impl MemoryStore {
+    pub fn load(config: &Config, path: &Path) -> Result<Self> {
+        let mut store: Self = if path.exists() {
+            let contents = read_to_string(path)
+                .with_context(|| format!("Failed to read memory file at '{}'", path.display()))?;
+            let contents = config.vault.decrypt_text(&contents).with_context(|| {
+                format!("Failed to decrypt memory file at '{}'", path.display())
+            })?;
+            serde_yaml::from_str(&contents)
+                .with_context(|| format!("Failed to load memory file at '{}'", path.display()))?
+        } else {
+            Self::default()
+        };
+        store.path = path.to_path_buf();
+        store.vault = config.session_vault();
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        ensure_parent_exists(&self.path)?;
+        let mut contents = serde_yaml::to_string(&self)?;
+        if let Some(vault) = &self.vault {
+            contents = format!("{}\n", vault.encrypt_text(&contents)?);
+        }
+        write(&self.path, contents)
+            .with_context(|| format!("Failed to write memory file at '{}'", self.path.display()))
+    }
+
+    pub fn add(&mut self, content: &str, tags: Vec<String>, embedding: Option<Vec<f32>>) -> usize {
+        let id = self.memories.iter().map(|m| m.id).max().unwrap_or(0) + 1;
+        self.memories.push(MemoryEntry {
+            id,
+            content: content.to_string(),
+            tags,
+            created_at: now(),
+            embedding,
+        });
+        id
+    }
+
+    pub fn forget(&mut self, id: usize) -> bool {
+        let len = self.memories.len();
+        self.memories.retain(|m| m.id != id);
+        self.memories.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memories.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.memories.len()
+    }
+
+    pub fn memories(&self) -> &[MemoryEntry] {
+        &self.memories
+    }
+
+    /// Whether a memory with the same content, ignoring case, is already stored.
+    pub fn contains(&self, content: &str) -> bool {
+        self.memories
+            .iter()
+            .any(|m| m.content.eq_ignore_ascii_case(content.trim()))
+    }
+
+    /// Ranks memories against the query. Vector similarity is used for entries
+    /// that were embedded with the same dimensions as the query; everything else
+    /// falls back to keyword overlap.
+    pub fn search(
+        &self,
+        query: &str,
+        query_embedding: Option<&[f32]>,
+        top_k: usize,
+    ) -> Vec<&MemoryEntry> {
+        let query_terms = tokenize(query);
+        let mut scored: Vec<(&MemoryEntry, f32)> = self
+            .memories
+            .iter()
+            .filter_map(|entry| {
+                let score = match (query_embedding, entry.embedding.as_deref()) {
+                    (Some(a), Some(b)) if a.len() == b.len() => cosine_similarity(a, b),
+                    _ => keyword_score(&query_terms, entry),
+                };
+                (score > 0.0).then_some((entry, score))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+            .into_iter()
+            .take(top_k)
+            .map(|(entry, _)| entry)
+            .collect()
+    }
+
+    pub fn render_for_model(entries: &[&MemoryEntry]) -> String {
+        render_entries(
+            "## Relevant Memories",
+            "The following facts were recalled from your long-term memory. Use them if they are relevant to the request.",
+            entries,
+        )
+    }
+
+    /// Renders memories from the global memory store, which the user keeps across all sessions
+    /// and agents, rather than the agent's own.
+    pub fn render_global_for_model(entries: &[&MemoryEntry]) -> String {
+        render_entries(
+            "## Remembered About the User",
+            "The following facts were recalled from the user's memory, which is shared by all sessions and agents. Use them if they are relevant to the request.",
+            entries,
+        )
+    }
+}
+
+fn render_entries(heading: &str, intro: &str, entries: &[&MemoryEntry]) -> String {
+    let mut lines = vec![heading.to_string(), intro.to_string()];
+    for entry in entries {
+        if entry.tags.is_empty() {
+            lines.push(format!("- [{}] {}", entry.id, entry.content));
+        } else {
+            lines.push(format!(
+                "- [{}] {} (tags: {})",
+                entry.id,
+                entry.content,
+                entry.tags.join(", ")
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Embeds the given texts with the configured `rag_embedding_model`. Returns `None`
+/// when no embedding model is configured or the request fails, so callers can fall
+/// back to keyword matching.
+pub async fn embed_memory_texts(
+    config: &GlobalConfig,
+    texts: Vec<String>,
+    query: bool,
+) -> Option<EmbeddingsOutput> {
+    let model_id = config.read().rag_embedding_model.clone()?;
+    let result = async {
+        let model = Model::retrieve_model(&config.read(), &model_id, ModelType::Embedding)?;
+        let client = init_client(config, Some(model))?;
+        client.embeddings(&EmbeddingsData::new(texts, query)).await
+    }
+    .await;
+    match result {
+        Ok(output) => Some(output),
+        Err(err) => {
+            debug!("Failed to embed memory texts: {err}");
+            None
+        }
+    }
+}
+
+/// Looks up the memories relevant to the given text, from the active agent's memory (when
+/// `with_agent`) and the global memory store (when `memory` is on), and renders them for
+/// injection into the system prompt. The text is only embedded once for both.
+pub async fn recall_memories(
+    config: &GlobalConfig,
+    text: &str,
+    with_agent: bool,
+) -> Option<String> {
+    let (agent_top_k, global) = {
+        let cfg = config.read();
+        let agent_top_k = cfg
+            .agent
+            .as_ref()
+            .filter(|agent| with_agent && agent.memory().is_some_and(|memory| !memory.is_empty()))
+            .map(|agent| agent.memory_top_k());
+        let global = match cfg.memory {
+            true => MemoryStore::load(&cfg, &Config::memory_file())
+                .inspect_err(|err| warn!("Failed to load the global memory: {err}"))
+                .ok()
+                .filter(|store| !store.is_empty()),
+            false => None,
+        };
+        (agent_top_k, global.map(|store| (store, cfg.memory_top_k)))
+    };
+    if agent_top_k.is_none() && global.is_none() {
+        return None;
+    }
+    let query_embedding = embed_memory_texts(config, vec![text.to_string()], true)
+        .await
+        .and_then(|mut v| v.pop());
+    let mut sections = vec![];
+    if let Some(top_k) = agent_top_k {
+        let cfg = config.read();
+        let entries = cfg
+            .agent
+            .as_ref()
+            .and_then(|agent| agent.memory())
+            .map(|memory| memory.search(text, query_embedding.as_deref(), top_k))
+            .unwrap_or_default();
+        if !entries.is_empty() {
+            sections.push(MemoryStore::render_for_model(&entries));
+        }
+    }
+    if let Some((store, top_k)) = &global {
+        let entries = store.search(text, query_embedding.as_deref(), *top_k);
+        if !entries.is_empty() {
+            sections.push(MemoryStore::render_global_for_model(&entries));
+        }
+    }
+    (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+/// Saves a fact to the global memory store, embedding it when an embedding model is set.
+/// Returns the new memory's id, or `None` if the same fact was already stored.
+pub async fn remember(
+    config: &GlobalConfig,
+    content: &str,
+    tags: Vec<String>,
+) -> Result<Option<usize>> {
+    let content = content.trim();
+    if content.is_empty() {
+        bail!("Nothing to remember");
+    }
+    if load_global_store(config)?.contains(content) {
+        return Ok(None);
+    }
+    let embedding = embed_memory_texts(config, vec![content.to_string()], false)
+        .await
+        .and_then(|mut v| v.pop());
+    // Loaded again, in case another Loki saved a memory while this one was being embedded
+    let mut store = load_global_store(config)?;
+    let id = store.add(content, tags, embedding);
+    store.save()?;
+    Ok(Some(id))
+}
+
+/// Asks the `memory_extractor_model` which durable facts about the user an exchange revealed,
+/// and saves the new ones to the global memory store, tagged `extracted`. Returns how many
+/// were saved.
+pub async fn extract_memories(config: &GlobalConfig, text: &str, output: &str) -> Result<usize> {
+    let Some(model_id) = config.read().memory_extractor_model.clone() else {
+        return Ok(0);
+    };
+    let known = load_global_store(config)?
+        .memories()
+        .iter()
+        .map(|entry| format!("- {}", entry.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut role = config.read().retrieve_role(EXTRACT_MEMORIES_ROLE)?;
+    role.set_model(Model::retrieve_model(
+        &config.read(),
+        &model_id,
+        ModelType::Chat,
+    )?);
+    let prompt = format!("KNOWN FACTS:\n{known}\n\nUSER: {text}\nASSISTANT: {output}");
+    let reply = Input::from_str(config, &prompt, Some(role))
+        .fetch_chat_text()
+        .await?;
+    let mut saved = 0;
+    for fact in parse_extracted_facts(&reply) {
+        if remember(config, &fact, vec!["extracted".into()])
+            .await?
+            .is_some()
+        {
+            saved += 1;
+        }
+    }
+    Ok(saved)
+}
+
+/// Lists the memories in the global memory store, or the ones most relevant to a query.
+pub async fn list_memories(config: &GlobalConfig, query: Option<&str>) -> Result<String> {
+    let store = load_global_store(config)?;
+    let entries: Vec<&MemoryEntry> = match query {
+        Some(query) => {
+            let query_embedding = embed_memory_texts(config, vec![query.to_string()], true)
+                .await
+                .and_then(|mut v| v.pop());
+            let top_k = config.read().memory_top_k;
+            store.search(query, query_embedding.as_deref(), top_k)
+        }
+        None => store.memories().iter().collect(),
+    };
+    if entries.is_empty() {
+        return Ok("No memories\n".into());
+    }
+    let output = entries
+        .iter()
+        .map(|entry| {
+            let tags = match entry.tags.is_empty() {
+                true => String::new(),
+                false => dimmed_text(&format!(" ({})", entry.tags.join(", "))),
+            };
+            format!("{:>4}  {}{tags}\n", entry.id, entry.content)
+        })
+        .collect();
+    Ok(output)
+}
+
+/// Removes a memory from the global memory store by its id.
+pub fn forget_memory(config: &GlobalConfig, id: usize) -> Result<()> {
+    let mut store = load_global_store(config)?;
+    if !store.forget(id) {
+        bail!("No memory with id {id}");
+    }
+    store.save()
+}
+
+/// The global memory store, which can only be used when `memory` is on.
+fn load_global_store(config: &GlobalConfig) -> Result<MemoryStore> {
+    let config = config.read();
+    if !config.memory {
+        bail!("The global memory is off; set 'memory: true' in the config to use it");
+    }
+    MemoryStore::load(&config, &Config::memory_file())
+}
+
+/// The facts in an extractor's reply, one per line, with any list markers dropped.
+fn parse_extracted_facts(reply: &str) -> Vec<String> {
+    reply
+        .lines()
+        .map(|line| line.trim().trim_start_matches(['-', '*']).trim())
+        .filter(|line| !line.is_empty() && !line.eq_ignore_ascii_case("NONE"))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+fn tokenize(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|v| v.chars().count() > 2)
+        .map(|v| v.to_lowercase())
+        .collect()
+}
+
+fn keyword_score(query_terms: &HashSet<String>, entry: &MemoryEntry) -> f32 {
+    if query_terms.is_empty() {
+        return 0.0;
+    }
+    let mut terms = tokenize(&entry.content);
+    for tag in &entry.tags {
+        terms.extend(tokenize(tag));
+    }
+    let matches = query_terms.intersection(&terms).count();
+    matches as f32 / query_terms.len() as f32
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_forget() {
+        let mut store = MemoryStore::default();
+        assert_eq!(store.add("User prefers tabs", vec![], None), 1);
+        assert_eq!(store.add("Project uses Rust", vec![], None), 2);
+        assert!(store.forget(1));
+        assert!(!store.forget(1));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.add("Another fact", vec![], None), 3);
+    }
+
+    #[test]
+    fn test_keyword_search() {
+        let mut store = MemoryStore::default();
+        store.add("The user prefers tabs over spaces", vec![], None);
+        store.add("Deployments happen on Fridays", vec!["ops".into()], None);
+        let results = store.search("what does the user prefer: tabs or spaces?", None, 5);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, 1);
+        let results = store.search("ops schedule", None, 5);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, 2);
+        assert!(store.search("unrelated", None, 5).is_empty());
+    }
+
+    #[test]
+    fn test_vector_search() {
+        let mut store = MemoryStore::default();
+        store.add("first", vec![], Some(vec![1.0, 0.0]));
+        store.add("second", vec![], Some(vec![0.0, 1.0]));
+        store.add("third", vec![], Some(vec![0.7, 0.7]));
+        let results = store.search("query", Some(&[0.0, 1.0]), 2);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].id, 2);
+        assert_eq!(results[1].id, 3);
+    }
+
+    #[test]
+    fn test_render_for_model() {
+        let mut store = MemoryStore::default();
+        store.add("User prefers tabs", vec!["style".into()], None);
+        let entries: Vec<&MemoryEntry> = store.memories.iter().collect();
+        let rendered = MemoryStore::render_for_model(&entries);
+        assert!(rendered.contains("## Relevant Memories"));
+        assert!(rendered.contains("- [1] User prefers tabs (tags: style)"));
+        let rendered = MemoryStore::render_global_for_model(&entries);
+        assert!(rendered.contains("## Remembered About the User"));
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut store = MemoryStore::default();
+        store.add("User prefers tabs", vec![], None);
+        assert!(store.contains("user prefers TABS "));
+        assert!(!store.contains("User prefers spaces"));
+    }
+
+    #[test]
+    fn test_parse_extracted_facts() {
+        let reply = "- The user's name is Alex\n\n* The user deploys with Nomad\nNONE\n";
+        assert_eq!(
+            parse_extracted_facts(reply),
+            vec!["The user's name is Alex", "The user deploys with Nomad"]
+        );
+        assert!(parse_extracted_facts("NONE").is_empty());
+    }
+}
@@ -1,28 +1,62 @@
 mod agent;
+mod agent_package;
+mod agent_tests;
+mod autosave;
+mod batch;
+pub(crate) mod budget;
+mod check;
+mod history;
 mod input;
 mod macros;
+pub(crate) mod memory;
+mod project;
 mod prompts;
 mod role;
 mod session;
+pub(crate) mod skill;
+mod template;
 pub(crate) mod todo;
+mod transcript;
 
+use self::agent::AGENT_VERSION_FILE_NAME;
 pub use self::agent::{Agent, AgentVariables, complete_agent_variables, list_agents};
+pub use self::agent_package::{install_agent, package_agent};
+use self::agent_tests::AGENT_TESTS_FILE_NAME;
+pub use self::agent_tests::run_agent_tests;
+use self::autosave::SessionAutosave;
+pub use self::batch::{DEFAULT_BATCH_CONCURRENCY, DEFAULT_BATCH_RETRIES, run_batch};
+use self::budget::{USAGE_FILE_NAME, Usage, UsageLedger};
+pub use self::check::check_config;
+use self::history::{AgentRun, HISTORY_FILE_NAME};
 pub use self::input::Input;
+use self::memory::{MEMORY_FILE_NAME, extract_memories};
+pub use self::project::remove_project_secrets;
+use self::project::{find_project_dir, overlay_config, trust_project};
 pub use self::role::{
     CODE_ROLE, CREATE_TITLE_ROLE, EXPLAIN_SHELL_ROLE, Role, RoleLike, SHELL_ROLE,
 };
 use self::session::Session;
+pub use self::transcript::{ExportFormat, render_sources, render_tool_result};
 pub use macros::macro_execute;
 use mem::take;
 
 use crate::client::{
     ClientConfig, MessageContentToolCalls, Model, ModelType, OPENAI_COMPATIBLE_PROVIDERS,
-    ProviderModels, create_client_config, list_client_types, list_models,
+    ProviderModels, create_client_config, is_connection_error, list_client_types, list_models,
 };
+use crate::function::audit::ToolCallRecord;
+use crate::function::cache::{DEFAULT_TOOL_CACHE_TTL_SECS, ToolCache};
+use crate::function::output::DEFAULT_TOOL_OUTPUT_LIMIT;
+use crate::function::permissions::{
+    DangerousPatterns, ToolPolicy, default_dangerous_tool_patterns,
+};
+use crate::function::preset::ToolPreset;
 use crate::function::user_interaction::USER_FUNCTION_PREFIX;
-use crate::function::{FunctionDeclaration, Functions, ToolCallTracker, ToolResult};
+use crate::function::{
+    FunctionDeclaration, Functions, ToolCall, ToolCallTracker, ToolConfig, ToolResult,
+};
 use crate::rag::Rag;
-use crate::render::{MarkdownRender, RenderOptions};
+use crate::render::{INLINE_IMAGE_MODES, ImageProtocol, MarkdownRender, RenderOptions};
 use crate::utils::*;
 
 use crate::config::macros::Macro;
@@ -33,7 +67,9 @@ use crate::mcp::{
 use crate::supervisor::Supervisor;
 use crate::supervisor::escalation::EscalationQueue;
 use crate::supervisor::mailbox::Inbox;
-use crate::vault::{GlobalVault, Vault, create_vault_password_file, interpolate_secrets};
+use crate::vault::{
+    GlobalVault, InjectedSecrets, Vault, create_vault_password_file, interpolate_secrets,
+};
 use anyhow::{Context, Result, anyhow, bail};
 use fancy_regex::Regex;
 use indexmap::IndexMap;
@@ -42,21 +78,23 @@ use inquire::{Confirm, MultiSelect, Select, Text, list_option::ListOption, valid
 use log::LevelFilter;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{Value, json};
 use std::collections::{HashMap, HashSet};
 use std::sync::LazyLock;
 use std::{
     env,
     fs::{
         File, OpenOptions, create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file,
+        write,
     },
     io::Write,
     mem,
     path::{Path, PathBuf},
     process,
     sync::{Arc, OnceLock},
+    time::Duration,
 };
-use syntect::highlighting::ThemeSet;
+use syntect::highlighting::{Theme, ThemeSet};
 use terminal_colorsaurus::{ColorScheme, QueryOptions, color_scheme};
 use tokio::runtime::Handle;
 
@@ -70,14 +108,24 @@ static PASSWORD_FILE_SECRET_RE: LazyLock<Regex> =
 /// Monokai Extended
 const DARK_THEME: &[u8] = include_bytes!("../../assets/monokai-extended.theme.bin");
 const LIGHT_THEME: &[u8] = include_bytes!("../../assets/monokai-extended-light.theme.bin");
+pub const BUILTIN_THEMES: [&str; 2] = ["dark", "light"];
+
+/// Whether each custom theme has a light background, so it's only loaded once to find out.
+static LIGHT_THEMES: LazyLock<RwLock<HashMap<String, bool>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
 
 const CONFIG_FILE_NAME: &str = "config.yaml";
 const ROLES_DIR_NAME: &str = "roles";
 const MACROS_DIR_NAME: &str = "macros";
+const TEMPLATES_DIR_NAME: &str = "templates";
 const ENV_FILE_NAME: &str = ".env";
 const MESSAGES_FILE_NAME: &str = "messages.md";
+const REPL_HISTORY_FILE_NAME: &str = "repl-history.jsonl";
 const SESSIONS_DIR_NAME: &str = "sessions";
 const RAGS_DIR_NAME: &str = "rags";
+const THEMES_DIR_NAME: &str = "themes";
+const CURRENT_THEME_FILE_NAME: &str = "current-theme";
+const TRUSTED_PROJECTS_FILE_NAME: &str = "trusted-projects.yaml";
 const FUNCTIONS_DIR_NAME: &str = "functions";
 const FUNCTIONS_BIN_DIR_NAME: &str = "bin";
 const AGENTS_DIR_NAME: &str = "agents";
@@ -85,6 +133,11 @@ const GLOBAL_TOOLS_DIR_NAME: &str = "tools";
 const GLOBAL_TOOLS_UTILS_DIR_NAME: &str = "utils";
 const BASH_PROMPT_UTILS_FILE_NAME: &str = "prompt-utils.sh";
 const MCP_FILE_NAME: &str = "mcp.json";
+const TODOS_DIR_NAME: &str = "todos";
+const DEFAULT_TODO_LIST_NAME: &str = "default";
+const TOOL_CALLS_DIR_NAME: &str = "calls";
+const DEFAULT_TOOL_CALLS_LOG_NAME: &str = "default";
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
 
 const CLIENTS_FIELD: &str = "clients";
 
@@ -123,6 +176,10 @@ const LEFT_PROMPT: &str = "{color.red}{model}){color.green}{?session {?agent {ag
 const RIGHT_PROMPT: &str = "{color.purple}{?session {?consume_tokens {consume_tokens}({consume_percent}%)}{!consume_tokens {consume_tokens}}}{color.reset}";
 
 static EDITOR: OnceLock<Option<String>> = OnceLock::new();
+static PROJECT_DIR: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
+    find_project_dir(&env::current_dir().ok()?, &Config::config_dir())
+        .filter(|dir| trust_project(dir, &Config::local_path(TRUSTED_PROJECTS_FILE_NAME)))
+});
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
@@ -134,18 +191,48 @@ pub struct Config {
     pub top_p: Option<f64>,
 
     pub dry_run: bool,
+    pub dry_run_tools: bool,
     pub stream: bool,
     pub save: bool,
     pub keybindings: String,
+    pub keymap: IndexMap<String, String>,
+    pub cursor_shapes: IndexMap<String, String>,
+    pub aliases: IndexMap<String, String>,
+    pub autosuggest: bool,
+    pub spinner: bool,
+    pub spinner_style: String,
+    pub spinner_text: bool,
+    pub inline_images: String,
+    pub link_references: bool,
     pub editor: Option<String>,
     pub wrap: Option<String>,
     pub wrap_code: bool,
+    pub code_line_numbers: bool,
+    pub code_labels: bool,
     vault_password_file: Option<PathBuf>,
 
     pub function_calling_support: bool,
     pub mapping_tools: IndexMap<String, String>,
     pub enabled_tools: Option<String>,
     pub visible_tools: Option<Vec<String>>,
+    pub dangerous_tool_patterns: DangerousPatterns,
+    pub deny_dangerous_tools_without_tty: bool,
+    pub tool_policy: ToolPolicy,
+    pub trusted_agent_keys: Vec<String>,
+    pub tool_timeout_secs: Option<u64>,
+    pub tool_timeouts: IndexMap<String, u64>,
+    pub max_concurrent_tool_calls: usize,
+    pub max_tool_calls_per_turn: usize,
+    pub max_tool_calls_per_session: usize,
+    pub max_tool_rounds: usize,
+    pub max_tool_call_repeats: usize,
+    pub tool_call_repeat_chain_len: usize,
+    pub tool_output_limit: usize,
+    pub summarize_tool_output: bool,
+    pub stream_tool_output: bool,
+    pub expand_tool_calls: bool,
+    pub tool_cache_ttl_secs: u64,
+    pub tools: IndexMap<String, ToolConfig>,
 
     pub mcp_server_support: bool,
     pub mapping_mcp_servers: IndexMap<String, String>,
@@ -156,10 +243,17 @@ pub struct Config {
     pub agent_session: Option<String>,
 
     pub save_session: Option<bool>,
+    pub encrypt_sessions: bool,
+    pub session_autosave_messages: usize,
+    pub session_autosave_secs: u64,
     pub compression_threshold: usize,
     pub summarization_prompt: Option<String>,
     pub summary_context_prompt: Option<String>,
 
+    pub memory: bool,
+    pub memory_top_k: usize,
+    pub memory_extractor_model: Option<String>,
+
     pub rag_embedding_model: Option<String>,
     pub rag_reranker_model: Option<String>,
     pub rag_top_k: usize,
@@ -176,7 +270,10 @@ pub struct Config {
     pub right_prompt: Option<String>,
 
     pub user_agent: Option<String>,
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: Option<u64>,
     pub save_shell_history: bool,
+    pub notify_after_secs: Option<u64>,
     pub sync_models_url: Option<String>,
 
     pub clients: Vec<ClientConfig>,
@@ -187,6 +284,8 @@ pub struct Config {
     #[serde(skip)]
     pub macro_flag: bool,
     #[serde(skip)]
+    pub batch_flag: bool,
+    #[serde(skip)]
     pub info_flag: bool,
     #[serde(skip)]
     pub agent_variables: Option<AgentVariables>,
@@ -201,18 +300,45 @@ pub struct Config {
     pub working_mode: WorkingMode,
     #[serde(skip)]
     pub last_message: Option<LastMessage>,
+    /// The response before the last one, to compare them with `.diff`.
+    #[serde(skip)]
+    pub previous_message: Option<LastMessage>,
+    /// The other answers to the last prompt, from `.regenerate` or `.retry`, to flip between.
+    #[serde(skip)]
+    pub alternatives: Vec<LastMessage>,
+    /// A shell command run with `!` in the REPL and its output, to attach to the next prompt.
+    #[serde(skip)]
+    pub shell_output: Option<(String, String)>,
+    /// Images pasted from the clipboard with `.paste-image`, to attach to the next prompt.
+    #[serde(skip)]
+    pub pasted_images: Vec<String>,
 
     #[serde(skip)]
     pub role: Option<Role>,
     #[serde(skip)]
     pub session: Option<Session>,
     #[serde(skip)]
+    session_autosave: Option<SessionAutosave>,
+    #[serde(skip)]
     pub rag: Option<Arc<Rag>>,
     #[serde(skip)]
     pub agent: Option<Agent>,
     #[serde(skip)]
     pub(crate) tool_call_tracker: Option<ToolCallTracker>,
     #[serde(skip)]
+    approved_tools: HashSet<String>,
+    #[serde(skip)]
+    tool_cache: ToolCache,
+    #[serde(skip)]
+    tool_calls: usize,
+    #[serde(skip)]
+    turn_tool_calls: usize,
+    #[serde(skip)]
+    turn_tool_rounds: usize,
+    /// The secrets injected into tool calls so far, which are kept out of all tool output.
+    #[serde(skip)]
+    pub injected_secrets: InjectedSecrets,
+    #[serde(skip)]
     pub supervisor: Option<Arc<RwLock<Supervisor>>>,
     #[serde(skip)]
     pub parent_supervisor: Option<Arc<RwLock<Supervisor>>>,
@@ -234,18 +360,48 @@ impl Default for Config {
             top_p: None,
 
             dry_run: false,
+            dry_run_tools: false,
             stream: true,
             save: false,
             keybindings: "emacs".into(),
+            keymap: Default::default(),
+            cursor_shapes: Default::default(),
+            aliases: Default::default(),
+            autosuggest: true,
+            spinner: true,
+            spinner_style: "dots".into(),
+            spinner_text: true,
+            inline_images: "auto".into(),
+            link_references: true,
             editor: None,
             wrap: None,
             wrap_code: false,
+            code_line_numbers: false,
+            code_labels: false,
             vault_password_file: None,
 
             function_calling_support: true,
             mapping_tools: Default::default(),
             enabled_tools: None,
             visible_tools: None,
+            dangerous_tool_patterns: default_dangerous_tool_patterns(),
+            deny_dangerous_tools_without_tty: false,
+            tool_policy: Default::default(),
+            trusted_agent_keys: vec![],
+            tool_timeout_secs: None,
+            tool_timeouts: Default::default(),
+            max_concurrent_tool_calls: 4,
+            max_tool_calls_per_turn: 0,
+            max_tool_calls_per_session: 0,
+            max_tool_rounds: 100,
+            max_tool_call_repeats: 2,
+            tool_call_repeat_chain_len: 3,
+            tool_output_limit: DEFAULT_TOOL_OUTPUT_LIMIT,
+            summarize_tool_output: false,
+            stream_tool_output: true,
+            expand_tool_calls: false,
+            tool_cache_ttl_secs: DEFAULT_TOOL_CACHE_TTL_SECS,
+            tools: Default::default(),
 
             mcp_server_support: true,
             mapping_mcp_servers: Default::default(),
@@ -256,10 +412,17 @@ impl Default for Config {
             agent_session: None,
 
             save_session: None,
+            encrypt_sessions: false,
+            session_autosave_messages: 1,
+            session_autosave_secs: 0,
             compression_threshold: 4000,
             summarization_prompt: None,
             summary_context_prompt: None,
 
+            memory: false,
+            memory_top_k: 5,
+            memory_extractor_model: None,
+
             rag_embedding_model: None,
             rag_reranker_model: None,
             rag_top_k: 5,
@@ -275,7 +438,10 @@ impl Default for Config {
             right_prompt: None,
 
             user_agent: None,
+            connect_timeout_secs: DEFAULT_CONNECT_TIMEOUT_SECS,
+            read_timeout_secs: None,
             save_shell_history: true,
+            notify_after_secs: None,
             sync_models_url: None,
 
             clients: vec![],
@@ -283,6 +449,7 @@ impl Default for Config {
             vault: Default::default(),
 
             macro_flag: false,
+            batch_flag: false,
             info_flag: false,
             agent_variables: None,
 
@@ -291,12 +458,23 @@ impl Default for Config {
             mcp_registry: Default::default(),
             working_mode: WorkingMode::Cmd,
             last_message: None,
+            previous_message: None,
+            alternatives: vec![],
+            shell_output: None,
+            pasted_images: vec![],
 
             role: None,
             session: None,
+            session_autosave: None,
             rag: None,
             agent: None,
             tool_call_tracker: Some(ToolCallTracker::default()),
+            approved_tools: HashSet::new(),
+            tool_cache: Default::default(),
+            tool_calls: 0,
+            turn_tool_calls: 0,
+            turn_tool_rounds: 0,
+            injected_secrets: Default::default(),
             supervisor: None,
             parent_supervisor: None,
             self_agent_id: None,
@@ -384,11 +562,13 @@ impl Config {
             Agent::install_builtin_agents()?;
 
             config.load_envs();
+            config.reset_tool_call_tracker();
 
             if let Some(wrap) = config.wrap.clone() {
                 config.set_wrap(&wrap)?;
             }
 
+            config.setup_spinner()?;
             config.load_functions()?;
             config
                 .load_mcp_servers(log_path, start_mcp_servers, abort_signal)
@@ -422,12 +602,29 @@ impl Config {
         Self::config_dir().join(name)
     }
 
+    /// The `.loki` directory of the project Loki is run in, found in the current directory or
+    /// the closest of its ancestors.
+    pub fn project_dir() -> Option<PathBuf> {
+        PROJECT_DIR.clone()
+    }
+
+    /// A file or directory in the project's `.loki` directory, if it's there.
+    pub fn project_path(name: &str) -> Option<PathBuf> {
+        Self::project_dir()
+            .map(|dir| dir.join(name))
+            .filter(|path| path.exists())
+    }
+
     pub fn cache_path() -> PathBuf {
         let base_dir = dirs::cache_dir().unwrap_or_else(env::temp_dir);
 
         base_dir.join(env!("CARGO_CRATE_NAME"))
     }
 
+    pub fn python_venvs_dir() -> PathBuf {
+        Self::cache_path().join("venvs")
+    }
+
     pub fn oauth_tokens_path() -> PathBuf {
         Self::cache_path().join("oauth")
     }
@@ -436,6 +633,10 @@ impl Config {
         Self::oauth_tokens_path().join(format!("{client_name}_oauth_tokens.json"))
     }
 
+    pub fn repl_history_file() -> PathBuf {
+        Self::cache_path().join(REPL_HISTORY_FILE_NAME)
+    }
+
     pub fn log_path() -> PathBuf {
         Config::cache_path().join(format!("{}.log", env!("CARGO_CRATE_NAME")))
     }
@@ -464,8 +665,13 @@ impl Config {
         }
     }
 
+    /// The file a role is defined in: the project's, when it has a role by that name, or else
+    /// the global one.
     pub fn role_file(name: &str) -> PathBuf {
-        Self::roles_dir().join(format!("{name}.md"))
+        Self::project_path(ROLES_DIR_NAME)
+            .map(|dir| dir.join(format!("{name}.md")))
+            .filter(|path| path.exists())
+            .unwrap_or_else(|| Self::roles_dir().join(format!("{name}.md")))
     }
 
     pub fn macros_dir() -> PathBuf {
@@ -475,6 +681,13 @@ impl Config {
         }
     }
 
+    pub fn templates_dir() -> PathBuf {
+        match env::var(get_env_name("templates_dir")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(TEMPLATES_DIR_NAME),
+        }
+    }
+
     pub fn macro_file(name: &str) -> PathBuf {
         Self::macros_dir().join(format!("{name}.yaml"))
     }
@@ -505,10 +718,33 @@ impl Config {
                 Ok(value) => PathBuf::from(value),
                 Err(_) => Self::local_path(SESSIONS_DIR_NAME),
             },
-            Some(agent) => Self::agent_data_dir(agent.name()).join(SESSIONS_DIR_NAME),
+            Some(agent) => Self::agent_sessions_dir(agent.name()),
         }
     }
 
+    pub fn agent_sessions_dir(name: &str) -> PathBuf {
+        Self::agent_data_dir(name).join(SESSIONS_DIR_NAME)
+    }
+
+    /// The session in a sessions directory that was saved last, autonamed ones included. Since
+    /// sessions are saved as they're left, it's the one used last.
+    pub fn last_session(sessions_dir: &Path) -> Option<String> {
+        let autoname_sessions = list_file_names(sessions_dir.join("_"), ".yaml")
+            .into_iter()
+            .map(|name| format!("_/{name}"));
+        list_file_names(sessions_dir, ".yaml")
+            .into_iter()
+            .chain(autoname_sessions)
+            .filter(|name| name != TEMP_SESSION_NAME)
+            .filter_map(|name| {
+                let path = sessions_dir.join(format!("{name}.yaml"));
+                let modified = path.metadata().and_then(|v| v.modified()).ok()?;
+                Some((modified, name))
+            })
+            .max()
+            .map(|(_, name)| name)
+    }
+
     pub fn rags_dir() -> PathBuf {
         match env::var(get_env_name("rags_dir")) {
             Ok(value) => PathBuf::from(value),
@@ -516,6 +752,17 @@ impl Config {
         }
     }
 
+    pub fn themes_dir() -> PathBuf {
+        match env::var(get_env_name("themes_dir")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(THEMES_DIR_NAME),
+        }
+    }
+
+    pub fn theme_file(name: &str) -> PathBuf {
+        Self::themes_dir().join(format!("{name}.tmTheme"))
+    }
+
     pub fn functions_dir() -> PathBuf {
         match env::var(get_env_name("functions_dir")) {
             Ok(value) => PathBuf::from(value),
@@ -531,6 +778,20 @@ impl Config {
         Self::functions_dir().join(MCP_FILE_NAME)
     }
 
+    /// The project's `mcp.json`, whose servers are added to the global ones.
+    pub fn project_mcp_config_file() -> Option<PathBuf> {
+        Self::project_path(MCP_FILE_NAME)
+    }
+
+    /// The directory a global tool is in: the project's `functions/tools`, when it has the tool,
+    /// or else the global one.
+    pub fn tool_dir(tool: &str) -> PathBuf {
+        Self::project_path(FUNCTIONS_DIR_NAME)
+            .map(|dir| dir.join(GLOBAL_TOOLS_DIR_NAME))
+            .filter(|dir| dir.join(tool).exists())
+            .unwrap_or_else(Self::global_tools_dir)
+    }
+
     pub fn global_tools_dir() -> PathBuf {
         Self::functions_dir().join(GLOBAL_TOOLS_DIR_NAME)
     }
@@ -550,6 +811,32 @@ impl Config {
         }
     }
 
+    /// Reads a saved session or `messages.md`, decrypting it if it was encrypted.
+    pub fn read_session_file(&self, path: &Path) -> Result<String> {
+        let content =
+            read_to_string(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+        self.vault
+            .decrypt_text(&content)
+            .with_context(|| format!("Failed to decrypt '{}'", path.display()))
+    }
+
+    /// The vault to encrypt sessions with when they're saved, if `encrypt_sessions` is on.
+    pub fn session_vault(&self) -> Option<GlobalVault> {
+        self.encrypt_sessions.then(|| self.vault.clone())
+    }
+
+    /// The append-only log of tool calls made in the active session, or outside of any session.
+    pub fn tool_calls_file(&self) -> PathBuf {
+        let name = self
+            .session
+            .as_ref()
+            .map(|session| session.name())
+            .unwrap_or(DEFAULT_TOOL_CALLS_LOG_NAME);
+        self.sessions_dir()
+            .join(TOOL_CALLS_DIR_NAME)
+            .join(format!("{name}.jsonl"))
+    }
+
     pub fn rag_file(&self, name: &str) -> PathBuf {
         match &self.agent {
             Some(agent) => Self::agent_rag_file(agent.name(), name),
@@ -579,12 +866,46 @@ impl Config {
         Self::agent_data_dir(name).join(FUNCTIONS_BIN_DIR_NAME)
     }
 
+    /// The global memory store, shared by all sessions and agents.
+    pub fn memory_file() -> PathBuf {
+        Self::local_path(MEMORY_FILE_NAME)
+    }
+
+    pub fn agent_memory_file(name: &str) -> PathBuf {
+        Self::agent_data_dir(name).join(MEMORY_FILE_NAME)
+    }
+
+    pub fn agent_usage_file(name: &str) -> PathBuf {
+        Self::agent_data_dir(name).join(USAGE_FILE_NAME)
+    }
+
+    pub fn agent_history_file(name: &str) -> PathBuf {
+        Self::agent_data_dir(name).join(HISTORY_FILE_NAME)
+    }
+
+    pub fn agent_tests_file(name: &str) -> PathBuf {
+        Self::agent_data_dir(name).join(AGENT_TESTS_FILE_NAME)
+    }
+
+    pub fn agent_version_file(name: &str) -> PathBuf {
+        Self::agent_data_dir(name).join(AGENT_VERSION_FILE_NAME)
+    }
+
+    pub fn agent_todo_file(agent_name: &str, session_name: Option<&str>) -> PathBuf {
+        Self::agent_data_dir(agent_name)
+            .join(TODOS_DIR_NAME)
+            .join(format!(
+                "{}.yaml",
+                session_name.unwrap_or(DEFAULT_TODO_LIST_NAME)
+            ))
+    }
+
     pub fn agent_rag_file(agent_name: &str, rag_name: &str) -> PathBuf {
         Self::agent_data_dir(agent_name).join(format!("{rag_name}.yaml"))
     }
 
     pub fn agent_functions_file(name: &str) -> Result<PathBuf> {
-        let allowed = ["tools.sh", "tools.py", "tools.js"];
+        let allowed = ["tools.sh", "tools.py", "tools.js", "tools.ts", "tools.ps1"];
 
         for entry in read_dir(Self::agent_data_dir(name))? {
             let entry = entry?;
@@ -751,26 +1072,95 @@ impl Config {
                     .unwrap_or_else(|| "null".into()),
             ),
             ("save_session", format_option_value(&self.save_session)),
+            ("encrypt_sessions", self.encrypt_sessions.to_string()),
+            (
+                "session_autosave_messages",
+                self.session_autosave_messages.to_string(),
+            ),
+            (
+                "session_autosave_secs",
+                self.session_autosave_secs.to_string(),
+            ),
             (
                 "compression_threshold",
                 self.compression_threshold.to_string(),
             ),
+            ("memory", self.memory.to_string()),
+            ("memory_top_k", self.memory_top_k.to_string()),
+            (
+                "memory_extractor_model",
+                format_option_value(&self.memory_extractor_model),
+            ),
             (
                 "rag_reranker_model",
                 format_option_value(&rag_reranker_model),
             ),
             ("rag_top_k", rag_top_k.to_string()),
             ("dry_run", self.dry_run.to_string()),
+            ("dry_run_tools", self.dry_run_tools.to_string()),
             (
                 "function_calling_support",
                 self.function_calling_support.to_string(),
             ),
+            (
+                "tool_timeout_secs",
+                format_option_value(&self.tool_timeout_secs),
+            ),
+            (
+                "connect_timeout_secs",
+                self.connect_timeout_secs.to_string(),
+            ),
+            (
+                "read_timeout_secs",
+                format_option_value(&self.read_timeout_secs),
+            ),
+            (
+                "max_concurrent_tool_calls",
+                self.max_concurrent_tool_calls.to_string(),
+            ),
+            (
+                "max_tool_calls_per_turn",
+                self.max_tool_calls_per_turn.to_string(),
+            ),
+            (
+                "max_tool_calls_per_session",
+                self.max_tool_calls_per_session.to_string(),
+            ),
+            ("max_tool_rounds", self.max_tool_rounds.to_string()),
+            (
+                "max_tool_call_repeats",
+                self.max_tool_call_repeats.to_string(),
+            ),
+            (
+                "tool_call_repeat_chain_len",
+                self.tool_call_repeat_chain_len.to_string(),
+            ),
+            ("tool_output_limit", self.tool_output_limit.to_string()),
+            (
+                "summarize_tool_output",
+                self.summarize_tool_output.to_string(),
+            ),
+            ("stream_tool_output", self.stream_tool_output.to_string()),
+            ("expand_tool_calls", self.expand_tool_calls.to_string()),
+            ("tool_cache_ttl_secs", self.tool_cache_ttl_secs.to_string()),
             ("mcp_server_support", self.mcp_server_support.to_string()),
             ("stream", self.stream.to_string()),
             ("save", self.save.to_string()),
+            (
+                "notify_after_secs",
+                format_option_value(&self.notify_after_secs),
+            ),
             ("keybindings", self.keybindings.clone()),
+            ("autosuggest", self.autosuggest.to_string()),
+            ("spinner", self.spinner.to_string()),
+            ("spinner_style", self.spinner_style.clone()),
+            ("spinner_text", self.spinner_text.to_string()),
+            ("inline_images", self.inline_images.clone()),
+            ("link_references", self.link_references.to_string()),
             ("wrap", wrap),
             ("wrap_code", self.wrap_code.to_string()),
+            ("code_line_numbers", self.code_line_numbers.to_string()),
+            ("code_labels", self.code_labels.to_string()),
             ("highlight", self.highlight.to_string()),
             ("theme", format_option_value(&self.theme)),
             ("config_file", display_path(&Self::config_file())),
@@ -780,13 +1170,18 @@ impl Config {
             ("sessions_dir", display_path(&self.sessions_dir())),
             ("rags_dir", display_path(&Self::rags_dir())),
             ("macros_dir", display_path(&Self::macros_dir())),
+            ("templates_dir", display_path(&Self::templates_dir())),
             ("functions_dir", display_path(&Self::functions_dir())),
             ("messages_file", display_path(&self.messages_file())),
+            ("memory_file", display_path(&Self::memory_file())),
             (
                 "vault_password_file",
                 display_path(&self.vault_password_file()),
             ),
         ];
+        if let Some(project_dir) = Self::project_dir() {
+            items.push(("project_dir", display_path(&project_dir)));
+        }
         if let Ok((_, Some(log_path))) = Self::log_config() {
             items.push(("log_path", display_path(&log_path)));
         }
@@ -889,6 +1284,18 @@ impl Config {
                 let value = value.parse().with_context(|| "Invalid value")?;
                 config.write().dry_run = value;
             }
+            "dry_run_tools" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().dry_run_tools = value;
+            }
+            "stream_tool_output" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().stream_tool_output = value;
+            }
+            "expand_tool_calls" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().expand_tool_calls = value;
+            }
             "function_calling_support" => {
                 let value = value.parse().with_context(|| "Invalid value")?;
                 if value && config.write().functions.is_empty() {
@@ -896,6 +1303,46 @@ impl Config {
                 }
                 config.write().function_calling_support = value;
             }
+            "tool_timeout_secs" => {
+                let value = parse_value(value)?;
+                config.write().tool_timeout_secs = value;
+            }
+            "connect_timeout_secs" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().connect_timeout_secs = value;
+            }
+            "read_timeout_secs" => {
+                let value = parse_value(value)?;
+                config.write().read_timeout_secs = value;
+            }
+            "notify_after_secs" => {
+                let value = parse_value(value)?;
+                config.write().notify_after_secs = value;
+            }
+            "max_tool_calls_per_turn" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().max_tool_calls_per_turn = value;
+            }
+            "max_tool_calls_per_session" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().max_tool_calls_per_session = value;
+            }
+            "max_tool_rounds" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().max_tool_rounds = value;
+            }
+            "max_tool_call_repeats" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                let mut config = config.write();
+                config.max_tool_call_repeats = value;
+                config.reset_tool_call_tracker();
+            }
+            "tool_call_repeat_chain_len" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                let mut config = config.write();
+                config.tool_call_repeat_chain_len = value;
+                config.reset_tool_call_tracker();
+            }
             "mcp_server_support" => {
                 let value = value.parse().with_context(|| "Invalid value")?;
                 config.write().functions.clear_mcp_meta_functions();
@@ -934,6 +1381,41 @@ impl Config {
                 let value = value.parse().with_context(|| "Invalid value")?;
                 config.write().highlight = value;
             }
+            "code_line_numbers" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().code_line_numbers = value;
+            }
+            "code_labels" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().code_labels = value;
+            }
+            "autosuggest" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().autosuggest = value;
+            }
+            "spinner" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().spinner = value;
+                config.read().setup_spinner()?;
+            }
+            "spinner_style" => {
+                let mut config = config.write();
+                configure_spinner(config.spinner, value, config.spinner_text)?;
+                config.spinner_style = value.to_string();
+            }
+            "spinner_text" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().spinner_text = value;
+                config.read().setup_spinner()?;
+            }
+            "inline_images" => {
+                ImageProtocol::from_setting(value)?;
+                config.write().inline_images = value.to_string();
+            }
+            "link_references" => {
+                let value = value.parse().with_context(|| "Invalid value")?;
+                config.write().link_references = value;
+            }
             _ => bail!("Unknown key '{key}'"),
         }
         Ok(())
@@ -1044,6 +1526,28 @@ impl Config {
         }
     }
 
+    /// How long a tool may run before it's killed: its entry in `tool_timeouts`, falling back to
+    /// `tool_timeout_secs`. A timeout of 0 means no limit.
+    pub fn tool_timeout(&self, name: &str) -> Option<Duration> {
+        self.tool_timeouts
+            .get(name)
+            .copied()
+            .or(self.tool_timeout_secs)
+            .filter(|v| *v > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// How long the results of a `cacheable` tool are reused: its `cache_ttl_secs` in `tools`,
+    /// falling back to `tool_cache_ttl_secs`. A TTL of 0 turns caching off.
+    pub fn tool_cache_ttl(&self, name: &str) -> Option<Duration> {
+        let ttl = self
+            .tools
+            .get(name)
+            .and_then(|tool_config| tool_config.cache_ttl_secs)
+            .unwrap_or(self.tool_cache_ttl_secs);
+        (ttl > 0).then(|| Duration::from_secs(ttl))
+    }
+
     pub fn set_compression_threshold(&mut self, value: Option<usize>) {
         if let Some(session) = self.session.as_mut() {
             session.set_compression_threshold(value);
@@ -1329,14 +1833,17 @@ impl Config {
 
     pub fn list_roles(with_builtin: bool) -> Vec<String> {
         let mut names = HashSet::new();
-        if let Ok(rd) = read_dir(Self::roles_dir()) {
-            for entry in rd.flatten() {
-                if let Some(name) = entry
-                    .file_name()
-                    .to_str()
-                    .and_then(|v| v.strip_suffix(".md"))
-                {
-                    names.insert(name.to_string());
+        let dirs = [Some(Self::roles_dir()), Self::project_path(ROLES_DIR_NAME)];
+        for dir in dirs.into_iter().flatten() {
+            if let Ok(rd) = read_dir(dir) {
+                for entry in rd.flatten() {
+                    if let Some(name) = entry
+                        .file_name()
+                        .to_str()
+                        .and_then(|v| v.strip_suffix(".md"))
+                    {
+                        names.insert(name.to_string());
+                    }
                 }
             }
         }
@@ -1392,6 +1899,20 @@ impl Config {
                         format!("Failed to cleanup previous '{TEMP_SESSION_NAME}' session")
                     })?;
                 }
+                if let Some(agent) = &self.agent {
+                    let todo_file = Self::agent_todo_file(agent.name(), Some(TEMP_SESSION_NAME));
+                    if todo_file.exists() {
+                        remove_file(todo_file).with_context(|| {
+                            format!("Failed to cleanup previous '{TEMP_SESSION_NAME}' todo list")
+                        })?;
+                    }
+                    if !agent.budget().is_empty() {
+                        UsageLedger::reset_session(
+                            &Self::agent_usage_file(agent.name()),
+                            TEMP_SESSION_NAME,
+                        )?;
+                    }
+                }
                 session = Some(Session::new(self, TEMP_SESSION_NAME));
             }
             Some(name) => {
@@ -1454,6 +1975,9 @@ impl Config {
                 }
             }
         }
+        if let (Some(agent), Some(session)) = (self.agent.as_mut(), session.as_ref()) {
+            agent.load_todo_list(Some(session.name()))?;
+        }
         self.session = session;
         self.init_agent_session_variables(new_session)?;
         Ok(())
@@ -1484,9 +2008,123 @@ impl Config {
             session.exit(&sessions_dir, self.working_mode.is_repl())?;
             self.discontinuous_last_message();
         }
+        // Agents run by a supervisor share the autosave and temp files of the config they were
+        // cloned from
+        if self.current_depth == 0 {
+            if let Some(autosave) = self.session_autosave.take() {
+                autosave.remove();
+            }
+            remove_kept_temp_files();
+        }
         Ok(())
     }
 
+    /// Whether the user chose to always allow the given tool. Decisions are
+    /// remembered for the current session, or for the process when no session
+    /// is active.
+    pub fn is_tool_approved(&self, name: &str) -> bool {
+        match &self.session {
+            Some(session) => session.is_tool_approved(name),
+            None => self.approved_tools.contains(name),
+        }
+    }
+
+    pub fn cached_tool_result(
+        &self,
+        name: &str,
+        arguments: &Value,
+        ttl: Duration,
+    ) -> Option<Value> {
+        match &self.session {
+            Some(session) => session.tool_cache().get(name, arguments, ttl),
+            None => self.tool_cache.get(name, arguments, ttl),
+        }
+    }
+
+    pub fn cache_tool_result(
+        &mut self,
+        name: &str,
+        arguments: &Value,
+        output: Value,
+        ttl: Duration,
+    ) {
+        match self.session.as_mut() {
+            Some(session) => session
+                .tool_cache_mut()
+                .insert(name, arguments, output, ttl),
+            None => self.tool_cache.insert(name, arguments, output, ttl),
+        }
+    }
+
+    /// Whether a tool call can stop to ask the user something, e.g. to approve it. Only the root
+    /// agent can, in a terminal, and never in a batch, whose prompts run at the same time.
+    pub fn can_ask_user(&self) -> bool {
+        *IS_STDOUT_TERMINAL && self.current_depth == 0 && !self.batch_flag
+    }
+
+    fn reset_turn_tool_counters(&mut self) {
+        self.turn_tool_calls = 0;
+        self.turn_tool_rounds = 0;
+    }
+
+    /// Starts loop detection afresh, with the current `max_tool_call_repeats` and
+    /// `tool_call_repeat_chain_len`. Setting `max_tool_call_repeats` to 0 turns it off.
+    pub fn reset_tool_call_tracker(&mut self) {
+        self.tool_call_tracker = (self.max_tool_call_repeats > 0).then(|| {
+            ToolCallTracker::new(
+                self.max_tool_call_repeats,
+                self.tool_call_repeat_chain_len.max(1),
+            )
+        });
+    }
+
+    /// Counts a round of tool calls (all the calls from one response) against
+    /// `max_tool_rounds`. Returns how many rounds over the limit this one is, if it's over.
+    pub fn count_tool_round(&mut self) -> Option<usize> {
+        self.turn_tool_rounds += 1;
+        match self.max_tool_rounds {
+            0 => None,
+            max => self.turn_tool_rounds.checked_sub(max).filter(|v| *v > 0),
+        }
+    }
+
+    /// Counts a tool call against `max_tool_calls_per_turn` and `max_tool_calls_per_session`.
+    /// Returns the message for the model instead, if the call would go over either limit.
+    pub fn count_tool_call(&mut self) -> Option<String> {
+        let session_calls = match &self.session {
+            Some(session) => session.tool_calls(),
+            None => self.tool_calls,
+        };
+        let max_per_turn = self.max_tool_calls_per_turn;
+        if max_per_turn > 0 && self.turn_tool_calls >= max_per_turn {
+            return Some(format!(
+                "Tool call quota exceeded: all {max_per_turn} tool calls allowed per turn have been used. Please don't call any more tools; answer with what you have so far."
+            ));
+        }
+        let max_per_session = self.max_tool_calls_per_session;
+        if max_per_session > 0 && session_calls >= max_per_session {
+            return Some(format!(
+                "Tool call quota exceeded: all {max_per_session} tool calls allowed per session have been used. Please don't call any more tools; answer with what you have so far, and let the user know they can start a new session to continue."
+            ));
+        }
+
+        self.turn_tool_calls += 1;
+        match self.session.as_mut() {
+            Some(session) => session.count_tool_call(),
+            None => self.tool_calls += 1,
+        }
+        None
+    }
+
+    pub fn approve_tool(&mut self, name: &str) {
+        match self.session.as_mut() {
+            Some(session) => session.approve_tool(name),
+            None => {
+                self.approved_tools.insert(name.to_string());
+            }
+        }
+    }
+
     pub fn save_session(&mut self, name: Option<&str>) -> Result<()> {
         let session_name = match &self.session {
             Some(session) => match name {
@@ -1505,6 +2143,139 @@ impl Config {
         Ok(())
     }
 
+    /// Writes the active session to a document, by default `<session>-<timestamp>.<ext>` in the
+    /// current directory, and returns where it went.
+    pub fn export_session(&self, format: ExportFormat, path: Option<&str>) -> Result<PathBuf> {
+        let Some(session) = &self.session else {
+            bail!("No session")
+        };
+        let sources = self.rag.as_ref().and_then(|rag| rag.get_last_sources());
+        let contents = self.render_session(session, sources.as_deref(), format)?;
+        let path = match path {
+            Some(path) => PathBuf::from(resolve_home_dir(path)),
+            None => {
+                let name = session
+                    .autoname()
+                    .unwrap_or_else(|| session.name())
+                    .replace('/', "-");
+                let timestamp = chrono::Local::now().format("%Y%m%dT%H%M%S");
+                PathBuf::from(format!("{name}-{timestamp}.{}", format.extension()))
+            }
+        };
+        if let Some(parent) = path.parent().filter(|v| !v.as_os_str().is_empty()) {
+            create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)
+            .with_context(|| format!("Failed to export the session to '{}'", path.display()))?;
+        Ok(path)
+    }
+
+    /// Renders a saved session as a document without opening it, for `--export-session`.
+    pub fn export_saved_session(&self, name: &str, format: ExportFormat) -> Result<String> {
+        let session_path = self.session_file(name);
+        if !session_path.exists() {
+            bail!("No session named '{name}'")
+        }
+        let session = Session::load(self, name, &session_path)?;
+        self.render_session(&session, None, format)
+    }
+
+    /// Saves a session from a JSON export, or a copy of a session file, to the sessions
+    /// directory under the name it was exported with, or else the file's name. Returns the name.
+    pub fn import_session(&self, path: &Path) -> Result<String> {
+        let err = || format!("Failed to import the session at '{}'", path.display());
+        let content = self.read_session_file(path).with_context(err)?;
+        let (name, mut session) = Session::import(&content).with_context(err)?;
+        session.set_vault(self.session_vault());
+        let Some(name) = name
+            .filter(|v| v != TEMP_SESSION_NAME)
+            .or_else(|| path.file_stem()?.to_str().map(|v| v.to_string()))
+        else {
+            bail!(
+                "Cannot tell what to name the session at '{}'",
+                path.display()
+            )
+        };
+        Session::check_import_name(&name).with_context(err)?;
+        let session_path = self.session_file(&name);
+        if session_path.exists() {
+            bail!("A session named '{name}' already exists; delete it or rename the file to import")
+        }
+        session.save(&name, &session_path, false)?;
+        Ok(name)
+    }
+
+    fn render_session(
+        &self,
+        session: &Session,
+        sources: Option<&str>,
+        format: ExportFormat,
+    ) -> Result<String> {
+        let theme = match format {
+            ExportFormat::Html => Some(Self::load_theme(self.theme().unwrap_or("dark"))?),
+            _ => None,
+        };
+        transcript::export_session(session, sources, format, theme)
+    }
+
+    /// Forks the active session at this point into a new saved session and switches to it. The
+    /// original is saved first, so it can be picked up again where the branch left it.
+    pub fn branch_session(&mut self, name: &str) -> Result<()> {
+        let Some(session) = &self.session else {
+            bail!("No session")
+        };
+        if name == TEMP_SESSION_NAME {
+            bail!("The session name '{name}' is reserved")
+        }
+        if session.name() == TEMP_SESSION_NAME {
+            bail!("Save this session with `.save session <name>` before branching from it")
+        }
+        let branch_path = self.session_file(name);
+        if branch_path.exists() {
+            bail!("Session '{name}' already exists")
+        }
+        let mut branch = session.branch(name);
+        let session_name = session.name().to_string();
+        let session_path = session
+            .path()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.session_file(&session_name));
+        let is_repl = self.working_mode.is_repl();
+        if let Some(session) = self.session.as_mut()
+            && session.dirty()
+        {
+            session.save(&session_name, &session_path, is_repl)?;
+        }
+        branch.save(name, &branch_path, is_repl)?;
+        self.session = Some(branch);
+        Ok(())
+    }
+
+    /// Pins or unpins the session's nth message (counting from 1); unpinning without a number
+    /// unpins them all.
+    pub fn pin_session_message(&mut self, n: Option<usize>, pinned: bool) -> Result<()> {
+        let Some(session) = self.session.as_mut() else {
+            bail!("No session")
+        };
+        let index = match n {
+            Some(0) => bail!("Messages are numbered from 1"),
+            n => n.map(|n| n - 1),
+        };
+        match (index, pinned) {
+            (Some(index), true) => session.pin_message(index),
+            (None, true) => bail!("Which message to pin?"),
+            (index, false) => session.unpin_message(index),
+        }
+    }
+
+    pub fn set_session_prompt(&mut self, prompt: Option<&str>) -> Result<()> {
+        match self.session.as_mut() {
+            Some(session) => session.set_extra_prompt(prompt.map(String::from)),
+            None => bail!("No session"),
+        }
+        Ok(())
+    }
+
     pub fn edit_session(&mut self) -> Result<()> {
         let name = match &self.session {
             Some(session) => session.name().to_string(),
@@ -1513,13 +2284,27 @@ impl Config {
         let session_path = self.session_file(&name);
         self.save_session(Some(&name))?;
         let editor = self.editor()?;
-        edit_file(&editor, &session_path).with_context(|| {
-            format!(
-                "Failed to edit '{}' with '{editor}'",
-                session_path.display()
-            )
-        })?;
-        self.session = Some(Session::load(self, &name, &session_path)?);
+        // An encrypted session is edited as a plain copy, which is encrypted again once saved
+        let edit_path = match self.encrypt_sessions {
+            true => write_private_temp_file(
+                &format!("{}.yaml", name.replace('/', "-")),
+                &self.read_session_file(&session_path)?,
+            )?,
+            false => session_path.clone(),
+        };
+        let ret = edit_file(&editor, &edit_path)
+            .with_context(|| format!("Failed to edit '{}' with '{editor}'", edit_path.display()))
+            .and_then(|_| Session::load(self, &name, &edit_path));
+        if edit_path != session_path
+            && let Some(edit_dir) = edit_path.parent()
+        {
+            let _ = remove_dir_all(edit_dir);
+        }
+        let mut session = ret?;
+        if edit_path != session_path {
+            session.save(&name, &session_path, false)?;
+        }
+        self.session = Some(session);
         self.discontinuous_last_message();
         Ok(())
     }
@@ -1537,6 +2322,19 @@ impl Config {
         Ok(())
     }
 
+    pub fn undo_session(&mut self) -> Result<()> {
+        match self.session.as_mut() {
+            Some(session) => {
+                if !session.undo_last_exchange() {
+                    bail!("No messages to undo")
+                }
+            }
+            None => bail!("No session"),
+        }
+        self.discontinuous_last_message();
+        Ok(())
+    }
+
     pub fn set_save_session_this_time(&mut self) -> Result<()> {
         if let Some(session) = self.session.as_mut() {
             session.set_save_session_this_time();
@@ -1589,6 +2387,14 @@ impl Config {
     }
 
     pub async fn compress_session(config: &GlobalConfig) -> Result<()> {
+        let summary = Self::summarize_session(config).await?;
+        Self::apply_session_summary(config, &summary);
+        Ok(())
+    }
+
+    /// Asks the model to summarize the session, using the `summarization_prompt`, without
+    /// changing the session yet.
+    pub async fn summarize_session(config: &GlobalConfig) -> Result<String> {
         match config.read().session.as_ref() {
             Some(session) => {
                 if !session.has_user_messages() {
@@ -1604,7 +2410,11 @@ impl Config {
             .clone()
             .unwrap_or_else(|| SUMMARIZATION_PROMPT.into());
         let input = Input::from_str(config, &prompt, None);
-        let summary = input.fetch_chat_text().await?;
+        input.fetch_chat_text().await
+    }
+
+    /// Replaces the session's history with a summary of it.
+    pub fn apply_session_summary(config: &GlobalConfig, summary: &str) {
         let summary_context_prompt = config
             .read()
             .summary_context_prompt
@@ -1624,7 +2434,6 @@ impl Config {
             session.compress(format!("{todo_prefix}{summary_context_prompt}{summary}"));
         }
         config.write().discontinuous_last_message();
-        Ok(())
     }
 
     pub fn is_compressing_session(&self) -> bool {
@@ -1661,6 +2470,30 @@ impl Config {
         });
     }
 
+    /// Has the `memory_extractor_model` look for facts worth remembering in the last exchange,
+    /// in the background, when the global memory is on.
+    pub fn maybe_extract_memories(config: GlobalConfig) {
+        let (text, output) = {
+            let cfg = config.read();
+            if !cfg.memory || cfg.memory_extractor_model.is_none() {
+                return;
+            }
+            match &cfg.last_message {
+                Some(LastMessage { input, output, .. }) if !output.is_empty() => {
+                    (input.text(), output.clone())
+                }
+                _ => return,
+            }
+        };
+        tokio::spawn(async move {
+            match extract_memories(&config, &text, &output).await {
+                Ok(0) => {}
+                Ok(saved) => debug!("Saved {saved} memories extracted from the last exchange"),
+                Err(err) => warn!("Failed to extract memories: {err}"),
+            }
+        });
+    }
+
     pub async fn autoname_session(config: &GlobalConfig) -> Result<()> {
         let text = match config
             .read()
@@ -1696,7 +2529,7 @@ impl Config {
                         format!("Failed to cleanup previous '{TEMP_RAG_NAME}' rag")
                     })?;
                 }
-                Rag::init(config, TEMP_RAG_NAME, &rag_path, &[], abort_signal).await?
+                Rag::init(config, TEMP_RAG_NAME, &rag_path, &[], &[], abort_signal).await?
             }
             Some(name) => {
                 let rag_path = config.read().rag_file(name);
@@ -1704,7 +2537,7 @@ impl Config {
                     if config.read().working_mode.is_cmd() {
                         bail!("Unknown RAG '{name}'")
                     }
-                    Rag::init(config, name, &rag_path, &[], abort_signal).await?
+                    Rag::init(config, name, &rag_path, &[], &[], abort_signal).await?
                 } else {
                     Rag::load(config, name, &rag_path)?
                 }
@@ -1714,6 +2547,20 @@ impl Config {
         Ok(())
     }
 
+    /// Attaches an existing RAG to the current agent, e.g. from the agent's prelude.
+    pub fn attach_agent_rag(config: &GlobalConfig, name: &str) -> Result<()> {
+        if config.read().rag.is_some() {
+            bail!("Cannot attach RAG '{name}' because the agent already has its own RAG")
+        }
+        let rag_path = config.read().rag_file(name);
+        if !rag_path.exists() {
+            bail!("Unknown RAG '{name}'")
+        }
+        let rag = Rag::load(config, name, &rag_path)?;
+        config.write().rag = Some(Arc::new(rag));
+        Ok(())
+    }
+
     pub async fn edit_rag_docs(config: &GlobalConfig, abort_signal: AbortSignal) -> Result<()> {
         let mut rag = match config.read().rag.clone() {
             Some(v) => v.as_ref().clone(),
@@ -1841,7 +2688,7 @@ impl Config {
         if config.read().agent.is_some() {
             bail!("Already in an agent, please run '.exit agent' first to exit the current agent.");
         }
-        let agent = Agent::init(config, agent_name, abort_signal.clone()).await?;
+        let mut agent = Agent::init(config, agent_name, abort_signal.clone()).await?;
         if !agent.model().supports_function_calling() {
             eprintln!(
                 "Warning: The model '{}' does not support function calling. Agent tools (including todo, spawning, and user interaction) will not be available.",
@@ -1855,6 +2702,9 @@ impl Config {
                 agent.agent_session().map(|v| v.to_string())
             }
         });
+        if session.is_none() && config.read().current_depth == 0 {
+            agent.load_todo_list(None)?;
+        }
         let should_init_supervisor = agent.can_spawn_agents();
         let max_concurrent = agent.max_concurrent_agents();
         let max_depth = agent.max_agent_depth();
@@ -1931,6 +2781,7 @@ impl Config {
         self.exit_session()?;
         if let Some(agent) = self.agent.as_mut() {
             agent.exit_session();
+            agent.load_todo_list(None)?;
             if self.working_mode.is_repl() {
                 self.init_agent_shared_variables()?;
             }
@@ -1938,6 +2789,51 @@ impl Config {
         Ok(())
     }
 
+    pub fn list_themes() -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_THEMES.iter().map(|v| v.to_string()).collect();
+        names.extend(
+            list_file_names(Self::themes_dir(), ".tmTheme")
+                .into_iter()
+                .filter(|v| !BUILTIN_THEMES.contains(&v.as_str())),
+        );
+        names
+    }
+
+    /// Loads a theme by name. `dark` and `light` are the builtin themes, unless `dark.tmTheme` or
+    /// `light.tmTheme` in the config directory replaces them; any other name is a `.tmTheme` file
+    /// in the themes directory.
+    pub fn load_theme(name: &str) -> Result<Theme> {
+        let path = if BUILTIN_THEMES.contains(&name) {
+            let path = Self::local_path(&format!("{name}.tmTheme"));
+            if !path.exists() {
+                return if name == "light" {
+                    decode_bin(LIGHT_THEME).context("Invalid builtin light theme")
+                } else {
+                    decode_bin(DARK_THEME).context("Invalid builtin dark theme")
+                };
+            }
+            path
+        } else {
+            let path = Self::theme_file(name);
+            if !path.exists() {
+                bail!("Unknown theme '{name}'; add it as '{}'", path.display());
+            }
+            path
+        };
+        ThemeSet::get_theme(&path).with_context(|| format!("Invalid theme at '{}'", path.display()))
+    }
+
+    /// Switches the theme, and remembers it for the next time Loki starts with this config
+    /// directory.
+    pub fn switch_theme(&mut self, name: &str) -> Result<()> {
+        Self::load_theme(name)?;
+        self.theme = Some(name.to_string());
+        let path = Self::local_path(CURRENT_THEME_FILE_NAME);
+        ensure_parent_exists(&path)?;
+        write(&path, name)
+            .with_context(|| format!("Failed to save the theme to '{}'", path.display()))
+    }
+
     pub fn list_macros() -> Vec<String> {
         list_file_names(Self::macros_dir(), ".yaml")
     }
@@ -2048,20 +2944,25 @@ impl Config {
                     })
                     .map(|v| v.name.to_string())
                     .collect();
+                // Tools may be qualified with their namespace, e.g. `global:fs_read`
+                let resolve = |tool: &str| {
+                    self.functions
+                        .resolve(tool.trim())
+                        .map(|v| v.name.to_string())
+                        .filter(|v| declaration_names.contains(v))
+                };
+                let mut preset_functions = vec![];
                 if enabled_tools == "all" {
-                    tool_names.extend(declaration_names);
+                    tool_names.extend(declaration_names.iter().cloned());
                 } else {
                     for item in enabled_tools.split(',') {
                         let item = item.trim();
-                        if let Some(values) = self.mapping_tools.get(item) {
-                            tool_names.extend(
-                                values
-                                    .split(',')
-                                    .map(|v| v.to_string())
-                                    .filter(|v| declaration_names.contains(v)),
-                            )
-                        } else if declaration_names.contains(item) {
-                            tool_names.insert(item.to_string());
+                        if let Some((_, function)) = self.find_tool_preset(item) {
+                            preset_functions.push(function);
+                        } else if let Some(values) = self.mapping_tools.get(item) {
+                            tool_names.extend(values.split(',').filter_map(resolve))
+                        } else if let Some(name) = resolve(item) {
+                            tool_names.insert(name);
                         }
                     }
                 }
@@ -2077,6 +2978,7 @@ impl Config {
                         }
                     })
                     .collect();
+                functions.extend(preset_functions);
             }
 
             if self.agent.is_none() {
@@ -2105,16 +3007,9 @@ impl Config {
                             && !v.name.starts_with(MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX)
                     })
                     .collect();
-                let tool_names: HashSet<String> = agent_functions
-                    .iter()
-                    .filter_map(|v| {
-                        if v.agent {
-                            None
-                        } else {
-                            Some(v.name.to_string())
-                        }
-                    })
-                    .collect();
+                // The agent's own tools take precedence over global ones with the same name
+                let tool_names: HashSet<String> =
+                    agent_functions.iter().map(|v| v.name.to_string()).collect();
                 agent_functions.extend(
                     functions
                         .into_iter()
@@ -2127,6 +3022,34 @@ impl Config {
         functions
     }
 
+    /// The tool preset a `mapping_tools` alias stands for, if it binds a tool to fixed
+    /// arguments, along with the declaration the model sees for the alias.
+    pub fn find_tool_preset(&self, alias: &str) -> Option<(ToolPreset, FunctionDeclaration)> {
+        let value = self.mapping_tools.get(alias)?;
+        let preset = match ToolPreset::parse(value) {
+            Ok(preset) => preset?,
+            Err(err) => {
+                warn!("Ignoring the mapping_tools alias '{alias}': {err:#}");
+                return None;
+            }
+        };
+        let Some(function) = self.functions.resolve(&preset.tool) else {
+            warn!(
+                "Ignoring the mapping_tools alias '{alias}': the tool '{}' wasn't found",
+                preset.tool
+            );
+            return None;
+        };
+        let declaration = preset.declaration(alias, function);
+        Some((
+            ToolPreset {
+                tool: function.name.clone(),
+                ..preset
+            },
+            declaration,
+        ))
+    }
+
     fn select_enabled_mcp_servers(&self, role: &Role) -> Vec<FunctionDeclaration> {
         let mut mcp_functions = vec![];
         if self.mcp_server_support {
@@ -2259,7 +3182,12 @@ impl Config {
     ) -> Vec<(String, Option<String>)> {
         let mut values: Vec<(String, Option<String>)> = vec![];
         let filter = args.last().unwrap_or(&"");
-        if args.len() == 1 {
+        if cmd == ".file" {
+            // Everything after `--` is the prompt, not more paths
+            if !args.iter().rev().skip(1).any(|v| *v == "--") {
+                values = map_completion_values(complete_path(filter));
+            }
+        } else if args.len() == 1 {
             values = match cmd {
                 ".role" => map_completion_values(Self::list_roles(true)),
                 ".model" => list_models(self, ModelType::Chat)
@@ -2303,11 +3231,31 @@ impl Config {
                         "rag_top_k",
                         "max_output_tokens",
                         "dry_run",
+                        "dry_run_tools",
                         "function_calling_support",
+                        "tool_timeout_secs",
+                        "connect_timeout_secs",
+                        "read_timeout_secs",
+                        "max_tool_calls_per_turn",
+                        "max_tool_calls_per_session",
+                        "max_tool_rounds",
+                        "max_tool_call_repeats",
+                        "tool_call_repeat_chain_len",
+                        "stream_tool_output",
+                        "expand_tool_calls",
                         "mcp_server_support",
                         "stream",
                         "save",
+                        "notify_after_secs",
                         "highlight",
+                        "code_line_numbers",
+                        "code_labels",
+                        "autosuggest",
+                        "spinner",
+                        "spinner_style",
+                        "spinner_text",
+                        "inline_images",
+                        "link_references",
                     ];
                     values.sort_unstable();
                     values
@@ -2318,6 +3266,30 @@ impl Config {
                 ".delete" => {
                     map_completion_values(vec!["role", "session", "rag", "macro", "agent-data"])
                 }
+                ".export" => map_completion_values(vec!["md", "html", "json"]),
+                ".theme" => {
+                    let mut values = Self::list_themes();
+                    values.push("preview".into());
+                    map_completion_values(values)
+                }
+                ".todo" => map_completion_values(vec!["list", "add", "done"]),
+                ".regenerate" => map_completion_values(vec!["--model", "--temperature"]),
+                ".retry" => map_completion_values(vec!["-m"]),
+                ".copy" => {
+                    let blocks = self
+                        .last_message
+                        .as_ref()
+                        .map(|v| extract_code_blocks(&strip_think_tag(&v.output)))
+                        .unwrap_or_default();
+                    let mut values = vec![("code".to_string(), None)];
+                    values.extend(
+                        blocks
+                            .iter()
+                            .enumerate()
+                            .map(|(i, block)| ((i + 1).to_string(), Some(block.summary()))),
+                    );
+                    values
+                }
                 ".vault" => {
                     let mut values = vec!["add", "get", "update", "delete", "list"];
                     values.sort_unstable();
@@ -2335,8 +3307,16 @@ impl Config {
                     None => vec![],
                 },
                 "dry_run" => complete_bool(self.dry_run),
+                "dry_run_tools" => complete_bool(self.dry_run_tools),
                 "stream" => complete_bool(self.stream),
+                "stream_tool_output" => complete_bool(self.stream_tool_output),
+                "expand_tool_calls" => complete_bool(self.expand_tool_calls),
                 "save" => complete_bool(self.save),
+                "autosuggest" => complete_bool(self.autosuggest),
+                "spinner" => complete_bool(self.spinner),
+                "spinner_style" => SPINNER_STYLES.iter().map(|v| v.to_string()).collect(),
+                "spinner_text" => complete_bool(self.spinner_text),
+                "inline_images" => INLINE_IMAGE_MODES.iter().map(|v| v.to_string()).collect(),
                 "function_calling_support" => complete_bool(self.function_calling_support),
                 "enabled_tools" => {
                     let mut prefix = String::new();
@@ -2394,9 +3374,26 @@ impl Config {
                     .map(|v| v.id())
                     .collect(),
                 "highlight" => complete_bool(self.highlight),
+                "code_line_numbers" => complete_bool(self.code_line_numbers),
+                "code_labels" => complete_bool(self.code_labels),
+                "link_references" => complete_bool(self.link_references),
                 _ => vec![],
             };
             values = candidates.into_iter().map(|v| (v, None)).collect();
+        } else if cmd == ".regenerate" {
+            values = match args[args.len() - 2] {
+                "--model" => list_models(self, ModelType::Chat)
+                    .into_iter()
+                    .map(|v| (v.id(), Some(v.description())))
+                    .collect(),
+                "--temperature" => vec![],
+                _ => map_completion_values(vec!["--model", "--temperature"]),
+            };
+        } else if cmd == ".retry" && args.len() == 2 && args[0] == "-m" {
+            values = list_models(self, ModelType::Chat)
+                .into_iter()
+                .map(|v| (v.id(), Some(v.description())))
+                .collect();
         } else if cmd == ".vault" && args.len() == 2 {
             values = self
                 .vault
@@ -2407,7 +3404,7 @@ impl Config {
                 .collect();
         } else if cmd == ".agent" {
             if args.len() == 2 {
-                let dir = Self::agent_data_dir(args[0]).join(SESSIONS_DIR_NAME);
+                let dir = Self::agent_sessions_dir(args[0]);
                 values = list_file_names(dir, ".yaml")
                     .into_iter()
                     .map(|v| (v, None))
@@ -2462,27 +3459,25 @@ impl Config {
         Ok(models_override.list)
     }
 
+    /// The theme in use: the active session's, if it sets one, or else the global one.
+    pub fn theme(&self) -> Option<&str> {
+        self.session
+            .as_ref()
+            .and_then(|session| session.theme())
+            .or(self.theme.as_deref())
+    }
+
     pub fn light_theme(&self) -> bool {
-        matches!(self.theme.as_deref(), Some("light"))
+        match self.theme() {
+            Some("light") => true,
+            None | Some("dark") => false,
+            Some(name) => is_light_theme(name),
+        }
     }
 
     pub fn render_options(&self) -> Result<RenderOptions> {
         let theme = if self.highlight {
-            let theme_mode = if self.light_theme() { "light" } else { "dark" };
-            let theme_filename = format!("{theme_mode}.tmTheme");
-            let theme_path = Self::local_path(&theme_filename);
-            if theme_path.exists() {
-                let theme = ThemeSet::get_theme(&theme_path)
-                    .with_context(|| format!("Invalid theme at '{}'", theme_path.display()))?;
-                Some(theme)
-            } else {
-                let theme = if self.light_theme() {
-                    decode_bin(LIGHT_THEME).context("Invalid builtin light theme")?
-                } else {
-                    decode_bin(DARK_THEME).context("Invalid builtin dark theme")?
-                };
-                Some(theme)
-            }
+            Some(Self::load_theme(self.theme().unwrap_or("dark"))?)
         } else {
             None
         };
@@ -2495,18 +3490,35 @@ impl Config {
             env::var("COLORTERM").as_ref().map(|v| v.as_str()),
             Ok("truecolor")
         );
-        Ok(RenderOptions::new(theme, wrap, self.wrap_code, truecolor))
+        Ok(RenderOptions::new(
+            theme,
+            wrap,
+            self.wrap_code,
+            self.code_line_numbers,
+            self.code_labels,
+            truecolor,
+        ))
     }
 
     pub fn render_prompt_left(&self) -> String {
         let variables = self.generate_prompt_context();
-        let left_prompt = self.left_prompt.as_deref().unwrap_or(LEFT_PROMPT);
+        let left_prompt = self
+            .session
+            .as_ref()
+            .and_then(|session| session.left_prompt())
+            .or(self.left_prompt.as_deref())
+            .unwrap_or(LEFT_PROMPT);
         render_prompt(left_prompt, &variables)
     }
 
     pub fn render_prompt_right(&self) -> String {
         let variables = self.generate_prompt_context();
-        let right_prompt = self.right_prompt.as_deref().unwrap_or(RIGHT_PROMPT);
+        let right_prompt = self
+            .session
+            .as_ref()
+            .and_then(|session| session.right_prompt())
+            .or(self.right_prompt.as_deref())
+            .unwrap_or(RIGHT_PROMPT);
         render_prompt(right_prompt, &variables)
     }
 
@@ -2547,6 +3559,9 @@ impl Config {
         if self.dry_run {
             output.insert("dry_run", "true".to_string());
         }
+        if self.dry_run_tools {
+            output.insert("dry_run_tools", "true".to_string());
+        }
         if self.stream {
             output.insert("stream", "true".to_string());
         }
@@ -2604,11 +3619,194 @@ impl Config {
         output
     }
 
+    /// Refuses further completions once the active agent has exceeded its budget.
+    pub fn guard_agent_budget(&self) -> Result<()> {
+        let Some(agent) = &self.agent else {
+            return Ok(());
+        };
+        let budget = agent.budget();
+        if budget.is_empty() {
+            return Ok(());
+        }
+        let name = agent.name();
+        let ledger = UsageLedger::load(&Self::agent_usage_file(name))?;
+        if let Some(max_tokens) = budget.max_tokens_per_session {
+            let used = match &self.session {
+                Some(session) => ledger.session(session.name()).total_tokens(),
+                None => agent.session_usage().total_tokens(),
+            };
+            if used >= max_tokens {
+                bail!(
+                    "Agent '{name}' has used {used} of its {max_tokens} token budget for this session. Start a new session or raise 'budget.max_tokens_per_session' in the agent config to continue."
+                );
+            }
+        }
+        if let Some(max_cost) = budget.max_cost_per_day {
+            let spent = ledger.today().cost;
+            if spent >= max_cost {
+                bail!(
+                    "Agent '{name}' has spent ${spent:.2} of its ${max_cost:.2} budget for today. Try again tomorrow or raise 'budget.max_cost_per_day' in the agent config to continue."
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Switches the active agent to the next of its `model_fallbacks` after a completion failed
+    /// because the current model couldn't be reached. Fallbacks whose context window is too small
+    /// for the agent's instructions and conversation so far are skipped.
+    pub fn use_agent_fallback_model(
+        config: &GlobalConfig,
+        input: &Input,
+        err: &anyhow::Error,
+    ) -> Result<Option<Model>> {
+        if !input.with_agent() || !is_connection_error(err) {
+            return Ok(None);
+        }
+        let messages = input.build_messages()?;
+        let current_model_id = input.role().model().id();
+        loop {
+            let mut cfg = config.write();
+            let Some(model_id) = cfg
+                .agent
+                .as_mut()
+                .and_then(|agent| agent.next_fallback_model_id())
+            else {
+                return Ok(None);
+            };
+            let model = match Model::retrieve_model(&cfg, &model_id, ModelType::Chat) {
+                Ok(model) => model,
+                Err(err) => {
+                    warn!("Skipping fallback model '{model_id}': {err}");
+                    continue;
+                }
+            };
+            if model.guard_max_input_tokens(&messages).is_err() {
+                eprintln!(
+                    "{}",
+                    warning_text(&format!(
+                        "Skipping fallback model '{model_id}': the agent's instructions and conversation don't fit in its context window"
+                    ))
+                );
+                continue;
+            }
+            eprintln!(
+                "{}",
+                warning_text(&format!(
+                    "Model '{current_model_id}' is unreachable; falling back to '{model_id}'"
+                ))
+            );
+            if let Some(role_like) = cfg.role_like_mut() {
+                role_like.set_model(model.clone());
+            }
+            return Ok(Some(model));
+        }
+    }
+
+    pub fn record_agent_usage(&mut self, usage: &Usage, tool_calls: &[ToolCall]) -> Result<()> {
+        let session_name = self.session.as_ref().map(|v| v.name().to_string());
+        let Some(agent) = self.agent.as_mut() else {
+            return Ok(());
+        };
+        if let Some(run) = agent.active_run_mut() {
+            run.add_usage(usage);
+            tool_calls.iter().for_each(|v| run.add_tool(&v.name));
+        }
+        if agent.budget().is_empty() {
+            return Ok(());
+        }
+        let usage_file = Self::agent_usage_file(agent.name());
+        let mut ledger = UsageLedger::load(&usage_file)?;
+        ledger.record(session_name.as_deref(), usage);
+        ledger.save(&usage_file)?;
+        if session_name.is_none() {
+            agent.add_session_usage(usage);
+        }
+        Ok(())
+    }
+
     pub fn before_chat_completion(&mut self, input: &Input) -> Result<()> {
+        let is_new_turn = input.tool_calls().is_none();
+        if is_new_turn && !input.regenerate() && input.continue_output().is_none() {
+            self.alternatives.clear();
+        }
+        if let Some(last_message) = self.last_message.take()
+            && !last_message.output.is_empty()
+        {
+            if is_new_turn && input.regenerate() {
+                self.alternatives.push(last_message.clone());
+            }
+            self.previous_message = Some(last_message);
+        }
         self.last_message = Some(LastMessage::new(input.clone(), String::new()));
+        // Follow-ups with tool results are part of the same turn
+        if is_new_turn {
+            self.reset_turn_tool_counters();
+        }
+        let session_name = self.session.as_ref().map(|v| v.name().to_string());
+        if let Some(agent) = self.agent.as_mut() {
+            agent.begin_run(&input.raw(), session_name.as_deref());
+        }
+        Ok(())
+    }
+
+    /// Makes one of the other answers to the last prompt the current one, in the session too, and
+    /// keeps the current answer in its place among the alternatives.
+    pub fn use_alternative(&mut self, index: usize) -> Result<()> {
+        if index >= self.alternatives.len() {
+            bail!("No alternative {}", index + 1)
+        }
+        let Some(current) = self.last_message.take() else {
+            bail!("No chat response")
+        };
+        // A retry that failed leaves nothing worth keeping
+        let chosen = if current.output.is_empty() {
+            self.alternatives.remove(index)
+        } else {
+            mem::replace(&mut self.alternatives[index], current)
+        };
+        if let Some(session) = chosen.input.session_mut(&mut self.session) {
+            session.replace_last_reply(&chosen.input, &chosen.output);
+        }
+        self.last_message = Some(chosen);
         Ok(())
     }
 
+    /// Appends the agent's run in progress, if any, to its history.
+    pub fn finish_agent_run(&mut self, error: Option<String>) {
+        let vault = self.session_vault();
+        let Some(agent) = self.agent.as_mut() else {
+            return;
+        };
+        let Some(run) = agent.take_run() else {
+            return;
+        };
+        let path = Self::agent_history_file(agent.name());
+        if let Err(err) = run.finish(error).append(&path, vault.as_deref()) {
+            warn!("Failed to record agent run: {err}");
+        }
+    }
+
+    pub fn agent_history(&self, limit: usize) -> Result<String> {
+        let Some(agent) = &self.agent else {
+            bail!("No agent")
+        };
+        let runs =
+            AgentRun::load_recent(&Self::agent_history_file(agent.name()), limit, &self.vault)?;
+        if runs.is_empty() {
+            return Ok(format!("No runs recorded for agent '{}'\n", agent.name()));
+        }
+        Ok(runs.iter().map(|v| format!("{}\n", v.render())).collect())
+    }
+
+    pub fn tool_calls(&self, limit: usize) -> Result<String> {
+        let calls = ToolCallRecord::load_recent(&self.tool_calls_file(), limit, &self.vault)?;
+        if calls.is_empty() {
+            return Ok("No tool calls recorded\n".to_string());
+        }
+        Ok(calls.iter().map(|v| format!("{}\n", v.render())).collect())
+    }
+
     pub fn after_chat_completion(
         &mut self,
         input: &Input,
@@ -2618,6 +3816,7 @@ impl Config {
         if !tool_results.is_empty() {
             return Ok(());
         }
+        self.finish_agent_run(None);
         self.last_message = Some(LastMessage::new(input.clone(), output.to_string()));
         if !self.dry_run {
             self.save_message(input, output)?;
@@ -2636,7 +3835,7 @@ impl Config {
         input.clear_patch();
         if let Some(session) = input.session_mut(&mut self.session) {
             session.add_message(&input, output)?;
-            return Ok(());
+            return self.autosave_session();
         }
 
         if !self.save {
@@ -2678,9 +3877,12 @@ impl Config {
             }
             None => String::new(),
         };
-        let output = format!(
+        let mut output = format!(
             "# CHAT: {summary} [{now}]{scope}\n{raw_input}\n--------\n{tool_calls}{output}\n--------\n\n",
         );
+        if self.encrypt_sessions {
+            output = format!("{}\n", self.vault.encrypt_text(&output)?);
+        }
         file.write_all(output.as_bytes())
             .with_context(|| "Failed to save message")
     }
@@ -2695,6 +3897,7 @@ impl Config {
                 agent.defined_variables(),
                 self.agent_variables.as_ref(),
                 self.info_flag,
+                &self.vault,
             )?;
             agent.set_shared_variables(new_variables);
         }
@@ -2717,6 +3920,7 @@ impl Config {
                         agent.defined_variables(),
                         self.agent_variables.as_ref(),
                         self.info_flag,
+                        &self.vault,
                     )?;
                     agent.set_shared_variables(new_variables.clone());
                     new_variables
@@ -2750,7 +3954,33 @@ impl Config {
 
     fn load_from_file(config_path: &Path) -> Result<(Self, String)> {
         let err = || format!("Failed to load config at '{}'", config_path.display());
-        let content = read_to_string(config_path).with_context(err)?;
+        let mut content = read_to_string(config_path).with_context(err)?;
+        if let Some(project_config_path) = Self::project_path(CONFIG_FILE_NAME) {
+            debug!(
+                "Overlaying the project config at '{}'",
+                project_config_path.display()
+            );
+            let project_err = || {
+                format!(
+                    "Failed to load project config at '{}'",
+                    project_config_path.display()
+                )
+            };
+            let project_content = read_to_string(&project_config_path).with_context(project_err)?;
+            let (merged, ignored) =
+                overlay_config(&content, &project_content).with_context(project_err)?;
+            if !ignored.is_empty() {
+                eprintln!(
+                    "{}",
+                    warning_text(&format!(
+                        "Ignoring settings the project config at '{}' can't change: {}",
+                        project_config_path.display(),
+                        ignored.join(", ")
+                    ))
+                );
+            }
+            content = merged;
+        }
         let config = Self::load_from_str(&content).with_context(err)?;
 
         Ok((config, content))
@@ -2819,6 +4049,9 @@ impl Config {
         if let Some(Some(v)) = read_env_bool(&get_env_name("dry_run")) {
             self.dry_run = v;
         }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("dry_run_tools")) {
+            self.dry_run_tools = v;
+        }
         if let Some(Some(v)) = read_env_bool(&get_env_name("stream")) {
             self.stream = v;
         }
@@ -2830,6 +4063,24 @@ impl Config {
         {
             self.keybindings = v;
         }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("autosuggest")) {
+            self.autosuggest = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("spinner")) {
+            self.spinner = v;
+        }
+        if let Ok(v) = env::var(get_env_name("spinner_style")) {
+            self.spinner_style = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("spinner_text")) {
+            self.spinner_text = v;
+        }
+        if let Ok(v) = env::var(get_env_name("inline_images")) {
+            self.inline_images = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("link_references")) {
+            self.link_references = v;
+        }
         if let Some(v) = read_env_value::<String>(&get_env_name("editor")) {
             self.editor = v;
         }
@@ -2839,6 +4090,12 @@ impl Config {
         if let Some(Some(v)) = read_env_bool(&get_env_name("wrap_code")) {
             self.wrap_code = v;
         }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("code_line_numbers")) {
+            self.code_line_numbers = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("code_labels")) {
+            self.code_labels = v;
+        }
 
         if let Some(Some(v)) = read_env_bool(&get_env_name("function_calling_support")) {
             self.function_calling_support = v;
@@ -2851,6 +4108,72 @@ impl Config {
         if let Some(v) = read_env_value::<String>(&get_env_name("enabled_tools")) {
             self.enabled_tools = v;
         }
+        if let Ok(v) = env::var(get_env_name("dangerous_tool_patterns"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.dangerous_tool_patterns = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("deny_dangerous_tools_without_tty")) {
+            self.deny_dangerous_tools_without_tty = v;
+        }
+        if let Ok(v) = env::var(get_env_name("tool_policy"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.tool_policy = v;
+        }
+        if let Ok(v) = env::var(get_env_name("trusted_agent_keys"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.trusted_agent_keys = v;
+        }
+        if let Some(v) = read_env_value::<u64>(&get_env_name("tool_timeout_secs")) {
+            self.tool_timeout_secs = v;
+        }
+        if let Ok(v) = env::var(get_env_name("tool_timeouts"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.tool_timeouts = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("max_concurrent_tool_calls")) {
+            self.max_concurrent_tool_calls = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("max_tool_calls_per_turn")) {
+            self.max_tool_calls_per_turn = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("max_tool_calls_per_session"))
+        {
+            self.max_tool_calls_per_session = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("max_tool_rounds")) {
+            self.max_tool_rounds = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("max_tool_call_repeats")) {
+            self.max_tool_call_repeats = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("tool_call_repeat_chain_len"))
+        {
+            self.tool_call_repeat_chain_len = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("tool_output_limit")) {
+            self.tool_output_limit = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("summarize_tool_output")) {
+            self.summarize_tool_output = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("stream_tool_output")) {
+            self.stream_tool_output = v;
+        }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("expand_tool_calls")) {
+            self.expand_tool_calls = v;
+        }
+        if let Some(Some(v)) = read_env_value::<u64>(&get_env_name("tool_cache_ttl_secs")) {
+            self.tool_cache_ttl_secs = v;
+        }
+        if let Ok(v) = env::var(get_env_name("tools"))
+            && let Ok(v) = serde_json::from_str(&v)
+        {
+            self.tools = v;
+        }
 
         if let Some(Some(v)) = read_env_bool(&get_env_name("mcp_server_support")) {
             self.mcp_server_support = v;
@@ -2877,9 +4200,27 @@ impl Config {
         if let Some(v) = read_env_bool(&get_env_name("save_session")) {
             self.save_session = v;
         }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("encrypt_sessions")) {
+            self.encrypt_sessions = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("session_autosave_messages")) {
+            self.session_autosave_messages = v;
+        }
+        if let Some(Some(v)) = read_env_value::<u64>(&get_env_name("session_autosave_secs")) {
+            self.session_autosave_secs = v;
+        }
         if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("compression_threshold")) {
             self.compression_threshold = v;
         }
+        if let Some(Some(v)) = read_env_bool(&get_env_name("memory")) {
+            self.memory = v;
+        }
+        if let Some(Some(v)) = read_env_value::<usize>(&get_env_name("memory_top_k")) {
+            self.memory_top_k = v;
+        }
+        if let Some(v) = read_env_value::<String>(&get_env_name("memory_extractor_model")) {
+            self.memory_extractor_model = v;
+        }
         if let Some(v) = read_env_value::<String>(&get_env_name("summarization_prompt")) {
             self.summarization_prompt = v;
         }
@@ -2918,6 +4259,15 @@ impl Config {
         if *NO_COLOR {
             self.highlight = false;
         }
+        // The theme last picked with `.theme` only applies when the config doesn't set one
+        if self.highlight
+            && self.theme.is_none()
+            && env::var(get_env_name("theme")).is_err()
+            && let Ok(v) = read_to_string(Self::local_path(CURRENT_THEME_FILE_NAME))
+            && !v.trim().is_empty()
+        {
+            self.theme = Some(v.trim().to_string());
+        }
         if self.highlight && self.theme.is_none() {
             if let Some(v) = read_env_value::<String>(&get_env_name("theme")) {
                 self.theme = v;
@@ -2940,9 +4290,18 @@ impl Config {
         if let Some(v) = read_env_value::<String>(&get_env_name("user_agent")) {
             self.user_agent = v;
         }
+        if let Some(Some(v)) = read_env_value::<u64>(&get_env_name("connect_timeout_secs")) {
+            self.connect_timeout_secs = v;
+        }
+        if let Some(v) = read_env_value::<u64>(&get_env_name("read_timeout_secs")) {
+            self.read_timeout_secs = v;
+        }
         if let Some(Some(v)) = read_env_bool(&get_env_name("save_shell_history")) {
             self.save_shell_history = v;
         }
+        if let Some(v) = read_env_value::<u64>(&get_env_name("notify_after_secs")) {
+            self.notify_after_secs = v;
+        }
         if let Some(v) = read_env_value::<String>(&get_env_name("sync_models_url")) {
             self.sync_models_url = v;
         }
@@ -3014,6 +4373,10 @@ impl Config {
             });
     }
 
+    fn setup_spinner(&self) -> Result<()> {
+        configure_spinner(self.spinner, &self.spinner_style, self.spinner_text)
+    }
+
     fn setup_user_agent(&mut self) {
         if let Some("auto") = self.user_agent.as_deref() {
             self.user_agent = Some(format!(
@@ -3162,6 +4525,18 @@ async fn create_config_file(config_path: &Path) -> Result<()> {
     Ok(())
 }
 
+fn is_light_theme(name: &str) -> bool {
+    if let Some(light) = LIGHT_THEMES.read().get(name) {
+        return *light;
+    }
+    let light = Config::load_theme(name)
+        .ok()
+        .and_then(|theme| theme.settings.background)
+        .is_some_and(|c| 0.299 * c.r as f32 + 0.587 * c.g as f32 + 0.114 * c.b as f32 > 128.0);
+    LIGHT_THEMES.write().insert(name.to_string(), light);
+    light
+}
+
 pub(crate) fn ensure_parent_exists(path: &Path) -> Result<()> {
     if path.exists() {
         return Ok(());
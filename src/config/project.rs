@@ -0,0 +1,430 @@
+use super::{CONFIG_FILE_NAME, FUNCTIONS_DIR_NAME, MCP_FILE_NAME, ROLES_DIR_NAME};
+
+use crate::utils::{IS_STDOUT_TERMINAL, warning_text};
+use crate::vault::SECRET_RE;
+
+use anyhow::{Context, Result};
+use inquire::Confirm;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_DIR_NAME: &str = ".loki";
+
+/// How much of each file in a project directory is read to tell whether it has changed.
+const MAX_HASHED_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Settings a project config can't change, since a repository could use them to send your API
+/// keys to another host, read your vault, run commands of its own, or loosen what tools may do.
+const PROTECTED_KEYS: &[&str] = &[
+    "clients",
+    "vault_password_file",
+    "sync_models_url",
+    "document_loaders",
+    "editor",
+    "tool_policy",
+    "dangerous_tool_patterns",
+    "deny_dangerous_tools_without_tty",
+    "trusted_agent_keys",
+    "encrypt_sessions",
+    "save_session",
+];
+
+/// The settings of a tool, in `tools`, that a project config can't change.
+const PROTECTED_TOOL_KEYS: &[&str] = &[
+    "env",
+    "secrets",
+    "sandbox",
+    "sandbox_image",
+    "sandbox_network",
+    "wasm_dirs",
+];
+
+/// The closest `.loki` directory in `dir` or its ancestors, other than the global config
+/// directory.
+pub fn find_project_dir(dir: &Path, config_dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_DIR_NAME))
+        .find(|dir| dir.is_dir() && dir != config_dir)
+}
+
+/// A project whose `.loki` directory has been trusted, with the hash of its contents then.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct TrustedProject {
+    path: PathBuf,
+    hash: String,
+}
+
+/// Whether the project in `dir` can be used. A project can start MCP servers and bring its own
+/// tools, which run commands, so it's only used once you've trusted it, and trusted again after
+/// anything in it changes. The projects you trust are kept in `trusted_file`. Without a
+/// terminal to ask in, an untrusted project is ignored.
+pub fn trust_project(dir: &Path, trusted_file: &Path) -> bool {
+    let hash = hash_project_dir(dir);
+    let mut trusted: Vec<TrustedProject> = fs::read_to_string(trusted_file)
+        .ok()
+        .and_then(|v| serde_yaml::from_str(&v).ok())
+        .unwrap_or_default();
+    if trusted.iter().any(|v| v.path == dir && v.hash == hash) {
+        return true;
+    }
+    if !*IS_STDOUT_TERMINAL {
+        eprintln!(
+            "{}",
+            warning_text(&format!(
+                "Ignoring the project config at '{}', which isn't trusted yet; run Loki in a terminal there to trust it",
+                dir.display()
+            ))
+        );
+        return false;
+    }
+    let question = match trusted.iter().any(|v| v.path == dir) {
+        true => format!(
+            "The project config at '{}' has changed since you trusted it. Trust it again?",
+            dir.display()
+        ),
+        false => format!(
+            "Trust the project config at '{}'? It can start MCP servers and run its own tools.",
+            dir.display()
+        ),
+    };
+    let approved = Confirm::new(&question)
+        .with_default(false)
+        .prompt()
+        .unwrap_or_default();
+    if approved {
+        trusted.retain(|v| v.path != dir);
+        trusted.push(TrustedProject {
+            path: dir.to_path_buf(),
+            hash,
+        });
+        let saved = trusted_file
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                fs::write(
+                    trusted_file,
+                    serde_yaml::to_string(&trusted).unwrap_or_default(),
+                )
+            });
+        if let Err(err) = saved {
+            eprintln!(
+                "{}",
+                warning_text(&format!(
+                    "Failed to save the trusted projects to '{}': {err}",
+                    trusted_file.display()
+                ))
+            );
+        }
+    }
+    approved
+}
+
+/// Hashes the names and contents of the files in the project directory that Loki loads. Symlinks
+/// are hashed by where they point rather than followed, so a link back up the tree can't send
+/// the walk around in circles, and only the start of a very large file is read.
+fn hash_project_dir(dir: &Path) -> String {
+    fn visit(root: &Path, path: &Path, hasher: &mut Sha256) {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return;
+        };
+        let name = path.strip_prefix(root).unwrap_or(path);
+        hasher.update(name.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        if metadata.is_symlink() {
+            let target = fs::read_link(path).unwrap_or_default();
+            hasher.update(b"symlink:");
+            hasher.update(target.to_string_lossy().as_bytes());
+        } else if metadata.is_dir() {
+            let Ok(rd) = fs::read_dir(path) else {
+                return;
+            };
+            let mut paths: Vec<PathBuf> = rd.flatten().map(|v| v.path()).collect();
+            paths.sort();
+            for path in paths {
+                visit(root, &path, hasher);
+            }
+        } else if let Ok(file) = fs::File::open(path) {
+            let mut contents = vec![];
+            let _ = file.take(MAX_HASHED_FILE_BYTES).read_to_end(&mut contents);
+            hasher.update(metadata.len().to_le_bytes());
+            hasher.update(&contents);
+        }
+        hasher.update([0]);
+    }
+    let mut hasher = Sha256::new();
+    for name in [
+        CONFIG_FILE_NAME,
+        MCP_FILE_NAME,
+        ROLES_DIR_NAME,
+        FUNCTIONS_DIR_NAME,
+    ] {
+        visit(dir, &dir.join(name), &mut hasher);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Lays a project's `config.yaml` over the global one. Settings in the project config replace
+/// the global ones, except for maps (like `tools`), which are merged key by key. Lists (like
+/// `visible_tools`) are replaced as a whole. Returns the merged config and the settings of the
+/// project config that were ignored, since a project can't change them or use vault secrets.
+pub fn overlay_config(global: &str, project: &str) -> Result<(String, Vec<String>)> {
+    let mut config: Value = serde_yaml::from_str(global).context("Invalid global config")?;
+    let mut project: Value = serde_yaml::from_str(project).context("Invalid project config")?;
+    if project.is_null() {
+        return Ok((global.to_string(), vec![]));
+    }
+    let ignored = remove_protected(&mut project);
+    overlay_value(&mut config, project);
+    let config = serde_yaml::to_string(&config).context("Failed to merge the project config")?;
+    Ok((config, ignored))
+}
+
+/// Removes what a project config isn't allowed to set, returning the settings removed.
+fn remove_protected(project: &mut Value) -> Vec<String> {
+    let mut removed = vec![];
+    let Value::Mapping(map) = project else {
+        return removed;
+    };
+    for key in PROTECTED_KEYS {
+        if map.remove(*key).is_some() {
+            removed.push(key.to_string());
+        }
+    }
+    if let Some(Value::Mapping(tools)) = map.get_mut("tools") {
+        for (name, tool) in tools.iter_mut() {
+            let Value::Mapping(tool) = tool else {
+                continue;
+            };
+            let name = name.as_str().unwrap_or_default();
+            for key in PROTECTED_TOOL_KEYS {
+                if tool.remove(*key).is_some() {
+                    removed.push(format!("tools.{name}.{key}"));
+                }
+            }
+        }
+    }
+    removed.extend(remove_project_secrets(project));
+    removed
+}
+
+/// Removes the values in a project's file that use a vault secret (`{{NAME}}`), which is only
+/// filled in for the global files, returning where they were.
+pub fn remove_project_secrets(value: &mut Value) -> Vec<String> {
+    let mut removed = vec![];
+    remove_secrets(value, "", &mut removed);
+    removed
+}
+
+fn remove_secrets(value: &mut Value, path: &str, removed: &mut Vec<String>) {
+    let child_path = |key: &str| match path {
+        "" => key.to_string(),
+        path => format!("{path}.{key}"),
+    };
+    match value {
+        Value::Mapping(map) => map.retain(|key, value| {
+            let key = child_path(key.as_str().unwrap_or_default());
+            if value.as_str().is_some_and(uses_secret) {
+                removed.push(key);
+                return false;
+            }
+            remove_secrets(value, &key, removed);
+            true
+        }),
+        Value::Sequence(items) => {
+            let before = items.len();
+            items.retain(|item| !item.as_str().is_some_and(uses_secret));
+            if items.len() < before {
+                removed.push(path.to_string());
+            }
+            for (i, item) in items.iter_mut().enumerate() {
+                remove_secrets(item, &child_path(&i.to_string()), removed);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn uses_secret(value: &str) -> bool {
+    SECRET_RE.is_match(value).unwrap_or_default()
+}
+
+fn overlay_value(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => overlay_value(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_config() {
+        let global = r#"
+model: openai:gpt-4o
+temperature: 0.2
+visible_tools: [fs_cat.sh, web_search.sh]
+tools:
+  web_search.sh:
+    env:
+      REGION: us
+clients:
+  - type: openai
+    api_key: '{{OPENAI_API_KEY}}'
+"#;
+        let project = r#"
+model: claude:claude-sonnet-4-5
+visible_tools: [fs_cat.sh]
+tools:
+  web_search.sh:
+    cwd: /tmp
+"#;
+        let (merged, ignored) = overlay_config(global, project).unwrap();
+        let merged: Value = serde_yaml::from_str(&merged).unwrap();
+        assert!(ignored.is_empty());
+        let expected: Value = serde_yaml::from_str(
+            r#"
+model: claude:claude-sonnet-4-5
+temperature: 0.2
+visible_tools: [fs_cat.sh]
+tools:
+  web_search.sh:
+    env:
+      REGION: us
+    cwd: /tmp
+clients:
+  - type: openai
+    api_key: '{{OPENAI_API_KEY}}'
+"#,
+        )
+        .unwrap();
+        assert_eq!(merged, expected);
+        assert_eq!(
+            serde_yaml::from_str::<Value>(&overlay_config(global, "").unwrap().0).unwrap(),
+            serde_yaml::from_str::<Value>(global).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_overlay_config_protected() {
+        let global = r#"
+model: openai:gpt-4o
+tools:
+  execute_command:
+    sandbox: docker
+"#;
+        let project = r#"
+model: claude:claude-sonnet-4-5
+clients:
+  - type: openai
+    api_base: https://example.com
+tool_policy:
+  allow: ['*']
+tools:
+  execute_command:
+    sandbox: none
+    cwd: /tmp
+  deploy:
+    args_defaults:
+      token: '{{DEPLOY_TOKEN}}'
+      region: us
+"#;
+        let (merged, ignored) = overlay_config(global, project).unwrap();
+        let merged: Value = serde_yaml::from_str(&merged).unwrap();
+        let expected: Value = serde_yaml::from_str(
+            r#"
+model: claude:claude-sonnet-4-5
+tools:
+  execute_command:
+    sandbox: docker
+    cwd: /tmp
+  deploy:
+    args_defaults:
+      region: us
+"#,
+        )
+        .unwrap();
+        assert_eq!(merged, expected);
+        assert_eq!(
+            ignored,
+            [
+                "clients",
+                "tool_policy",
+                "tools.execute_command.sandbox",
+                "tools.deploy.args_defaults.token"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hash_project_dir() {
+        let dir =
+            std::env::temp_dir().join(format!("loki-test-project-hash-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("functions/tools")).unwrap();
+        fs::write(dir.join("config.yaml"), "model: openai:gpt-4o\n").unwrap();
+        let hash = hash_project_dir(&dir);
+        assert_eq!(hash_project_dir(&dir), hash);
+        fs::write(dir.join("functions/tools/run.sh"), "rm -rf ~").unwrap();
+        assert_ne!(hash_project_dir(&dir), hash);
+        let hash = hash_project_dir(&dir);
+        fs::write(dir.join("notes.md"), "not loaded").unwrap();
+        assert_eq!(hash_project_dir(&dir), hash);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hash_project_dir_symlink_cycle() {
+        let dir = std::env::temp_dir().join(format!(
+            "loki-test-project-hash-cycle-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("functions")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("functions/up")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("functions/again")).unwrap();
+        let hash = hash_project_dir(&dir);
+        assert_eq!(hash_project_dir(&dir), hash);
+        fs::remove_file(dir.join("functions/again")).unwrap();
+        std::os::unix::fs::symlink("/", dir.join("functions/again")).unwrap();
+        assert_ne!(hash_project_dir(&dir), hash);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_find_project_dir() {
+        let root =
+            std::env::temp_dir().join(format!("loki-test-find-project-dir-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let nested = root.join("repo").join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(root.join("repo").join(PROJECT_DIR_NAME)).unwrap();
+        fs::create_dir_all(root.join(PROJECT_DIR_NAME)).unwrap();
+
+        let config_dir = root.join("config");
+        assert_eq!(
+            find_project_dir(&nested, &config_dir),
+            Some(root.join("repo").join(PROJECT_DIR_NAME))
+        );
+        assert_eq!(
+            find_project_dir(&nested, &root.join("repo").join(PROJECT_DIR_NAME)),
+            Some(root.join(PROJECT_DIR_NAME))
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -129,3 +129,22 @@ pub(in crate::config) const DEFAULT_USER_INTERACTION_INSTRUCTIONS: &str = indoc!
     Use these tools when you need user decisions, preferences, or clarification.
     If you are running as a subagent, these questions are automatically escalated to the root agent for resolution."
 };
+
+pub(in crate::config) const DEFAULT_MEMORY_INSTRUCTIONS: &str = indoc! {"
+    ## Long-Term Memory
+
+    You have a persistent memory that survives across sessions:
+        - `memory__save --content \"...\" --tags [\"...\"]`: Remember a durable fact, preference, or decision.
+        - `memory__search --query \"...\"`: Look up previously saved memories.
+        - `memory__forget --id <id>`: Remove a memory that is wrong or outdated.
+
+    Memories relevant to the current request are automatically included under \"Relevant Memories\".
+    Save only information that will be useful in future conversations; do not save transient task details."
+};
+
+pub(in crate::config) const DEFAULT_SKILLS_INSTRUCTIONS: &str = indoc! {"
+    ## Skills
+
+    You have skills: packaged instructions and scripts for specialized tasks. Only their descriptions are listed below.
+    When a task matches a skill, call `load_skill --name \"...\"` to load its full instructions before you start, then follow them.
+"};
@@ -13,6 +13,7 @@ pub const SHELL_ROLE: &str = "shell";
 pub const EXPLAIN_SHELL_ROLE: &str = "explain-shell";
 pub const CODE_ROLE: &str = "code";
 pub const CREATE_TITLE_ROLE: &str = "create-title";
+pub const EXTRACT_MEMORIES_ROLE: &str = "extract-memories";
 
 pub const INPUT_PLACEHOLDER: &str = "__INPUT__";
 
@@ -20,6 +21,15 @@ pub const INPUT_PLACEHOLDER: &str = "__INPUT__";
 #[folder = "assets/roles/"]
 struct RolesAsset;
 
+/// The settings a role's front matter can have.
+pub const ROLE_METADATA_KEYS: [&str; 5] = [
+    "model",
+    "temperature",
+    "top_p",
+    "enabled_tools",
+    "enabled_mcp_servers",
+];
+
 static RE_METADATA: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(?s)-{3,}\s*(.*?)\s*-{3,}\s*(.*)").unwrap());
 
@@ -62,14 +72,7 @@ pub struct Role {
 
 impl Role {
     pub fn new(name: &str, content: &str) -> Self {
-        let mut metadata = "";
-        let mut prompt = content.trim();
-        if let Ok(Some(caps)) = RE_METADATA.captures(content)
-            && let (Some(metadata_value), Some(prompt_value)) = (caps.get(1), caps.get(2))
-        {
-            metadata = metadata_value.as_str().trim();
-            prompt = prompt_value.as_str().trim();
-        }
+        let (metadata, prompt) = split_metadata(content);
         let mut prompt = prompt.to_string();
         interpolate_variables(&mut prompt);
         let mut role = Self {
@@ -322,6 +325,16 @@ impl RoleLike for Role {
     }
 }
 
+/// Splits a role file into its front matter, which is empty if it has none, and its prompt.
+pub(super) fn split_metadata(content: &str) -> (&str, &str) {
+    if let Ok(Some(caps)) = RE_METADATA.captures(content)
+        && let (Some(metadata), Some(prompt)) = (caps.get(1), caps.get(2))
+    {
+        return (metadata.as_str().trim(), prompt.as_str().trim());
+    }
+    ("", content.trim())
+}
+
 fn parse_structure_prompt(prompt: &str) -> (&str, Vec<(&str, &str)>) {
     let mut text = prompt;
     let mut search_input = true;
@@ -9,11 +9,14 @@ use fancy_regex::Regex;
 use inquire::{Confirm, Text, validator::Validation};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
-use std::fs::{read_to_string, write};
+use std::collections::{HashMap, HashSet};
+use std::fs::write;
 use std::path::Path;
 use std::sync::LazyLock;
 
+const PINNED_MESSAGES_PROMPT: &str =
+    "These messages from the chat history were pinned, so they're kept word for word:";
+
 static RE_AUTONAME_PREFIX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d{8}T\d{6}-").unwrap());
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -32,9 +35,17 @@ pub struct Session {
     save_session: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     compression_threshold: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    left_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    right_prompt: Option<String>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     role_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branched_from: Option<String>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     agent_variables: AgentVariables,
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -43,8 +54,17 @@ pub struct Session {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     compressed_messages: Vec<Message>,
     messages: Vec<Message>,
+    /// Indexes into `messages` that are carried verbatim through compression.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pinned: Vec<usize>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     data_urls: HashMap<String, String>,
+    /// The model, tokens, latency, and cost behind each reply, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    usage: Vec<MessageUsage>,
+    /// How many tools have been called in the session, for `max_tool_calls_per_session`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    tool_calls: usize,
 
     #[serde(skip)]
     model: Model,
@@ -64,6 +84,16 @@ pub struct Session {
     autoname: Option<AutoName>,
     #[serde(skip)]
     tokens: usize,
+    #[serde(skip)]
+    approved_tools: HashSet<String>,
+    #[serde(skip)]
+    tool_cache: ToolCache,
+    #[serde(skip)]
+    pending_usage: Option<MessageUsage>,
+    #[serde(skip)]
+    extra_prompt: Option<String>,
+    #[serde(skip)]
+    vault: Option<GlobalVault>,
 }
 
 impl Session {
@@ -75,15 +105,18 @@ impl Session {
             ..Default::default()
         };
         session.set_role(role);
+        session.vault = config.session_vault();
         session.dirty = false;
         session
     }
 
     pub fn load(config: &Config, name: &str, path: &Path) -> Result<Self> {
-        let content = read_to_string(path)
+        let content = config
+            .read_session_file(path)
             .with_context(|| format!("Failed to load session {} at {}", name, path.display()))?;
         let mut session: Self =
             serde_yaml::from_str(&content).with_context(|| format!("Invalid session {name}"))?;
+        session.vault = config.session_vault();
 
         session.model = Model::retrieve_model(config, &session.model_id, ModelType::Chat)?;
 
@@ -109,6 +142,41 @@ impl Session {
         Ok(session)
     }
 
+    /// Reads a session from a `.export json` document or a copy of a session file, along with
+    /// the name it was exported under, if any.
+    pub fn import(content: &str) -> Result<(Option<String>, Self)> {
+        let mut data: Value =
+            serde_yaml::from_str(content).context("Not a JSON export or a session file")?;
+        let name = data
+            .get("name")
+            .and_then(Value::as_str)
+            .map(|v| v.to_string());
+        if let Some(data) = data.as_object_mut() {
+            for key in ["name", "exported_at", "sources"] {
+                data.remove(key);
+            }
+        }
+        let mut session: Self =
+            serde_json::from_value(data).context("Not a JSON export or a session file")?;
+        session.name = name.clone().unwrap_or_default();
+        Ok((name, session))
+    }
+
+    /// Checks that an imported session's name can't point outside the sessions directory. Only
+    /// the `_/` prefix of autonamed sessions may contain a separator.
+    pub fn check_import_name(name: &str) -> Result<()> {
+        let base = name.strip_prefix("_/").unwrap_or(name);
+        if base.is_empty() || base.starts_with('.') || base.contains(['/', '\\', ':']) {
+            bail!("Invalid session name '{name}'")
+        }
+        Ok(())
+    }
+
+    /// The vault to encrypt the session with when it's saved, if any.
+    pub fn set_vault(&mut self, vault: Option<GlobalVault>) {
+        self.vault = vault;
+    }
+
     pub fn is_empty(&self) -> bool {
         self.messages.is_empty() && self.compressed_messages.is_empty()
     }
@@ -117,6 +185,51 @@ impl Session {
         &self.name
     }
 
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// The session this one was forked from with `.branch`.
+    pub fn branched_from(&self) -> Option<&str> {
+        self.branched_from.as_deref()
+    }
+
+    /// Forks the session as it stands into a new, not yet saved, session with the given name.
+    pub fn branch(&self, name: &str) -> Self {
+        let mut branch = self.clone();
+        branch.name = name.to_string();
+        branch.path = None;
+        branch.autoname = None;
+        branch.branched_from = Some(self.name.clone());
+        branch.dirty = true;
+        branch
+    }
+
+    pub fn is_tool_approved(&self, name: &str) -> bool {
+        self.approved_tools.contains(name)
+    }
+
+    pub fn approve_tool(&mut self, name: &str) {
+        self.approved_tools.insert(name.to_string());
+    }
+
+    pub fn tool_calls(&self) -> usize {
+        self.tool_calls
+    }
+
+    pub fn count_tool_call(&mut self) {
+        self.tool_calls += 1;
+        self.dirty = true;
+    }
+
+    pub fn tool_cache(&self) -> &ToolCache {
+        &self.tool_cache
+    }
+
+    pub fn tool_cache_mut(&mut self) -> &mut ToolCache {
+        &mut self.tool_cache
+    }
+
     pub fn role_name(&self) -> Option<&str> {
         self.role_name.as_deref()
     }
@@ -129,6 +242,19 @@ impl Session {
         self.save_session
     }
 
+    /// The theme to use in this session instead of the global one.
+    pub fn theme(&self) -> Option<&str> {
+        self.theme.as_deref()
+    }
+
+    pub fn left_prompt(&self) -> Option<&str> {
+        self.left_prompt.as_deref()
+    }
+
+    pub fn right_prompt(&self) -> Option<&str> {
+        self.right_prompt.as_deref()
+    }
+
     pub fn tokens(&self) -> usize {
         self.tokens
     }
@@ -145,6 +271,90 @@ impl Session {
         self.messages.iter().filter(|v| v.role.is_user()).count()
     }
 
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Maps an image's data URL back to the file or URL it was loaded from.
+    pub fn resolve_url(&self, url: &str) -> String {
+        resolve_data_url(&self.data_urls, url.to_string())
+    }
+
+    /// The usage of each reply, including the ones that have since been compressed, undone, or
+    /// regenerated.
+    pub fn usage(&self) -> &[MessageUsage] {
+        &self.usage
+    }
+
+    pub fn total_usage(&self) -> Usage {
+        let mut total = Usage::default();
+        self.usage.iter().for_each(|v| total.add(&v.usage));
+        total
+    }
+
+    pub fn render_total_usage(&self) -> Option<String> {
+        if self.usage.is_empty() {
+            return None;
+        }
+        let total = self.total_usage();
+        Some(format!(
+            "{} replies, {} in + {} out tokens, ${:.4}",
+            self.usage.len(),
+            total.input_tokens,
+            total.output_tokens,
+            total.cost
+        ))
+    }
+
+    /// The usage of the reply at `index` in `messages`.
+    pub fn message_usage(&self, index: usize) -> Option<&MessageUsage> {
+        self.usage.iter().find(|v| v.message == Some(index))
+    }
+
+    /// Counts a completion towards the reply it's building up to, whose usage is saved along with
+    /// it once it's added to the session.
+    pub fn add_pending_usage(
+        &mut self,
+        input: &Input,
+        model_id: &str,
+        usage: &Usage,
+        latency: Duration,
+    ) {
+        let usage = MessageUsage {
+            message: None,
+            model: model_id.to_string(),
+            usage: *usage,
+            latency_ms: latency.as_millis() as u64,
+        };
+        match self.pending_usage.as_mut() {
+            // Follow-ups with tool results are part of the same reply
+            Some(pending) if input.tool_calls().is_some() => pending.merge(usage),
+            _ => self.pending_usage = Some(usage),
+        }
+    }
+
+    fn attach_pending_usage(&mut self) {
+        let Some(mut usage) = self.pending_usage.take() else {
+            return;
+        };
+        let index = self.messages.len().saturating_sub(1);
+        match self.usage.iter_mut().find(|v| v.message == Some(index)) {
+            Some(existing) => existing.merge(usage),
+            None => {
+                usage.message = Some(index);
+                self.usage.push(usage);
+            }
+        }
+    }
+
+    /// Keeps the usage of the replies from `index` on, but no longer ties it to `messages`.
+    fn unlink_usage(&mut self, index: usize) {
+        self.usage
+            .iter_mut()
+            .filter(|v| v.message.is_some_and(|message| message >= index))
+            .for_each(|v| v.message = None);
+    }
+
     pub fn export(&self) -> Result<String> {
         let mut data = json!({
             "path": self.path,
@@ -165,6 +375,15 @@ impl Session {
         if let Some(save_session) = self.save_session() {
             data["save_session"] = save_session.into();
         }
+        if let Some(theme) = &self.theme {
+            data["theme"] = theme.clone().into();
+        }
+        if let Some(left_prompt) = &self.left_prompt {
+            data["left_prompt"] = left_prompt.clone().into();
+        }
+        if let Some(right_prompt) = &self.right_prompt {
+            data["right_prompt"] = right_prompt.clone().into();
+        }
         let (tokens, percent) = self.tokens_usage();
         data["total_tokens"] = tokens.into();
         if let Some(max_input_tokens) = self.model().max_input_tokens() {
@@ -173,6 +392,12 @@ impl Session {
         if percent != 0.0 {
             data["total/max"] = format!("{percent}%").into();
         }
+        if let Some(extra_prompt) = &self.extra_prompt {
+            data["extra_prompt"] = extra_prompt.clone().into();
+        }
+        if !self.usage.is_empty() {
+            data["usage"] = json!(self.usage);
+        }
         data["messages"] = json!(self.messages);
 
         let output = serde_yaml::to_string(&data)
@@ -195,6 +420,10 @@ impl Session {
             items.push(("autoname", autoname.to_string()));
         }
 
+        if let Some(branched_from) = &self.branched_from {
+            items.push(("branched_from", branched_from.clone()));
+        }
+
         items.push(("model", self.model().id()));
 
         if let Some(temperature) = self.temperature() {
@@ -220,10 +449,18 @@ impl Session {
             items.push(("compression_threshold", compression_threshold.to_string()));
         }
 
+        if let Some(theme) = &self.theme {
+            items.push(("theme", theme.clone()));
+        }
+
         if let Some(max_input_tokens) = self.model().max_input_tokens() {
             items.push(("max_input_tokens", max_input_tokens.to_string()));
         }
 
+        if let Some(usage) = self.render_total_usage() {
+            items.push(("usage", usage));
+        }
+
         let mut lines: Vec<String> = items
             .iter()
             .map(|(name, value)| format!("{name:<20}{value}"))
@@ -234,7 +471,7 @@ impl Session {
         if !self.is_empty() {
             let resolve_url_fn = |url: &str| resolve_data_url(&self.data_urls, url.to_string());
 
-            for message in &self.messages {
+            for (index, message) in self.messages.iter().enumerate() {
                 match message.role {
                     MessageRole::System => {
                         lines.push(
@@ -246,6 +483,9 @@ impl Session {
                         if let MessageContent::Text(text) = &message.content {
                             lines.push(render.render(text));
                         }
+                        if let Some(usage) = self.message_usage(index) {
+                            lines.push(dimmed_text(&usage.render()));
+                        }
                         lines.push("".into());
                     }
                     MessageRole::User => {
@@ -363,6 +603,12 @@ impl Session {
         }) {
             prompt = format!("{system_prompt}\n\n{prompt}",);
         }
+        let pinned = self.render_pinned_messages();
+        if !pinned.is_empty() {
+            prompt = format!("{prompt}\n\n{PINNED_MESSAGES_PROMPT}\n\n{pinned}");
+        }
+        self.pinned.clear();
+        self.unlink_usage(0);
         self.compressed_messages.append(&mut self.messages);
         self.messages.push(Message::new(
             MessageRole::System,
@@ -372,6 +618,56 @@ impl Session {
         self.update_tokens();
     }
 
+    pub fn pinned(&self) -> &[usize] {
+        &self.pinned
+    }
+
+    /// Pins the message at `index` so it's kept word for word when the session is compressed.
+    pub fn pin_message(&mut self, index: usize) -> Result<()> {
+        match self.messages.get(index) {
+            Some(message) if message.role.is_user() || message.role.is_assistant() => {}
+            Some(_) => bail!("Only your messages and the replies to them can be pinned"),
+            None => bail!("No message {}", index + 1),
+        }
+        if !self.pinned.contains(&index) {
+            self.pinned.push(index);
+            self.pinned.sort_unstable();
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Unpins the message at `index`, or every pinned message.
+    pub fn unpin_message(&mut self, index: Option<usize>) -> Result<()> {
+        match index {
+            Some(index) => {
+                let Some(position) = self.pinned.iter().position(|v| *v == index) else {
+                    bail!("Message {} isn't pinned", index + 1)
+                };
+                self.pinned.remove(position);
+            }
+            None => self.pinned.clear(),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn render_pinned_messages(&self) -> String {
+        self.pinned
+            .iter()
+            .filter_map(|index| self.messages.get(*index))
+            .map(|message| {
+                let role = if message.role.is_user() {
+                    "USER"
+                } else {
+                    "ASSISTANT"
+                };
+                format!("{role}: {}", message.content.to_text())
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
     pub fn need_autoname(&self) -> bool {
         self.autoname.as_ref().map(|v| v.need()).unwrap_or_default()
     }
@@ -386,6 +682,16 @@ impl Session {
         self.autoname.as_ref().and_then(|v| v.chat_history.clone())
     }
 
+    /// A temporary instruction added to the system prompt for the rest of the session. It isn't
+    /// saved with the session.
+    pub fn extra_prompt(&self) -> Option<&str> {
+        self.extra_prompt.as_deref()
+    }
+
+    pub fn set_extra_prompt(&mut self, value: Option<String>) {
+        self.extra_prompt = value;
+    }
+
     pub fn autoname(&self) -> Option<&str> {
         self.autoname.as_ref().and_then(|v| v.name.as_deref())
     }
@@ -451,8 +757,11 @@ impl Session {
 
         self.path = Some(session_path.display().to_string());
 
-        let content = serde_yaml::to_string(&self)
+        let mut content = serde_yaml::to_string(&self)
             .with_context(|| format!("Failed to serde session '{}'", self.name))?;
+        if let Some(vault) = &self.vault {
+            content = format!("{}\n", vault.encrypt_text(&content)?);
+        }
         write(session_path, content).with_context(|| {
             format!(
                 "Failed to write session '{}' to '{}'",
@@ -491,11 +800,7 @@ impl Session {
                 *text = format!("{text}{output}");
             }
         } else if input.regenerate() {
-            if let Some(message) = self.messages.last_mut()
-                && let MessageContent::Text(text) = &mut message.content
-            {
-                *text = output.to_string();
-            }
+            self.replace_last_reply(input, output);
         } else {
             if self.messages.is_empty() {
                 if self.name == TEMP_SESSION_NAME && self.save_session == Some(true) {
@@ -520,20 +825,68 @@ impl Session {
                 MessageContent::Text(output.to_string()),
             ));
         }
+        self.attach_pending_usage();
         self.dirty = true;
         self.update_tokens();
         Ok(())
     }
 
+    /// Replaces the last reply, along with any tool calls that led up to it.
+    pub fn replace_last_reply(&mut self, input: &Input, output: &str) {
+        match self.messages.iter().rposition(|v| v.role.is_user()) {
+            Some(index) => self.messages.truncate(index + 1),
+            None => {
+                self.messages.pop();
+            }
+        }
+        let len = self.messages.len();
+        self.pinned.retain(|index| *index < len);
+        self.unlink_usage(len);
+        if let Some(tool_calls) = input.tool_calls() {
+            self.messages.push(Message::new(
+                MessageRole::Tool,
+                MessageContent::ToolCalls(tool_calls.clone()),
+            ))
+        }
+        self.messages.push(Message::new(
+            MessageRole::Assistant,
+            MessageContent::Text(output.to_string()),
+        ));
+        self.dirty = true;
+        self.update_tokens();
+    }
+
     pub fn clear_messages(&mut self) {
         self.messages.clear();
+        self.pinned.clear();
         self.compressed_messages.clear();
         self.data_urls.clear();
+        self.usage.clear();
+        self.pending_usage = None;
         self.autoname = None;
         self.dirty = true;
         self.update_tokens();
     }
 
+    /// Removes the last user message along with everything after it: the reply, and any tool
+    /// calls it took to get there. Returns `false` if there's no exchange to remove.
+    pub fn undo_last_exchange(&mut self) -> bool {
+        let Some(index) = self.messages.iter().rposition(|v| v.role.is_user()) else {
+            return false;
+        };
+        self.messages.truncate(index);
+        // Only the role's prompt is left, which is rebuilt along with the next message
+        if !self.has_user_messages() && self.compressed_messages.is_empty() {
+            self.messages.clear();
+        }
+        let len = self.messages.len();
+        self.pinned.retain(|index| *index < len);
+        self.unlink_usage(len);
+        self.dirty = true;
+        self.update_tokens();
+        true
+    }
+
     pub fn echo_messages(&self, input: &Input) -> String {
         let messages = self.build_messages(input);
         serde_yaml::to_string(&messages).unwrap_or_else(|_| "Unable to echo message".into())
@@ -664,3 +1017,124 @@ impl AutoName {
         !self.naming && self.chat_history.is_some() && self.name.is_none()
     }
 }
+
+/// The model, estimated tokens, latency, and cost behind a reply, summed over the tool calls it
+/// took to get there.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageUsage {
+    /// The index of the reply in `messages`, until it's compressed, undone, or regenerated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<usize>,
+    pub model: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub latency_ms: u64,
+}
+
+impl MessageUsage {
+    pub fn render(&self) -> String {
+        format!(
+            "{} ({:.1}s, {} in + {} out tokens, ${:.4})",
+            self.model,
+            self.latency_ms as f64 / 1000.0,
+            self.usage.input_tokens,
+            self.usage.output_tokens,
+            self.usage.cost,
+        )
+    }
+
+    fn merge(&mut self, other: MessageUsage) {
+        self.model = other.model;
+        self.usage.add(&other.usage);
+        self.latency_ms += other.latency_ms;
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(model: &str, output_tokens: usize) -> MessageUsage {
+        MessageUsage {
+            model: model.to_string(),
+            usage: Usage {
+                input_tokens: 100,
+                output_tokens,
+                cost: 0.01,
+            },
+            latency_ms: 1500,
+            ..Default::default()
+        }
+    }
+
+    fn reply(text: &str) -> Message {
+        Message::new(MessageRole::Assistant, MessageContent::Text(text.into()))
+    }
+
+    #[test]
+    fn test_check_import_name() {
+        for name in ["work", "_/2024-05-01-plans", "a..b"] {
+            assert!(Session::check_import_name(name).is_ok(), "{name}");
+        }
+        for name in [
+            "", "_/", "..", "_/..", "../x", "_/../x", "/etc/x", "a/b", "_/a/b", "a\\b", "C:x",
+            ".hidden",
+        ] {
+            assert!(Session::check_import_name(name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_message_usage() {
+        let mut session = Session::default();
+        session.messages = vec![
+            Message::new(MessageRole::User, MessageContent::Text("Hi".into())),
+            reply("Hello"),
+        ];
+        session.pending_usage = Some(usage("openai:gpt-4o", 10));
+        session.attach_pending_usage();
+        assert_eq!(session.message_usage(1).unwrap().usage.output_tokens, 10);
+
+        // Continuing a reply adds to its usage
+        session.pending_usage = Some(usage("openai:gpt-4o-mini", 5));
+        session.attach_pending_usage();
+        let message_usage = session.message_usage(1).unwrap();
+        assert_eq!(message_usage.model, "openai:gpt-4o-mini");
+        assert_eq!(message_usage.usage.output_tokens, 15);
+        assert_eq!(message_usage.latency_ms, 3000);
+        assert_eq!(session.usage().len(), 1);
+
+        // Undone replies still count towards the total
+        session.messages.truncate(1);
+        session.unlink_usage(1);
+        session.messages.push(reply("Hey"));
+        session.pending_usage = Some(usage("openai:gpt-4o", 20));
+        session.attach_pending_usage();
+        assert_eq!(session.message_usage(1).unwrap().usage.output_tokens, 20);
+        assert_eq!(session.usage().len(), 2);
+        assert_eq!(session.total_usage().output_tokens, 35);
+        assert_eq!(
+            session.render_total_usage().unwrap(),
+            "2 replies, 300 in + 35 out tokens, $0.0300"
+        );
+
+        let yaml = serde_yaml::to_string(&session).unwrap();
+        let loaded: Session = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(loaded.usage(), session.usage());
+        assert!(yaml.contains("latency_ms: 1500"));
+    }
+
+    #[test]
+    fn test_tool_calls_are_saved() {
+        let mut session = Session::default();
+        session.count_tool_call();
+        session.count_tool_call();
+        let yaml = serde_yaml::to_string(&session).unwrap();
+        let loaded: Session = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(loaded.tool_calls(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    fs::{read_dir, read_to_string},
+    path::{Path, PathBuf},
+};
+
+pub const SKILLS_DIR_NAME: &str = "skills";
+pub const SKILL_FILE_NAME: &str = "SKILL.md";
+
+#[derive(Debug, Default, Deserialize)]
+struct SkillFrontmatter {
+    name: Option<String>,
+    #[serde(default)]
+    description: String,
+}
+
+/// A skill is a folder under the agent's `skills` dir holding a `SKILL.md` and any scripts
+/// or resources it refers to. Only the name and description are kept in memory; the full
+/// contents are read when the model loads the skill.
+#[derive(Debug, Clone)]
+pub struct Skill {
+    pub name: String,
+    pub description: String,
+    dir: PathBuf,
+}
+
+impl Skill {
+    /// Lists the skills in a `skills` dir, sorted by name.
+    pub fn list(skills_dir: &Path) -> Result<Vec<Self>> {
+        if !skills_dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut skills = vec![];
+        for entry in read_dir(skills_dir)? {
+            let dir = entry?.path();
+            let skill_file = dir.join(SKILL_FILE_NAME);
+            if !skill_file.is_file() {
+                continue;
+            }
+            let contents = read_to_string(&skill_file)
+                .with_context(|| format!("Failed to read skill at '{}'", skill_file.display()))?;
+            let (frontmatter, body) = split_frontmatter(&contents);
+            let frontmatter: SkillFrontmatter = match frontmatter {
+                Some(v) => serde_yaml::from_str(v).with_context(|| {
+                    format!("Invalid frontmatter in '{}'", skill_file.display())
+                })?,
+                None => SkillFrontmatter::default(),
+            };
+            let name = frontmatter.name.unwrap_or_else(|| {
+                dir.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            });
+            let description = if frontmatter.description.is_empty() {
+                body.lines()
+                    .map(|v| v.trim().trim_start_matches('#').trim())
+                    .find(|v| !v.is_empty())
+                    .unwrap_or_default()
+                    .to_string()
+            } else {
+                frontmatter.description
+            };
+            skills.push(Self {
+                name,
+                description,
+                dir,
+            });
+        }
+        skills.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(skills)
+    }
+
+    /// The skill's instructions, followed by the paths of the other files in its folder.
+    pub fn load(&self) -> Result<String> {
+        let skill_file = self.dir.join(SKILL_FILE_NAME);
+        let contents = read_to_string(&skill_file)
+            .with_context(|| format!("Failed to read skill at '{}'", skill_file.display()))?;
+        let (_, body) = split_frontmatter(&contents);
+        let mut files = vec![];
+        collect_files(&self.dir, &mut files)?;
+        files.retain(|v| *v != skill_file);
+        files.sort();
+        let mut output = body.trim().to_string();
+        if !files.is_empty() {
+            output.push_str(&format!(
+                "\n\nFiles in this skill (in '{}'):\n{}",
+                self.dir.display(),
+                files
+                    .iter()
+                    .map(|v| format!("- {}", v.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ));
+        }
+        Ok(output)
+    }
+}
+
+fn split_frontmatter(contents: &str) -> (Option<&str>, &str) {
+    let Some(rest) = contents
+        .strip_prefix("---\n")
+        .or_else(|| contents.strip_prefix("---\r\n"))
+    else {
+        return (None, contents);
+    };
+    match rest.find("\n---") {
+        Some(end) => {
+            let body = &rest[end + 4..];
+            (Some(&rest[..end]), body.trim_start_matches(['\r', '\n']))
+        }
+        None => (None, contents),
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_list_and_load_skills() {
+        let skills_dir =
+            std::env::temp_dir().join(format!("loki-test-skills-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&skills_dir);
+        std::fs::create_dir_all(skills_dir.join("pdf/scripts")).unwrap();
+        std::fs::create_dir_all(skills_dir.join("changelog")).unwrap();
+        std::fs::create_dir_all(skills_dir.join("empty")).unwrap();
+        std::fs::write(
+            skills_dir.join("pdf/SKILL.md"),
+            "---\nname: pdf-forms\ndescription: Fill in PDF forms\n---\n\n# PDF Forms\nRun the script.\n",
+        )
+        .unwrap();
+        std::fs::write(skills_dir.join("pdf/scripts/fill.py"), "").unwrap();
+        std::fs::write(
+            skills_dir.join("changelog/SKILL.md"),
+            "# Write changelog entries\n\nUse the Keep a Changelog format.\n",
+        )
+        .unwrap();
+
+        let skills = Skill::list(&skills_dir).unwrap();
+        let summary: Vec<_> = skills
+            .iter()
+            .map(|v| (v.name.as_str(), v.description.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("changelog", "Write changelog entries"),
+                ("pdf-forms", "Fill in PDF forms")
+            ]
+        );
+        let contents = skills[1].load().unwrap();
+        assert!(contents.starts_with("# PDF Forms\nRun the script."));
+        assert!(contents.ends_with(&format!(
+            "- {}",
+            skills_dir.join("pdf/scripts/fill.py").display()
+        )));
+        assert!(Skill::list(&skills_dir.join("missing")).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&skills_dir);
+    }
+}
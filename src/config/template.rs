@@ -0,0 +1,181 @@
+use super::*;
+
+use anyhow::{Context, Result, bail};
+use fancy_regex::Regex;
+use std::{fs::read_to_string, path::Path, sync::LazyLock};
+
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+static INCLUDE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{>\s*([^}]+?)\s*\}\}").unwrap());
+static BLOCK_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{\{\s*(?:#(if|unless)\s+([^}]+?)|(else)|/(if|unless))\s*\}\}").unwrap()
+});
+
+struct Block {
+    kind: String,
+    condition: bool,
+    in_else: bool,
+    parent_active: bool,
+}
+
+impl Block {
+    fn is_active(&self) -> bool {
+        self.parent_active && self.condition != self.in_else
+    }
+}
+
+/// Renders the includes (`{{> path}}`) and conditional blocks (`{{#if var}}`,
+/// `{{#unless var}}`, `{{else}}`) of an agent's instructions.
+pub fn render_template(text: &str, variables: &AgentVariables) -> Result<String> {
+    let text = expand_includes(text, &Config::templates_dir(), 0)?;
+    render_blocks(&text, variables)
+}
+
+fn expand_includes(text: &str, templates_dir: &Path, depth: usize) -> Result<String> {
+    if depth > MAX_INCLUDE_DEPTH {
+        bail!(
+            "Templates are nested more than {MAX_INCLUDE_DEPTH} levels deep; is there a circular include?"
+        );
+    }
+    let mut output = String::new();
+    let mut last = 0;
+    for captures in INCLUDE_RE.captures_iter(text) {
+        let captures = captures?;
+        let (Some(whole), Some(name)) = (captures.get(0), captures.get(1)) else {
+            continue;
+        };
+        let path = resolve_template_path(templates_dir, name.as_str())?;
+        let contents = read_to_string(&path)
+            .with_context(|| format!("Failed to read template at '{}'", path.display()))?;
+        output.push_str(&text[last..whole.start()]);
+        output.push_str(&expand_includes(&contents, templates_dir, depth + 1)?);
+        last = whole.end();
+    }
+    output.push_str(&text[last..]);
+    Ok(output)
+}
+
+fn resolve_template_path(templates_dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = safe_join_path(templates_dir, name)
+        .ok_or_else(|| anyhow!("Invalid template path: '{name}'"))?;
+    if !path.exists() && path.extension().is_none() {
+        let path_with_extension = path.with_extension("md");
+        if path_with_extension.exists() {
+            return Ok(path_with_extension);
+        }
+    }
+    if !path.exists() {
+        bail!(
+            "Template '{name}' not found in '{}'",
+            templates_dir.display()
+        );
+    }
+    Ok(path)
+}
+
+fn render_blocks(text: &str, variables: &AgentVariables) -> Result<String> {
+    let mut output = String::new();
+    let mut blocks: Vec<Block> = vec![];
+    let is_active = |blocks: &[Block]| blocks.last().is_none_or(|b| b.is_active());
+    let mut last = 0;
+    for captures in BLOCK_RE.captures_iter(text) {
+        let captures = captures?;
+        let Some(whole) = captures.get(0) else {
+            continue;
+        };
+        if is_active(&blocks) {
+            output.push_str(&text[last..whole.start()]);
+        }
+        last = whole.end();
+
+        if let (Some(kind), Some(expr)) = (captures.get(1), captures.get(2)) {
+            let mut condition = evaluate_condition(expr.as_str(), variables);
+            if kind.as_str() == "unless" {
+                condition = !condition;
+            }
+            blocks.push(Block {
+                kind: kind.as_str().to_string(),
+                condition,
+                in_else: false,
+                parent_active: is_active(&blocks),
+            });
+        } else if captures.get(3).is_some() {
+            match blocks.last_mut() {
+                Some(block) if !block.in_else => block.in_else = true,
+                _ => bail!("Unexpected '{{{{else}}}}' in template"),
+            }
+        } else if let Some(kind) = captures.get(4) {
+            match blocks.pop() {
+                Some(block) if block.kind == kind.as_str() => {}
+                _ => bail!("Unexpected '{{{{/{}}}}}' in template", kind.as_str()),
+            }
+        }
+    }
+    if let Some(block) = blocks.last() {
+        bail!("Unclosed '{{{{#{}}}}}' block in template", block.kind);
+    }
+    output.push_str(&text[last..]);
+    Ok(output)
+}
+
+/// A condition is either a variable name, which is true when the variable is set to a
+/// non-empty value other than `false`, or a comparison like `env == "prod"`.
+fn evaluate_condition(expr: &str, variables: &AgentVariables) -> bool {
+    let value_of = |name: &str| variables.get(name.trim()).map(|v| v.as_str());
+    let unquote = |value: &str| value.trim().trim_matches(['"', '\'']).to_string();
+    if let Some((name, value)) = expr.split_once("!=") {
+        return value_of(name) != Some(unquote(value).as_str());
+    }
+    if let Some((name, value)) = expr.split_once("==") {
+        return value_of(name) == Some(unquote(value).as_str());
+    }
+    value_of(expr).is_some_and(|v| !v.is_empty() && v != "false")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render_blocks() {
+        let variables: AgentVariables = [
+            ("env".to_string(), "prod".to_string()),
+            ("verbose".to_string(), "false".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let text = "a{{#if env == \"prod\"}} careful{{#if verbose}} loud{{else}} quiet{{/if}}{{/if}}\
+                    {{#unless missing}} b{{/unless}}{{#if env != prod}} c{{/if}}";
+        assert_eq!(
+            render_blocks(text, &variables).unwrap(),
+            "a careful quiet b"
+        );
+        assert!(render_blocks("{{#if env}}a", &variables).is_err());
+        assert!(render_blocks("{{#if env}}a{{/unless}}", &variables).is_err());
+    }
+
+    #[test]
+    fn test_expand_includes() {
+        let templates_dir =
+            std::env::temp_dir().join(format!("loki-test-templates-{}", std::process::id()));
+        std::fs::create_dir_all(templates_dir.join("common")).unwrap();
+        std::fs::write(
+            templates_dir.join("common/safety.md"),
+            "Be safe. {{> sign}}",
+        )
+        .unwrap();
+        std::fs::write(templates_dir.join("sign.md"), "Bye.").unwrap();
+        std::fs::write(templates_dir.join("loop.md"), "{{> loop.md}}").unwrap();
+
+        assert_eq!(
+            expand_includes("Hi. {{> common/safety.md }}", &templates_dir, 0).unwrap(),
+            "Hi. Be safe. Bye."
+        );
+        assert!(expand_includes("{{> missing.md}}", &templates_dir, 0).is_err());
+        assert!(expand_includes("{{> ../secret}}", &templates_dir, 0).is_err());
+        assert!(expand_includes("{{> loop}}", &templates_dir, 0).is_err());
+        let _ = std::fs::remove_dir_all(&templates_dir);
+    }
+}
@@ -1,4 +1,11 @@
+use super::ensure_parent_exists;
+
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -40,6 +47,23 @@ impl TodoList {
         }
     }
 
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = read_to_string(path)
+            .with_context(|| format!("Failed to read todo list at '{}'", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to load todo list at '{}'", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        ensure_parent_exists(path)?;
+        let contents = serde_yaml::to_string(self)?;
+        write(path, contents)
+            .with_context(|| format!("Failed to write todo list to '{}'", path.display()))
+    }
+
     pub fn add(&mut self, task: &str) -> usize {
         let id = self.todos.iter().map(|t| t.id).max().unwrap_or(0) + 1;
         self.todos.push(TodoItem {
@@ -169,6 +193,21 @@ mod tests {
         assert!(!list.has_incomplete());
     }
 
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("loki-todo-{}.yaml", std::process::id()));
+        let mut list = TodoList::new("Persist");
+        list.add("Step 1");
+        list.mark_done(1);
+        list.save(&path).unwrap();
+        let loaded = TodoList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.goal, "Persist");
+        assert_eq!(loaded.todos.len(), 1);
+        assert!(loaded.todos[0].done);
+        assert!(TodoList::load(&path).unwrap().is_empty());
+    }
+
     #[test]
     fn test_serialization_roundtrip() {
         let mut list = TodoList::new("Roundtrip");
@@ -0,0 +1,196 @@
+use super::session::Session;
+use super::*;
+
+use crate::client::{MessageContent, MessageContentPart, MessageRole};
+use crate::function::ToolResult;
+use crate::render::render_html;
+
+use anyhow::{Result, bail};
+use serde_json::json;
+use syntect::highlighting::Theme;
+
+/// The document formats `.export` can write a session to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown export format '{value}'; expected one of md, html, or json"),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Writes out a session as a standalone document, with its tool calls and the sources of the
+/// last RAG search. HTML documents highlight code with the theme.
+pub fn export_session(
+    session: &Session,
+    sources: Option<&str>,
+    format: ExportFormat,
+    theme: Option<Theme>,
+) -> Result<String> {
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(session, sources)),
+        ExportFormat::Html => {
+            render_html(session.name(), &render_markdown(session, sources), theme)
+        }
+        ExportFormat::Json => {
+            let mut data = json!({
+                "name": session.name(),
+                "exported_at": now(),
+            });
+            // The rest of the session comes along so `--import-session` can restore all of it
+            if let (Some(data), Value::Object(fields)) =
+                (data.as_object_mut(), serde_json::to_value(session)?)
+            {
+                data.extend(fields);
+            }
+            data["sources"] = json!(sources);
+            Ok(serde_json::to_string_pretty(&data)?)
+        }
+    }
+}
+
+fn render_markdown(session: &Session, sources: Option<&str>) -> String {
+    let mut details = format!("- Model: `{}`\n- Exported: {}", session.model().id(), now());
+    if let Some(usage) = session.render_total_usage() {
+        details.push_str(&format!("\n- Usage: {usage}"));
+    }
+    let mut sections = vec![format!("# {}", session.name()), details];
+    for (index, message) in session.messages().iter().enumerate() {
+        let mut body = match &message.content {
+            MessageContent::Text(text) => text.trim().to_string(),
+            MessageContent::Array(parts) => parts
+                .iter()
+                .map(|part| match part {
+                    MessageContentPart::Text { text } => text.trim().to_string(),
+                    MessageContentPart::ImageUrl { image_url } => {
+                        format!("![image]({})", session.resolve_url(&image_url.url))
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            MessageContent::ToolCalls(tool_calls) => {
+                let mut blocks = vec![];
+                if !tool_calls.text.trim().is_empty() {
+                    blocks.push(tool_calls.text.trim().to_string());
+                }
+                blocks.extend(tool_calls.tool_results.iter().map(render_tool_result));
+                blocks.join("\n\n")
+            }
+        };
+        let title = match message.role {
+            MessageRole::System => "System",
+            MessageRole::User => "User",
+            MessageRole::Assistant => "Assistant",
+            MessageRole::Tool => "Tool calls",
+        };
+        if let Some(usage) = session.message_usage(index) {
+            body.push_str(&format!("\n\n*{}*", usage.render()));
+        }
+        sections.push(format!("## {title}\n\n{body}"));
+    }
+    if let Some(sources) = sources.filter(|v| !v.trim().is_empty()) {
+        sections.push(render_sources(sources));
+    }
+    let mut output = sections.join("\n\n");
+    output.push('\n');
+    output
+}
+
+/// A tool call as Markdown, with its output folded away.
+pub fn render_tool_result(result: &ToolResult) -> String {
+    let arguments = serde_json::to_string_pretty(&result.call.arguments)
+        .unwrap_or_else(|_| result.call.arguments.to_string());
+    let output =
+        serde_json::to_string_pretty(&result.output).unwrap_or_else(|_| result.output.to_string());
+    format!(
+        "**Call** `{}`\n\n{}\n\n<details>\n<summary>Output</summary>\n\n{}\n\n</details>",
+        result.call.name,
+        code_block("json", &arguments),
+        code_block("json", &output),
+    )
+}
+
+/// The sources of the last RAG search as a Markdown list, one document per item.
+pub fn render_sources(sources: &str) -> String {
+    let items: Vec<String> = sources
+        .lines()
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| format!("- {}", v.trim()))
+        .collect();
+    format!("## Sources\n\n{}", items.join("\n"))
+}
+
+/// Fences text in a code block, with a fence longer than any run of backticks in it.
+fn code_block(lang: &str, text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{lang}\n{text}\n{fence}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_block() {
+        assert_eq!(code_block("json", "{}"), "```json\n{}\n```");
+        assert_eq!(
+            code_block("md", "```rust\nfn main() {}\n```"),
+            "````md\n```rust\nfn main() {}\n```\n````"
+        );
+    }
+
+    #[test]
+    fn test_json_export_round_trip() {
+        let exported = r#"{
+            "name": "debugging",
+            "exported_at": "2025-01-01T12:00:00+00:00",
+            "model": "openai:gpt-4o",
+            "temperature": 0.2,
+            "messages": [
+                {"role": "user", "content": "Why does this panic?"},
+                {"role": "assistant", "content": "The index is out of bounds."}
+            ],
+            "sources": null
+        }"#;
+        let (name, session) = Session::import(exported).unwrap();
+        assert_eq!(name.as_deref(), Some("debugging"));
+        assert_eq!(session.messages().len(), 2);
+
+        let json = export_session(&session, None, ExportFormat::Json, None).unwrap();
+        let (_, reimported) = Session::import(&json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&reimported).unwrap(),
+            serde_json::to_value(&session).unwrap()
+        );
+        assert!(Session::import("# debugging\n\n## User\n\nHi").is_err());
+    }
+
+    #[test]
+    fn test_parse_export_format() {
+        assert_eq!(ExportFormat::parse("md").unwrap(), ExportFormat::Markdown);
+        assert_eq!(ExportFormat::parse("html").unwrap().extension(), "html");
+        assert!(ExportFormat::parse("pdf").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::config::{GlobalConfig, ensure_parent_exists};
+use crate::utils::{sha256, warning_text};
+use crate::vault::Vault;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{OpenOptions, read_to_string},
+    io::Write,
+    path::Path,
+    time::Duration,
+};
+
+const OUTPUT_HASH_LEN: usize = 16;
+
+/// A single tool call, as appended to the session's tool call log. The log is only ever
+/// appended to, so it's a complete record of what tools were run with what arguments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    pub called_at: String,
+    pub name: String,
+    pub arguments: Value,
+    /// The code the tool script or WASM module exited with. Built-in functions, MCP tools, and
+    /// tools that were killed for timing out have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    /// The start of the SHA-256 hash of the output, to check it against what the model saw
+    /// without storing it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub error: bool,
+    #[serde(default, skip_serializing_if = "ToolCallOutcome::is_ran")]
+    pub outcome: ToolCallOutcome,
+}
+
+/// What came of a tool call: whether the tool actually ran, or why it didn't.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallOutcome {
+    #[default]
+    Ran,
+    /// The output was reused from an earlier call.
+    Cached,
+    /// The tool policy, the agent's permissions, or the user refused the call.
+    Denied,
+    /// The agent's tool call quota was used up.
+    QuotaExceeded,
+    /// The call repeated itself too many times in a row.
+    LoopBlocked,
+    /// The call was only previewed with `--dry-run tools`.
+    DryRun,
+}
+
+impl ToolCallOutcome {
+    fn is_ran(&self) -> bool {
+        *self == Self::Ran
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Ran => "ran",
+            Self::Cached => "cached",
+            Self::Denied => "denied",
+            Self::QuotaExceeded => "quota exceeded",
+            Self::LoopBlocked => "loop blocked",
+            Self::DryRun => "dry run",
+        }
+    }
+}
+
+impl ToolCallRecord {
+    pub fn new(
+        name: &str,
+        arguments: &Value,
+        output: &Value,
+        exit_code: Option<i32>,
+        duration: Duration,
+    ) -> Self {
+        let output_hash = match output {
+            Value::Null => None,
+            Value::String(output) => Some(hash_output(output)),
+            output => Some(hash_output(&output.to_string())),
+        };
+        Self {
+            called_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            name: name.to_string(),
+            arguments: arguments.clone(),
+            exit_code,
+            duration_ms: duration.as_millis() as u64,
+            output_hash,
+            error: output.get("tool_call_error").is_some(),
+            outcome: ToolCallOutcome::Ran,
+        }
+    }
+
+    /// A call that didn't run the tool, along with what the model got instead.
+    pub fn skipped(
+        name: &str,
+        arguments: &Value,
+        output: &Value,
+        outcome: ToolCallOutcome,
+    ) -> Self {
+        Self {
+            error: false,
+            ..Self::new(name, arguments, output, None, Duration::ZERO)
+        }
+        .with_outcome(outcome)
+    }
+
+    pub fn with_outcome(mut self, outcome: ToolCallOutcome) -> Self {
+        self.outcome = outcome;
+        self
+    }
+
+    /// Appends the call to the log of the active session. A call that can't be logged still
+    /// goes ahead, but the user is warned.
+    pub fn log(&self, config: &GlobalConfig) {
+        let (path, vault) = {
+            let config = config.read();
+            (config.tool_calls_file(), config.session_vault())
+        };
+        if let Err(err) = self.append(&path, vault.as_deref()) {
+            eprintln!("{}", warning_text(&format!("⚠️ {err} ⚠️")));
+        }
+    }
+
+    /// Appends a line to the log, encrypted with `vault` if it's given.
+    pub fn append(&self, path: &Path, vault: Option<&Vault>) -> Result<()> {
+        let mut line = serde_json::to_string(self)?;
+        if let Some(vault) = vault {
+            line = vault.encrypt_text(&format!("{line}\n"))?;
+        }
+        ensure_parent_exists(path)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open tool call log at '{}'", path.display()))?;
+        writeln!(file, "{line}")
+            .with_context(|| format!("Failed to write tool call log at '{}'", path.display()))
+    }
+
+    /// Loads the last `limit` calls, oldest first, decrypting any that were encrypted. Lines that
+    /// can't be parsed are skipped.
+    pub fn load_recent(path: &Path, limit: usize, vault: &Vault) -> Result<Vec<Self>> {
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let contents = read_to_string(path)
+            .with_context(|| format!("Failed to read tool call log at '{}'", path.display()))?;
+        let contents = vault
+            .decrypt_text(&contents)
+            .with_context(|| format!("Failed to decrypt tool call log at '{}'", path.display()))?;
+        let calls: Vec<Self> = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let skip = calls.len().saturating_sub(limit);
+        Ok(calls.into_iter().skip(skip).collect())
+    }
+
+    pub fn render(&self) -> String {
+        let mut details = vec![format!("{:.1}s", self.duration_ms as f64 / 1000.0)];
+        if let Some(exit_code) = self.exit_code {
+            details.push(format!("exit {exit_code}"));
+        }
+        if !self.outcome.is_ran() {
+            details.push(self.outcome.label().to_string());
+        }
+        let status = if self.error { "error" } else { "ok" };
+        let output = match &self.output_hash {
+            Some(hash) => format!("\n    output: {hash}"),
+            None => String::new(),
+        };
+        format!(
+            "{} {} ({}) {status}\n    {}{output}",
+            self.called_at,
+            self.name,
+            details.join(", "),
+            self.arguments,
+        )
+    }
+}
+
+fn hash_output(output: &str) -> String {
+    sha256(output)[..OUTPUT_HASH_LEN].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_call_log() {
+        let path =
+            std::env::temp_dir().join(format!("loki-test-calls-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let arguments = json!({"path": "Cargo.toml"});
+        for i in 0..3 {
+            let output = match i {
+                2 => json!({"tool_call_error": "Tool call 'fs_cat' exited with code 1"}),
+                _ => json!({"output": format!("contents {i}")}),
+            };
+            let record = ToolCallRecord::new(
+                "fs_cat",
+                &arguments,
+                &output,
+                Some(i),
+                Duration::from_millis(1500),
+            );
+            let record = if i == 1 {
+                record.with_outcome(ToolCallOutcome::Cached)
+            } else {
+                record
+            };
+            record.append(&path, None).unwrap();
+        }
+        ToolCallRecord::skipped(
+            "fs_rm",
+            &arguments,
+            &json!({"tool_call_error": "The user denied the call to 'fs_rm'"}),
+            ToolCallOutcome::Denied,
+        )
+        .append(&path, None)
+        .unwrap();
+
+        let calls = ToolCallRecord::load_recent(&path, 3, &Vault::default()).unwrap();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].outcome, ToolCallOutcome::Cached);
+        assert!(!calls[0].error);
+        assert_eq!(calls[1].outcome, ToolCallOutcome::Ran);
+        assert_eq!(calls[1].exit_code, Some(2));
+        assert!(calls[1].error);
+        assert_eq!(calls[1].arguments, arguments);
+        assert_eq!(calls[1].duration_ms, 1500);
+        assert_eq!(calls[1].output_hash.as_ref().map(String::len), Some(16));
+        assert_ne!(calls[0].output_hash, calls[1].output_hash);
+        assert_eq!(calls[2].outcome, ToolCallOutcome::Denied);
+        assert_eq!(calls[2].exit_code, None);
+        assert!(!calls[2].error);
+        assert!(calls[2].render().contains("denied"));
+        let _ = std::fs::remove_file(&path);
+    }
+}
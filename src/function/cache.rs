@@ -0,0 +1,96 @@
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_TOOL_CACHE_TTL_SECS: u64 = 300;
+
+/// Results of `cacheable` tool calls, keyed by the tool and its arguments, so identical calls
+/// aren't re-run while the result is still fresh.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCache {
+    entries: HashMap<String, (Instant, Value)>,
+}
+
+impl ToolCache {
+    pub fn get(&self, name: &str, arguments: &Value, ttl: Duration) -> Option<Value> {
+        let (cached_at, output) = self.entries.get(&cache_key(name, arguments))?;
+        (cached_at.elapsed() < ttl).then(|| output.clone())
+    }
+
+    pub fn insert(&mut self, name: &str, arguments: &Value, output: Value, ttl: Duration) {
+        self.entries
+            .retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+        self.entries
+            .insert(cache_key(name, arguments), (Instant::now(), output));
+    }
+}
+
+fn cache_key(name: &str, arguments: &Value) -> String {
+    format!("{name} {}", canonical_json(arguments))
+}
+
+/// Serializes `value` with object keys sorted, so the same arguments always produce the same key
+/// no matter what order the model passed them in.
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(key, _)| *key);
+            let entries = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect::<Vec<_>>();
+            format!("{{{}}}", entries.join(","))
+        }
+        Value::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(canonical_json)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_cache() {
+        let mut cache = ToolCache::default();
+        let ttl = Duration::from_secs(60);
+        cache.insert(
+            "search",
+            &json!({"query": "rust", "filters": {"lang": "en", "year": 2024}}),
+            json!("results"),
+            ttl,
+        );
+
+        assert_eq!(
+            cache.get(
+                "search",
+                &json!({"filters": {"year": 2024, "lang": "en"}, "query": "rust"}),
+                ttl
+            ),
+            Some(json!("results"))
+        );
+        assert_eq!(cache.get("search", &json!({"query": "go"}), ttl), None);
+        assert_eq!(cache.get("fetch", &json!({"query": "rust"}), ttl), None);
+        assert_eq!(
+            cache.get(
+                "search",
+                &json!({"query": "rust", "filters": {"lang": "en", "year": 2024}}),
+                Duration::ZERO
+            ),
+            None
+        );
+    }
+}
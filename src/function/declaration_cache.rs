@@ -0,0 +1,109 @@
+use super::FunctionDeclaration;
+use crate::config::Config;
+use crate::utils::sha256;
+
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, path::PathBuf};
+
+/// The declarations parsed from a tool script, saved in the cache directory so unchanged scripts
+/// aren't parsed again on every startup. There's one entry per script, which is only used while
+/// the script's contents and the version of Loki that parsed it stay the same.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    version: String,
+    hash: String,
+    declarations: Vec<CachedDeclaration>,
+}
+
+/// A declaration along with the fields that aren't sent to the model, and so aren't serialized
+/// with it.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedDeclaration {
+    #[serde(flatten)]
+    declaration: FunctionDeclaration,
+    agent: bool,
+    cacheable: bool,
+}
+
+pub fn load(path: &Path, src: &str) -> Option<Vec<FunctionDeclaration>> {
+    let contents = fs::read_to_string(entry_file(path)).ok()?;
+    let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+    if entry.version != env!("CARGO_PKG_VERSION") || entry.hash != sha256(src) {
+        return None;
+    }
+    debug!("Using the cached tool definitions for {}", path.display());
+    Some(
+        entry
+            .declarations
+            .into_iter()
+            .map(|cached| FunctionDeclaration {
+                agent: cached.agent,
+                cacheable: cached.cacheable,
+                ..cached.declaration
+            })
+            .collect(),
+    )
+}
+
+pub fn save(path: &Path, src: &str, declarations: &[FunctionDeclaration]) {
+    let entry = CacheEntry {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        hash: sha256(src),
+        declarations: declarations
+            .iter()
+            .map(|declaration| CachedDeclaration {
+                declaration: declaration.clone(),
+                agent: declaration.agent,
+                cacheable: declaration.cacheable,
+            })
+            .collect(),
+    };
+    let entry_file = entry_file(path);
+    // The cache only saves time, so failing to write it isn't worth failing over
+    let saved = entry_file
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&entry_file, serde_json::to_string(&entry)?));
+    if let Err(err) = saved {
+        warn!(
+            "Failed to cache the tool definitions for {}: {err}",
+            path.display()
+        );
+    }
+}
+
+fn entry_file(path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    Config::cache_path()
+        .join("declarations")
+        .join(format!("{}.json", &sha256(&path.to_string_lossy())[..16]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::JsonSchema;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cached_declaration_keeps_unsent_fields() {
+        let cached = CachedDeclaration {
+            declaration: FunctionDeclaration {
+                name: "fetch_logs".to_string(),
+                description: "Fetch logs".to_string(),
+                parameters: JsonSchema {
+                    type_value: Some("object".to_string()),
+                    ..Default::default()
+                },
+                agent: true,
+                cacheable: true,
+            },
+            agent: true,
+            cacheable: true,
+        };
+        let json = serde_json::to_string(&cached).unwrap();
+        let cached: CachedDeclaration = serde_json::from_str(&json).unwrap();
+        assert_eq!(cached.declaration.name, "fetch_logs");
+        assert_eq!((cached.agent, cached.cacheable), (true, true));
+    }
+}
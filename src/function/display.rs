@@ -0,0 +1,108 @@
+use crate::utils::{dimmed_text, truncate_to_width};
+
+use crossterm::terminal;
+use serde_json::Value;
+
+/// Prints a tool call before it runs: on one line with its arguments abbreviated, or with them
+/// in full when `expand` is set.
+pub fn print_tool_call(name: &str, arguments: &Value, expand: bool) {
+    let text = if expand {
+        format!("Call {name}\n{}", pretty_json(arguments))
+    } else {
+        fit_line(&format!("Call {}", summarize_call(name, arguments)))
+    };
+    println!("{}", dimmed_text(&text));
+}
+
+/// Prints what a tool call returned: its first line and how many more there are, or all of it
+/// when `expand` is set.
+pub fn print_tool_output(name: &str, output: &Value, expand: bool) {
+    let text = if expand {
+        format!("↳ {name}\n{}", pretty_json(output))
+    } else {
+        fit_line(&format!("↳ {name}: {}", summarize_output(output)))
+    };
+    println!("{}", dimmed_text(&text));
+}
+
+/// A tool call as its name and `key=value` pairs, with each value on one line.
+fn summarize_call(name: &str, arguments: &Value) -> String {
+    let arguments = match arguments {
+        Value::Object(arguments) => arguments
+            .iter()
+            .map(|(key, value)| match value {
+                Value::String(value) => {
+                    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if value.contains(' ') || value.is_empty() {
+                        format!("{key}={}", Value::String(value))
+                    } else {
+                        format!("{key}={value}")
+                    }
+                }
+                value => format!("{key}={value}"),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        Value::Null => String::new(),
+        arguments => arguments.to_string(),
+    };
+    format!("{name} {arguments}").trim_end().to_string()
+}
+
+fn summarize_output(output: &Value) -> String {
+    let text = match output {
+        Value::String(text) => text.clone(),
+        output => output.to_string(),
+    };
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    match lines.as_slice() {
+        [] => "(no output)".into(),
+        [line] => line.to_string(),
+        [line, rest @ ..] => format!("{line} (+{} lines)", rest.len()),
+    }
+}
+
+fn pretty_json(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string()),
+    }
+}
+
+/// Cuts a summary down to one row of the terminal.
+fn fit_line(text: &str) -> String {
+    let columns = terminal::size().map_or(80, |(columns, _)| columns as usize);
+    truncate_to_width(text, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_summarize_call() {
+        assert_eq!(
+            summarize_call(
+                "fs_write",
+                &json!({"path": "src/main.rs", "contents": "fn main() {\n    todo!()\n}", "force": true})
+            ),
+            r#"fs_write path=src/main.rs contents="fn main() { todo!() }" force=true"#
+        );
+        assert_eq!(summarize_call("get_time", &json!({})), "get_time");
+    }
+
+    #[test]
+    fn test_summarize_output() {
+        assert_eq!(
+            summarize_output(&json!("one\n\ntwo\nthree\n")),
+            "one (+2 lines)"
+        );
+        assert_eq!(summarize_output(&json!({"ok": true})), r#"{"ok":true}"#);
+        assert_eq!(summarize_output(&json!("")), "(no output)");
+    }
+}
@@ -0,0 +1,146 @@
+use crate::utils::{dimmed_text, warning_text};
+
+use indexmap::IndexMap;
+use serde_json::{Value, json};
+use std::{collections::HashMap, env, path::Path, process::Command};
+
+/// What a tool call would have done, shown instead of running it when `dry_run_tools` is on.
+#[derive(Debug)]
+pub struct DryRunPreview {
+    tool: String,
+    command: String,
+    cwd: Option<String>,
+    env: IndexMap<String, String>,
+    /// The directories a WASM tool would be given access to.
+    dirs: Vec<String>,
+}
+
+impl DryRunPreview {
+    /// Previews a tool script's command line, working directory, and the environment variables
+    /// it sets on top of Loki's own.
+    pub fn from_command(tool: &str, command: &Command) -> Self {
+        let mut cmd_args = vec![command.get_program().to_string_lossy().to_string()];
+        cmd_args.extend(command.get_args().map(|v| v.to_string_lossy().to_string()));
+
+        let current_path = env::var("PATH").unwrap_or_default();
+        let mut env = command
+            .get_envs()
+            .filter_map(|(key, value)| {
+                let key = key.to_string_lossy().to_string();
+                let value = value?.to_string_lossy().to_string();
+                // Only show what's added to the PATH rather than all of it
+                let value = match value.strip_suffix(&current_path) {
+                    Some(prepended) if key == "PATH" && !current_path.is_empty() => {
+                        format!("{prepended}$PATH")
+                    }
+                    _ => value,
+                };
+                Some((key, value))
+            })
+            .collect::<IndexMap<_, _>>();
+        env.sort_keys();
+
+        Self {
+            tool: tool.to_string(),
+            command: shell_words::join(cmd_args),
+            cwd: command
+                .get_current_dir()
+                .map(|v| v.display().to_string())
+                .or_else(|| env::current_dir().ok().map(|v| v.display().to_string())),
+            env,
+            dirs: vec![],
+        }
+    }
+
+    /// Previews a WASM tool, which runs in-process with only the given directories and
+    /// environment variables.
+    pub fn from_wasm_module(
+        tool: &str,
+        module_path: &Path,
+        json_data: &str,
+        envs: &HashMap<String, String>,
+        dirs: &[String],
+    ) -> Self {
+        let mut env = envs
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<IndexMap<_, _>>();
+        env.sort_keys();
+
+        Self {
+            tool: tool.to_string(),
+            command: shell_words::join([
+                module_path.display().to_string(),
+                tool.to_string(),
+                json_data.to_string(),
+            ]),
+            cwd: None,
+            env,
+            dirs: dirs.to_vec(),
+        }
+    }
+
+    /// Previews a native tool, which runs in-process with the given arguments.
+    pub fn from_native_tool(tool: &str, json_data: &Value, cwd: Option<&Path>) -> Self {
+        Self {
+            tool: tool.to_string(),
+            command: format!("{tool} {json_data}"),
+            cwd: cwd
+                .map(|v| v.display().to_string())
+                .or_else(|| env::current_dir().ok().map(|v| v.display().to_string())),
+            env: IndexMap::new(),
+            dirs: vec![],
+        }
+    }
+
+    /// Previews a call to a tool on an MCP server.
+    pub fn from_mcp_invocation(tool: &str, server: &str, json_data: &Value) -> Self {
+        let mcp_tool = json_data
+            .get("tool")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let arguments = json_data.get("arguments").cloned().unwrap_or(json!({}));
+
+        Self {
+            tool: tool.to_string(),
+            command: format!("{server}/{mcp_tool} {arguments}"),
+            cwd: None,
+            env: IndexMap::new(),
+            dirs: vec![],
+        }
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "{}",
+            warning_text(&format!("Dry run: '{}' was not run", self.tool))
+        );
+        let mut lines = vec![format!("command: {}", self.command)];
+        if let Some(cwd) = &self.cwd {
+            lines.push(format!("cwd: {cwd}"));
+        }
+        if !self.dirs.is_empty() {
+            lines.push(format!("dirs: {}", self.dirs.join(", ")));
+        }
+        if !self.env.is_empty() {
+            lines.push("env:".to_string());
+            lines.extend(
+                self.env
+                    .iter()
+                    .map(|(key, value)| format!("  {key}={value}")),
+            );
+        }
+        eprintln!("{}", dimmed_text(&lines.join("\n")));
+    }
+
+    /// The result sent back to the model in place of the tool's output. The preview itself is
+    /// only shown to the user, since the environment can hold secrets.
+    pub fn to_tool_result(&self) -> Value {
+        json!({
+            "dry_run": format!(
+                "'{}' was not run because Loki is in dry-run mode",
+                self.tool
+            ),
+        })
+    }
+}
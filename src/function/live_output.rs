@@ -0,0 +1,134 @@
+use crate::utils::{NO_COLOR, count_rows};
+
+use crossterm::{
+    cursor::{MoveToColumn, MoveUp},
+    execute,
+    terminal::{self, Clear, ClearType},
+};
+use parking_lot::Mutex;
+use std::io::{self, Write};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+static TERMINAL: LazyLock<Mutex<TerminalState>> = LazyLock::new(Default::default);
+
+/// What's been printed to the terminal by tools, so their output can be erased again.
+#[derive(Debug, Default)]
+struct TerminalState {
+    /// The number of rows printed so far, not counting the unfinished line.
+    rows: usize,
+    line: Vec<u8>,
+    /// The tool that printed last.
+    last_writer: Option<usize>,
+}
+
+impl TerminalState {
+    fn end_line(&mut self, width: usize) {
+        self.rows += line_rows(&self.line, width);
+        self.line.clear();
+    }
+}
+
+/// Shows a tool's stdout and stderr in the terminal, dimmed, as it's printed. Once the tool
+/// succeeds, its output is collapsed so only what the model makes of it remains.
+#[derive(Debug)]
+pub struct LiveOutput {
+    id: usize,
+    /// The row the tool's output starts on, once it's printed something.
+    first_row: Mutex<Option<usize>>,
+}
+
+impl LiveOutput {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            first_row: Mutex::new(None),
+        }
+    }
+
+    pub fn write(&self, chunk: &[u8]) {
+        let width = terminal_width();
+        let mut state = TERMINAL.lock();
+        let mut out = io::stdout().lock();
+        {
+            let mut first_row = self.first_row.lock();
+            if first_row.is_none() {
+                // Start on a line of our own, in case another tool left one unfinished
+                if !state.line.is_empty() {
+                    let _ = out.write_all(b"\r\n");
+                    state.end_line(width);
+                }
+                *first_row = Some(state.rows);
+            }
+        }
+
+        if !*NO_COLOR {
+            let _ = out.write_all(b"\x1b[2m");
+        }
+        let mut last_pos = 0;
+        for (i, &byte) in chunk.iter().enumerate() {
+            match byte {
+                b'\n' => {
+                    let _ = out.write_all(&chunk[last_pos..i]);
+                    let _ = out.write_all(b"\r\n");
+                    last_pos = i + 1;
+                    state.end_line(width);
+                }
+                b'\r' => state.line.clear(),
+                _ => state.line.push(byte),
+            }
+        }
+        let _ = out.write_all(&chunk[last_pos..]);
+        if !*NO_COLOR {
+            let _ = out.write_all(b"\x1b[0m");
+        }
+        let _ = out.flush();
+        state.last_writer = Some(self.id);
+    }
+
+    /// Erases everything the tool printed, as long as nothing else has been printed by a tool
+    /// since and it's all still on screen.
+    pub fn collapse(&self) {
+        let Some(first_row) = *self.first_row.lock() else {
+            return;
+        };
+        let mut state = TERMINAL.lock();
+        if state.last_writer != Some(self.id) {
+            return;
+        }
+        let width = terminal_width();
+        let mut rows = state.rows - first_row;
+        if !state.line.is_empty() {
+            rows += line_rows(&state.line, width) - 1;
+        }
+        let height = terminal::size()
+            .map(|(_, h)| h as usize)
+            .unwrap_or_default();
+        if rows >= height {
+            return;
+        }
+
+        let mut out = io::stdout();
+        let _ = execute!(out, MoveToColumn(0));
+        if rows > 0 {
+            let _ = execute!(out, MoveUp(rows as u16));
+        }
+        let _ = execute!(out, Clear(ClearType::FromCursorDown));
+        state.rows = first_row;
+        state.line.clear();
+        state.last_writer = None;
+    }
+}
+
+fn terminal_width() -> usize {
+    terminal::size()
+        .map(|(w, _)| w as usize)
+        .unwrap_or(80)
+        .max(1)
+}
+
+/// The number of rows a line takes up in the terminal once it wraps.
+fn line_rows(line: &[u8], width: usize) -> usize {
+    count_rows(&String::from_utf8_lossy(line), width)
+}
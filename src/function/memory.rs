@@ -0,0 +1,217 @@
+use super::{FunctionDeclaration, JsonSchema};
+use crate::config::GlobalConfig;
+use crate::config::memory::embed_memory_texts;
+
+use anyhow::{Result, bail};
+use indexmap::IndexMap;
+use serde_json::{Value, json};
+
+pub const MEMORY_FUNCTION_PREFIX: &str = "memory__";
+
+pub fn memory_function_declarations() -> Vec<FunctionDeclaration> {
+    vec![
+        FunctionDeclaration {
+            name: format!("{MEMORY_FUNCTION_PREFIX}save"),
+            description: "Save a durable fact, preference, or decision to long-term memory so it is available in future sessions.".to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(IndexMap::from([
+                    (
+                        "content".to_string(),
+                        JsonSchema {
+                            type_value: Some("string".to_string()),
+                            description: Some("The fact to remember, written as a standalone statement".into()),
+                            ..Default::default()
+                        },
+                    ),
+                    (
+                        "tags".to_string(),
+                        JsonSchema {
+                            type_value: Some("array".to_string()),
+                            description: Some("Optional keywords to help find this memory later".into()),
+                            items: Some(Box::new(JsonSchema {
+                                type_value: Some("string".to_string()),
+                                ..Default::default()
+                            })),
+                            ..Default::default()
+                        },
+                    ),
+                ])),
+                required: Some(vec!["content".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        },
+        FunctionDeclaration {
+            name: format!("{MEMORY_FUNCTION_PREFIX}search"),
+            description: "Search long-term memory for facts relevant to a query.".to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(IndexMap::from([
+                    (
+                        "query".to_string(),
+                        JsonSchema {
+                            type_value: Some("string".to_string()),
+                            description: Some("What to look for".into()),
+                            ..Default::default()
+                        },
+                    ),
+                    (
+                        "top_k".to_string(),
+                        JsonSchema {
+                            type_value: Some("integer".to_string()),
+                            description: Some("Maximum number of memories to return".into()),
+                            ..Default::default()
+                        },
+                    ),
+                ])),
+                required: Some(vec!["query".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        },
+        FunctionDeclaration {
+            name: format!("{MEMORY_FUNCTION_PREFIX}forget"),
+            description: "Remove a memory by its id. Use when a saved fact is wrong or outdated."
+                .to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(IndexMap::from([(
+                    "id".to_string(),
+                    JsonSchema {
+                        type_value: Some("integer".to_string()),
+                        description: Some("The id of the memory to remove".into()),
+                        ..Default::default()
+                    },
+                )])),
+                required: Some(vec!["id".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        },
+    ]
+}
+
+pub async fn handle_memory_tool(
+    config: &GlobalConfig,
+    cmd_name: &str,
+    args: &Value,
+) -> Result<Value> {
+    let action = cmd_name
+        .strip_prefix(MEMORY_FUNCTION_PREFIX)
+        .unwrap_or(cmd_name);
+
+    match action {
+        "save" => {
+            let content = args
+                .get("content")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .trim();
+            if content.is_empty() {
+                return Ok(json!({"error": "content is required"}));
+            }
+            let tags: Vec<String> = args
+                .get("tags")
+                .and_then(Value::as_array)
+                .map(|v| {
+                    v.iter()
+                        .filter_map(Value::as_str)
+                        .map(|v| v.to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let embedding = embed_memory_texts(config, vec![content.to_string()], false)
+                .await
+                .and_then(|mut v| v.pop());
+            let mut cfg = config.write();
+            let memory = match cfg.agent.as_mut() {
+                Some(agent) => agent.memory_mut(),
+                None => bail!("No active agent"),
+            };
+            match memory {
+                Some(memory) => {
+                    let id = memory.add(content, tags, embedding);
+                    memory.save()?;
+                    Ok(json!({"status": "ok", "id": id}))
+                }
+                None => bail!("Memory is not enabled for this agent"),
+            }
+        }
+        "search" => {
+            let query = args
+                .get("query")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            if query.is_empty() {
+                return Ok(json!({"error": "query is required"}));
+            }
+            let top_k = {
+                let cfg = config.read();
+                match cfg.agent.as_ref() {
+                    Some(agent) => args
+                        .get("top_k")
+                        .and_then(Value::as_u64)
+                        .map(|v| v as usize)
+                        .unwrap_or_else(|| agent.memory_top_k()),
+                    None => bail!("No active agent"),
+                }
+            };
+            let query_embedding = embed_memory_texts(config, vec![query.to_string()], true)
+                .await
+                .and_then(|mut v| v.pop());
+            let cfg = config.read();
+            let memory = cfg.agent.as_ref().and_then(|agent| agent.memory());
+            match memory {
+                Some(memory) => {
+                    let memories: Vec<Value> = memory
+                        .search(query, query_embedding.as_deref(), top_k)
+                        .into_iter()
+                        .map(|entry| {
+                            json!({
+                                "id": entry.id,
+                                "content": entry.content,
+                                "tags": entry.tags,
+                                "created_at": entry.created_at,
+                            })
+                        })
+                        .collect();
+                    Ok(json!({"memories": memories}))
+                }
+                None => bail!("Memory is not enabled for this agent"),
+            }
+        }
+        "forget" => {
+            let id = args
+                .get("id")
+                .and_then(|v| {
+                    v.as_u64()
+                        .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+                })
+                .map(|v| v as usize);
+            let Some(id) = id else {
+                return Ok(json!({"error": "id is required and must be a number"}));
+            };
+            let mut cfg = config.write();
+            let memory = match cfg.agent.as_mut() {
+                Some(agent) => agent.memory_mut(),
+                None => bail!("No active agent"),
+            };
+            match memory {
+                Some(memory) => {
+                    if memory.forget(id) {
+                        memory.save()?;
+                        Ok(json!({"status": "ok", "message": format!("Forgot memory {id}")}))
+                    } else {
+                        Ok(json!({"error": format!("Memory {id} not found")}))
+                    }
+                }
+                None => bail!("Memory is not enabled for this agent"),
+            }
+        }
+        _ => bail!("Unknown memory action: {action}"),
+    }
+}
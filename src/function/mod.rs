@@ -1,6 +1,22 @@
+pub(crate) mod audit;
+pub(crate) mod cache;
+pub(crate) mod declaration_cache;
+pub(crate) mod display;
+pub(crate) mod dry_run;
+pub(crate) mod live_output;
+pub(crate) mod memory;
+pub(crate) mod namespace;
+pub(crate) mod native;
+pub(crate) mod output;
+pub(crate) mod permissions;
+pub(crate) mod preset;
+pub(crate) mod progress;
+pub(crate) mod sandbox;
+pub(crate) mod skill;
 pub(crate) mod supervisor;
 pub(crate) mod todo;
 pub(crate) mod user_interaction;
+pub(crate) mod wasm;
 
 use crate::{
     config::{Agent, Config, GlobalConfig},
@@ -10,15 +26,31 @@ use crate::{
 use crate::config::ensure_parent_exists;
 use crate::mcp::{
     MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX, MCP_INVOKE_META_FUNCTION_NAME_PREFIX,
-    MCP_SEARCH_META_FUNCTION_NAME_PREFIX,
+    MCP_SEARCH_META_FUNCTION_NAME_PREFIX, McpRegistry,
 };
-use crate::parsers::{bash, python};
+use crate::parsers::validate::validate_declarations;
+use crate::parsers::{bash, javascript, powershell, python};
+use crate::vault::InjectedSecrets;
 use anyhow::{Context, Result, anyhow, bail};
+use audit::{ToolCallOutcome, ToolCallRecord};
+use display::{print_tool_call, print_tool_output};
+use dry_run::DryRunPreview;
+use futures_util::future::join_all;
 use indexmap::IndexMap;
 use indoc::formatdoc;
+use inquire::{Confirm, Select};
+use live_output::LiveOutput;
+use memory::MEMORY_FUNCTION_PREFIX;
+use namespace::{ToolNamespace, is_reserved_tool_name};
+use native::is_native_tool;
+use output::limit_tool_output;
+use permissions::{ToolPermission, find_tool_permission, resolve_tool_permission};
+use progress::{ProgressWatcher, ToolProgress};
 use rust_embed::Embed;
+use sandbox::{DEFAULT_SANDBOX_IMAGE, DockerSandbox, SandboxType, kill_container};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
+use skill::LOAD_SKILL_FUNCTION_NAME;
 use std::collections::VecDeque;
 use std::ffi::OsStr;
 use std::fs::File;
@@ -27,11 +59,14 @@ use std::{
     collections::{HashMap, HashSet},
     env, fs, io,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 use strum_macros::AsRefStr;
 use supervisor::SUPERVISOR_FUNCTION_PREFIX;
 use todo::TODO_FUNCTION_PREFIX;
+use tokio::sync::Semaphore;
 use user_interaction::USER_FUNCTION_PREFIX;
 
 #[derive(Embed)]
@@ -42,6 +77,17 @@ struct FunctionAssets;
 const PATH_SEP: &str = ";";
 #[cfg(not(windows))]
 const PATH_SEP: &str = ":";
+/// The extension of the file listing the command line that runs a tool on Windows.
+#[cfg(windows)]
+const WINDOWS_LAUNCHER_EXTENSION: &str = "launch.json";
+
+const APPROVE_OPTION: &str = "Approve";
+const DENY_OPTION: &str = "Deny";
+const ALWAYS_ALLOW_OPTION: &str = "Always allow";
+const PYTHON_REQUIREMENTS_FILE_NAME: &str = "requirements.txt";
+/// Lets Node.js (22.6+) run TypeScript tools directly by stripping their type annotations.
+const TYPESCRIPT_NODE_FLAGS: &str =
+    "--experimental-strip-types --disable-warning=ExperimentalWarning";
 
 #[derive(AsRefStr)]
 enum BinaryType<'a> {
@@ -53,6 +99,10 @@ enum BinaryType<'a> {
 enum Language {
     Bash,
     Python,
+    JavaScript,
+    TypeScript,
+    PowerShell,
+    Wasm,
     Unsupported,
 }
 
@@ -61,6 +111,10 @@ impl From<&String> for Language {
         match s.to_lowercase().as_str() {
             "sh" => Language::Bash,
             "py" => Language::Python,
+            "js" => Language::JavaScript,
+            "ts" => Language::TypeScript,
+            "ps1" => Language::PowerShell,
+            "wasm" => Language::Wasm,
             _ => Language::Unsupported,
         }
     }
@@ -72,7 +126,9 @@ impl Language {
         match self {
             Language::Bash => "bash",
             Language::Python => "python",
-            Language::Unsupported => "sh",
+            Language::JavaScript | Language::TypeScript => "node",
+            Language::PowerShell => "pwsh",
+            Language::Wasm | Language::Unsupported => "sh",
         }
     }
 
@@ -80,14 +136,39 @@ impl Language {
         match self {
             Language::Bash => "sh",
             Language::Python => "py",
+            Language::JavaScript => "js",
+            Language::TypeScript => "ts",
+            Language::PowerShell => "ps1",
             _ => "sh",
         }
     }
+
+    /// The extension of the embedded `run-*` script used to launch tools in this language.
+    /// TypeScript tools are imported by the JavaScript runners, which Node runs with type stripping.
+    fn runner_extension(self) -> &'static str {
+        match self {
+            Language::TypeScript => "js",
+            _ => self.to_extension(),
+        }
+    }
 }
 
+/// Runs the tool calls a model made. Errors that stop them from running are tagged as tool
+/// failures, for `loki`'s exit code.
 pub async fn eval_tool_calls(
+    config: &GlobalConfig,
+    calls: Vec<ToolCall>,
+    abort_signal: AbortSignal,
+) -> Result<Vec<ToolResult>> {
+    run_tool_calls(config, calls, abort_signal)
+        .await
+        .map_err(|err| ExitCode::Tool.tag(err))
+}
+
+async fn run_tool_calls(
     config: &GlobalConfig,
     mut calls: Vec<ToolCall>,
+    abort_signal: AbortSignal,
 ) -> Result<Vec<ToolResult>> {
     let mut output = vec![];
     if calls.is_empty() {
@@ -97,27 +178,114 @@ pub async fn eval_tool_calls(
     if calls.is_empty() {
         bail!("The request was aborted because an infinite loop of function calls was detected.")
     }
-    let mut is_all_null = true;
-    for call in calls {
-        if let Some(checker) = &config.read().tool_call_tracker
-            && let Some(msg) = checker.check_loop(&call.clone())
-        {
+    let rounds_exceeded = config.write().count_tool_round();
+    match rounds_exceeded {
+        Some(1) => {
+            // Give the model one last chance to answer with what it has
+            let msg = "The maximum number of tool-call rounds for this turn has been reached. Do not call any more tools. Answer the user with the information you already have, and let them know they can ask you to continue.";
+            eprintln!(
+                "{}",
+                warning_text("⚠️ Maximum tool-call rounds reached; asking for a final answer ⚠️")
+            );
+            return Ok(calls
+                .into_iter()
+                .map(|call| ToolResult::new(call, json!({"tool_rounds_exceeded": msg})))
+                .collect());
+        }
+        Some(_) => {
+            eprintln!(
+                "{}",
+                warning_text(
+                    "⚠️ Maximum tool-call rounds reached; handing back to the user. Ask to continue if you want more. ⚠️"
+                )
+            );
+            return Ok(vec![]);
+        }
+        None => {}
+    }
+    // Calls to aliases with preset arguments run the tool they stand for, but the results are
+    // still reported against the calls the model made
+    let runs = calls
+        .iter()
+        .map(|call| call.resolve_preset(config))
+        .collect::<Vec<_>>();
+    // Calls are checked and approved in order. Consecutive tool scripts and MCP tools then run
+    // concurrently, while built-in functions, which may prompt the user or depend on the order
+    // they're called in, run one at a time.
+    let mut results: Vec<Option<Value>> = vec![None; calls.len()];
+    let mut pending = vec![];
+    for (i, (call, run)) in calls.iter().zip(&runs).enumerate() {
+        let loop_msg = config
+            .read()
+            .tool_call_tracker
+            .as_ref()
+            .and_then(|checker| checker.check_loop(&call.clone()));
+        if let Some(msg) = loop_msg {
             let dup_msg = format!("{{\"tool_call_loop_alert\":{}}}", &msg.trim());
             println!(
                 "{}",
                 warning_text(format!("{}: ⚠️ Tool-call loop detected! ⚠️", &call.name).as_str())
             );
-            let val = json!(dup_msg);
-            output.push(ToolResult::new(call, val));
-            is_all_null = false;
+            let output = json!(dup_msg);
+            ToolCallRecord::skipped(
+                &run.name,
+                &run.arguments,
+                &output,
+                ToolCallOutcome::LoopBlocked,
+            )
+            .log(config);
+            results[i] = Some(output);
             continue;
         }
-        let mut result = call.eval(config).await?;
-        if result.is_null() {
-            result = json!("DONE");
+        if let Some(denied) = run.authorize(config)? {
+            results[i] = Some(denied);
+            continue;
+        }
+        let quota_exceeded = config.write().count_tool_call();
+        if let Some(msg) = quota_exceeded {
+            eprintln!(
+                "{}",
+                warning_text(&format!("⚠️ Skipped the call to '{}': {msg} ⚠️", call.name))
+            );
+            let output = json!({"quota_exceeded": msg});
+            ToolCallRecord::skipped(
+                &run.name,
+                &run.arguments,
+                &output,
+                ToolCallOutcome::QuotaExceeded,
+            )
+            .log(config);
+            results[i] = Some(output);
+            continue;
+        }
+        if run.is_builtin() {
+            eval_concurrently(config, &runs, &mut pending, &mut results, &abort_signal).await?;
+            results[i] = Some(run.eval(config, abort_signal.clone()).await?);
         } else {
-            is_all_null = false;
+            pending.push(i);
         }
+    }
+    eval_concurrently(config, &runs, &mut pending, &mut results, &abort_signal).await?;
+
+    let (show_output, expand) = {
+        let config = config.read();
+        (
+            *IS_STDOUT_TERMINAL && config.current_depth == 0 && !is_quiet(),
+            config.expand_tool_calls,
+        )
+    };
+    let mut is_all_null = true;
+    for (call, result) in calls.into_iter().zip(results) {
+        let result = match result.unwrap_or_default() {
+            Value::Null => json!("DONE"),
+            result => {
+                is_all_null = false;
+                if show_output {
+                    print_tool_output(&call.name, &result, expand);
+                }
+                limit_tool_output(config, &call.name, result).await?
+            }
+        };
         output.push(ToolResult::new(call, result));
     }
     if is_all_null {
@@ -154,6 +322,85 @@ pub async fn eval_tool_calls(
     Ok(output)
 }
 
+/// Runs the `pending` calls, at most `max_concurrent_tool_calls` at a time, storing each result
+/// at the call's index.
+async fn eval_concurrently(
+    config: &GlobalConfig,
+    calls: &[ToolCall],
+    pending: &mut Vec<usize>,
+    results: &mut [Option<Value>],
+    abort_signal: &AbortSignal,
+) -> Result<()> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let semaphore = Semaphore::new(config.read().max_concurrent_tool_calls.max(1));
+    let outputs = join_all(pending.iter().map(|&i| {
+        let semaphore = &semaphore;
+        async move {
+            let _permit = semaphore.acquire().await?;
+            calls[i].eval(config, abort_signal.clone()).await
+        }
+    }))
+    .await;
+    for (i, output) in pending.drain(..).zip(outputs) {
+        results[i] = Some(output?);
+    }
+    Ok(())
+}
+
+/// Settings for a single tool, from the `tools` section of the config, that parameterize it
+/// without editing its script.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ToolConfig {
+    /// Extra environment variables the tool runs with.
+    #[serde(default)]
+    pub env: IndexMap<String, String>,
+    /// The directory the tool runs in; defaults to the current directory.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// Values for arguments the model leaves out.
+    #[serde(default)]
+    pub args_defaults: IndexMap<String, Value>,
+    /// Secrets from the vault the tool gets as environment variables of the same name. They're
+    /// the only ones it can be given, and they're redacted from what it returns to the model.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// Runs the tool in an ephemeral container with `cwd` mounted, unless the active agent is
+    /// already sandboxed.
+    #[serde(default)]
+    pub sandbox: SandboxType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_image: Option<String>,
+    #[serde(default)]
+    pub sandbox_network: bool,
+    /// Host directories a WASM tool can access, mounted at the same path; append `:ro` for
+    /// read-only access. WASM tools can't touch the filesystem otherwise.
+    #[serde(default)]
+    pub wasm_dirs: Vec<String>,
+    /// How long results of the tool are reused, if it's `cacheable`; overrides
+    /// `tool_cache_ttl_secs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl_secs: Option<u64>,
+}
+
+impl ToolConfig {
+    /// The container the tool runs in, if it's sandboxed.
+    pub fn sandbox(&self) -> Option<DockerSandbox> {
+        match self.sandbox {
+            SandboxType::None => None,
+            SandboxType::Docker => Some(DockerSandbox {
+                image: self
+                    .sandbox_image
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_SANDBOX_IMAGE.to_string()),
+                network: self.sandbox_network,
+                work_dir: self.cwd.clone(),
+            }),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ToolResult {
     pub call: ToolCall,
@@ -169,6 +416,13 @@ impl ToolResult {
 #[derive(Debug, Clone, Default)]
 pub struct Functions {
     declarations: Vec<FunctionDeclaration>,
+    /// The module each WASM tool function is implemented by.
+    wasm_modules: HashMap<String, PathBuf>,
+    /// The namespace each tool function comes from, keyed by the name it's declared with.
+    namespaces: HashMap<String, ToolNamespace>,
+    /// The actual names of the tools declared under a namespaced name because another tool
+    /// took their name.
+    bare_names: HashMap<String, String>,
 }
 
 impl Functions {
@@ -195,7 +449,10 @@ impl Functions {
                 .and_then(OsStr::to_str)
                 .map(|s| s.to_lowercase());
             #[cfg_attr(not(unix), expect(unused))]
-            let is_script = matches!(file_extension.as_deref(), Some("sh") | Some("py"));
+            let is_script = matches!(
+                file_extension.as_deref(),
+                Some("sh") | Some("py") | Some("js")
+            );
 
             if file_path.exists() {
                 debug!(
@@ -224,9 +481,26 @@ impl Functions {
         Self::install_global_tools()?;
         Self::clear_global_functions_bin_dir()?;
 
-        let declarations = Self {
-            declarations: Self::build_global_tool_declarations(visible_tools)?,
+        // Native tools are always available, so there's nothing to build for them
+        let visible_tools: Vec<String> = visible_tools
+            .iter()
+            .filter(|v| !is_native_tool(v))
+            .cloned()
+            .collect();
+        let visible_tools = Self::runnable_tools(visible_tools);
+        let visible_tools = visible_tools.as_slice();
+
+        let (global_tools_declarations, wasm_modules) =
+            Self::build_global_tool_declarations(visible_tools)?;
+        let mut declarations = Self {
+            wasm_modules,
+            ..Default::default()
         };
+        declarations.extend_namespaced(global_tools_declarations, ToolNamespace::Global);
+        declarations.extend_namespaced(
+            native::native_function_declarations(),
+            ToolNamespace::Native,
+        );
 
         info!(
             "Building global function binaries in {}",
@@ -241,7 +515,18 @@ impl Functions {
         Self::install_global_tools()?;
         Self::clear_agent_bin_dir(name)?;
 
-        let global_tools_declarations = if !global_tools.is_empty() {
+        let (native_tools, global_tools): (Vec<String>, Vec<String>) = global_tools
+            .iter()
+            .cloned()
+            .partition(|v| is_native_tool(v));
+        let global_tools = Self::runnable_tools(global_tools);
+        let global_tools = global_tools.as_slice();
+        let native_tools_declarations = native::native_function_declarations()
+            .into_iter()
+            .filter(|v| native_tools.contains(&v.name))
+            .collect();
+
+        let (global_tools_declarations, wasm_modules) = if !global_tools.is_empty() {
             info!("Loading global tools for agent: {name}: {global_tools:?}");
             let tools_declarations = Self::build_global_tool_declarations(global_tools)?;
 
@@ -253,7 +538,7 @@ impl Functions {
             tools_declarations
         } else {
             debug!("No global tools found for agent: {}", name);
-            Vec::new()
+            Default::default()
         };
         let agent_script_declarations = match Config::agent_functions_file(name) {
             Ok(path) if path.exists() => {
@@ -276,19 +561,151 @@ impl Functions {
                 Vec::new()
             }
         };
-        let declarations = [global_tools_declarations, agent_script_declarations].concat();
+        let mut functions = Self {
+            wasm_modules,
+            ..Default::default()
+        };
+        functions.extend_namespaced(global_tools_declarations, ToolNamespace::Global);
+        functions.extend_namespaced(agent_script_declarations, ToolNamespace::Agent);
+        functions.extend_namespaced(native_tools_declarations, ToolNamespace::Native);
 
-        Ok(Self { declarations })
+        Ok(functions)
+    }
+
+    /// The tools that can run on this system. Bash tools need Git Bash on Windows, so without it
+    /// they're left out, rather than keeping Loki from starting.
+    fn runnable_tools(tools: Vec<String>) -> Vec<String> {
+        #[cfg(windows)]
+        if crate::utils::native::runtime::bash_path().is_none() {
+            let (bash_tools, tools): (Vec<String>, Vec<String>) = tools
+                .into_iter()
+                .partition(|tool| Path::new(tool).extension().is_some_and(|ext| ext == "sh"));
+            if !bash_tools.is_empty() {
+                warn!(
+                    "Skipping bash tools because Git Bash ('bash.exe') wasn't found: {}",
+                    bash_tools.join(", ")
+                );
+            }
+            return tools;
+        }
+        tools
+    }
+
+    /// Adds tool declarations from the given namespace. When a name is already taken by a tool
+    /// from another namespace, the tool from the namespace with the higher precedence keeps it,
+    /// and the other one is declared with its namespaced name (e.g. `global__deploy`). Of two
+    /// tools from the same namespace, the first one is kept. Tools named like a built-in
+    /// function are dropped.
+    fn extend_namespaced(
+        &mut self,
+        declarations: Vec<FunctionDeclaration>,
+        namespace: ToolNamespace,
+    ) {
+        for mut declaration in declarations {
+            let name = declaration.name.clone();
+            if is_reserved_tool_name(&name) {
+                warn!(
+                    "Ignoring tool '{}' because its name is reserved for a built-in function",
+                    namespace.qualify(&name)
+                );
+                continue;
+            }
+            if let Some(&existing) = self.namespaces.get(&name) {
+                if existing == namespace {
+                    warn!(
+                        "Tool '{}' is defined more than once; only the first one will be used",
+                        namespace.qualify(&name)
+                    );
+                    continue;
+                }
+                let (declared_name, moved) = match existing < namespace {
+                    true => (namespace.declared_name(&name), namespace),
+                    false => (existing.declared_name(&name), existing),
+                };
+                if self.namespaces.contains_key(&declared_name) {
+                    warn!(
+                        "Tool '{}' conflicts with '{}' and '{declared_name}', and will be ignored",
+                        namespace.qualify(&name),
+                        existing.qualify(&name)
+                    );
+                    continue;
+                }
+                warn!(
+                    "Tool '{}' conflicts with '{}', so '{}' is declared as '{declared_name}'",
+                    namespace.qualify(&name),
+                    existing.qualify(&name),
+                    moved.qualify(&name)
+                );
+                if moved == namespace {
+                    declaration.name = declared_name.clone();
+                    self.add_namespaced(declaration, namespace, &name);
+                    continue;
+                }
+                if let Some(existing_declaration) =
+                    self.declarations.iter_mut().find(|v| v.name == name)
+                {
+                    existing_declaration.name = declared_name.clone();
+                }
+                if let Some(module) = self.wasm_modules.remove(&name) {
+                    self.wasm_modules.insert(declared_name.clone(), module);
+                }
+                self.namespaces.insert(declared_name.clone(), existing);
+                self.bare_names.insert(declared_name, name.clone());
+            }
+            self.add_namespaced(declaration, namespace, &name);
+        }
+    }
+
+    fn add_namespaced(
+        &mut self,
+        declaration: FunctionDeclaration,
+        namespace: ToolNamespace,
+        bare_name: &str,
+    ) {
+        if declaration.name != bare_name {
+            self.bare_names
+                .insert(declaration.name.clone(), bare_name.to_string());
+        }
+        self.namespaces.insert(declaration.name.clone(), namespace);
+        self.declarations.push(declaration);
     }
 
     pub fn find(&self, name: &str) -> Option<&FunctionDeclaration> {
         self.declarations.iter().find(|v| v.name == name)
     }
 
+    /// The declaration a tool name refers to. The name may be qualified with its namespace
+    /// (e.g. `global:fs_read`), in which case only a tool from that namespace matches, even if
+    /// it's declared with its namespaced name.
+    pub fn resolve(&self, tool: &str) -> Option<&FunctionDeclaration> {
+        let (namespace, name) = ToolNamespace::split(tool);
+        match namespace {
+            Some(namespace) => self.declarations.iter().find(|v| {
+                self.namespaces.get(&v.name) == Some(&namespace) && self.bare_name(&v.name) == name
+            }),
+            None => self.find(name),
+        }
+    }
+
+    /// The actual name of the tool declared as `name`, which differs when it's declared with
+    /// its namespaced name.
+    pub fn bare_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.bare_names.get(name).map_or(name, String::as_str)
+    }
+
     pub fn contains(&self, name: &str) -> bool {
         self.declarations.iter().any(|v| v.name == name)
     }
 
+    /// Whether `name` is one of the native tools, rather than a script with the same name.
+    pub fn is_native(&self, name: &str) -> bool {
+        self.namespaces.get(name) == Some(&ToolNamespace::Native)
+    }
+
+    pub fn wasm_module(&self, name: &str) -> Option<&PathBuf> {
+        self.wasm_modules.get(name)
+    }
+
     pub fn declarations(&self) -> &[FunctionDeclaration] {
         &self.declarations
     }
@@ -301,6 +718,16 @@ impl Functions {
         self.declarations.extend(todo::todo_function_declarations());
     }
 
+    pub fn append_memory_functions(&mut self) {
+        self.declarations
+            .extend(memory::memory_function_declarations());
+    }
+
+    pub fn append_skill_functions(&mut self) {
+        self.declarations
+            .extend(skill::skill_function_declarations());
+    }
+
     pub fn append_supervisor_functions(&mut self) {
         self.declarations
             .extend(supervisor::supervisor_function_declarations());
@@ -324,6 +751,8 @@ impl Functions {
                 && !d.name.starts_with(MCP_SEARCH_META_FUNCTION_NAME_PREFIX)
                 && !d.name.starts_with(MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX)
         });
+        self.namespaces
+            .retain(|_, namespace| *namespace != ToolNamespace::Mcp);
     }
 
     pub fn append_mcp_meta_functions(&mut self, mcp_servers: Vec<String>) {
@@ -392,6 +821,7 @@ impl Functions {
                     ..Default::default()
                 },
                 agent: false,
+                cacheable: false,
             };
             let search_functions_declaration = FunctionDeclaration {
                 name: search_function_name.clone(),
@@ -408,6 +838,7 @@ impl Functions {
                     ..Default::default()
                 },
                 agent: false,
+                cacheable: false,
             };
             let describe_functions_declaration = FunctionDeclaration {
                 name: describe_function_name.clone(),
@@ -419,25 +850,43 @@ impl Functions {
                     ..Default::default()
                 },
                 agent: false,
+                cacheable: false,
             };
-            self.declarations.push(invoke_function_declaration);
-            self.declarations.push(search_functions_declaration);
-            self.declarations.push(describe_functions_declaration);
+            for declaration in [
+                invoke_function_declaration,
+                search_functions_declaration,
+                describe_functions_declaration,
+            ] {
+                let name = declaration.name.clone();
+                self.add_namespaced(declaration, ToolNamespace::Mcp, &name);
+            }
         }
     }
 
     fn build_global_tool_declarations(
         enabled_tools: &[String],
-    ) -> Result<Vec<FunctionDeclaration>> {
-        let global_tools_directory = Config::global_tools_dir();
+    ) -> Result<(Vec<FunctionDeclaration>, HashMap<String, PathBuf>)> {
         let mut function_declarations = Vec::new();
+        let mut wasm_modules = HashMap::new();
 
         for tool in enabled_tools {
-            let declaration = Self::generate_declarations(&global_tools_directory.join(tool))?;
+            let tool_path = Config::tool_dir(tool).join(tool);
+            let declaration = Self::generate_declarations(&tool_path)?;
+            if tool_path.extension().and_then(OsStr::to_str) == Some("wasm") {
+                for function in &declaration {
+                    // The first tool with a given name wins, so only its module is kept
+                    if !function_declarations
+                        .iter()
+                        .any(|v: &FunctionDeclaration| v.name == function.name)
+                    {
+                        wasm_modules.insert(function.name.clone(), tool_path.clone());
+                    }
+                }
+            }
             function_declarations.extend(declaration);
         }
 
-        Ok(function_declarations)
+        Ok((function_declarations, wasm_modules))
     }
 
     fn generate_declarations(tools_file_path: &Path) -> Result<Vec<FunctionDeclaration>> {
@@ -463,8 +912,18 @@ impl Functions {
                             anyhow!("Unable to extract language from tool file: {file_name}")
                         })?,
                 );
+                // Read before generating, since building a bash tool rewrites its file
+                let src = match language {
+                    Language::Wasm => None,
+                    _ => fs::read_to_string(tools_file_path).ok(),
+                };
+                if let Some(src) = &src
+                    && let Some(declarations) = declaration_cache::load(tools_file_path, src)
+                {
+                    return Ok(declarations);
+                }
 
-                match language {
+                let declarations = match language {
                     Language::Bash => {
                         bash::generate_bash_declarations(tool_file, tools_file_path, file_name)
                     }
@@ -473,10 +932,33 @@ impl Functions {
                         file_name,
                         tools_file_path.parent(),
                     ),
+                    Language::JavaScript | Language::TypeScript => {
+                        javascript::generate_javascript_declarations(
+                            tool_file,
+                            file_name,
+                            tools_file_path.parent(),
+                        )
+                    }
+                    Language::PowerShell => powershell::generate_powershell_declarations(
+                        tool_file,
+                        file_name,
+                        tools_file_path.parent(),
+                    ),
+                    Language::Wasm => wasm::generate_wasm_declarations(tools_file_path),
                     Language::Unsupported => {
                         bail!("Unsupported tool file extension: {}", language.as_ref())
                     }
+                }?;
+                validate_declarations(tools_file_path, src.as_deref(), &declarations)?;
+
+                // Cached against the script as it is now, since building a bash tool rewrites it
+                if src.is_some()
+                    && let Ok(src) = fs::read_to_string(tools_file_path)
+                {
+                    declaration_cache::save(tools_file_path, &src, &declarations);
                 }
+
+                Ok(declarations)
             }
             Err(err) if err.kind() == io::ErrorKind::NotFound => {
                 bail!(
@@ -509,8 +991,13 @@ impl Functions {
                     anyhow::format_err!("Unable to extract file name from path: {tool:?}")
                 })?;
 
-            if language == Language::Unsupported {
-                bail!("Unsupported tool file extension: {}", language.as_ref());
+            match language {
+                Language::Unsupported => {
+                    bail!("Unsupported tool file extension: {}", language.as_ref())
+                }
+                // WASM tools run in-process, so there's nothing to build.
+                Language::Wasm => continue,
+                _ => {}
             }
 
             Self::build_binaries(binary_name, language, BinaryType::Tool(agent_name))?;
@@ -571,30 +1058,50 @@ impl Functions {
         Self::build_binaries(name, language, BinaryType::Agent)
     }
 
+    /// The `requirements.txt` next to a Python tool (or an agent's `tools.py`), and the dedicated
+    /// virtualenv under the cache directory the runner installs it into on first use. The
+    /// virtualenv is keyed by the contents of the requirements, so it's recreated when they change.
+    fn python_venv(
+        binary_name: &str,
+        binary_type: &BinaryType,
+    ) -> Result<Option<(PathBuf, PathBuf)>> {
+        let tools_dir = match binary_type {
+            BinaryType::Tool(_) => Config::tool_dir(&format!("{binary_name}.py")),
+            BinaryType::Agent => Config::agent_data_dir(binary_name),
+        };
+        let requirements_file = tools_dir.join(PYTHON_REQUIREMENTS_FILE_NAME);
+        if !requirements_file.is_file() {
+            return Ok(None);
+        }
+        let requirements = fs::read_to_string(&requirements_file).with_context(|| {
+            format!(
+                "Failed to read Python requirements '{}'",
+                requirements_file.display()
+            )
+        })?;
+        let venv_dir = Config::python_venvs_dir().join(&sha256(&requirements)[..16]);
+        Ok(Some((requirements_file, venv_dir)))
+    }
+
+    /// Builds a tool's runner script, along with a `.cmd` wrapper that runs it from a shell and a
+    /// launcher file that Loki runs it with, so tools run natively, without needing Git Bash
+    /// (except for bash tools) or passing their JSON arguments through `cmd.exe`.
     #[cfg(windows)]
     fn build_binaries(
         binary_name: &str,
         language: Language,
         binary_type: BinaryType,
     ) -> Result<()> {
-        use native::runtime;
-        let (binary_file, binary_script_file) = match binary_type {
-            BinaryType::Tool(None) => (
-                Config::functions_bin_dir().join(format!("{binary_name}.cmd")),
-                Config::functions_bin_dir()
-                    .join(format!("run-{binary_name}.{}", language.to_extension())),
-            ),
-            BinaryType::Tool(Some(agent_name)) => (
-                Config::agent_bin_dir(agent_name).join(format!("{binary_name}.cmd")),
-                Config::agent_bin_dir(agent_name)
-                    .join(format!("run-{binary_name}.{}", language.to_extension())),
-            ),
-            BinaryType::Agent => (
-                Config::agent_bin_dir(binary_name).join(format!("{binary_name}.cmd")),
-                Config::agent_bin_dir(binary_name)
-                    .join(format!("run-{binary_name}.{}", language.to_extension())),
-            ),
+        use crate::utils::native::runtime;
+        let bin_dir = match binary_type {
+            BinaryType::Tool(None) => Config::functions_bin_dir(),
+            BinaryType::Tool(Some(agent_name)) => Config::agent_bin_dir(agent_name),
+            BinaryType::Agent => Config::agent_bin_dir(binary_name),
         };
+        let binary_file = bin_dir.join(format!("{binary_name}.cmd"));
+        let launcher_file = bin_dir.join(format!("{binary_name}.{WINDOWS_LAUNCHER_EXTENSION}"));
+        let binary_script_file =
+            bin_dir.join(format!("run-{binary_name}.{}", language.runner_extension()));
         info!(
             "Building binary runner for function: {} ({})",
             binary_name,
@@ -603,47 +1110,53 @@ impl Functions {
         let embedded_file = FunctionAssets::get(&format!(
             "scripts/run-{}.{}",
             binary_type.as_ref().to_lowercase(),
-            language.to_extension()
+            language.runner_extension()
         ))
         .ok_or_else(|| {
             anyhow!(
                 "Failed to load embedded script for run-{}.{}",
                 binary_type.as_ref().to_lowercase(),
-                language.to_extension()
+                language.runner_extension()
             )
         })?;
         let content_template = unsafe { std::str::from_utf8_unchecked(&embedded_file.data) };
+        let tool_dir = Config::tool_dir(&format!("{binary_name}.{}", language.to_extension()));
+        // Backslashes are escapes in the runners' string literals, so paths use forward
+        // slashes, which every runtime on Windows understands
+        let script_path = |path: &Path| runtime::simplify_path(path).replace('\\', "/");
         let content = match binary_type {
             BinaryType::Tool(None) => {
                 let root_dir = Config::functions_dir();
-                let tool_path = format!(
-                    "{}/{binary_name}",
-                    &Config::global_tools_dir().to_string_lossy()
-                );
+                let tool_path = tool_dir.join(binary_name);
                 content_template
                     .replace("{function_name}", binary_name)
-                    .replace("{root_dir}", &root_dir.to_string_lossy())
-                    .replace("{tool_path}", &tool_path)
+                    .replace("{root_dir}", &script_path(&root_dir))
+                    .replace("{tool_path}", &script_path(&tool_path))
             }
             BinaryType::Tool(Some(agent_name)) => {
                 let root_dir = Config::agent_data_dir(agent_name);
-                let tool_path = format!(
-                    "{}/{binary_name}",
-                    &Config::global_tools_dir().to_string_lossy()
-                );
+                let tool_path = tool_dir.join(binary_name);
                 content_template
                     .replace("{function_name}", binary_name)
-                    .replace("{root_dir}", &root_dir.to_string_lossy())
-                    .replace("{tool_path}", &tool_path)
+                    .replace("{root_dir}", &script_path(&root_dir))
+                    .replace("{tool_path}", &script_path(&tool_path))
             }
             BinaryType::Agent => content_template
                 .replace("{agent_name}", binary_name)
-                .replace("{config_dir}", &Config::config_dir().to_string_lossy()),
+                .replace("{config_dir}", &script_path(&Config::config_dir())),
         }
         .replace(
             "{prompt_utils_file}",
-            &Config::bash_prompt_utils_file().to_string_lossy(),
-        );
+            &script_path(&Config::bash_prompt_utils_file()),
+        )
+        .replace("{tool_extension}", language.to_extension());
+        let (requirements_file, venv_dir) = match language {
+            Language::Python => Self::python_venv(binary_name, &binary_type)?.unwrap_or_default(),
+            _ => Default::default(),
+        };
+        let content = content
+            .replace("{requirements_file}", &script_path(&requirements_file))
+            .replace("{venv_dir}", &script_path(&venv_dir));
         if binary_script_file.exists() {
             fs::remove_file(&binary_script_file)?;
         }
@@ -656,42 +1169,55 @@ impl Functions {
             binary_file.display()
         );
 
-        let run = match language {
+        let mut launcher = match language {
             Language::Bash => {
-                let shell = runtime::bash_path().ok_or_else(|| anyhow!("Shell not found"))?;
-                format!("{shell} --noprofile --norc")
-            }
-            Language::Python if Path::new(".venv").exists() => {
-                let executable_path = env::current_dir()?
-                    .join(".venv")
-                    .join("Scripts")
-                    .join("activate.bat");
-                let canonicalized_path = fs::canonicalize(&executable_path)?;
-                format!(
-                    "call \"{}\" && {}",
-                    canonicalized_path.to_string_lossy(),
-                    language.to_cmd()
-                )
+                let shell = runtime::bash_path().ok_or_else(|| {
+                    anyhow!("Bash tools need Git Bash on Windows, but 'bash.exe' wasn't found")
+                })?;
+                vec![shell, "--noprofile".into(), "--norc".into()]
             }
             Language::Python => {
                 let executable_path = which::which("python")
                     .or_else(|_| which::which("python3"))
                     .map_err(|_| anyhow!("Python executable not found in PATH"))?;
-                let canonicalized_path = fs::canonicalize(&executable_path)?;
-                canonicalized_path.to_string_lossy().into_owned()
+                vec![runtime::canonicalize(&executable_path)?]
+            }
+            Language::JavaScript => {
+                let executable_path = which::which(language.to_cmd())
+                    .map_err(|_| anyhow!("Node.js executable not found in PATH"))?;
+                vec![runtime::canonicalize(&executable_path)?]
+            }
+            Language::TypeScript => {
+                let executable_path = which::which(language.to_cmd())
+                    .map_err(|_| anyhow!("Node.js executable not found in PATH"))?;
+                let mut launcher = vec![runtime::canonicalize(&executable_path)?];
+                launcher.extend(TYPESCRIPT_NODE_FLAGS.split_whitespace().map(String::from));
+                launcher
+            }
+            Language::PowerShell => {
+                // Prefer PowerShell 7+, falling back to the Windows PowerShell that ships with Windows
+                let executable_path = which::which(language.to_cmd())
+                    .or_else(|_| which::which("powershell"))
+                    .map_err(|_| anyhow!("PowerShell executable not found in PATH"))?;
+                vec![
+                    runtime::canonicalize(&executable_path)?,
+                    "-NoProfile".into(),
+                    "-ExecutionPolicy".into(),
+                    "Bypass".into(),
+                    "-File".into(),
+                ]
             }
             _ => bail!("Unsupported language: {}", language.as_ref()),
         };
-        let bin_dir = binary_file
-            .parent()
-            .expect("Failed to get parent directory of binary file")
-            .canonicalize()?
-            .to_string_lossy()
-            .into_owned();
-        let wrapper_binary = binary_script_file
-            .canonicalize()?
-            .to_string_lossy()
-            .into_owned();
+        launcher.push(runtime::canonicalize(&binary_script_file)?);
+        fs::write(&launcher_file, serde_json::to_string(&launcher)?)?;
+
+        let bin_dir = runtime::canonicalize(&bin_dir)?;
+        let run = launcher
+            .iter()
+            .map(|arg| format!("\"{arg}\""))
+            .collect::<Vec<_>>()
+            .join(" ");
         let content = formatdoc!(
             r#"
 						@echo off
@@ -699,7 +1225,7 @@ impl Functions {
 
 						set "bin_dir={bin_dir}"
 
-						{run} "{wrapper_binary}" %*"#,
+						{run} %*"#,
         );
 
         let mut file = File::create(&binary_file)?;
@@ -731,23 +1257,21 @@ impl Functions {
         let embedded_file = FunctionAssets::get(&format!(
             "scripts/run-{}.{}",
             binary_type.as_ref().to_lowercase(),
-            language.to_extension()
+            language.runner_extension()
         ))
         .ok_or_else(|| {
             anyhow!(
                 "Failed to load embedded script for run-{}.{}",
                 binary_type.as_ref().to_lowercase(),
-                language.to_extension()
+                language.runner_extension()
             )
         })?;
         let content_template = unsafe { std::str::from_utf8_unchecked(&embedded_file.data) };
+        let tool_dir = Config::tool_dir(&format!("{binary_name}.{}", language.to_extension()));
         let content = match binary_type {
             BinaryType::Tool(None) => {
                 let root_dir = Config::functions_dir();
-                let tool_path = format!(
-                    "{}/{binary_name}",
-                    &Config::global_tools_dir().to_string_lossy()
-                );
+                let tool_path = format!("{}/{binary_name}", &tool_dir.to_string_lossy());
                 content_template
                     .replace("{function_name}", binary_name)
                     .replace("{root_dir}", &root_dir.to_string_lossy())
@@ -755,10 +1279,7 @@ impl Functions {
             }
             BinaryType::Tool(Some(agent_name)) => {
                 let root_dir = Config::agent_data_dir(agent_name);
-                let tool_path = format!(
-                    "{}/{binary_name}",
-                    &Config::global_tools_dir().to_string_lossy()
-                );
+                let tool_path = format!("{}/{binary_name}", &tool_dir.to_string_lossy());
                 content_template
                     .replace("{function_name}", binary_name)
                     .replace("{root_dir}", &root_dir.to_string_lossy())
@@ -771,7 +1292,23 @@ impl Functions {
         .replace(
             "{prompt_utils_file}",
             &Config::bash_prompt_utils_file().to_string_lossy(),
-        );
+        )
+        .replace("{tool_extension}", language.to_extension());
+        let (requirements_file, venv_dir) = match language {
+            Language::Python => Self::python_venv(binary_name, &binary_type)?.unwrap_or_default(),
+            _ => Default::default(),
+        };
+        let content = content
+            .replace("{requirements_file}", &requirements_file.to_string_lossy())
+            .replace("{venv_dir}", &venv_dir.to_string_lossy());
+        let content = match language {
+            Language::TypeScript => content.replacen(
+                "#!/usr/bin/env node",
+                &format!("#!/usr/bin/env -S node {TYPESCRIPT_NODE_FLAGS}"),
+                1,
+            ),
+            _ => content,
+        };
         if binary_file.exists() {
             fs::remove_file(&binary_file)?;
         }
@@ -791,6 +1328,9 @@ pub struct FunctionDeclaration {
     pub parameters: JsonSchema,
     #[serde(skip_serializing, default)]
     pub agent: bool,
+    /// Whether the tool's results can be reused for identical calls (`@meta cacheable`).
+    #[serde(skip_serializing, default)]
+    pub cacheable: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -869,49 +1409,174 @@ impl ToolCall {
         self
     }
 
-    pub async fn eval(&self, config: &GlobalConfig) -> Result<Value> {
-        let (call_name, cmd_name, mut cmd_args, envs) = match &config.read().agent {
-            Some(agent) => self.extract_call_config_from_agent(config, agent)?,
-            None => self.extract_call_config_from_config(config)?,
+    /// Prints the call and checks it against the agent's permissions and the dangerous tool
+    /// patterns, asking the user when needed. Returns the result to send back to the model
+    /// instead of running the call, if it isn't allowed to run.
+    pub fn authorize(&self, config: &GlobalConfig) -> Result<Option<Value>> {
+        let (call_name, _, _, _) = self.extract_call_config(config)?;
+        let json_data = self.parse_arguments(config, &call_name)?;
+
+        if *IS_STDOUT_TERMINAL && config.read().current_depth == 0 && !is_quiet() {
+            print_tool_call(&self.name, &json_data, config.read().expand_tool_calls);
+        }
+
+        let denied = self.check_permission(config, &json_data)?;
+        if let Some(output) = &denied {
+            ToolCallRecord::skipped(&self.name, &json_data, output, ToolCallOutcome::Denied)
+                .log(config);
+        }
+        Ok(denied)
+    }
+
+    /// The call to run for this one. A call to a `mapping_tools` alias with preset arguments is
+    /// a call to the tool it stands for, with the preset arguments filled in.
+    fn resolve_preset(&self, config: &GlobalConfig) -> Self {
+        let Some((preset, _)) = config.read().find_tool_preset(&self.name) else {
+            return self.clone();
         };
-        let agent_name = config
-            .read()
-            .agent
-            .as_ref()
-            .map(|agent| agent.name().to_owned());
+        let mut arguments = match &self.arguments {
+            Value::String(arguments) if arguments.trim().is_empty() => json!({}),
+            Value::String(arguments) => {
+                serde_json::from_str(arguments).unwrap_or_else(|_| self.arguments.clone())
+            }
+            Value::Null => json!({}),
+            arguments => arguments.clone(),
+        };
+        // Invalid arguments are passed along as-is, to be reported when the tool is called
+        if let Some(arguments) = arguments.as_object_mut() {
+            preset.apply(arguments);
+        }
+        Self {
+            name: preset.tool,
+            arguments,
+            ..self.clone()
+        }
+    }
 
-        let json_data = if self.arguments.is_object() {
-            self.arguments.clone()
-        } else if let Some(arguments) = self.arguments.as_str() {
-            let arguments: Value = serde_json::from_str(arguments).map_err(|_| {
-                anyhow!("The call '{call_name}' has invalid arguments: {arguments}")
-            })?;
-            arguments
-        } else {
-            bail!(
-                "The call '{call_name}' has invalid arguments: {}",
-                self.arguments
-            );
+    /// Runs the call. It must have been approved with [`ToolCall::authorize`] first.
+    pub async fn eval(&self, config: &GlobalConfig, abort_signal: AbortSignal) -> Result<Value> {
+        let started = Instant::now();
+        let (call_name, cmd_name, mut cmd_args, mut envs) = self.extract_call_config(config)?;
+        let (agent_name, sandbox) = match &config.read().agent {
+            Some(agent) => (Some(agent.name().to_owned()), agent.sandbox()),
+            None => (None, None),
+        };
+        let timeout = config.read().tool_timeout(&self.name);
+        let stream_output = config.read().stream_tool_output;
+        let (cwd, tool_sandbox, wasm_dirs, tool_secrets) = match config.read().tools.get(&self.name)
+        {
+            Some(tool_config) => {
+                envs.extend(tool_config.env.clone());
+                (
+                    tool_config.cwd.clone(),
+                    tool_config.sandbox(),
+                    tool_config.wasm_dirs.clone(),
+                    tool_config.secrets.clone(),
+                )
+            }
+            None => (None, None, vec![], vec![]),
+        };
+        let sandbox = sandbox.or(tool_sandbox);
+        let is_native = self.is_native(config);
+        let wasm_module = self.wasm_module(config);
+        let cache_ttl = match self.is_cacheable(config) {
+            true => config.read().tool_cache_ttl(&self.name),
+            false => None,
         };
 
+        let json_data = self.parse_arguments(config, &call_name)?;
         cmd_args.push(json_data.to_string());
 
-        let prompt = format!("Call {cmd_name} {}", cmd_args.join(" "));
+        if config.read().dry_run_tools && !self.is_builtin() {
+            let mcp_invoke_prefix = format!("{MCP_INVOKE_META_FUNCTION_NAME_PREFIX}_");
+            let preview = if let Some(server) = cmd_name.strip_prefix(&mcp_invoke_prefix) {
+                DryRunPreview::from_mcp_invocation(&self.name, server, &json_data)
+            } else if is_native {
+                DryRunPreview::from_native_tool(&self.name, &json_data, cwd.as_deref())
+            } else if let Some(module_path) = &wasm_module {
+                DryRunPreview::from_wasm_module(
+                    &call_name,
+                    module_path,
+                    &json_data.to_string(),
+                    &envs,
+                    &wasm_dirs,
+                )
+            } else {
+                match LlmFunctionCommand::new(
+                    cmd_name,
+                    cmd_args,
+                    envs,
+                    agent_name,
+                    sandbox.as_ref(),
+                    cwd.as_deref(),
+                ) {
+                    Ok(command) => DryRunPreview::from_command(&self.name, &command.command),
+                    Err(err) => return Ok(json!({"tool_call_error": err.to_string()})),
+                }
+            };
+            preview.print();
+            let output = preview.to_tool_result();
+            ToolCallRecord::skipped(&self.name, &json_data, &output, ToolCallOutcome::DryRun)
+                .log(config);
+            return Ok(output);
+        }
 
-        if *IS_STDOUT_TERMINAL && config.read().current_depth == 0 {
-            println!("{}", dimmed_text(&prompt));
+        if let Some(ttl) = cache_ttl
+            && let Some(output) = config
+                .read()
+                .cached_tool_result(&self.name, &json_data, ttl)
+        {
+            debug!("Reusing the cached result of {call_name}");
+            ToolCallRecord::new(&self.name, &json_data, &output, None, started.elapsed())
+                .with_outcome(ToolCallOutcome::Cached)
+                .log(config);
+            return Ok(output);
         }
 
+        // Secrets are only filled in now, so the model, the tool call log, and the cache only
+        // ever see their placeholders. They only go into the environment, from placeholders in the
+        // config and the tool's own `secrets`, never into the arguments the model wrote. Built-in
+        // functions never get them, since they may keep what they're given. Once injected, a
+        // secret is redacted from the output of every tool.
+        let envs = if self.is_builtin() {
+            envs
+        } else {
+            let vault = config.read().vault.clone();
+            let mut secrets = InjectedSecrets::default();
+            let mut envs = envs
+                .into_iter()
+                .map(|(key, value)| (key, secrets.inject(&value, &vault)))
+                .collect::<HashMap<_, _>>();
+            for name in tool_secrets {
+                match secrets.get(&name, &vault) {
+                    Some(value) => {
+                        envs.insert(name, value);
+                    }
+                    None => {
+                        return Ok(json!({
+                            "tool_call_error": format!("The secret '{name}' isn't in the vault")
+                        }));
+                    }
+                }
+            }
+            config.write().injected_secrets.extend(secrets);
+            envs
+        };
+
+        let wasm_json_data = json_data.to_string();
+        let mut exit_code = None;
         let output = match cmd_name.as_str() {
             _ if cmd_name.starts_with(MCP_SEARCH_META_FUNCTION_NAME_PREFIX) => {
-                Self::search_mcp_tools(config, &cmd_name, &json_data).unwrap_or_else(|e| {
-                    let error_msg = format!("MCP search failed: {e}");
-                    eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
-                    json!({"tool_call_error": error_msg})
-                })
+                Self::search_mcp_tools(config, &cmd_name, &json_data, abort_signal)
+                    .await
+                    .unwrap_or_else(|e| {
+                        let error_msg = format!("MCP search failed: {e}");
+                        eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
+                        json!({"tool_call_error": error_msg})
+                    })
             }
             _ if cmd_name.starts_with(MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX) => {
-                Self::describe_mcp_tool(config, &cmd_name, json_data)
+                Self::describe_mcp_tool(config, &cmd_name, json_data.clone(), abort_signal)
                     .await
                     .unwrap_or_else(|e| {
                         let error_msg = format!("MCP describe failed: {e}");
@@ -920,7 +1585,7 @@ impl ToolCall {
                     })
             }
             _ if cmd_name.starts_with(MCP_INVOKE_META_FUNCTION_NAME_PREFIX) => {
-                Self::invoke_mcp_tool(config, &cmd_name, &json_data)
+                Self::invoke_mcp_tool(config, &cmd_name, &json_data, abort_signal)
                     .await
                     .unwrap_or_else(|e| {
                         let error_msg = format!("MCP tool invocation failed: {e}");
@@ -935,6 +1600,22 @@ impl ToolCall {
                     json!({"tool_call_error": error_msg})
                 })
             }
+            _ if cmd_name.starts_with(MEMORY_FUNCTION_PREFIX) => {
+                memory::handle_memory_tool(config, &cmd_name, &json_data)
+                    .await
+                    .unwrap_or_else(|e| {
+                        let error_msg = format!("Memory tool failed: {e}");
+                        eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
+                        json!({"tool_call_error": error_msg})
+                    })
+            }
+            _ if cmd_name == LOAD_SKILL_FUNCTION_NAME => {
+                skill::handle_skill_tool(config, &json_data).unwrap_or_else(|e| {
+                    let error_msg = format!("Loading skill failed: {e}");
+                    eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
+                    json!({"tool_call_error": error_msg})
+                })
+            }
             _ if cmd_name.starts_with(SUPERVISOR_FUNCTION_PREFIX) => {
                 supervisor::handle_supervisor_tool(config, &cmd_name, &json_data)
                     .await
@@ -953,24 +1634,277 @@ impl ToolCall {
                         json!({"tool_call_error": error_msg})
                     })
             }
-            _ => match run_llm_function(cmd_name, cmd_args, envs, agent_name) {
-                Ok(Some(contents)) => serde_json::from_str(&contents)
-                    .ok()
-                    .unwrap_or_else(|| json!({"output": contents})),
-                Ok(None) => Value::Null,
+            // Native tools run in-process, so they'd get around the sandbox
+            _ if is_native && sandbox.is_some() => {
+                let error_msg =
+                    format!("'{call_name}' is a native tool and can't run in a sandbox");
+                eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
+                json!({"tool_call_error": error_msg})
+            }
+            _ if is_native => {
+                let run = native::run_native_tool(config, &call_name, &json_data, cwd.as_deref());
+                let result = match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, run)
+                        .await
+                        .unwrap_or_else(|_| Err(anyhow!("timed out after {}s", timeout.as_secs()))),
+                    None => run.await,
+                };
+                match result {
+                    Ok(output) => json!({"output": output}),
+                    Err(e) => {
+                        let error_msg = format!("Tool call '{call_name}' failed: {e}");
+                        eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
+                        json!({"tool_call_error": error_msg})
+                    }
+                }
+            }
+            _ => match tokio::task::spawn_blocking(move || match wasm_module {
+                Some(module_path) => wasm::run_wasm_function(
+                    &module_path,
+                    &call_name,
+                    wasm_json_data,
+                    envs,
+                    &wasm_dirs,
+                    timeout,
+                ),
+                None => LlmFunctionCommand::new(
+                    cmd_name,
+                    cmd_args,
+                    envs,
+                    agent_name,
+                    sandbox.as_ref(),
+                    cwd.as_deref(),
+                )
+                .and_then(|command| command.run(timeout, stream_output)),
+            })
+            .await?
+            {
+                Ok(ToolRunOutput {
+                    output: Some(contents),
+                    exit_code: code,
+                    progress,
+                }) => {
+                    exit_code = code;
+                    let (contents, progress) = {
+                        let injected_secrets = &config.read().injected_secrets;
+                        (
+                            injected_secrets.redact(&contents),
+                            progress.map(|progress| injected_secrets.redact(&progress.to_string())),
+                        )
+                    };
+                    let mut output = serde_json::from_str(&contents)
+                        .ok()
+                        .unwrap_or_else(|| json!({"output": contents}));
+                    if let Some(progress) = progress
+                        && let Some(output) = output.as_object_mut()
+                    {
+                        output.insert("progress".into(), json!(progress));
+                    }
+                    if let Some(ttl) = cache_ttl
+                        && output.get("tool_call_error").is_none()
+                    {
+                        config.write().cache_tool_result(
+                            &self.name,
+                            &json_data,
+                            output.clone(),
+                            ttl,
+                        );
+                    }
+                    output
+                }
+                Ok(ToolRunOutput {
+                    output: None,
+                    exit_code: code,
+                    ..
+                }) => {
+                    exit_code = code;
+                    Value::Null
+                }
                 Err(e) => serde_json::from_str(&e.to_string())
                     .ok()
                     .unwrap_or_else(|| json!({"output": e.to_string()})),
             },
         };
+        let output = config.read().injected_secrets.redact_json(output);
+        ToolCallRecord::new(
+            &self.name,
+            &json_data,
+            &output,
+            exit_code,
+            started.elapsed(),
+        )
+        .log(config);
 
         Ok(output)
     }
 
+    fn check_permission(&self, config: &GlobalConfig, json_data: &Value) -> Result<Option<Value>> {
+        let ((permission, denied_by), can_ask_user) = {
+            let cfg = config.read();
+            let agent_permissions = cfg
+                .agent
+                .as_ref()
+                .map(|agent| agent.permissions())
+                .filter(|permissions| !permissions.is_empty());
+            let policy = cfg.tool_policy.permissions();
+            // The agent's permissions take precedence over the global tool policy
+            let resolve =
+                |name: &str| match agent_permissions.and_then(|v| find_tool_permission(v, name)) {
+                    Some(permission) => (permission, "The agent's permissions deny"),
+                    None => (
+                        resolve_tool_permission(&policy, name),
+                        "The tool policy denies",
+                    ),
+                };
+            let mut resolved = resolve(&self.name);
+            if self.name.starts_with(MCP_INVOKE_META_FUNCTION_NAME_PREFIX)
+                && let Some(tool) = json_data.get("tool").and_then(Value::as_str)
+            {
+                let tool_resolved = resolve(tool);
+                if tool_resolved.0 > resolved.0 {
+                    resolved = tool_resolved;
+                }
+            }
+            (resolved, cfg.can_ask_user())
+        };
+
+        let error_msg = match permission {
+            ToolPermission::Allow => {
+                return self.check_dangerous_call(config, json_data, can_ask_user);
+            }
+            ToolPermission::Deny => format!("{denied_by} calling '{}'", self.name),
+            ToolPermission::Ask if can_ask_user => {
+                let approved = Confirm::new(&format!("Allow the call to '{}'?", self.name))
+                    .with_default(false)
+                    .prompt()?;
+                if approved {
+                    return Ok(None);
+                }
+                format!("The user denied the call to '{}'", self.name)
+            }
+            ToolPermission::Ask => format!(
+                "Calling '{}' requires user approval, but no interactive terminal is available",
+                self.name
+            ),
+        };
+        eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
+        Ok(Some(json!({"tool_call_error": error_msg})))
+    }
+
+    /// Whether this is one of Loki's built-in functions rather than a tool script or MCP tool.
+    fn is_builtin(&self) -> bool {
+        [
+            MCP_SEARCH_META_FUNCTION_NAME_PREFIX,
+            MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX,
+            TODO_FUNCTION_PREFIX,
+            MEMORY_FUNCTION_PREFIX,
+            LOAD_SKILL_FUNCTION_NAME,
+            SUPERVISOR_FUNCTION_PREFIX,
+            USER_FUNCTION_PREFIX,
+        ]
+        .iter()
+        .any(|prefix| self.name.starts_with(prefix))
+    }
+
+    /// The call's arguments, with any `args_defaults` from the tool's config filled in.
+    fn parse_arguments(&self, config: &GlobalConfig, call_name: &str) -> Result<Value> {
+        let mut arguments = if self.arguments.is_object() {
+            self.arguments.clone()
+        } else if let Some(arguments) = self.arguments.as_str() {
+            serde_json::from_str(arguments)
+                .map_err(|_| anyhow!("The call '{call_name}' has invalid arguments: {arguments}"))?
+        } else {
+            bail!(
+                "The call '{call_name}' has invalid arguments: {}",
+                self.arguments
+            );
+        };
+        if let Some(tool_config) = config.read().tools.get(&self.name)
+            && let Some(arguments) = arguments.as_object_mut()
+        {
+            for (key, value) in &tool_config.args_defaults {
+                if arguments.get(key).is_none_or(Value::is_null) {
+                    arguments.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Ok(arguments)
+    }
+
+    fn check_dangerous_call(
+        &self,
+        config: &GlobalConfig,
+        json_data: &Value,
+        can_ask_user: bool,
+    ) -> Result<Option<Value>> {
+        if self.is_builtin() {
+            return Ok(None);
+        }
+
+        let tool_name = match json_data.get("tool").and_then(Value::as_str) {
+            Some(tool) if self.name.starts_with(MCP_INVOKE_META_FUNCTION_NAME_PREFIX) => {
+                format!("{}:{tool}", self.name)
+            }
+            _ => self.name.clone(),
+        };
+        // "Always allow" only covers the pattern it was chosen for, not every call to the tool
+        let approval_key = |pattern: &str| format!("{tool_name}#{pattern}");
+        let (pattern, deny_without_tty) = {
+            let cfg = config.read();
+            let pattern = cfg
+                .dangerous_tool_patterns
+                .find(&tool_name, json_data, |pattern| {
+                    cfg.is_tool_approved(&approval_key(pattern))
+                });
+            match pattern {
+                Some(pattern) => (pattern.to_string(), cfg.deny_dangerous_tools_without_tty),
+                None => return Ok(None),
+            }
+        };
+
+        let error_msg = if can_ask_user {
+            println!(
+                "{}",
+                warning_text(&format!(
+                    "'{tool_name}' matches the dangerous tool pattern '{pattern}':"
+                ))
+            );
+            println!("{}", serde_json::to_string_pretty(json_data)?);
+            let choice = Select::new(
+                "Run this tool call?",
+                vec![APPROVE_OPTION, DENY_OPTION, ALWAYS_ALLOW_OPTION],
+            )
+            .prompt()?;
+            match choice {
+                APPROVE_OPTION => return Ok(None),
+                ALWAYS_ALLOW_OPTION => {
+                    config.write().approve_tool(&approval_key(&pattern));
+                    return Ok(None);
+                }
+                _ => format!("The user denied the call to '{tool_name}'"),
+            }
+        } else if !deny_without_tty {
+            eprintln!(
+                "{}",
+                warning_text(&format!(
+                    "⚠️ The call to '{tool_name}' matches the dangerous tool pattern '{pattern}'; running it since no interactive terminal is available to approve it ⚠️"
+                ))
+            );
+            return Ok(None);
+        } else {
+            format!(
+                "The call to '{tool_name}' matches the dangerous tool pattern '{pattern}' and requires user approval, but no interactive terminal is available"
+            )
+        };
+        eprintln!("{}", warning_text(&format!("⚠️ {error_msg} ⚠️")));
+        Ok(Some(json!({"tool_call_error": error_msg})))
+    }
+
     async fn describe_mcp_tool(
         config: &GlobalConfig,
         cmd_name: &str,
         json_data: Value,
+        abort_signal: AbortSignal,
     ) -> Result<Value> {
         let server_id = cmd_name.replace(&format!("{MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX}_"), "");
         let tool = json_data
@@ -978,6 +1912,7 @@ impl ToolCall {
             .ok_or_else(|| anyhow!("Missing 'tool' in arguments"))?
             .as_str()
             .ok_or_else(|| anyhow!("Invalid 'tool' in arguments"))?;
+        McpRegistry::start_deferred_server(config, &server_id, abort_signal).await?;
         let registry_arc = {
             let cfg = config.read();
             cfg.mcp_registry
@@ -989,7 +1924,12 @@ impl ToolCall {
         Ok(serde_json::to_value(result)?)
     }
 
-    fn search_mcp_tools(config: &GlobalConfig, cmd_name: &str, json_data: &Value) -> Result<Value> {
+    async fn search_mcp_tools(
+        config: &GlobalConfig,
+        cmd_name: &str,
+        json_data: &Value,
+        abort_signal: AbortSignal,
+    ) -> Result<Value> {
         let server = cmd_name.replace(&format!("{MCP_SEARCH_META_FUNCTION_NAME_PREFIX}_"), "");
         let query = json_data
             .get("query")
@@ -1002,6 +1942,7 @@ impl ToolCall {
             .unwrap_or_else(|| Value::from(8u64))
             .as_u64()
             .ok_or_else(|| anyhow!("Invalid 'top_k' in arguments"))? as usize;
+        McpRegistry::start_deferred_server(config, &server, abort_signal).await?;
         let registry_arc = {
             let cfg = config.read();
             cfg.mcp_registry
@@ -1021,6 +1962,7 @@ impl ToolCall {
         config: &GlobalConfig,
         cmd_name: &str,
         json_data: &Value,
+        abort_signal: AbortSignal,
     ) -> Result<Value> {
         let server = cmd_name.replace(&format!("{MCP_INVOKE_META_FUNCTION_NAME_PREFIX}_"), "");
         let tool = json_data
@@ -1032,6 +1974,7 @@ impl ToolCall {
             .get("arguments")
             .cloned()
             .unwrap_or_else(|| json!({}));
+        McpRegistry::start_deferred_server(config, &server, abort_signal).await?;
         let registry_arc = {
             let cfg = config.read();
             cfg.mcp_registry
@@ -1043,6 +1986,46 @@ impl ToolCall {
         Ok(serde_json::to_value(result)?)
     }
 
+    /// Whether the call's tool declared its results `cacheable`.
+    fn is_cacheable(&self, config: &GlobalConfig) -> bool {
+        let cfg = config.read();
+        cfg.agent
+            .as_ref()
+            .and_then(|agent| agent.functions().find(&self.name))
+            .or_else(|| cfg.functions.find(&self.name))
+            .is_some_and(|function| function.cacheable)
+    }
+
+    /// Whether the call is to one of the native tools.
+    fn is_native(&self, config: &GlobalConfig) -> bool {
+        let cfg = config.read();
+        match cfg
+            .agent
+            .as_ref()
+            .filter(|agent| agent.functions().contains(&self.name))
+        {
+            Some(agent) => agent.functions().is_native(&self.name),
+            None => cfg.functions.is_native(&self.name),
+        }
+    }
+
+    /// The module implementing the call, if it's a WASM tool.
+    fn wasm_module(&self, config: &GlobalConfig) -> Option<PathBuf> {
+        let cfg = config.read();
+        cfg.agent
+            .as_ref()
+            .and_then(|agent| agent.functions().wasm_module(&self.name))
+            .or_else(|| cfg.functions.wasm_module(&self.name))
+            .cloned()
+    }
+
+    fn extract_call_config(&self, config: &GlobalConfig) -> Result<CallConfig> {
+        match &config.read().agent {
+            Some(agent) => self.extract_call_config_from_agent(config, agent),
+            None => self.extract_call_config_from_config(config),
+        }
+    }
+
     fn extract_call_config_from_agent(
         &self,
         config: &GlobalConfig,
@@ -1060,6 +2043,7 @@ impl ToolCall {
                         agent.variable_envs(),
                     ))
                 } else {
+                    let function_name = agent.functions().bare_name(&function_name).to_string();
                     Ok((
                         function_name.clone(),
                         function_name,
@@ -1074,124 +2058,448 @@ impl ToolCall {
 
     fn extract_call_config_from_config(&self, config: &GlobalConfig) -> Result<CallConfig> {
         let function_name = self.name.clone();
-        match config.read().functions.contains(&function_name) {
-            true => Ok((
-                function_name.clone(),
-                function_name,
-                vec![],
-                Default::default(),
-            )),
+        let cfg = config.read();
+        match cfg.functions.contains(&function_name) {
+            true => {
+                let function_name = cfg.functions.bare_name(&function_name).to_string();
+                Ok((
+                    function_name.clone(),
+                    function_name,
+                    vec![],
+                    Default::default(),
+                ))
+            }
             false => bail!("Unexpected call: {function_name} {}", self.arguments),
         }
     }
 }
 
-pub fn run_llm_function(
-    cmd_name: String,
-    cmd_args: Vec<String>,
-    mut envs: HashMap<String, String>,
-    agent_name: Option<String>,
-) -> Result<Option<String>> {
-    let mut bin_dirs: Vec<PathBuf> = vec![];
-    let mut command_name = cmd_name.clone();
-    if let Some(agent_name) = agent_name {
-        command_name = cmd_args[0].clone();
-        let dir = Config::agent_bin_dir(&agent_name);
-        if dir.exists() {
-            bin_dirs.push(dir);
-        }
-    } else {
-        bin_dirs.push(Config::functions_bin_dir());
-    }
-    let current_path = env::var("PATH").context("No PATH environment variable")?;
-    let prepend_path = bin_dirs
-        .iter()
-        .map(|v| format!("{}{PATH_SEP}", v.display()))
-        .collect::<Vec<_>>()
-        .join("");
-    envs.insert("PATH".into(), format!("{prepend_path}{current_path}"));
-
-    let temp_file = temp_file("-eval-", "");
-    envs.insert("LLM_OUTPUT".into(), temp_file.display().to_string());
-
-    #[cfg(windows)]
-    let cmd_name = polyfill_cmd_name(&cmd_name, &bin_dirs);
+/// The command that runs a tool, and the files it reports its output, errors, and progress
+/// through.
+struct LlmFunctionCommand {
+    command: Command,
+    command_name: String,
+    output_file: PathBuf,
+    error_file: PathBuf,
+    progress_file: PathBuf,
+    /// The container the tool runs in, if it's sandboxed.
+    container: Option<String>,
+}
 
-    envs.insert("CLICOLOR_FORCE".into(), "1".into());
-    envs.insert("FORCE_COLOR".into(), "1".into());
+impl LlmFunctionCommand {
+    fn new(
+        cmd_name: String,
+        cmd_args: Vec<String>,
+        mut envs: HashMap<String, String>,
+        agent_name: Option<String>,
+        sandbox: Option<&DockerSandbox>,
+        cwd: Option<&Path>,
+    ) -> Result<Self> {
+        let mut bin_dirs: Vec<PathBuf> = vec![];
+        // What a sandboxed tool can read besides its work dir: its runner, its own script, and
+        // the shared utils, rather than the whole config dir with its secrets and sessions
+        let mut sandbox_mounts: Vec<PathBuf> = vec![Config::global_utils_dir()];
+        let mut command_name = cmd_name.clone();
+        if let Some(agent_name) = agent_name {
+            command_name = cmd_args[0].clone();
+            let dir = Config::agent_bin_dir(&agent_name);
+            if dir.exists() {
+                bin_dirs.push(dir);
+            }
+            sandbox_mounts.push(Config::agent_data_dir(&agent_name));
+        } else {
+            bin_dirs.push(Config::functions_bin_dir());
+            sandbox_mounts.push(Config::functions_bin_dir());
+            sandbox_mounts.extend(
+                ["sh", "py", "js", "ts", "ps1"]
+                    .into_iter()
+                    .map(|ext| format!("{cmd_name}.{ext}"))
+                    .map(|file| Config::tool_dir(&file).join(file))
+                    .find(|path| path.exists()),
+            );
+        }
+        let current_path = env::var("PATH").context("No PATH environment variable")?;
+        let prepend_path = bin_dirs
+            .iter()
+            .map(|v| format!("{}{PATH_SEP}", v.display()))
+            .collect::<Vec<_>>()
+            .join("");
+        envs.insert("PATH".into(), format!("{prepend_path}{current_path}"));
+
+        let error_file = temp_file("-error-", "");
+        envs.insert("LLM_ERROR".into(), error_file.display().to_string());
+        let output_file = temp_file("-eval-", "");
+        envs.insert("LLM_OUTPUT".into(), output_file.display().to_string());
+        let progress_file = temp_file("-progress-", "");
+        envs.insert("LLM_PROGRESS".into(), progress_file.display().to_string());
+
+        envs.insert("CLICOLOR_FORCE".into(), "1".into());
+        envs.insert("FORCE_COLOR".into(), "1".into());
+
+        let (command, container) = match sandbox {
+            Some(sandbox) => {
+                let (command, container) = sandbox.command(
+                    &cmd_name,
+                    &cmd_args,
+                    &envs,
+                    &bin_dirs,
+                    &sandbox_mounts,
+                    &[&output_file, &error_file, &progress_file],
+                )?;
+                (command, Some(container))
+            }
+            None => {
+                #[cfg(windows)]
+                let mut command = match windows_launcher(&cmd_name, &bin_dirs) {
+                    Some(launcher) => {
+                        let mut command = Command::new(&launcher[0]);
+                        command.args(&launcher[1..]);
+                        command
+                    }
+                    None => Command::new(polyfill_cmd_name(&cmd_name, &bin_dirs)),
+                };
+                #[cfg(not(windows))]
+                let mut command = Command::new(&cmd_name);
+                command.args(&cmd_args).envs(envs);
+                if let Some(cwd) = cwd {
+                    if !cwd.is_dir() {
+                        bail!(
+                            "The working directory '{}' configured for {command_name} doesn't exist",
+                            cwd.display()
+                        );
+                    }
+                    command.current_dir(cwd);
+                }
+                (command, None)
+            }
+        };
 
-    let mut child = Command::new(&cmd_name)
-        .args(&cmd_args)
-        .envs(envs)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|err| anyhow!("Unable to run {command_name}, {err}"))?;
+        Ok(Self {
+            command,
+            command_name,
+            output_file,
+            error_file,
+            progress_file,
+            container,
+        })
+    }
 
-    let stdout = child.stdout.take().expect("Failed to capture stdout");
-    let mut stderr = child.stderr.take().expect("Failed to capture stderr");
+    /// Runs the tool, killing it if it's still running after `timeout`. With `stream_output`,
+    /// what it prints is shown as it runs.
+    fn run(mut self, timeout: Option<Duration>, stream_output: bool) -> Result<ToolRunOutput> {
+        let command_name = self.command_name.clone();
+        let mut child = self
+            .command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| match self.container.is_some() {
+                true => anyhow!("Unable to run {command_name} in the docker sandbox, {err}"),
+                false => anyhow!("Unable to run {command_name}, {err}"),
+            })?;
 
-    let stdout_thread = std::thread::spawn(move || {
-        let mut buffer = [0; 1024];
-        let mut reader = stdout;
-        let mut out = io::stdout();
-        while let Ok(n) = reader.read(&mut buffer) {
-            if n == 0 {
-                break;
+        let mut stdout = child.stdout.take().expect("Failed to capture stdout");
+        let mut stderr = child.stderr.take().expect("Failed to capture stderr");
+        let live_output = (stream_output && *IS_STDOUT_TERMINAL && !is_quiet())
+            .then(|| Arc::new(LiveOutput::new()));
+
+        let stdout_live_output = live_output.clone();
+        let stdout_thread = std::thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            let mut out = io::stdout();
+            while let Ok(n) = stdout.read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+                let chunk = &buffer[0..n];
+                if let Some(live_output) = &stdout_live_output {
+                    live_output.write(chunk);
+                    continue;
+                }
+                if !stream_output {
+                    continue;
+                }
+                let mut last_pos = 0;
+                for (i, &byte) in chunk.iter().enumerate() {
+                    if byte == b'\n' {
+                        let _ = out.write_all(&chunk[last_pos..i]);
+                        let _ = out.write_all(b"\r\n");
+                        last_pos = i + 1;
+                    }
+                }
+                if last_pos < n {
+                    let _ = out.write_all(&chunk[last_pos..n]);
+                }
+                let _ = out.flush();
             }
-            let chunk = &buffer[0..n];
-            let mut last_pos = 0;
-            for (i, &byte) in chunk.iter().enumerate() {
-                if byte == b'\n' {
-                    let _ = out.write_all(&chunk[last_pos..i]);
-                    let _ = out.write_all(b"\r\n");
-                    last_pos = i + 1;
+        });
+
+        let stderr_live_output = live_output.clone();
+        let stderr_thread = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            let mut buffer = [0; 1024];
+            while let Ok(n) = stderr.read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+                if let Some(live_output) = &stderr_live_output {
+                    live_output.write(&buffer[0..n]);
                 }
+                buf.extend_from_slice(&buffer[0..n]);
+            }
+            buf
+        });
+
+        let progress_watcher =
+            ProgressWatcher::start(self.progress_file.clone(), live_output.clone());
+
+        let status = match timeout {
+            Some(timeout) => wait_with_timeout(&mut child, timeout),
+            None => child.wait().map(Some),
+        }
+        .map_err(|err| anyhow!("Unable to run {command_name}, {err}"))?;
+        // The container outlives the `docker` command when that's killed, by the timeout or a
+        // signal, rather than exiting with the tool
+        if let Some(container) = &self.container
+            && status.is_none_or(|status| status.code().is_none())
+        {
+            kill_container(container);
+        }
+        let progress = progress_watcher.finish();
+        let Some(status) = status else {
+            // The output threads are left to finish on their own, since a process the tool
+            // detached from its tree may still be holding the pipes open
+            drop((stdout_thread, stderr_thread));
+            let tool_error_message = format!(
+                "Tool call '{command_name}' timed out after {}s and was killed",
+                timeout.unwrap_or_default().as_secs()
+            );
+            eprintln!("{}", warning_text(&format!("⚠️ {tool_error_message} ⚠️")));
+            return Ok(ToolRunOutput {
+                output: Some(json!({"tool_call_error": tool_error_message}).to_string()),
+                exit_code: None,
+                progress,
+            });
+        };
+        let _ = stdout_thread.join();
+        let stderr_bytes = stderr_thread.join().unwrap_or_default();
+
+        let exit_code = status.code().unwrap_or_default();
+        if let Some(tool_error) = ToolError::read(&self.error_file) {
+            let error_json = tool_error.to_tool_call_error(&command_name);
+            debug!("Tool call error: {error_json:?}");
+            return Ok(ToolRunOutput {
+                output: Some(error_json.to_string()),
+                exit_code: Some(exit_code),
+                progress,
+            });
+        }
+
+        if exit_code != 0 {
+            let stderr = String::from_utf8_lossy(&stderr_bytes).trim().to_string();
+            // Streamed stderr is already on screen
+            if !stderr.is_empty() && live_output.is_none() {
+                eprintln!("{stderr}");
             }
-            if last_pos < n {
-                let _ = out.write_all(&chunk[last_pos..n]);
+            let tool_error_message =
+                format!("Tool call '{command_name}' exited with code {exit_code}");
+            eprintln!("{}", warning_text(&format!("⚠️ {tool_error_message} ⚠️")));
+            let mut error_json = json!({"tool_call_error": tool_error_message});
+            if !stderr.is_empty() {
+                error_json["stderr"] = json!(stderr);
             }
-            let _ = out.flush();
+            debug!("Tool call error: {error_json:?}");
+            return Ok(ToolRunOutput {
+                output: Some(error_json.to_string()),
+                exit_code: Some(exit_code),
+                progress,
+            });
+        }
+        if let Some(live_output) = &live_output {
+            live_output.collapse();
         }
-    });
 
-    let stderr_thread = std::thread::spawn(move || {
-        let mut buf = Vec::new();
-        let _ = stderr.read_to_end(&mut buf);
-        buf
-    });
+        let mut output = None;
+        if self.output_file.exists() {
+            let contents = fs::read_to_string(&self.output_file)
+                .context("Failed to retrieve tool call output")?;
+            if !contents.is_empty() {
+                debug!("Tool {command_name} output: {}", contents);
+                output = Some(contents);
+            }
+        };
+        Ok(ToolRunOutput {
+            output,
+            exit_code: Some(exit_code),
+            progress,
+        })
+    }
+}
 
-    let status = child
-        .wait()
-        .map_err(|err| anyhow!("Unable to run {command_name}, {err}"))?;
-    let _ = stdout_thread.join();
-    let stderr_bytes = stderr_thread.join().unwrap_or_default();
+impl Drop for LlmFunctionCommand {
+    /// Removes the files the tool reported through, which the sandbox creates to mount them even
+    /// when the tool is never run.
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.output_file);
+        let _ = fs::remove_file(&self.error_file);
+        let _ = fs::remove_file(&self.progress_file);
+    }
+}
+
+pub fn run_llm_function(
+    cmd_name: String,
+    cmd_args: Vec<String>,
+    envs: HashMap<String, String>,
+    agent_name: Option<String>,
+    sandbox: Option<&DockerSandbox>,
+    timeout: Option<Duration>,
+    cwd: Option<&Path>,
+) -> Result<ToolRunOutput> {
+    LlmFunctionCommand::new(cmd_name, cmd_args, envs, agent_name, sandbox, cwd)?.run(timeout, true)
+}
+
+/// What a tool script or WASM module returned to the model.
+#[derive(Debug, Default)]
+pub struct ToolRunOutput {
+    pub output: Option<String>,
+    /// The code the tool exited with, or `None` if it was killed for taking too long.
+    pub exit_code: Option<i32>,
+    /// The last status the tool reported through `LLM_PROGRESS`.
+    pub progress: Option<ToolProgress>,
+}
+
+/// An error a tool reported by writing to `LLM_ERROR`, either as JSON or as plain text.
+#[derive(Debug, Deserialize)]
+struct ToolError {
+    error: String,
+    #[serde(default)]
+    hint: Option<String>,
+    #[serde(default)]
+    retryable: Option<bool>,
+}
 
-    let exit_code = status.code().unwrap_or_default();
-    if exit_code != 0 {
-        let stderr = String::from_utf8_lossy(&stderr_bytes).trim().to_string();
-        if !stderr.is_empty() {
-            eprintln!("{stderr}");
+impl ToolError {
+    fn read(error_file: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(error_file).ok()?;
+        let contents = contents.trim();
+        if contents.is_empty() {
+            return None;
         }
-        let tool_error_message = format!("Tool call '{command_name}' exited with code {exit_code}");
+        Some(serde_json::from_str(contents).unwrap_or_else(|_| Self {
+            error: contents.to_string(),
+            hint: None,
+            retryable: None,
+        }))
+    }
+
+    fn to_tool_call_error(&self, command_name: &str) -> Value {
+        let tool_error_message = format!("Tool call '{command_name}' failed: {}", self.error);
         eprintln!("{}", warning_text(&format!("⚠️ {tool_error_message} ⚠️")));
         let mut error_json = json!({"tool_call_error": tool_error_message});
-        if !stderr.is_empty() {
-            error_json["stderr"] = json!(stderr);
+        if let Some(hint) = &self.hint {
+            error_json["hint"] = json!(hint);
+        }
+        if let Some(retryable) = self.retryable {
+            error_json["retryable"] = json!(retryable);
+        }
+        error_json
+    }
+}
+
+/// Waits for the child to exit, killing it if it's still running after `timeout`. Returns
+/// `None` if it was killed.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            kill_process_tree(child)?;
+            child.wait()?;
+            return Ok(None);
         }
-        debug!("Tool call error: {error_json:?}");
-        return Ok(Some(error_json.to_string()));
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Kills the child and everything it started, since the runner runs the tool as a child of its
+/// own. The tree is found from the process table rather than by giving the tool a process group
+/// of its own, which would stop tools that prompt on the terminal.
+fn kill_process_tree(child: &mut Child) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let signal = |name: &str, pids: &[u32]| {
+            if pids.is_empty() {
+                return;
+            }
+            let _ = Command::new("kill")
+                .arg(format!("-{name}"))
+                .args(pids.iter().map(|pid| pid.to_string()))
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        };
+        // Stopped processes can't start any more, so the tree is read again once they're stopped
+        let mut pids = process_descendants(child.id());
+        pids.push(child.id());
+        signal("STOP", &pids);
+        signal("KILL", &process_descendants(child.id()));
     }
-    let mut output = None;
-    if temp_file.exists() {
-        let contents =
-            fs::read_to_string(temp_file).context("Failed to retrieve tool call output")?;
-        if !contents.is_empty() {
-            debug!("Tool {command_name} output: {}", contents);
-            output = Some(contents);
+    #[cfg(windows)]
+    {
+        let killed = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &child.id().to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if killed {
+            return Ok(());
         }
+    }
+    child.kill()
+}
+
+/// The processes started by `pid`, and by those, and so on.
+#[cfg(unix)]
+fn process_descendants(pid: u32) -> Vec<u32> {
+    let Ok(output) = Command::new("ps").args(["-A", "-o", "pid=,ppid="]).output() else {
+        return vec![];
     };
-    Ok(output)
+    let processes: Vec<(u32, u32)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+        })
+        .collect();
+    let mut pids = vec![pid];
+    let mut i = 0;
+    while i < pids.len() {
+        let parent = pids[i];
+        pids.extend(
+            processes
+                .iter()
+                .filter(|(_, ppid)| *ppid == parent)
+                .map(|(pid, _)| *pid),
+        );
+        i += 1;
+    }
+    pids.split_off(1)
+}
+
+/// The command line that runs a tool Loki built, read from its launcher file. Running it directly
+/// rather than through its `.cmd` wrapper keeps `cmd.exe` from mangling the JSON arguments.
+#[cfg(windows)]
+fn windows_launcher<T: AsRef<Path>>(cmd_name: &str, bin_dir: &[T]) -> Option<Vec<String>> {
+    bin_dir.iter().find_map(|dir| {
+        let path = dir
+            .as_ref()
+            .join(format!("{cmd_name}.{WINDOWS_LAUNCHER_EXTENSION}"));
+        let launcher: Vec<String> = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        (!launcher.is_empty()).then_some(launcher)
+    })
 }
 
 #[cfg(windows)]
@@ -1303,3 +2611,35 @@ impl ToolCallTracker {
         self.last_calls.push_back(call);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_with_timeout_kills_process_tree() {
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30 & wait"])
+            .spawn()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let descendants = process_descendants(child.id());
+        assert!(!descendants.is_empty());
+        let status = wait_with_timeout(&mut child, Duration::from_millis(100)).unwrap();
+        assert!(status.is_none());
+        std::thread::sleep(Duration::from_millis(200));
+        for pid in descendants {
+            // A killed process that nothing has reaped yet shows up as a zombie
+            let output = Command::new("ps")
+                .args(["-o", "stat=", "-p", &pid.to_string()])
+                .output()
+                .unwrap();
+            let state = String::from_utf8_lossy(&output.stdout);
+            assert!(
+                state.trim().is_empty() || state.starts_with('Z'),
+                "process {pid} is still running"
+            );
+        }
+    }
+}
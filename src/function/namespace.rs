@@ -0,0 +1,156 @@
+use crate::mcp::{
+    MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX, MCP_INVOKE_META_FUNCTION_NAME_PREFIX,
+    MCP_SEARCH_META_FUNCTION_NAME_PREFIX,
+};
+
+use super::memory::MEMORY_FUNCTION_PREFIX;
+use super::skill::LOAD_SKILL_FUNCTION_NAME;
+use super::supervisor::SUPERVISOR_FUNCTION_PREFIX;
+use super::todo::TODO_FUNCTION_PREFIX;
+use super::user_interaction::USER_FUNCTION_PREFIX;
+use strum_macros::AsRefStr;
+
+/// Where a tool comes from. Tools can be referred to by a qualified name like `global:fs_read`
+/// to pick one from a specific namespace.
+///
+/// When tools from different namespaces share a name, the one from the namespace listed first
+/// here is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum ToolNamespace {
+    Agent,
+    Global,
+    /// The functions Loki adds for each MCP server (e.g. `mcp_invoke_github`).
+    Mcp,
+    /// The tools implemented by Loki itself, which a script of the same name takes the place of.
+    Native,
+}
+
+impl ToolNamespace {
+    /// Splits a tool name that may be qualified with its namespace (e.g. `agent:deploy`) into
+    /// the namespace, if there is one, and the bare name.
+    pub fn split(tool: &str) -> (Option<Self>, &str) {
+        let Some((namespace, name)) = tool.split_once(':') else {
+            return (None, tool);
+        };
+        let namespace = match namespace {
+            "agent" => Self::Agent,
+            "global" => Self::Global,
+            "mcp" => Self::Mcp,
+            "native" => Self::Native,
+            _ => return (None, tool),
+        };
+        (Some(namespace), name)
+    }
+
+    pub fn qualify(self, name: &str) -> String {
+        format!("{}:{name}", self.as_ref())
+    }
+
+    /// The name a tool is declared to the model with when a tool from a namespace with higher
+    /// precedence has taken its name, e.g. `global__deploy`. Models only accept letters, digits,
+    /// `_`, and `-` in function names, so the qualified name can't be used.
+    pub fn declared_name(self, name: &str) -> String {
+        format!("{}__{name}", self.as_ref())
+    }
+}
+
+/// Whether `name` belongs to one of Loki's built-in functions, including the functions for MCP
+/// servers. These always take precedence, so a tool with one of these names could never be called.
+pub fn is_reserved_tool_name(name: &str) -> bool {
+    [
+        MCP_INVOKE_META_FUNCTION_NAME_PREFIX,
+        MCP_SEARCH_META_FUNCTION_NAME_PREFIX,
+        MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX,
+        TODO_FUNCTION_PREFIX,
+        MEMORY_FUNCTION_PREFIX,
+        LOAD_SKILL_FUNCTION_NAME,
+        SUPERVISOR_FUNCTION_PREFIX,
+        USER_FUNCTION_PREFIX,
+    ]
+    .iter()
+    .any(|prefix| name.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::{FunctionDeclaration, Functions};
+    use pretty_assertions::assert_eq;
+
+    fn declaration(name: &str) -> FunctionDeclaration {
+        FunctionDeclaration {
+            name: name.into(),
+            description: String::new(),
+            parameters: Default::default(),
+            agent: false,
+            cacheable: false,
+        }
+    }
+
+    #[test]
+    fn test_split_tool_namespace() {
+        assert_eq!(
+            ToolNamespace::split("global:fs_read"),
+            (Some(ToolNamespace::Global), "fs_read")
+        );
+        assert_eq!(
+            ToolNamespace::split("agent:deploy"),
+            (Some(ToolNamespace::Agent), "deploy")
+        );
+        assert_eq!(
+            ToolNamespace::split("native:grep"),
+            (Some(ToolNamespace::Native), "grep")
+        );
+        assert_eq!(
+            ToolNamespace::split("mcp:mcp_invoke_github"),
+            (Some(ToolNamespace::Mcp), "mcp_invoke_github")
+        );
+        assert_eq!(ToolNamespace::split("fs_read"), (None, "fs_read"));
+        assert_eq!(
+            ToolNamespace::split("other:fs_read"),
+            (None, "other:fs_read")
+        );
+        assert_eq!(ToolNamespace::Agent.qualify("deploy"), "agent:deploy");
+        assert_eq!(
+            ToolNamespace::Global.declared_name("deploy"),
+            "global__deploy"
+        );
+    }
+
+    #[test]
+    fn test_extend_namespaced() {
+        let mut functions = Functions::default();
+        functions.extend_namespaced(
+            vec![declaration("deploy"), declaration("fs_read")],
+            ToolNamespace::Global,
+        );
+        functions.extend_namespaced(
+            vec![declaration("deploy"), declaration("todo__add")],
+            ToolNamespace::Agent,
+        );
+        functions.extend_namespaced(vec![declaration("fs_read")], ToolNamespace::Native);
+
+        let names: Vec<_> = functions
+            .declarations()
+            .iter()
+            .map(|v| v.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            ["global__deploy", "fs_read", "deploy", "native__fs_read"]
+        );
+        assert_eq!(functions.bare_name("global__deploy"), "deploy");
+        assert_eq!(functions.bare_name("deploy"), "deploy");
+        assert_eq!(
+            functions.resolve("global:deploy").map(|v| v.name.as_str()),
+            Some("global__deploy")
+        );
+        assert_eq!(
+            functions.resolve("agent:deploy").map(|v| v.name.as_str()),
+            Some("deploy")
+        );
+        assert!(functions.is_native("native__fs_read"));
+        assert!(functions.resolve("agent:fs_read").is_none());
+    }
+}
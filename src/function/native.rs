@@ -0,0 +1,413 @@
+use super::{FunctionDeclaration, JsonSchema};
+use crate::config::GlobalConfig;
+use crate::utils::{fetch_with_loaders, glob_regex, is_url, render_word_diff};
+
+use anyhow::{Context, Result, bail};
+use fancy_regex::Regex;
+use indexmap::IndexMap;
+use inquire::Confirm;
+use path_absolutize::Absolutize;
+use serde_json::Value;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+pub const NATIVE_TOOL_NAMES: [&str; 4] = ["fs_read", "fs_write", "http_fetch", "grep"];
+
+const DEFAULT_READ_LIMIT: usize = 2000;
+const MAX_LINE_LENGTH: usize = 2000;
+const MAX_GREP_RESULTS: usize = 50;
+const GREP_EXCLUDED_DIRS: [&str; 10] = [
+    ".git",
+    "node_modules",
+    "target",
+    "dist",
+    "build",
+    "__pycache__",
+    "vendor",
+    ".build",
+    ".next",
+    ".venv",
+];
+const GREP_EXCLUDED_FILES: [&str; 5] = [
+    "*.min.js",
+    "*.min.css",
+    "*.map",
+    "*.lock",
+    "package-lock.json",
+];
+
+/// Whether `name` is one of the tools implemented by Loki itself rather than by a script.
+pub fn is_native_tool(name: &str) -> bool {
+    NATIVE_TOOL_NAMES.contains(&name)
+}
+
+pub fn native_function_declarations() -> Vec<FunctionDeclaration> {
+    vec![
+        FunctionDeclaration {
+            name: "fs_read".to_string(),
+            description: "Read a file with line numbers, offset, and limit. For directories, lists entries. Use the grep tool to find specific content before reading, then read with offset to target the relevant section.".to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(IndexMap::from([
+                    string_property("path", "The path of the file or directory to read"),
+                    (
+                        "offset".to_string(),
+                        JsonSchema {
+                            type_value: Some("integer".to_string()),
+                            description: Some("The line number to start reading from (1-indexed, default: 1)".into()),
+                            ..Default::default()
+                        },
+                    ),
+                    (
+                        "limit".to_string(),
+                        JsonSchema {
+                            type_value: Some("integer".to_string()),
+                            description: Some(format!("The maximum number of lines to read (default: {DEFAULT_READ_LIMIT})")),
+                            ..Default::default()
+                        },
+                    ),
+                ])),
+                required: Some(vec!["path".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        },
+        FunctionDeclaration {
+            name: "fs_write".to_string(),
+            description: "Write the full file contents to a file at the specified path, creating any missing parent directories.".to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(IndexMap::from([
+                    string_property("path", "The path of the file to write to"),
+                    string_property("contents", "The full contents to write to the file"),
+                ])),
+                required: Some(vec!["path".to_string(), "contents".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        },
+        FunctionDeclaration {
+            name: "http_fetch".to_string(),
+            description: "Fetch the contents of a URL. HTML pages are converted to Markdown.".to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(IndexMap::from([string_property(
+                    "url",
+                    "The http or https URL to fetch",
+                )])),
+                required: Some(vec!["url".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        },
+        FunctionDeclaration {
+            name: "grep".to_string(),
+            description: "Search file contents using regular expressions. Returns matching file paths and lines. Use this to find relevant code before reading files.".to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(IndexMap::from([
+                    string_property("pattern", "The regex pattern to search for in file contents"),
+                    string_property("path", "The directory to search in (defaults to the current working directory)"),
+                    string_property("include", "File pattern to filter by (e.g. \"*.rs\", \"*.{ts,tsx}\", \"*.py\")"),
+                ])),
+                required: Some(vec!["pattern".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        },
+    ]
+}
+
+fn string_property(name: &str, description: &str) -> (String, JsonSchema) {
+    (
+        name.to_string(),
+        JsonSchema {
+            type_value: Some("string".to_string()),
+            description: Some(description.to_string()),
+            ..Default::default()
+        },
+    )
+}
+
+/// Runs a native tool, resolving relative paths against `cwd` (or the current directory).
+/// Returns the text sent back to the model.
+pub async fn run_native_tool(
+    config: &GlobalConfig,
+    name: &str,
+    args: &Value,
+    cwd: Option<&Path>,
+) -> Result<String> {
+    let cwd = match cwd {
+        Some(cwd) => cwd.to_path_buf(),
+        None => env::current_dir()?,
+    };
+    // The same switches that turn off the prompts of the script tools' `guard_*` helpers
+    let auto_confirm = env::var("AUTO_CONFIRM").is_ok_and(|v| !v.is_empty())
+        || config.read().agent.as_ref().is_some_and(|agent| {
+            agent
+                .variable_envs()
+                .get("LLM_AGENT_VAR_AUTO_CONFIRM")
+                .is_some_and(|v| !v.is_empty())
+        });
+    let can_ask_user = config.read().can_ask_user();
+    match name {
+        "http_fetch" => {
+            let url = string_arg(args, "url")?;
+            if !is_url(url) {
+                bail!("Only http and https URLs can be fetched")
+            }
+            let loaders = config.read().document_loaders.clone();
+            let (contents, _) = fetch_with_loaders(&loaders, url, false).await?;
+            Ok(contents)
+        }
+        _ => {
+            let name = name.to_string();
+            let args = args.clone();
+            tokio::task::spawn_blocking(move || match name.as_str() {
+                "fs_read" => fs_read(
+                    &cwd.join(string_arg(&args, "path")?),
+                    usize_arg(&args, "offset")?.unwrap_or(1).max(1),
+                    usize_arg(&args, "limit")?.unwrap_or(DEFAULT_READ_LIMIT),
+                ),
+                "fs_write" => {
+                    let path = cwd.join(string_arg(&args, "path")?);
+                    let contents = string_arg(&args, "contents")?;
+                    if !auto_confirm && can_ask_user {
+                        guard_write(&path, contents, &cwd)?;
+                    }
+                    fs_write(&path, contents)
+                }
+                "grep" => {
+                    let path = args.get("path").and_then(Value::as_str).unwrap_or(".");
+                    grep(
+                        string_arg(&args, "pattern")?,
+                        &cwd.join(path),
+                        args.get("include").and_then(Value::as_str),
+                    )
+                }
+                _ => bail!("Unknown native tool: {name}"),
+            })
+            .await?
+        }
+    }
+}
+
+fn string_arg<'a>(args: &'a Value, name: &str) -> Result<&'a str> {
+    match args.get(name).and_then(Value::as_str) {
+        Some(value) => Ok(value),
+        None => bail!("'{name}' is required"),
+    }
+}
+
+fn usize_arg(args: &Value, name: &str) -> Result<Option<usize>> {
+    match args.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .or_else(|| value.as_str().and_then(|v| v.parse().ok()))
+            .map(|v| Some(v as usize))
+            .with_context(|| format!("'{name}' must be a positive number")),
+    }
+}
+
+fn fs_read(path: &Path, offset: usize, limit: usize) -> Result<String> {
+    if !path.exists() {
+        bail!("Path not found: {}", path.display())
+    }
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                match entry.path().is_dir() {
+                    true => format!("{name}/"),
+                    false => name,
+                }
+            })
+            .collect::<Vec<_>>();
+        entries.sort();
+        return Ok(entries.join("\n"));
+    }
+
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read '{}' as text", path.display()))?;
+    let lines = contents.lines().collect::<Vec<_>>();
+    let mut output = lines
+        .iter()
+        .enumerate()
+        .skip(offset.saturating_sub(1))
+        .take(limit)
+        .map(|(i, line)| format!("{}: {}", i + 1, truncate_line(line)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let end_line = offset.saturating_sub(1).saturating_add(limit);
+    if end_line < lines.len() {
+        output.push_str(&format!(
+            "\n\n({} total lines. Use offset {} to read more.)",
+            lines.len(),
+            end_line + 1
+        ));
+    }
+    Ok(output)
+}
+
+/// Asks before changing a file, showing the changes, or before creating one outside the working
+/// directory, like the `guard_operation` and `guard_path` helpers of the `fs_write` script did.
+fn guard_write(path: &Path, contents: &str, cwd: &Path) -> Result<()> {
+    let prompt = if path.is_file() {
+        let old = fs::read_to_string(path).unwrap_or_default();
+        println!("{}", render_word_diff(&old, contents));
+        "Apply changes?".to_string()
+    } else if !path.absolutize_from(cwd)?.starts_with(cwd) {
+        format!("Write '{}'?", path.display())
+    } else {
+        return Ok(());
+    };
+    if !Confirm::new(&prompt).with_default(false).prompt()? {
+        bail!("Operation aborted!")
+    }
+    Ok(())
+}
+
+fn fs_write(path: &Path, contents: &str) -> Result<String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create '{}'", parent.display()))?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write '{}'", path.display()))?;
+    Ok(format!(
+        "The file contents were written to: {}",
+        path.display()
+    ))
+}
+
+fn grep(pattern: &str, path: &Path, include: Option<&str>) -> Result<String> {
+    if !path.is_dir() {
+        bail!("Directory not found: {}", path.display())
+    }
+    let re = Regex::new(pattern).with_context(|| format!("Invalid pattern: {pattern}"))?;
+    let excluded = GREP_EXCLUDED_FILES
+        .iter()
+        .filter_map(|pattern| glob_regex(pattern))
+        .collect::<Vec<_>>();
+    let include = match include {
+        Some(include) => {
+            Some(glob_regex(include).with_context(|| format!("Invalid include: {include}"))?)
+        }
+        None => None,
+    };
+    let mut files = vec![];
+    list_grep_files(path, include.as_ref(), &excluded, &mut files);
+
+    let mut results = vec![];
+    'files: for file in files {
+        // Files that aren't text are skipped
+        let Ok(contents) = fs::read_to_string(&file) else {
+            continue;
+        };
+        for (i, line) in contents.lines().enumerate() {
+            if re.is_match(line).unwrap_or_default() {
+                results.push(format!(
+                    "{}:{}:{}",
+                    file.display(),
+                    i + 1,
+                    truncate_line(line)
+                ));
+                if results.len() >= MAX_GREP_RESULTS {
+                    break 'files;
+                }
+            }
+        }
+    }
+
+    if results.is_empty() {
+        return Ok(format!("No matches found for: {pattern}"));
+    }
+    let mut output = results.join("\n");
+    if results.len() >= MAX_GREP_RESULTS {
+        output.push_str(&format!(
+            "\n\n(Results limited to {MAX_GREP_RESULTS} matches. Narrow your search with include or a more specific pattern.)"
+        ));
+    }
+    Ok(output)
+}
+
+/// Lists the files under `dir` to search, in a stable order, skipping dependency and build
+/// directories and generated files.
+fn list_grep_files(
+    dir: &Path,
+    include: Option<&Regex>,
+    excluded: &[Regex],
+    files: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for path in entries {
+        let name = path
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if path.is_dir() {
+            if !GREP_EXCLUDED_DIRS.contains(&name.as_str()) {
+                list_grep_files(&path, include, excluded, files);
+            }
+        } else if !excluded
+            .iter()
+            .any(|re| re.is_match(&name).unwrap_or_default())
+            && include.is_none_or(|re| re.is_match(&name).unwrap_or_default())
+        {
+            files.push(path);
+        }
+    }
+}
+
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_LENGTH) {
+        Some((i, _)) => format!("{}... (truncated)", &line[..i]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_native_fs_tools() {
+        let dir = env::temp_dir().join(format!("loki-test-native-tools-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let file = dir.join("src/main.rs");
+        fs_write(&file, "fn main() {\n    println!(\"hello\");\n}\n").unwrap();
+        fs_write(&dir.join("target/debug/main.rs"), "println!(\"hello\");").unwrap();
+        fs_write(&dir.join("notes.txt"), "hello").unwrap();
+
+        assert_eq!(
+            fs_read(&file, 2, 1).unwrap(),
+            "2:     println!(\"hello\");\n\n(3 total lines. Use offset 3 to read more.)"
+        );
+        assert_eq!(fs_read(&dir, 1, 10).unwrap(), "notes.txt\nsrc/\ntarget/");
+        assert_eq!(fs_read(&file, 3, usize::MAX).unwrap(), "3: }");
+        assert_eq!(
+            grep("hel+o", &dir, Some("*.rs")).unwrap(),
+            format!("{}:2:    println!(\"hello\");", file.display())
+        );
+        assert_eq!(
+            grep("goodbye", &dir, None).unwrap(),
+            "No matches found for: goodbye"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,134 @@
+use super::supervisor::summarize;
+use crate::config::GlobalConfig;
+use crate::utils::{keep_temp_file, temp_file, warning_text};
+
+use anyhow::{Context, Result};
+use log::debug;
+use serde_json::{Value, json};
+use std::fs;
+
+pub const DEFAULT_TOOL_OUTPUT_LIMIT: usize = 32000;
+
+/// Shrinks a tool result longer than `tool_output_limit` characters so it doesn't blow out the
+/// context window. The full output is saved to a temp file that the new result points to, and
+/// the model gets either an LLM summary of it (with `summarize_tool_output`) or its start and end.
+pub async fn limit_tool_output(
+    config: &GlobalConfig,
+    tool_name: &str,
+    output: Value,
+) -> Result<Value> {
+    let (limit, summarize_output, summarization_model_id) = {
+        let cfg = config.read();
+        (
+            cfg.tool_output_limit,
+            cfg.summarize_tool_output,
+            cfg.agent
+                .as_ref()
+                .and_then(|agent| agent.summarization_model().map(|v| v.to_string())),
+        )
+    };
+    let text = match &output {
+        Value::String(text) => text.clone(),
+        output => output.to_string(),
+    };
+    let len = text.chars().count();
+    if limit == 0 || len <= limit {
+        return Ok(output);
+    }
+
+    let output_file = temp_file("-tool-output-", ".txt");
+    fs::write(&output_file, &text).with_context(|| {
+        format!(
+            "Failed to save the output of '{tool_name}' to '{}'",
+            output_file.display()
+        )
+    })?;
+    debug!(
+        "Output from '{tool_name}' is {len} chars (limit {limit}), saved to '{}'",
+        output_file.display()
+    );
+    keep_temp_file(output_file.clone());
+
+    let summary = if summarize_output {
+        let request =
+            format!("Summarize the following output of the '{tool_name}' tool:\n\n{text}");
+        match summarize(config, summarization_model_id.as_deref(), &request).await {
+            Ok(summary) => Some(summary),
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    warning_text(&format!(
+                        "Failed to summarize the output of '{tool_name}', truncating it instead: {err}"
+                    ))
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let (output, how) = match summary {
+        Some(summary) => (summary, "summarized"),
+        None => (truncate_middle(&text, limit), "truncated"),
+    };
+
+    Ok(json!({
+        "output": output,
+        "note": format!(
+            "The output was {len} characters, too long to include in full, so it was {how}. The full output is saved in the file '{}'.",
+            output_file.display()
+        ),
+        "full_output_file": output_file.display().to_string(),
+    }))
+}
+
+/// Keeps the first two thirds and the last third of `limit` characters of `text`, cutting at line
+/// breaks where possible.
+fn truncate_middle(text: &str, limit: usize) -> String {
+    let len = text.chars().count();
+    let head_len = limit * 2 / 3;
+    let tail_len = limit - head_len;
+    let byte_index = |n: usize| text.char_indices().nth(n).map_or(text.len(), |(i, _)| i);
+
+    let mut head_end = byte_index(head_len);
+    if let Some(i) = text[..head_end].rfind('\n')
+        && i >= head_end / 2
+    {
+        head_end = i + 1;
+    }
+    let mut tail_start = byte_index(len - tail_len);
+    if let Some(i) = text[tail_start..].find('\n')
+        && i < (text.len() - tail_start) / 2
+    {
+        tail_start += i + 1;
+    }
+
+    let omitted = text[head_end..tail_start].chars().count();
+    format!(
+        "{}\n[... {omitted} characters omitted ...]\n{}",
+        text[..head_end].trim_end_matches('\n'),
+        &text[tail_start..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_truncate_middle() {
+        let text = (1..=20)
+            .map(|i| format!("line {i:02}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(
+            truncate_middle(&text, 48),
+            "line 01\nline 02\nline 03\nline 04\n[... 112 characters omitted ...]\nline 19\nline 20"
+        );
+        assert_eq!(
+            truncate_middle("ééééééééé", 6),
+            "éééé\n[... 3 characters omitted ...]\néé"
+        );
+    }
+}
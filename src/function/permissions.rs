@@ -0,0 +1,226 @@
+use crate::utils::wildcard_match;
+
+use fancy_regex::Regex;
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// The arguments that hold commands, which are the only ones checked against the dangerous
+/// tool patterns, so e.g. writing a file that mentions `rm -rf` isn't flagged.
+const COMMAND_ARGUMENTS: [&str; 6] = ["command", "commands", "cmd", "script", "code", "args"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolPermission {
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// Maps tool names (or `*`/`?` wildcard patterns) to the permission granted to them.
+pub type ToolPermissions = IndexMap<String, ToolPermission>;
+
+/// Resolves the permission for a tool. When several entries match, the most restrictive
+/// one wins: any matching `deny` beats `ask`, which beats `allow`. Tools that match no
+/// entry are allowed.
+pub fn resolve_tool_permission(permissions: &ToolPermissions, name: &str) -> ToolPermission {
+    find_tool_permission(permissions, name).unwrap_or(ToolPermission::Allow)
+}
+
+/// Like [`resolve_tool_permission`], but returns `None` for tools that match no entry.
+pub fn find_tool_permission(permissions: &ToolPermissions, name: &str) -> Option<ToolPermission> {
+    permissions
+        .iter()
+        .filter(|(pattern, _)| *pattern == name || wildcard_match(pattern, name))
+        .map(|(_, permission)| *permission)
+        .max()
+}
+
+/// The global `tool_policy`, listing the tools (or `*`/`?` wildcard patterns) that run
+/// automatically, need approval, or are denied. It applies to every tool call, including
+/// built-in functions and MCP tools; an agent's `permissions` take precedence over it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolPolicy {
+    #[serde(default)]
+    pub auto: Vec<String>,
+    #[serde(default)]
+    pub ask: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ToolPolicy {
+    /// The policy as permissions. A tool listed more than once gets the most restrictive
+    /// permission.
+    pub fn permissions(&self) -> ToolPermissions {
+        let mut permissions = ToolPermissions::new();
+        for (tools, permission) in [
+            (&self.auto, ToolPermission::Allow),
+            (&self.ask, ToolPermission::Ask),
+            (&self.deny, ToolPermission::Deny),
+        ] {
+            for tool in tools {
+                permissions.insert(tool.clone(), permission);
+            }
+        }
+        permissions
+    }
+}
+
+/// Regex patterns for tool calls that should be approved by the user before they run.
+pub fn default_dangerous_tool_patterns() -> DangerousPatterns {
+    let patterns = [
+        r"\brm\s",
+        r"\b(curl|wget)\b.*\|\s*(sudo\s+)?\w*sh\b",
+        r"\bgit\s+push\b",
+        r"\bgit\s+reset\s+--hard\b",
+        r"\bsudo\s",
+        r"\bmkfs\b",
+        r"\bdd\s+if=",
+    ];
+    DangerousPatterns::new(patterns.map(String::from).to_vec())
+        .expect("the default dangerous tool patterns are valid")
+}
+
+/// The `dangerous_tool_patterns`, compiled once when the config is loaded.
+#[derive(Debug, Clone, Default)]
+pub struct DangerousPatterns(Vec<(String, Regex)>);
+
+impl DangerousPatterns {
+    pub fn new(patterns: Vec<String>) -> Result<Self, String> {
+        patterns
+            .into_iter()
+            .map(|pattern| match Regex::new(&pattern) {
+                Ok(re) => Ok((pattern, re)),
+                Err(err) => Err(format!("Invalid dangerous tool pattern '{pattern}': {err}")),
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Returns the first pattern that matches the tool name or a command in its arguments,
+    /// skipping the ones the user has already allowed for this tool.
+    pub fn find(
+        &self,
+        name: &str,
+        args: &Value,
+        is_allowed: impl Fn(&str) -> bool,
+    ) -> Option<&str> {
+        let mut texts = vec![name];
+        collect_commands(args, false, &mut texts);
+        self.0
+            .iter()
+            .filter(|(pattern, _)| !is_allowed(pattern))
+            .find(|(_, re)| texts.iter().any(|text| re.is_match(text).unwrap_or(false)))
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for DangerousPatterns {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::new(Vec::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+fn collect_commands<'a>(value: &'a Value, is_command: bool, texts: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) if is_command => texts.push(s),
+        Value::Array(values) => values
+            .iter()
+            .for_each(|v| collect_commands(v, is_command, texts)),
+        Value::Object(map) => map.iter().for_each(|(key, v)| {
+            collect_commands(
+                v,
+                is_command || COMMAND_ARGUMENTS.contains(&key.as_str()),
+                texts,
+            )
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_policy_permissions() {
+        let policy = ToolPolicy {
+            auto: vec!["read_*".into(), "deploy_prod".into()],
+            ask: vec!["write_*".into(), "execute_command".into()],
+            deny: vec!["*_prod".into(), "deploy_prod".into()],
+        };
+        let permissions = policy.permissions();
+        let resolve = |name| find_tool_permission(&permissions, name);
+        assert_eq!(resolve("read_file"), Some(ToolPermission::Allow));
+        assert_eq!(resolve("write_file"), Some(ToolPermission::Ask));
+        assert_eq!(resolve("execute_command"), Some(ToolPermission::Ask));
+        assert_eq!(resolve("write_prod"), Some(ToolPermission::Deny));
+        assert_eq!(resolve("read_prod"), Some(ToolPermission::Deny));
+        assert_eq!(resolve("deploy_prod"), Some(ToolPermission::Deny));
+        assert_eq!(resolve("fs_ls"), None);
+    }
+
+    #[test]
+    fn test_resolve_tool_permission() {
+        let permissions: ToolPermissions = [
+            ("fs_write", ToolPermission::Ask),
+            ("execute_*", ToolPermission::Deny),
+            ("*", ToolPermission::Allow),
+        ]
+        .into_iter()
+        .map(|(name, permission)| (name.to_string(), permission))
+        .collect();
+        assert_eq!(
+            resolve_tool_permission(&permissions, "fs_write"),
+            ToolPermission::Ask
+        );
+        assert_eq!(
+            resolve_tool_permission(&permissions, "execute_command"),
+            ToolPermission::Deny
+        );
+        assert_eq!(
+            resolve_tool_permission(&permissions, "fs_read"),
+            ToolPermission::Allow
+        );
+        assert_eq!(
+            resolve_tool_permission(&ToolPermissions::new(), "fs_write"),
+            ToolPermission::Allow
+        );
+    }
+
+    #[test]
+    fn test_find_dangerous_pattern() {
+        let patterns = default_dangerous_tool_patterns();
+        let find = |name, args: Value| patterns.find(name, &args, |_| false).map(String::from);
+        assert_eq!(
+            find("execute_command", json!({"command": "rm -rf build"})),
+            Some(r"\brm\s".to_string())
+        );
+        assert_eq!(
+            find(
+                "mcp_invoke_shell",
+                json!({"tool": "run", "arguments": {"cmd": "sudo reboot"}})
+            ),
+            Some(r"\bsudo\s".to_string())
+        );
+        assert_eq!(
+            find(
+                "fs_write",
+                json!({"path": "README.md", "contents": "Run rm -rf build"})
+            ),
+            None
+        );
+        assert_eq!(find("execute_command", json!({"command": "ls -la"})), None);
+        assert_eq!(
+            patterns.find(
+                "execute_command",
+                &json!({"command": "sudo rm -rf build"}),
+                |pattern| pattern == r"\brm\s"
+            ),
+            Some(r"\bsudo\s")
+        );
+        assert!(DangerousPatterns::new(vec!["(".into()]).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use super::FunctionDeclaration;
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+
+/// A `mapping_tools` alias that binds a single tool to fixed argument values, written as
+/// `tool{key: value, ...}` (e.g. `fetch_logs{env: "prod"}`). The model sees the alias as a tool
+/// of its own, without the preset parameters, so it can't change them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolPreset {
+    pub tool: String,
+    pub args: Map<String, Value>,
+}
+
+impl ToolPreset {
+    /// Parses a `mapping_tools` value. Values that are just a list of tools aren't presets.
+    pub fn parse(value: &str) -> Result<Option<Self>> {
+        let value = value.trim();
+        let Some((tool, args)) = value.split_once('{') else {
+            return Ok(None);
+        };
+        let tool = tool.trim();
+        if tool.is_empty() || tool.contains(',') {
+            bail!("Invalid tool preset '{value}': it must bind exactly one tool")
+        }
+        // The arguments are a YAML flow mapping, so both `{env: prod}` and `{"env": "prod"}` work
+        let args: Value = serde_yaml::from_str(&format!("{{{args}"))
+            .with_context(|| format!("Invalid tool preset '{value}'"))?;
+        let Value::Object(args) = args else {
+            bail!("Invalid tool preset '{value}': the arguments must be a mapping")
+        };
+        Ok(Some(Self {
+            tool: tool.to_string(),
+            args,
+        }))
+    }
+
+    /// The declaration the model sees for the `alias`: the tool's own declaration, without the
+    /// preset parameters.
+    pub fn declaration(&self, alias: &str, function: &FunctionDeclaration) -> FunctionDeclaration {
+        let mut declaration = function.clone();
+        declaration.name = alias.to_string();
+        let parameters = &mut declaration.parameters;
+        if let Some(properties) = parameters.properties.as_mut() {
+            properties.retain(|name, _| !self.args.contains_key(name));
+        }
+        if let Some(required) = parameters.required.as_mut() {
+            required.retain(|name| !self.args.contains_key(name));
+        }
+        declaration
+    }
+
+    /// The arguments to call the tool with. The preset values always win over the model's.
+    pub fn apply(&self, arguments: &mut Map<String, Value>) {
+        arguments.extend(self.args.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_tool_preset() {
+        let preset = ToolPreset::parse(r#"fetch_logs{env: "prod", lines: 100}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(preset.tool, "fetch_logs");
+        assert_eq!(
+            Value::Object(preset.args),
+            json!({"env": "prod", "lines": 100})
+        );
+
+        let preset = ToolPreset::parse("global:fetch_logs { \"env\": \"prod\" }")
+            .unwrap()
+            .unwrap();
+        assert_eq!(preset.tool, "global:fetch_logs");
+
+        assert_eq!(ToolPreset::parse("fs_cat,fs_ls").unwrap(), None);
+        assert!(ToolPreset::parse("fs_cat,fs_ls{env: prod}").is_err());
+        assert!(ToolPreset::parse("fetch_logs{env: prod").is_err());
+    }
+}
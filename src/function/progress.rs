@@ -0,0 +1,162 @@
+use super::live_output::LiveOutput;
+
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+const PROGRESS_BAR_WIDTH: usize = 20;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A status a tool reported by writing a line to `LLM_PROGRESS`: a percentage, a status, or both
+/// (e.g. `40% Downloading files`, `40`, or `Indexing`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolProgress {
+    pub percent: Option<f64>,
+    pub status: String,
+}
+
+impl ToolProgress {
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        let (first, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match first.trim_end_matches('%').parse::<f64>() {
+            Ok(percent) if percent.is_finite() => Some(Self {
+                percent: Some(percent.clamp(0.0, 100.0)),
+                status: rest.trim().to_string(),
+            }),
+            _ => Some(Self {
+                percent: None,
+                status: line.to_string(),
+            }),
+        }
+    }
+
+    /// The last status in a tool's `LLM_PROGRESS` file.
+    fn read(file: &PathBuf) -> Option<Self> {
+        let contents = fs::read_to_string(file).ok()?;
+        contents.lines().rev().find_map(Self::parse)
+    }
+
+    fn render(&self) -> String {
+        match self.percent {
+            Some(percent) => {
+                let filled = (percent / 100.0 * PROGRESS_BAR_WIDTH as f64).round() as usize;
+                format!(
+                    "[{}{}] {self}",
+                    "█".repeat(filled),
+                    "░".repeat(PROGRESS_BAR_WIDTH - filled)
+                )
+            }
+            None => format!("… {self}"),
+        }
+    }
+}
+
+impl fmt::Display for ToolProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.percent {
+            Some(percent) if self.status.is_empty() => write!(f, "{percent}%"),
+            Some(percent) => write!(f, "{percent}% {}", self.status),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
+
+/// Watches a tool's `LLM_PROGRESS` file while it runs, showing the latest status as a progress
+/// bar alongside the tool's live output.
+pub struct ProgressWatcher {
+    file: PathBuf,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Option<ToolProgress>>>,
+    live_output: Option<Arc<LiveOutput>>,
+}
+
+impl ProgressWatcher {
+    pub fn start(file: PathBuf, live_output: Option<Arc<LiveOutput>>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = live_output.clone().map(|live_output| {
+            let file = file.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut last = None;
+                while !stop.load(Ordering::Relaxed) {
+                    let progress = ToolProgress::read(&file);
+                    if let Some(progress) = &progress
+                        && last.as_ref() != Some(progress)
+                    {
+                        // Redraw the status over the previous one, on the same line
+                        live_output.write(format!("\r\x1b[K{}", progress.render()).as_bytes());
+                        last = Some(progress.clone());
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                last
+            })
+        });
+        Self {
+            file,
+            stop,
+            handle,
+            live_output,
+        }
+    }
+
+    /// Stops watching, returning the last status the tool reported.
+    pub fn finish(mut self) -> Option<ToolProgress> {
+        self.stop.store(true, Ordering::Relaxed);
+        let rendered = self
+            .handle
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .flatten();
+        let progress = ToolProgress::read(&self.file);
+        let _ = fs::remove_file(&self.file);
+        if let Some(live_output) = &self.live_output
+            && (rendered.is_some() || progress.is_some())
+        {
+            // The tool may have reported its last status after the bar was last drawn
+            if let Some(progress) = progress.as_ref().filter(|p| rendered.as_ref() != Some(*p)) {
+                live_output.write(format!("\r\x1b[K{}", progress.render()).as_bytes());
+            }
+            live_output.write(b"\n");
+        }
+        progress
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_tool_progress() {
+        let progress = ToolProgress::parse("40% Downloading files").unwrap();
+        assert_eq!(progress.percent, Some(40.0));
+        assert_eq!(progress.to_string(), "40% Downloading files");
+        assert_eq!(
+            progress.render(),
+            "[████████░░░░░░░░░░░░] 40% Downloading files"
+        );
+
+        assert_eq!(ToolProgress::parse("250").unwrap().to_string(), "100%");
+        assert_eq!(
+            ToolProgress::parse(" Indexing ").unwrap(),
+            ToolProgress {
+                percent: None,
+                status: "Indexing".to_string()
+            }
+        );
+        assert_eq!(ToolProgress::parse("  "), None);
+    }
+}
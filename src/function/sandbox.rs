@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+pub const DEFAULT_SANDBOX_IMAGE: &str = "python:3-slim";
+const SANDBOX_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxType {
+    #[default]
+    None,
+    Docker,
+}
+
+/// A container that an agent's tools run in instead of on the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DockerSandbox {
+    pub image: String,
+    pub network: bool,
+    /// The directory mounted read-write as the container's working directory; defaults to
+    /// the current directory.
+    pub work_dir: Option<PathBuf>,
+}
+
+impl DockerSandbox {
+    /// Builds the `docker run` command for a tool, along with the name of its container, which
+    /// is unique to the run so it can be killed with [`kill_container`]. The given paths (the
+    /// tool's binaries and scripts) are mounted read-only at the same paths so the tool binaries
+    /// resolve their scripts, while the work dir and the tool's output files are the only things
+    /// the tool can write to.
+    pub fn command(
+        &self,
+        cmd_name: &str,
+        cmd_args: &[String],
+        envs: &HashMap<String, String>,
+        bin_dirs: &[PathBuf],
+        read_only_paths: &[PathBuf],
+        output_files: &[&Path],
+    ) -> Result<(Command, String)> {
+        let work_dir = match &self.work_dir {
+            Some(dir) => env::current_dir()?.join(dir),
+            None => env::current_dir()?,
+        };
+        let work_dir = work_dir
+            .canonicalize()
+            .with_context(|| format!("Invalid sandbox work dir '{}'", work_dir.display()))?;
+
+        let mut command = Command::new("docker");
+        // `--init` passes signals on to the tool, which doesn't get them as the container's PID 1
+        let container_name = format!("loki-tool-{}", uuid::Uuid::new_v4());
+        command.args(["run", "--rm", "--init", "--name", &container_name]);
+        if !self.network {
+            command.args(["--network", "none"]);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = work_dir.metadata()?;
+            command
+                .arg("--user")
+                .arg(format!("{}:{}", metadata.uid(), metadata.gid()));
+        }
+        for path in read_only_paths.iter().filter(|v| v.exists()) {
+            command
+                .arg("-v")
+                .arg(format!("{}:{}:ro", path.display(), path.display()));
+        }
+        command
+            .arg("-v")
+            .arg(format!("{}:{}", work_dir.display(), work_dir.display()));
+        for output_file in output_files {
+            if !output_file.exists() {
+                std::fs::write(output_file, "")?;
+            }
+            command.arg("-v").arg(format!(
+                "{}:{}",
+                output_file.display(),
+                output_file.display()
+            ));
+        }
+        command.arg("-w").arg(&work_dir);
+
+        let path = bin_dirs
+            .iter()
+            .map(|v| v.display().to_string())
+            .chain([SANDBOX_PATH.to_string()])
+            .collect::<Vec<_>>()
+            .join(":");
+        command.arg("-e").arg(format!("PATH={path}"));
+        // Only the names are passed on the command line so variable values (which may be
+        // secrets) don't show up in the process list; docker reads them from its own env.
+        for (key, value) in envs {
+            if key != "PATH" {
+                command.arg("-e").arg(key);
+                command.env(key, value);
+            }
+        }
+
+        command.arg(&self.image).arg(cmd_name).args(cmd_args);
+        Ok((command, container_name))
+    }
+}
+
+/// Kills a tool's container. Killing the `docker` command that started it doesn't stop the
+/// container, and it's only removed once it stops. Errors are ignored, since the container may
+/// have already stopped.
+pub fn kill_container(container_name: &str) {
+    let _ = Command::new("docker")
+        .args(["kill", container_name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_docker_command() {
+        let dir = env::temp_dir().join(format!("loki-test-sandbox-{}", std::process::id()));
+        let tool_dir = dir.join("tools");
+        std::fs::create_dir_all(&tool_dir).unwrap();
+        let sandbox = DockerSandbox {
+            image: "alpine".into(),
+            network: false,
+            work_dir: Some(dir.clone()),
+        };
+        let output_file = dir.join("output");
+        let envs = HashMap::from([("LLM_AGENT_VAR_TOKEN".to_string(), "secret".to_string())]);
+        let (command, container_name) = sandbox
+            .command(
+                "demo",
+                &["run".into(), "{}".into()],
+                &envs,
+                &[PathBuf::from("/loki/agents/demo/bin")],
+                &[tool_dir.clone(), dir.join("missing")],
+                &[&output_file],
+            )
+            .unwrap();
+        let args: Vec<_> = command
+            .get_args()
+            .map(|v| v.to_string_lossy().to_string())
+            .collect();
+
+        assert!(
+            args.windows(2)
+                .any(|v| v == ["--name", container_name.as_str()])
+        );
+        assert!(args.contains(&"--init".to_string()));
+        assert!(args.windows(2).any(|v| v == ["--network", "none"]));
+        assert!(args.contains(&format!("PATH=/loki/agents/demo/bin:{SANDBOX_PATH}")));
+        assert!(args.windows(2).any(|v| v == ["-e", "LLM_AGENT_VAR_TOKEN"]));
+        assert!(!args.iter().any(|v| v.contains("secret")));
+        assert!(args.contains(&format!("{0}:{0}:ro", tool_dir.display())));
+        assert!(!args.iter().any(|v| v.contains("missing")));
+        assert_eq!(args[args.len() - 4..], ["alpine", "demo", "run", "{}"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
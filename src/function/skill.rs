@@ -0,0 +1,55 @@
+use super::{FunctionDeclaration, JsonSchema};
+use crate::config::GlobalConfig;
+
+use anyhow::{Result, bail};
+use indexmap::IndexMap;
+use serde_json::{Value, json};
+
+pub const LOAD_SKILL_FUNCTION_NAME: &str = "load_skill";
+
+pub fn skill_function_declarations() -> Vec<FunctionDeclaration> {
+    vec![FunctionDeclaration {
+        name: LOAD_SKILL_FUNCTION_NAME.to_string(),
+        description: "Load the full instructions of one of your skills, along with the paths of the scripts and resources that come with it.".to_string(),
+        parameters: JsonSchema {
+            type_value: Some("object".to_string()),
+            properties: Some(IndexMap::from([(
+                "name".to_string(),
+                JsonSchema {
+                    type_value: Some("string".to_string()),
+                    description: Some("The name of the skill to load".into()),
+                    ..Default::default()
+                },
+            )])),
+            required: Some(vec!["name".to_string()]),
+            ..Default::default()
+        },
+        agent: false,
+        cacheable: false,
+    }]
+}
+
+pub fn handle_skill_tool(config: &GlobalConfig, args: &Value) -> Result<Value> {
+    let name = args
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .trim();
+    if name.is_empty() {
+        return Ok(json!({"error": "name is required"}));
+    }
+    let cfg = config.read();
+    let Some(agent) = cfg.agent.as_ref() else {
+        bail!("No active agent")
+    };
+    match agent.skills().iter().find(|v| v.name == name) {
+        Some(skill) => Ok(json!({"name": skill.name, "instructions": skill.load()?})),
+        None => {
+            let available: Vec<&str> = agent.skills().iter().map(|v| v.name.as_str()).collect();
+            Ok(json!({
+                "error": format!("Unknown skill '{name}'"),
+                "available_skills": available,
+            }))
+        }
+    }
+}
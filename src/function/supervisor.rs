@@ -4,7 +4,7 @@ use crate::config::{Config, GlobalConfig, Input, Role, RoleLike};
 use crate::supervisor::escalation::EscalationQueue;
 use crate::supervisor::mailbox::{Envelope, EnvelopePayload, Inbox};
 use crate::supervisor::{AgentExitStatus, AgentHandle, AgentResult};
-use crate::utils::{AbortSignal, create_abort_signal};
+use crate::utils::{AbortSignal, create_abort_signal, notify_when_done};
 
 use anyhow::{Result, anyhow, bail};
 use chrono::Utc;
@@ -14,6 +14,7 @@ use parking_lot::RwLock;
 use serde_json::{Value, json};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Instant;
 use uuid::Uuid;
 
 pub const SUPERVISOR_FUNCTION_PREFIX: &str = "agent__";
@@ -46,6 +47,7 @@ pub fn escalation_function_declarations() -> Vec<FunctionDeclaration> {
             ..Default::default()
         },
         agent: false,
+        cacheable: false,
     }]
 }
 
@@ -86,6 +88,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}check"),
@@ -104,6 +107,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}collect"),
@@ -122,6 +126,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}list"),
@@ -132,6 +137,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}cancel"),
@@ -150,6 +156,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}task_create"),
@@ -206,6 +213,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}task_list"),
@@ -216,6 +224,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}task_complete"),
@@ -234,6 +243,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}task_fail"),
@@ -252,6 +262,7 @@ pub fn supervisor_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
     ]
 }
@@ -285,6 +296,7 @@ pub fn teammate_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{SUPERVISOR_FUNCTION_PREFIX}check_inbox"),
@@ -295,6 +307,7 @@ pub fn teammate_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
     ]
 }
@@ -338,9 +351,22 @@ fn run_child_agent(
             let client = input.create_client()?;
             child_config.write().before_chat_completion(&input)?;
 
-            let (output, tool_results) =
+            let ret =
                 call_chat_completions(&input, false, false, client.as_ref(), abort_signal.clone())
-                    .await?;
+                    .await;
+            let (output, tool_results) = match ret {
+                Ok(ret) => ret,
+                Err(err) => {
+                    let fallback = Config::use_agent_fallback_model(&child_config, &input, &err)?;
+                    match fallback {
+                        Some(model) => {
+                            input.set_model(model);
+                            continue;
+                        }
+                        None => return Err(err),
+                    }
+                }
+            };
 
             child_config
                 .write()
@@ -451,8 +477,15 @@ async fn handle_spawn(config: &GlobalConfig, args: &Value) -> Result<Value> {
     let spawn_agent_name = agent_name.clone();
     let spawn_abort = child_abort.clone();
 
+    let notify_after_secs = config.read().notify_after_secs;
     let join_handle = tokio::spawn(async move {
+        let started = Instant::now();
         let result = run_child_agent(child_config, input, spawn_abort).await;
+        let summary = match &result {
+            Ok(_) => format!("Agent '{spawn_agent_name}' finished"),
+            Err(_) => format!("Agent '{spawn_agent_name}' failed"),
+        };
+        notify_when_done(notify_after_secs, started, &summary, &spawn_agent_id);
 
         match result {
             Ok(output) => Ok(AgentResult {
@@ -949,7 +982,7 @@ fn handle_task_fail(config: &GlobalConfig, args: &Value) -> Result<Value> {
     }))
 }
 
-const SUMMARIZATION_PROMPT: &str = r#"You are a precise summarization assistant. Your job is to condense a sub-agent's output into a compact summary that preserves all actionable information.
+const SUMMARIZATION_PROMPT: &str = r#"You are a precise summarization assistant. Your job is to condense the output of a sub-agent or tool into a compact summary that preserves all actionable information.
 
 Rules:
 - Preserve ALL code snippets, file paths, error messages, and concrete recommendations
@@ -987,24 +1020,11 @@ async fn summarize_output(config: &GlobalConfig, agent_name: &str, output: &str)
         threshold
     );
 
-    let model = {
-        let cfg = config.read();
-        match summarization_model_id {
-            Some(ref model_id) => Model::retrieve_model(&cfg, model_id, ModelType::Chat)?,
-            None => cfg.current_model().clone(),
-        }
-    };
-
-    let mut role = Role::new("summarizer", SUMMARIZATION_PROMPT);
-    role.set_model(model);
-
     let user_message = format!(
         "Summarize the following sub-agent output from '{}':\n\n{}",
         agent_name, output
     );
-    let input = Input::from_str(config, &user_message, Some(role));
-
-    let summary = input.fetch_chat_text().await?;
+    let summary = summarize(config, summarization_model_id.as_deref(), &user_message).await?;
 
     debug!(
         "Summarized output from '{}': {} chars -> {} chars",
@@ -1015,3 +1035,25 @@ async fn summarize_output(config: &GlobalConfig, agent_name: &str, output: &str)
 
     Ok(summary)
 }
+
+/// Sends `request` through an LLM summarization pass, using `model_id` or, if it's not set, the
+/// current model.
+pub(crate) async fn summarize(
+    config: &GlobalConfig,
+    model_id: Option<&str>,
+    request: &str,
+) -> Result<String> {
+    let model = {
+        let cfg = config.read();
+        match model_id {
+            Some(model_id) => Model::retrieve_model(&cfg, model_id, ModelType::Chat)?,
+            None => cfg.current_model().clone(),
+        }
+    };
+
+    let mut role = Role::new("summarizer", SUMMARIZATION_PROMPT);
+    role.set_model(model);
+
+    let input = Input::from_str(config, request, Some(role));
+    input.fetch_chat_text().await
+}
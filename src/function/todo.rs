@@ -29,6 +29,7 @@ pub fn todo_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{TODO_FUNCTION_PREFIX}add"),
@@ -47,6 +48,7 @@ pub fn todo_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{TODO_FUNCTION_PREFIX}done"),
@@ -65,6 +67,7 @@ pub fn todo_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{TODO_FUNCTION_PREFIX}list"),
@@ -75,6 +78,7 @@ pub fn todo_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{TODO_FUNCTION_PREFIX}clear"),
@@ -85,6 +89,7 @@ pub fn todo_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
     ]
 }
@@ -47,6 +47,7 @@ pub fn user_interaction_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{USER_FUNCTION_PREFIX}confirm"),
@@ -65,6 +66,7 @@ pub fn user_interaction_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{USER_FUNCTION_PREFIX}input"),
@@ -83,6 +85,7 @@ pub fn user_interaction_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
         FunctionDeclaration {
             name: format!("{USER_FUNCTION_PREFIX}checkbox"),
@@ -115,6 +118,7 @@ pub fn user_interaction_function_declarations() -> Vec<FunctionDeclaration> {
                 ..Default::default()
             },
             agent: false,
+            cacheable: false,
         },
     ]
 }
@@ -128,8 +132,16 @@ pub async fn handle_user_tool(
         .strip_prefix(USER_FUNCTION_PREFIX)
         .unwrap_or(cmd_name);
 
-    let depth = config.read().current_depth;
+    let (depth, batch_flag) = {
+        let cfg = config.read();
+        (cfg.current_depth, cfg.batch_flag)
+    };
 
+    if batch_flag {
+        return Ok(json!({
+            "tool_call_error": "No user is available to answer during a batch run; decide for yourself and say what you assumed"
+        }));
+    }
     if depth == 0 {
         handle_direct(action, args)
     } else {
@@ -0,0 +1,318 @@
+use super::{FunctionDeclaration, ToolRunOutput};
+use crate::utils::warning_text;
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::debug;
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+#[cfg(feature = "wasm")]
+use {
+    parking_lot::Mutex,
+    std::{
+        sync::{
+            LazyLock, OnceLock,
+            mpsc::{self, RecvTimeoutError},
+        },
+        thread,
+        time::{Instant, SystemTime},
+    },
+    wasmtime::{Engine, Linker, Module, Store, Trap, UpdateDeadline},
+    wasmtime_wasi::{
+        DirPerms, FilePerms, I32Exit, WasiCtxBuilder,
+        p1::{self, WasiP1Ctx},
+        p2::pipe::MemoryOutputPipe,
+    },
+};
+
+/// The argument a WASM tool module is run with to print its function declarations.
+const WASM_SCHEMA_ARG: &str = "--schema";
+const WASM_SCHEMA_TIMEOUT: Duration = Duration::from_secs(10);
+#[cfg(feature = "wasm")]
+const MAX_OUTPUT_BYTES: usize = 64 * 1024 * 1024;
+
+/// The engine every module is compiled for and run on, since creating one is expensive.
+#[cfg(feature = "wasm")]
+static ENGINE: OnceLock<Engine> = OnceLock::new();
+/// Compiled modules, keyed by path, along with the modification time they were compiled at.
+#[cfg(feature = "wasm")]
+static MODULES: LazyLock<Mutex<HashMap<PathBuf, (Option<SystemTime>, Module)>>> =
+    LazyLock::new(Default::default);
+
+/// What a finished module run wrote and exited with.
+#[cfg_attr(not(feature = "wasm"), allow(dead_code))]
+struct WasmRun {
+    exit_code: i32,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// Runs `<module> --schema` and parses what it prints: a function declaration, or an array of
+/// them, in the same shape as the declarations Loki generates for script tools.
+pub fn generate_wasm_declarations(module_path: &Path) -> Result<Vec<FunctionDeclaration>> {
+    let run = run_module(
+        module_path,
+        vec![module_name(module_path), WASM_SCHEMA_ARG.to_string()],
+        HashMap::new(),
+        &[],
+        Some(WASM_SCHEMA_TIMEOUT),
+    )?
+    .ok_or_else(|| {
+        anyhow!(
+            "Timed out reading the schema of WASM tool '{}'",
+            module_path.display()
+        )
+    })?;
+    if run.exit_code != 0 {
+        bail!(
+            "WASM tool '{}' exited with code {} when asked for its schema: {}",
+            module_path.display(),
+            run.exit_code,
+            String::from_utf8_lossy(&run.stderr).trim()
+        );
+    }
+
+    let schema: Value = serde_json::from_slice(&run.stdout).with_context(|| {
+        format!(
+            "WASM tool '{}' printed an invalid schema",
+            module_path.display()
+        )
+    })?;
+    let declarations = match schema {
+        Value::Array(_) => serde_json::from_value(schema)?,
+        _ => vec![serde_json::from_value(schema)?],
+    };
+    Ok(declarations)
+}
+
+/// Runs a function of a WASM tool as `<module> <function> <json arguments>`. Whatever the module
+/// writes to stdout is the result. It can only see the `dirs` it's given and the `envs`.
+pub fn run_wasm_function(
+    module_path: &Path,
+    function_name: &str,
+    json_data: String,
+    envs: HashMap<String, String>,
+    dirs: &[String],
+    timeout: Option<Duration>,
+) -> Result<ToolRunOutput> {
+    let args = vec![
+        module_name(module_path),
+        function_name.to_string(),
+        json_data,
+    ];
+    let Some(run) = run_module(module_path, args, envs, dirs, timeout)? else {
+        let tool_error_message = format!(
+            "Tool call '{function_name}' timed out after {}s and was killed",
+            timeout.unwrap_or_default().as_secs()
+        );
+        eprintln!("{}", warning_text(&format!("⚠️ {tool_error_message} ⚠️")));
+        return Ok(ToolRunOutput {
+            output: Some(json!({"tool_call_error": tool_error_message}).to_string()),
+            exit_code: None,
+            progress: None,
+        });
+    };
+
+    let stderr = String::from_utf8_lossy(&run.stderr).trim().to_string();
+    if !stderr.is_empty() {
+        eprintln!("{stderr}");
+    }
+    if run.exit_code != 0 {
+        let tool_error_message = format!(
+            "Tool call '{function_name}' exited with code {}",
+            run.exit_code
+        );
+        eprintln!("{}", warning_text(&format!("⚠️ {tool_error_message} ⚠️")));
+        let mut error_json = json!({"tool_call_error": tool_error_message});
+        if !stderr.is_empty() {
+            error_json["stderr"] = json!(stderr);
+        }
+        debug!("Tool call error: {error_json:?}");
+        return Ok(ToolRunOutput {
+            output: Some(error_json.to_string()),
+            exit_code: Some(run.exit_code),
+            progress: None,
+        });
+    }
+
+    let output = String::from_utf8_lossy(&run.stdout).to_string();
+    debug!("Tool {function_name} output: {output}");
+    Ok(ToolRunOutput {
+        output: (!output.is_empty()).then_some(output),
+        exit_code: Some(run.exit_code),
+        progress: None,
+    })
+}
+
+/// Parses a `wasm_dirs` entry: a host path, mounted at the same path in the guest, with an
+/// optional `:ro` suffix for read-only access.
+#[cfg_attr(not(feature = "wasm"), allow(dead_code))]
+fn parse_wasm_dir(dir: &str) -> (PathBuf, bool) {
+    match dir.strip_suffix(":ro") {
+        Some(path) => (PathBuf::from(path), true),
+        None => (PathBuf::from(dir), false),
+    }
+}
+
+fn module_name(module_path: &Path) -> String {
+    module_path
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(feature = "wasm")]
+fn engine() -> Result<Engine> {
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine.clone());
+    }
+    let mut engine_config = wasmtime::Config::new();
+    engine_config.epoch_interruption(true);
+    let engine = Engine::new(&engine_config)?;
+    Ok(ENGINE.get_or_init(|| engine).clone())
+}
+
+/// Compiles the module, or reuses the one compiled before if the file hasn't changed since.
+#[cfg(feature = "wasm")]
+fn load_module(engine: &Engine, module_path: &Path) -> Result<Module> {
+    let modified = module_path.metadata().and_then(|v| v.modified()).ok();
+    if let Some((compiled_at, module)) = MODULES.lock().get(module_path)
+        && *compiled_at == modified
+    {
+        return Ok(module.clone());
+    }
+    let module = Module::from_file(engine, module_path)
+        .with_context(|| format!("Failed to load WASM tool '{}'", module_path.display()))?;
+    MODULES
+        .lock()
+        .insert(module_path.to_path_buf(), (modified, module.clone()));
+    Ok(module)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn run_module(
+    module_path: &Path,
+    _args: Vec<String>,
+    _envs: HashMap<String, String>,
+    _dirs: &[String],
+    _timeout: Option<Duration>,
+) -> Result<Option<WasmRun>> {
+    bail!(
+        "Unable to run WASM tool '{}': Loki was built without the 'wasm' feature",
+        module_path.display()
+    )
+}
+
+/// Runs the module's `_start` on its own thread, since the WASI implementation drives its
+/// own Tokio runtime. Returns `None` if it's still running after `timeout`.
+#[cfg(feature = "wasm")]
+fn run_module(
+    module_path: &Path,
+    args: Vec<String>,
+    envs: HashMap<String, String>,
+    dirs: &[String],
+    timeout: Option<Duration>,
+) -> Result<Option<WasmRun>> {
+    let module_path = module_path.to_path_buf();
+    let dirs = dirs.iter().map(|v| parse_wasm_dir(v)).collect::<Vec<_>>();
+    thread::spawn(move || {
+        let engine = engine()?;
+        let module = load_module(&engine, &module_path)?;
+        let mut linker: Linker<WasiP1Ctx> = Linker::new(&engine);
+        p1::add_to_linker_sync(&mut linker, |ctx| ctx)?;
+
+        let stdout = MemoryOutputPipe::new(MAX_OUTPUT_BYTES);
+        let stderr = MemoryOutputPipe::new(MAX_OUTPUT_BYTES);
+        let mut builder = WasiCtxBuilder::new();
+        builder
+            .args(&args)
+            .envs(&envs.into_iter().collect::<Vec<_>>())
+            .stdout(stdout.clone())
+            .stderr(stderr.clone());
+        for (path, read_only) in dirs {
+            let (dir_perms, file_perms) = match read_only {
+                true => (DirPerms::READ, FilePerms::READ),
+                false => (DirPerms::all(), FilePerms::all()),
+            };
+            builder
+                .preopened_dir(&path, path.display().to_string(), dir_perms, file_perms)
+                .with_context(|| {
+                    format!(
+                        "Unable to give the WASM tool access to '{}'",
+                        path.display()
+                    )
+                })?;
+        }
+
+        // The engine is shared, so a timeout bumps the epoch for every running module; each one
+        // only stops once its own deadline has passed.
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut store = Store::new(&engine, builder.build_p1());
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| match deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Trap::Interrupt.into()),
+            _ => Ok(UpdateDeadline::Continue(1)),
+        });
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        if let Some(timeout) = timeout {
+            let engine = engine.clone();
+            thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+                    engine.increment_epoch();
+                }
+            });
+        }
+
+        let instance = linker.instantiate(&mut store, &module)?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .context("WASM tools must be WASI command modules that export '_start'")?;
+        let result = start.call(&mut store, ());
+        drop(done_tx);
+
+        let exit_code = match result {
+            Ok(()) => 0,
+            Err(err) => match err.downcast_ref::<I32Exit>() {
+                Some(exit) => exit.0,
+                None if err.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => return Ok(None),
+                None => {
+                    return Err(
+                        err.context(format!("WASM tool '{}' crashed", module_path.display()))
+                    );
+                }
+            },
+        };
+        drop(store);
+
+        Ok(Some(WasmRun {
+            exit_code,
+            stdout: stdout.contents().to_vec(),
+            stderr: stderr.contents().to_vec(),
+        }))
+    })
+    .join()
+    .map_err(|_| anyhow!("The WASM runtime panicked"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_wasm_dir() {
+        assert_eq!(
+            parse_wasm_dir("/home/me/notes:ro"),
+            (PathBuf::from("/home/me/notes"), true)
+        );
+        assert_eq!(
+            parse_wasm_dir("/tmp/scratch"),
+            (PathBuf::from("/tmp/scratch"), false)
+        );
+    }
+}
@@ -19,17 +19,19 @@ use crate::client::{
     ModelType, call_chat_completions, call_chat_completions_streaming, list_models, oauth,
 };
 use crate::config::{
-    Agent, CODE_ROLE, Config, EXPLAIN_SHELL_ROLE, GlobalConfig, Input, SHELL_ROLE,
-    TEMP_SESSION_NAME, WorkingMode, ensure_parent_exists, list_agents, load_env_file,
-    macro_execute,
+    Agent, CODE_ROLE, Config, EXPLAIN_SHELL_ROLE, ExportFormat, GlobalConfig, Input, RoleLike,
+    SHELL_ROLE, TEMP_SESSION_NAME, WorkingMode, check_config, ensure_parent_exists, install_agent,
+    list_agents, load_env_file, macro_execute, package_agent, render_sources, render_tool_result,
+    run_agent_tests, run_batch,
 };
-use crate::render::{prompt_theme, render_error};
+use crate::function::ToolResult;
+use crate::render::{prompt_theme, render_error, render_html};
 use crate::repl::Repl;
 use crate::utils::*;
 
-use crate::cli::Cli;
+use crate::cli::{Cli, DryRun, OutputFormat};
 use crate::vault::Vault;
-use anyhow::{Result, anyhow, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use clap::{CommandFactory, Parser};
 use clap_complete::CompleteEnv;
 use client::ClientConfig;
@@ -41,14 +43,19 @@ use log4rs::config::{Appender, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use oauth::OAuthProvider;
 use parking_lot::RwLock;
-use std::path::PathBuf;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use std::{env, mem, process, sync::Arc};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     load_env_file()?;
     CompleteEnv::with_factory(Cli::command).complete();
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    set_quiet(cli.quiet);
 
     if let Some(shell) = cli.completions {
         let mut cmd = Cli::command();
@@ -60,8 +67,15 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
-    let text = cli.text()?;
-    let working_mode = if text.is_none() && cli.file.is_empty() {
+    let mut text = cli.text()?;
+    if cli.paste {
+        paste_clipboard(&mut text, &mut cli.file)?;
+    }
+    let working_mode = if text.is_none()
+        && cli.file.is_empty()
+        && cli.test_agent.is_none()
+        && cli.batch_file.is_none()
+    {
         WorkingMode::Repl
     } else {
         WorkingMode::Cmd
@@ -74,7 +88,9 @@ async fn main() -> Result<()> {
         || cli.list_agents
         || cli.list_rags
         || cli.list_macros
-        || cli.list_sessions;
+        || cli.list_sessions
+        || cli.export_session.is_some()
+        || cli.import_session.is_some();
     let vault_flags = cli.add_secret.is_some()
         || cli.get_secret.is_some()
         || cli.update_secret.is_some()
@@ -94,18 +110,34 @@ async fn main() -> Result<()> {
         return Vault::handle_vault_flags(cli, Config::init_bare()?);
     }
 
+    if let Some(agent) = &cli.package_agent {
+        package_agent(agent)?;
+        return Ok(());
+    }
+    if let Some(package_path) = &cli.install_agent {
+        return install_agent(&Config::init_bare()?, &PathBuf::from(package_path));
+    }
+
     let abort_signal = create_abort_signal();
-    let start_mcp_servers = cli.agent.is_none() && cli.role.is_none();
-    let config = Arc::new(RwLock::new(
-        Config::init(
-            working_mode,
-            info_flag,
-            start_mcp_servers,
-            log_path,
-            abort_signal.clone(),
-        )
-        .await?,
-    ));
+    if cli.check_config {
+        if let Err(err) = check_config() {
+            exit_with_error(ExitCode::Config.tag(err), &abort_signal);
+        }
+        return Ok(());
+    }
+    let start_mcp_servers = cli.agent.is_none() && cli.test_agent.is_none() && cli.role.is_none();
+    let config = match Config::init(
+        working_mode,
+        info_flag,
+        start_mcp_servers,
+        log_path,
+        abort_signal.clone(),
+    )
+    .await
+    {
+        Ok(config) => Arc::new(RwLock::new(config)),
+        Err(err) => exit_with_error(ExitCode::Config.tag(err), &abort_signal),
+    };
 
     {
         let cfg = config.read();
@@ -114,13 +146,25 @@ async fn main() -> Result<()> {
         }
     }
 
-    if let Err(err) = run(config, cli, text, abort_signal).await {
-        render_error(err);
-        process::exit(1);
+    let ret = run(config, cli, text, abort_signal.clone()).await;
+    remove_kept_temp_files();
+    if let Err(err) = ret {
+        exit_with_error(err, &abort_signal);
     }
     Ok(())
 }
 
+/// Prints the error and exits with the status that says what kind of error it is.
+fn exit_with_error(err: anyhow::Error, abort_signal: &AbortSignal) -> ! {
+    let code = match abort_signal.aborted() {
+        true => ExitCode::Aborted,
+        false => ExitCode::of(&err),
+    };
+    render_error(err);
+    remove_kept_temp_files();
+    process::exit(code as i32);
+}
+
 async fn run(
     config: GlobalConfig,
     cli: Cli,
@@ -159,21 +203,51 @@ async fn run(
         return Ok(());
     }
 
-    if cli.dry_run {
-        config.write().dry_run = true;
+    match cli.dry_run {
+        Some(DryRun::Messages) => config.write().dry_run = true,
+        Some(DryRun::Tools) => config.write().dry_run_tools = true,
+        None => {}
+    }
+    if let Some(timeout) = cli.timeout {
+        config.write().read_timeout_secs = Some(timeout);
+    }
+    if let Some(timeout) = cli.connect_timeout {
+        config.write().connect_timeout_secs = timeout;
+    }
+
+    if let Some(agent) = &cli.test_agent {
+        return run_agent_tests(&config, agent, cli.model.as_deref(), abort_signal).await;
     }
 
+    if config.read().working_mode.is_repl() && !config.read().info_flag {
+        let sessions_dir = match &cli.agent {
+            Some(agent) => Config::agent_sessions_dir(agent),
+            None => config.read().sessions_dir(),
+        };
+        config.read().recover_sessions(&sessions_dir);
+    }
+    let resumed_session = match &cli.agent {
+        Some(agent) => resumed_session(&cli, &Config::agent_sessions_dir(agent)),
+        None => resumed_session(&cli, &config.read().sessions_dir()),
+    }
+    .map_err(|err| ExitCode::Config.tag(err))?;
+    let mut prelude_prompt = None;
     if let Some(agent) = &cli.agent {
         if cli.build_tools {
             info!("Building tools for agent '{agent}'...");
+            Agent::sync_rag(&config, agent, abort_signal.clone()).await?;
             Agent::init(&config, agent, abort_signal.clone()).await?;
             return Ok(());
         }
 
-        let session = cli.session.as_ref().map(|v| match v {
-            Some(v) => v.as_str(),
-            None => TEMP_SESSION_NAME,
-        });
+        let prelude = Agent::load_prelude(agent)?;
+        let session = resumed_session
+            .as_deref()
+            .or(cli.session.as_ref().map(|v| match v {
+                Some(v) => v.as_str(),
+                None => TEMP_SESSION_NAME,
+            }))
+            .or(prelude.session.as_deref());
         if !cli.agent_variable.is_empty() {
             config.write().agent_variables = Some(
                 cli.agent_variable
@@ -185,24 +259,35 @@ async fn run(
 
         let ret = Config::use_agent(&config, agent, session, abort_signal.clone()).await;
         config.write().agent_variables = None;
-        ret?;
+        ret.map_err(|err| ExitCode::Config.tag(err))?;
+        if let Some(rag) = &prelude.rag {
+            Config::attach_agent_rag(&config, rag)?;
+        }
+        prelude_prompt = prelude.prompt;
     } else {
         if let Some(prompt) = &cli.prompt {
             config.write().use_prompt(prompt)?;
         } else if let Some(name) = &cli.role {
-            Config::use_role_safely(&config, name, abort_signal.clone()).await?;
+            Config::use_role_safely(&config, name, abort_signal.clone())
+                .await
+                .map_err(|err| ExitCode::Config.tag(err))?;
         } else if cli.execute {
             Config::use_role_safely(&config, SHELL_ROLE, abort_signal.clone()).await?;
         } else if cli.code {
             Config::use_role_safely(&config, CODE_ROLE, abort_signal.clone()).await?;
         }
-        if let Some(session) = &cli.session {
+        if let Some(session) = &resumed_session {
+            Config::use_session_safely(&config, Some(session), abort_signal.clone())
+                .await
+                .map_err(|err| ExitCode::Config.tag(err))?;
+        } else if let Some(session) = &cli.session {
             Config::use_session_safely(
                 &config,
                 session.as_ref().map(|v| v.as_str()),
                 abort_signal.clone(),
             )
-            .await?;
+            .await
+            .map_err(|err| ExitCode::Config.tag(err))?;
         }
         if let Some(rag) = &cli.rag {
             Config::use_rag(&config, Some(rag), abort_signal.clone()).await?;
@@ -218,8 +303,25 @@ async fn run(
         println!("{sessions}");
         return Ok(());
     }
+    if let Some(name) = &cli.export_session {
+        let format = ExportFormat::parse(&cli.format)?;
+        let contents = config
+            .read()
+            .export_saved_session(name, format)
+            .map_err(|err| ExitCode::Config.tag(err))?;
+        println!("{}", contents.trim_end());
+        return Ok(());
+    }
+    if let Some(path) = &cli.import_session {
+        let name = config.read().import_session(path)?;
+        println!("✓ Imported the session as '{name}'");
+        return Ok(());
+    }
     if let Some(model_id) = &cli.model {
-        config.write().set_model(model_id)?;
+        config
+            .write()
+            .set_model(model_id)
+            .map_err(|err| ExitCode::Config.tag(err))?;
     }
     if cli.no_stream {
         config.write().stream = false;
@@ -227,7 +329,7 @@ async fn run(
     if cli.empty_session {
         config.write().empty_session()?;
     }
-    if cli.save_session {
+    if cli.save_session || cli.continue_session || cli.resume.is_some() {
         config.write().set_save_session_this_time()?;
     }
     if cli.info {
@@ -246,6 +348,17 @@ async fn run(
         macro_execute(&config, name, text.as_deref(), abort_signal.clone()).await?;
         return Ok(());
     }
+    if let Some(path) = &cli.batch_file {
+        return run_batch(
+            &config,
+            path,
+            cli.batch_output.as_deref(),
+            cli.batch_concurrency,
+            cli.batch_retries,
+            abort_signal,
+        )
+        .await;
+    }
     if cli.execute && !is_repl {
         let input = create_input(&config, text, &cli.file, abort_signal.clone()).await?;
         shell_execute(&config, &SHELL, input, abort_signal.clone()).await?;
@@ -256,17 +369,166 @@ async fn run(
 
     match is_repl {
         false => {
+            let started_at = now();
+            let start = Instant::now();
+            let format = cli.output.unwrap_or_default();
+            let mut report = DirectiveReport::default();
+            let title = text
+                .as_deref()
+                .and_then(|v| v.lines().find(|line| !line.trim().is_empty()))
+                .unwrap_or("Answer")
+                .to_string();
+            let session = config
+                .read()
+                .session
+                .as_ref()
+                .map(|session| session.name().to_string());
             let mut input = create_input(&config, text, &cli.file, abort_signal.clone()).await?;
             input.use_embeddings(abort_signal.clone()).await?;
-            start_directive(&config, input, cli.code, abort_signal).await
+            // The answer is only printed as it comes in when it isn't going anywhere else
+            let print = format == OutputFormat::Text && cli.output_file.is_none();
+            let ret =
+                start_directive(&config, input, cli.code, print, &mut report, abort_signal).await;
+            if let Err(err) = &ret {
+                config.write().finish_agent_run(Some(err.to_string()));
+            }
+            ret?;
+            let answer = match format {
+                OutputFormat::Text => None,
+                OutputFormat::Md => Some(report.output.clone()),
+                OutputFormat::Json => {
+                    let sources = config
+                        .read()
+                        .rag
+                        .as_ref()
+                        .and_then(|rag| rag.get_last_sources());
+                    let tool_calls: Vec<_> = report
+                        .tool_results
+                        .iter()
+                        .map(|result| {
+                            json!({
+                                "name": result.call.name,
+                                "arguments": result.call.arguments,
+                                "output": result.output,
+                            })
+                        })
+                        .collect();
+                    let data = json!({
+                        "output": report.output,
+                        "model": report.model,
+                        "usage": {
+                            "input_tokens": report.input_tokens,
+                            "output_tokens": report.output_tokens,
+                        },
+                        "tool_calls": tool_calls,
+                        "sources": sources,
+                        "timing": {
+                            "started_at": started_at,
+                            "elapsed_ms": start.elapsed().as_millis() as u64,
+                        },
+                    });
+                    // Appended answers stay one per line, so the file can be read as JSON lines
+                    match cli.append {
+                        true => Some(serde_json::to_string(&data)?),
+                        false => Some(serde_json::to_string_pretty(&data)?),
+                    }
+                }
+                OutputFormat::Html => {
+                    let mut sections = vec![report.output.trim().to_string()];
+                    sections.extend(report.tool_results.iter().map(render_tool_result));
+                    if let Some(sources) = config
+                        .read()
+                        .rag
+                        .as_ref()
+                        .and_then(|rag| rag.get_last_sources())
+                    {
+                        sections.push(render_sources(&sources));
+                    }
+                    let theme = Config::load_theme(config.read().theme().unwrap_or("dark"))?;
+                    Some(render_html(&title, &sections.join("\n\n"), Some(theme))?)
+                }
+            };
+            match &cli.output_file {
+                Some(template) => {
+                    let path = render_path_template(
+                        template,
+                        &[
+                            ("date", chrono::Local::now().format("%Y-%m-%d").to_string()),
+                            ("time", chrono::Local::now().format("%H-%M-%S").to_string()),
+                            ("session", session.unwrap_or_default()),
+                            ("model", report.model.clone()),
+                        ],
+                    );
+                    let answer = answer.unwrap_or(report.output);
+                    write_output_file(&path, &answer, cli.append)?;
+                }
+                None => {
+                    if let Some(answer) = answer {
+                        println!("{answer}");
+                    }
+                }
+            }
+            Ok(())
         }
         true => {
             if !*IS_STDOUT_TERMINAL {
                 bail!("No TTY for REPL")
             }
-            start_interactive(&config).await
+            start_interactive(&config, prelude_prompt).await
+        }
+    }
+}
+
+/// The session `--continue` or `--resume` reopens, if either was given. With nothing to continue,
+/// `--continue` starts a new session, which is saved so the next `--continue` picks it up.
+fn resumed_session(cli: &Cli, sessions_dir: &Path) -> Result<Option<String>> {
+    if let Some(name) = &cli.resume {
+        if !sessions_dir.join(format!("{name}.yaml")).exists() {
+            bail!("Session '{name}' not found");
+        }
+        return Ok(Some(name.clone()));
+    }
+    if cli.continue_session {
+        let session =
+            Config::last_session(sessions_dir).unwrap_or_else(|| TEMP_SESSION_NAME.to_string());
+        return Ok(Some(session));
+    }
+    Ok(None)
+}
+
+/// Puts the text in the clipboard in front of the prompt for `--paste`, or saves the image in it
+/// to a temporary file to include like `--file`.
+fn paste_clipboard(text: &mut Option<String>, files: &mut Vec<String>) -> Result<()> {
+    match get_text()? {
+        Some(pasted) => {
+            *text = Some(match text.take() {
+                Some(text) => format!("{pasted}\n{text}"),
+                None => pasted,
+            });
+        }
+        None => {
+            let path = temp_file("-clipboard-", ".png");
+            save_image(&path).context("The clipboard has no text or image to paste")?;
+            files.push(path.display().to_string());
+            keep_temp_file(path);
         }
     }
+    Ok(())
+}
+
+/// Writes a one-off answer for `--output-file`, after anything already there with `--append`.
+fn write_output_file(path: &Path, answer: &str, append: bool) -> Result<()> {
+    ensure_parent_exists(path)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| format!("Failed to open '{}'", path.display()))?;
+    writeln!(file, "{}", answer.trim_end())
+        .with_context(|| format!("Failed to write to '{}'", path.display()))?;
+    Ok(())
 }
 
 async fn apply_prelude_safely(config: &RwLock<Config>, abort_signal: AbortSignal) -> Result<()> {
@@ -285,28 +547,64 @@ async fn apply_prelude_safely(config: &RwLock<Config>, abort_signal: AbortSignal
     Ok(())
 }
 
+/// What a one-off query came to across its rounds of tool calls, for `--output` and
+/// `--output-file`.
+#[derive(Debug, Default)]
+struct DirectiveReport {
+    output: String,
+    model: String,
+    /// Estimated, like the usage recorded for agent budgets.
+    input_tokens: usize,
+    output_tokens: usize,
+    tool_results: Vec<ToolResult>,
+}
+
 #[async_recursion::async_recursion]
 async fn start_directive(
     config: &GlobalConfig,
-    input: Input,
+    mut input: Input,
     code_mode: bool,
+    print: bool,
+    report: &mut DirectiveReport,
     abort_signal: AbortSignal,
 ) -> Result<()> {
     let client = input.create_client()?;
     let extract_code = !*IS_STDOUT_TERMINAL && code_mode;
     config.write().before_chat_completion(&input)?;
-    let (output, tool_results) = if !input.stream() || extract_code {
+    let ret = if !input.stream() || extract_code || !print {
         call_chat_completions(
             &input,
-            true,
+            print,
             extract_code,
             client.as_ref(),
             abort_signal.clone(),
         )
-        .await?
+        .await
     } else {
-        call_chat_completions_streaming(&input, client.as_ref(), abort_signal.clone()).await?
+        call_chat_completions_streaming(&input, client.as_ref(), abort_signal.clone()).await
+    };
+    let (output, tool_results) = match ret {
+        Ok(ret) => ret,
+        Err(err) => {
+            let fallback = Config::use_agent_fallback_model(config, &input, &err)?;
+            return match fallback {
+                Some(model) => {
+                    input.set_model(model);
+                    start_directive(config, input, code_mode, print, report, abort_signal).await
+                }
+                None => Err(err),
+            };
+        }
     };
+    let model = input.role().model();
+    report.model = model.id();
+    report.input_tokens += input
+        .build_messages()
+        .map(|messages| model.total_tokens(&messages))
+        .unwrap_or_default();
+    report.output_tokens += estimate_token_length(&output);
+    report.output = output.clone();
+    report.tool_results.extend(tool_results.iter().cloned());
     config
         .write()
         .after_chat_completion(&input, &output, &tool_results)?;
@@ -316,6 +614,8 @@ async fn start_directive(
             config,
             input.merge_tool_results(output, tool_results),
             code_mode,
+            print,
+            report,
             abort_signal,
         )
         .await?;
@@ -325,8 +625,11 @@ async fn start_directive(
     Ok(())
 }
 
-async fn start_interactive(config: &GlobalConfig) -> Result<()> {
+async fn start_interactive(config: &GlobalConfig, prelude_prompt: Option<String>) -> Result<()> {
     let mut repl: Repl = Repl::init(config)?;
+    if let Some(prompt) = prelude_prompt {
+        repl.submit(&prompt).await;
+    }
     repl.run().await
 }
 
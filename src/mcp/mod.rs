@@ -1,5 +1,5 @@
-use crate::config::Config;
-use crate::utils::{AbortSignal, abortable_run_with_spinner};
+use crate::config::{Config, GlobalConfig, remove_project_secrets};
+use crate::utils::{AbortSignal, abortable_run_with_spinner, warning_text};
 use crate::vault::interpolate_secrets;
 use anyhow::{Context, Result, anyhow};
 use bm25::{Document, Language, SearchEngine, SearchEngineBuilder};
@@ -15,10 +15,11 @@ use serde_json::{Value, json};
 use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fs::OpenOptions;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 use tokio::process::Command;
+use tokio::sync::Mutex;
 
 pub const MCP_INVOKE_META_FUNCTION_NAME_PREFIX: &str = "mcp_invoke";
 pub const MCP_SEARCH_META_FUNCTION_NAME_PREFIX: &str = "mcp_search";
@@ -26,6 +27,9 @@ pub const MCP_DESCRIBE_META_FUNCTION_NAME_PREFIX: &str = "mcp_describe";
 
 type ConnectedServer = RunningService<RoleClient, ()>;
 
+/// Held while a deferred server starts, so concurrent tool calls don't start the same one twice.
+static DEFERRED_START_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct CatalogItem {
     pub name: String,
@@ -62,13 +66,13 @@ impl Clone for ServerCatalog {
 }
 
 #[derive(Debug, Clone, Deserialize)]
-struct McpServersConfig {
+pub(crate) struct McpServersConfig {
     #[serde(rename = "mcpServers")]
-    mcp_servers: HashMap<String, McpServer>,
+    pub(crate) mcp_servers: HashMap<String, McpServer>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
-struct McpServer {
+pub(crate) struct McpServer {
     command: String,
     args: Option<Vec<String>>,
     env: Option<HashMap<String, JsonField>>,
@@ -89,6 +93,7 @@ pub struct McpRegistry {
     config: Option<McpServersConfig>,
     servers: HashMap<String, Arc<ConnectedServer>>,
     catalogs: HashMap<String, ServerCatalog>,
+    deferred: HashSet<String>,
 }
 
 impl McpRegistry {
@@ -103,31 +108,80 @@ impl McpRegistry {
             log_path,
             ..Default::default()
         };
-        if !Config::mcp_config_file().try_exists().with_context(|| {
-            format!(
-                "Failed to check MCP config file at {}",
-                Config::mcp_config_file().display()
+        let mut mcp_servers = HashMap::new();
+        let mut is_configured = false;
+        // A project's servers are added to the global ones, replacing any with the same name
+        let paths = [
+            Some((Config::mcp_config_file(), false)),
+            Config::project_mcp_config_file().map(|path| (path, true)),
+        ];
+        for (path, is_project) in paths.into_iter().flatten() {
+            if let Some(servers_config) = Self::load_config_file(&path, is_project, config).await? {
+                mcp_servers.extend(servers_config.mcp_servers);
+                is_configured = true;
+            }
+        }
+        if !is_configured {
+            return Ok(registry);
+        }
+        registry.config = Some(McpServersConfig { mcp_servers });
+
+        if start_mcp_servers && config.mcp_server_support {
+            abortable_run_with_spinner(
+                registry.start_select_mcp_servers(enabled_mcp_servers),
+                "Loading MCP servers",
+                abort_signal,
             )
-        })? {
+            .await?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Reads the servers in an MCP config file, if it exists and isn't empty. Vault secrets are
+    /// only filled in for the global file; a project's file can't use them.
+    async fn load_config_file(
+        path: &Path,
+        is_project: bool,
+        config: &Config,
+    ) -> Result<Option<McpServersConfig>> {
+        if !path
+            .try_exists()
+            .with_context(|| format!("Failed to check MCP config file at {}", path.display()))?
+        {
             debug!(
-                "MCP config file does not exist at {}, skipping MCP initialization",
-                Config::mcp_config_file().display()
+                "MCP config file does not exist at {}, skipping it",
+                path.display()
             );
-            return Ok(registry);
+            return Ok(None);
         }
-        let err = || {
-            format!(
-                "Failed to load MCP config file at {}",
-                Config::mcp_config_file().display()
-            )
-        };
-        let content = tokio::fs::read_to_string(Config::mcp_config_file())
-            .await
-            .with_context(err)?;
+        let err = || format!("Failed to load MCP config file at {}", path.display());
+        let content = tokio::fs::read_to_string(path).await.with_context(err)?;
 
         if content.trim().is_empty() {
-            debug!("MCP config file is empty, skipping MCP initialization");
-            return Ok(registry);
+            debug!(
+                "MCP config file at {} is empty, skipping it",
+                path.display()
+            );
+            return Ok(None);
+        }
+
+        if is_project {
+            let value: Value = serde_json::from_str(&content).with_context(err)?;
+            let mut value = serde_yaml::to_value(value).with_context(err)?;
+            let ignored = remove_project_secrets(&mut value);
+            if !ignored.is_empty() {
+                eprintln!(
+                    "{}",
+                    warning_text(&format!(
+                        "Ignoring settings in the project MCP config at '{}' that use vault secrets: {}",
+                        path.display(),
+                        ignored.join(", ")
+                    ))
+                );
+            }
+            let mcp_servers_config = serde_yaml::from_value(value).with_context(err)?;
+            return Ok(Some(mcp_servers_config));
         }
 
         let (parsed_content, missing_secrets) = interpolate_secrets(&content, &config.vault);
@@ -141,20 +195,8 @@ impl McpRegistry {
             )));
         }
 
-        let mcp_servers_config: McpServersConfig =
-            serde_json::from_str(&parsed_content).with_context(err)?;
-        registry.config = Some(mcp_servers_config);
-
-        if start_mcp_servers && config.mcp_server_support {
-            abortable_run_with_spinner(
-                registry.start_select_mcp_servers(enabled_mcp_servers),
-                "Loading MCP servers",
-                abort_signal,
-            )
-            .await?;
-        }
-
-        Ok(registry)
+        let mcp_servers_config = serde_json::from_str(&parsed_content).with_context(err)?;
+        Ok(Some(mcp_servers_config))
     }
 
     pub async fn reinit(
@@ -163,6 +205,7 @@ impl McpRegistry {
         abort_signal: AbortSignal,
     ) -> Result<Self> {
         debug!("Reinitializing MCP registry");
+        registry.deferred.clear();
 
         let desired_ids = registry.resolve_server_ids(enabled_mcp_servers.clone());
         let desired_set: HashSet<String> = desired_ids.iter().cloned().collect();
@@ -185,6 +228,67 @@ impl McpRegistry {
         Ok(registry)
     }
 
+    /// Like `reinit`, but the selected servers that aren't running yet are only started on
+    /// the first call to one of their meta functions.
+    pub async fn reinit_deferred(
+        mut registry: McpRegistry,
+        enabled_mcp_servers: Option<String>,
+        abort_signal: AbortSignal,
+    ) -> Result<Self> {
+        debug!("Reinitializing MCP registry with deferred servers");
+
+        let desired_ids = registry.resolve_server_ids(enabled_mcp_servers);
+        let desired_set: HashSet<String> = desired_ids.iter().cloned().collect();
+
+        abortable_run_with_spinner(
+            registry.stop_unused_servers(&desired_set),
+            "Stopping unused MCP servers",
+            abort_signal,
+        )
+        .await?;
+
+        registry.deferred = desired_ids
+            .into_iter()
+            .filter(|id| !registry.servers.contains_key(id))
+            .collect();
+        debug!("Deferred MCP servers: {:?}", registry.deferred);
+
+        Ok(registry)
+    }
+
+    /// Starts a deferred server, if it hasn't been started yet. Aborting `abort_signal` gives up
+    /// on it, which also lets other calls waiting to start a deferred server go ahead.
+    pub async fn start_deferred_server(
+        config: &GlobalConfig,
+        id: &str,
+        abort_signal: AbortSignal,
+    ) -> Result<()> {
+        let _guard = DEFERRED_START_LOCK.lock().await;
+        let registry = match &config.read().mcp_registry {
+            Some(registry) if registry.deferred.contains(id) => Self {
+                log_path: registry.log_path.clone(),
+                config: registry.config.clone(),
+                ..Default::default()
+            },
+            _ => return Ok(()),
+        };
+
+        let (id, server, catalog) = abortable_run_with_spinner(
+            registry.start_server(id.to_string()),
+            &format!("Starting MCP server: {id}"),
+            abort_signal,
+        )
+        .await?;
+
+        if let Some(registry) = config.write().mcp_registry.as_mut() {
+            registry.deferred.remove(&id);
+            registry.servers.insert(id.clone(), server);
+            registry.catalogs.insert(id, catalog);
+        }
+
+        Ok(())
+    }
+
     async fn start_select_mcp_servers(
         &mut self,
         enabled_mcp_servers: Option<String>,
@@ -353,6 +457,15 @@ impl McpRegistry {
         self.servers.keys().cloned().collect()
     }
 
+    /// The started servers, followed by the deferred ones that will be started on first use.
+    pub fn list_enabled_servers(&self) -> Vec<String> {
+        self.servers
+            .keys()
+            .chain(self.deferred.iter())
+            .cloned()
+            .collect()
+    }
+
     pub fn list_configured_servers(&self) -> Vec<String> {
         if let Some(config) = &self.config {
             config.mcp_servers.keys().cloned().collect()
@@ -433,6 +546,6 @@ impl McpRegistry {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.servers.is_empty()
+        self.servers.is_empty() && self.deferred.is_empty()
     }
 }
@@ -28,11 +28,17 @@ pub fn generate_bash_declarations(
 
     let command_value = argc::export(&build_script, file_name)
         .with_context(|| format!("Failed to parse script at '{tool_file:?}'"))?;
+    let cacheable = command_value
+        .extra
+        .get("metadata")
+        .and_then(|metadata| metadata.get("cacheable"))
+        .is_some_and(|value| value.as_str() != Some("false"));
     if command_value.subcommands.is_empty() {
-        let function_declaration =
+        let mut function_declaration =
             command_to_function_declaration(&command_value).ok_or_else(|| {
                 anyhow::format_err!("Tool definition missing or empty description: {file_name}")
             })?;
+        function_declaration.cacheable = cacheable;
         Ok(vec![function_declaration])
     } else {
         let mut declarations = vec![];
@@ -43,6 +49,7 @@ pub fn generate_bash_declarations(
 
             if let Some(mut function_declaration) = command_to_function_declaration(subcommand) {
                 function_declaration.agent = true;
+                function_declaration.cacheable = cacheable;
                 declarations.push(function_declaration);
             } else {
                 bail!(
@@ -67,6 +74,7 @@ fn command_to_function_declaration(cmd: &CommandValue) -> Option<FunctionDeclara
         description: cmd.describe.clone(),
         parameters: parse_parameters_schema(&cmd.flag_options),
         agent: false,
+        cacheable: false,
     })
 }
 
@@ -0,0 +1,401 @@
+use super::is_cacheable;
+use crate::function::{FunctionDeclaration, JsonSchema};
+use anyhow::{Context, Result, bail};
+use fancy_regex::Regex;
+use indexmap::IndexMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static JSDOC_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)/\*\*(.*?)\*/").unwrap());
+static FUNCTION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^\s*(?:(?:export\s+)?(?:async\s+)?function\s*\*?\s*([A-Za-z_$][\w$]*)\s*\(|(?:export\s+)?(?:const|let|var)\s+([A-Za-z_$][\w$]*)\s*=\s*(?:async\s+)?(?:function\b|\(|[A-Za-z_$][\w$]*\s*=>)|(?:module\.)?exports\.([A-Za-z_$][\w$]*)\s*=)",
+    )
+    .unwrap()
+});
+
+#[derive(Debug)]
+struct Param {
+    name: String,
+    ty: String,
+    required: bool,
+    description: String,
+}
+
+pub fn generate_javascript_declarations(
+    mut tool_file: File,
+    file_name: &str,
+    parent: Option<&Path>,
+) -> Result<Vec<FunctionDeclaration>> {
+    let mut src = String::new();
+    tool_file
+        .read_to_string(&mut src)
+        .with_context(|| format!("Failed to load script at '{tool_file:?}'"))?;
+
+    let is_tool = parent
+        .and_then(|p| p.file_name())
+        .is_some_and(|n| n == "tools");
+    let mut declarations = javascript_to_function_declarations(file_name, &src, is_tool)?;
+
+    if is_tool {
+        for d in &mut declarations {
+            d.agent = true;
+        }
+    }
+    if is_cacheable(&src) {
+        for d in &mut declarations {
+            d.cacheable = true;
+        }
+    }
+
+    Ok(declarations)
+}
+
+fn javascript_to_function_declarations(
+    file_name: &str,
+    src: &str,
+    is_tool: bool,
+) -> Result<Vec<FunctionDeclaration>> {
+    let mut out = Vec::new();
+
+    for captures in JSDOC_RE.captures_iter(src) {
+        let captures = captures.context("failed to parse javascript")?;
+        let (Some(comment), Some(whole)) = (captures.get(1), captures.get(0)) else {
+            continue;
+        };
+        let Some(func_name) = function_name_after(&src[whole.end()..]) else {
+            continue;
+        };
+
+        if func_name.starts_with('_') && func_name != "_instructions" {
+            continue;
+        }
+
+        if is_tool && func_name != "run" {
+            continue;
+        }
+
+        let (description, params) = parse_jsdoc(comment.as_str());
+        if description.is_empty() {
+            bail!("Missing or empty description on function: {func_name}");
+        }
+
+        let name = if is_tool && func_name == "run" {
+            underscore(file_name)
+        } else {
+            func_name
+        };
+
+        out.push(FunctionDeclaration {
+            name,
+            description,
+            parameters: build_parameters_schema(&params),
+            agent: !is_tool,
+            cacheable: false,
+        });
+    }
+
+    Ok(out)
+}
+
+/// Returns the name of the function declared directly after a JSDoc comment.
+fn function_name_after(rest: &str) -> Option<String> {
+    let captures = FUNCTION_RE.captures(rest).ok()??;
+    (1..=3)
+        .find_map(|i| captures.get(i))
+        .map(|m| m.as_str().to_string())
+}
+
+fn parse_jsdoc(comment: &str) -> (String, Vec<Param>) {
+    let lines: Vec<&str> = comment
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix('*').unwrap_or(line).trim()
+        })
+        .collect();
+
+    let mut description = vec![];
+    let mut tags: Vec<String> = vec![];
+    for line in lines {
+        if line.starts_with('@') {
+            tags.push(line.to_string());
+        } else if let Some(tag) = tags.last_mut() {
+            if !line.is_empty() {
+                tag.push(' ');
+                tag.push_str(line);
+            }
+        } else {
+            description.push(line);
+        }
+    }
+
+    let mut params: Vec<Param> = tags
+        .iter()
+        .filter_map(|tag| {
+            tag.strip_prefix("@param")
+                .or_else(|| tag.strip_prefix("@arg"))
+                .and_then(parse_param)
+        })
+        .collect();
+
+    // Destructured arguments are documented as `args.name`, so the parent entry
+    // (e.g. `@param {Object} args`) is dropped in favor of its properties.
+    let parents: Vec<String> = params
+        .iter()
+        .filter_map(|p| p.name.split_once('.').map(|(parent, _)| parent.to_string()))
+        .collect();
+    params.retain(|p| !parents.contains(&p.name));
+    for p in &mut params {
+        if let Some((_, name)) = p.name.rsplit_once('.') {
+            p.name = name.to_string();
+        }
+    }
+
+    (description.join("\n").trim().to_string(), params)
+}
+
+fn parse_param(tag: &str) -> Option<Param> {
+    let mut rest = tag.trim();
+    let mut ty = String::new();
+    if let Some(stripped) = rest.strip_prefix('{') {
+        let end = stripped.find('}')?;
+        ty = stripped[..end].trim().to_string();
+        rest = stripped[end + 1..].trim_start();
+    }
+
+    let (mut name, description) = match rest.strip_prefix('[') {
+        Some(stripped) => {
+            let end = stripped.find(']')?;
+            (
+                format!("[{}]", &stripped[..end]),
+                stripped[end + 1..].trim(),
+            )
+        }
+        None => match rest.split_once(char::is_whitespace) {
+            Some((name, description)) => (name.to_string(), description.trim()),
+            None => (rest.to_string(), ""),
+        },
+    };
+
+    let mut required = true;
+    if let Some(inner) = name.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        name = inner
+            .split('=')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        required = false;
+    }
+    if let Some(inner) = ty.strip_suffix('=') {
+        ty = inner.to_string();
+        required = false;
+    }
+    if name.is_empty() {
+        return None;
+    }
+
+    let description = description
+        .strip_prefix('-')
+        .unwrap_or(description)
+        .trim()
+        .to_string();
+
+    Some(Param {
+        name,
+        ty,
+        required,
+        description,
+    })
+}
+
+fn underscore(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .split('_')
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn build_parameters_schema(params: &[Param]) -> JsonSchema {
+    let mut props: IndexMap<String, JsonSchema> = IndexMap::new();
+    let mut req: Vec<String> = Vec::new();
+
+    for p in params {
+        let name = p.name.replace('-', "_");
+        let mut schema = JsonSchema::default();
+
+        if !p.description.is_empty() {
+            schema.description = Some(p.description.clone());
+        }
+
+        apply_type_to_schema(&p.ty, &mut schema);
+
+        if p.required {
+            req.push(name.clone());
+        }
+
+        props.insert(name, schema);
+    }
+
+    JsonSchema {
+        type_value: Some("object".into()),
+        description: None,
+        properties: Some(props),
+        items: None,
+        any_of: None,
+        enum_value: None,
+        default: None,
+        required: if req.is_empty() { None } else { Some(req) },
+    }
+}
+
+fn apply_type_to_schema(ty: &str, s: &mut JsonSchema) {
+    let t = ty.trim().trim_start_matches('?');
+
+    let inner = t
+        .strip_suffix("[]")
+        .or_else(|| t.strip_prefix("Array<").and_then(|v| v.strip_suffix('>')));
+    if let Some(inner) = inner {
+        s.type_value = Some("array".into());
+        let mut item = JsonSchema::default();
+        apply_type_to_schema(inner, &mut item);
+        s.items = Some(Box::new(item));
+        return;
+    }
+
+    if t.contains('|') {
+        let vals: Vec<String> = t
+            .split('|')
+            .map(|v| v.trim())
+            .filter(|v| v.starts_with(['"', '\'']))
+            .map(|v| v.trim_matches(['"', '\'']).to_string())
+            .collect();
+        if !vals.is_empty() {
+            s.type_value = Some("string".into());
+            s.enum_value = Some(vals);
+            return;
+        }
+    }
+
+    s.type_value = Some(
+        match t.to_lowercase().as_str() {
+            "boolean" | "bool" => "boolean",
+            "integer" | "int" => "integer",
+            "number" => "number",
+            "object" => "object",
+            _ => "string",
+        }
+        .into(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_javascript_to_function_declarations() {
+        let src = r#"
+/**
+ * Get the current weather in a given location.
+ * @param {Object} args
+ * @param {string} args.location - The city, e.g. San Francisco
+ * @param {"celsius"|"fahrenheit"} [args.unit] - The unit
+ * @param {number=} args.days How many days
+ * @param {string[]} args.tags
+ */
+export async function get_current_weather({ location, unit, days, tags }) {}
+
+/**
+ * A helper that isn't exposed.
+ */
+function _private() {}
+
+/**
+ * Run a command
+ * in the shell.
+ */
+const run_command = async (args) => {};
+"#;
+        let declarations = javascript_to_function_declarations("tools", src, false).unwrap();
+        let names: Vec<&str> = declarations.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["get_current_weather", "run_command"]);
+        assert_eq!(declarations[1].description, "Run a command\nin the shell.");
+
+        let declaration = &declarations[0];
+        assert_eq!(
+            declaration.description,
+            "Get the current weather in a given location."
+        );
+        assert!(declaration.agent);
+        let properties = declaration.parameters.properties.as_ref().unwrap();
+        let keys: Vec<&str> = properties.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["location", "unit", "days", "tags"]);
+        assert_eq!(
+            properties["location"].description.as_deref(),
+            Some("The city, e.g. San Francisco")
+        );
+        assert_eq!(
+            properties["unit"].enum_value,
+            Some(vec!["celsius".to_string(), "fahrenheit".to_string()])
+        );
+        assert_eq!(properties["days"].type_value.as_deref(), Some("number"));
+        assert_eq!(
+            properties["tags"]
+                .items
+                .as_ref()
+                .unwrap()
+                .type_value
+                .as_deref(),
+            Some("string")
+        );
+        assert_eq!(
+            declaration.parameters.required,
+            Some(vec!["location".to_string(), "tags".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_javascript_tool() {
+        let src = r#"
+/**
+ * Not a tool.
+ */
+function helper() {}
+
+/**
+ * Read a file.
+ * @param {Object} args
+ * @param {string} args.path The file to read
+ */
+exports.run = async function (args) {};
+"#;
+        let declarations = javascript_to_function_declarations("read-file", src, true).unwrap();
+        assert_eq!(declarations.len(), 1);
+        assert_eq!(declarations[0].name, "read_file");
+        assert!(!declarations[0].agent);
+
+        let err = javascript_to_function_declarations(
+            "tools",
+            "/** @param {string} x */\nfunction undocumented(x) {}\n",
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing or empty description on function: undocumented"
+        );
+    }
+}
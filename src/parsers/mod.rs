@@ -1,2 +1,20 @@
 pub(crate) mod bash;
+pub(crate) mod javascript;
+pub(crate) mod powershell;
 pub(crate) mod python;
+pub(crate) mod validate;
+
+/// Whether a Python, JavaScript or PowerShell tool script opts into result caching with a
+/// `# @meta cacheable` (or `// @meta cacheable`) comment.
+fn is_cacheable(src: &str) -> bool {
+    src.lines().any(|line| {
+        let line = line.trim_start();
+        let Some(comment) = line.strip_prefix('#').or_else(|| line.strip_prefix("//")) else {
+            return false;
+        };
+        let mut words = comment.split_whitespace();
+        words.next() == Some("@meta")
+            && words.next() == Some("cacheable")
+            && words.next() != Some("false")
+    })
+}
@@ -0,0 +1,535 @@
+use super::is_cacheable;
+use crate::function::{FunctionDeclaration, JsonSchema};
+use anyhow::{Context, Result, bail};
+use fancy_regex::Regex;
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static FUNCTION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?im)^[ \t]*function[ \t]+([A-Za-z_][\w-]*)").unwrap());
+
+#[derive(Debug, Default)]
+struct Param {
+    name: String,
+    ty: String,
+    required: bool,
+    default: Option<Value>,
+    enum_values: Option<Vec<String>>,
+    description: String,
+}
+
+pub fn generate_powershell_declarations(
+    mut tool_file: File,
+    file_name: &str,
+    parent: Option<&Path>,
+) -> Result<Vec<FunctionDeclaration>> {
+    let mut src = String::new();
+    tool_file
+        .read_to_string(&mut src)
+        .with_context(|| format!("Failed to load script at '{tool_file:?}'"))?;
+
+    let is_tool = parent
+        .and_then(|p| p.file_name())
+        .is_some_and(|n| n == "tools");
+    let mut declarations = powershell_to_function_declarations(file_name, &src, is_tool)?;
+
+    if is_tool {
+        for d in &mut declarations {
+            d.agent = true;
+        }
+    }
+    if is_cacheable(&src) {
+        for d in &mut declarations {
+            d.cacheable = true;
+        }
+    }
+
+    Ok(declarations)
+}
+
+fn powershell_to_function_declarations(
+    file_name: &str,
+    src: &str,
+    is_tool: bool,
+) -> Result<Vec<FunctionDeclaration>> {
+    let mut out = Vec::new();
+
+    for captures in FUNCTION_RE.captures_iter(src) {
+        let captures = captures.context("failed to parse powershell")?;
+        let (Some(whole), Some(func_name)) = (captures.get(0), captures.get(1)) else {
+            continue;
+        };
+        let func_name = func_name.as_str().to_string();
+
+        if func_name.starts_with('_') && func_name != "_instructions" {
+            continue;
+        }
+
+        if is_tool && func_name != "run" {
+            continue;
+        }
+
+        let Some((inline_params, body)) = function_parts(&src[whole.end()..]) else {
+            continue;
+        };
+
+        // Comment-based help can sit either directly above the function or at the top of its body.
+        let (help, body) = match help_block_before(&src[..whole.start()]) {
+            Some(help) => (help, body),
+            None => match help_block_at_start(body) {
+                Some((help, rest)) => (help, rest),
+                None => ("", body),
+            },
+        };
+        let (description, param_descriptions) = parse_help(help);
+        if description.is_empty() {
+            bail!("Missing or empty description on function: {func_name}");
+        }
+
+        let param_block = inline_params.or_else(|| param_block_at_start(body));
+        let mut params = param_block.map(parse_params).unwrap_or_default();
+        for p in &mut params {
+            if let Some(description) = param_descriptions.get(&p.name.to_lowercase()) {
+                p.description = description.clone();
+            }
+        }
+
+        let name = if is_tool && func_name == "run" {
+            underscore(file_name)
+        } else {
+            func_name
+        };
+
+        out.push(FunctionDeclaration {
+            name,
+            description,
+            parameters: build_parameters_schema(&params),
+            agent: !is_tool,
+            cacheable: false,
+        });
+    }
+
+    Ok(out)
+}
+
+/// Splits what follows `function <name>` into its inline parameter list, if it has one, and the
+/// text after the opening brace of its body.
+fn function_parts(rest: &str) -> Option<(Option<&str>, &str)> {
+    let mut rest = rest.trim_start();
+    let mut inline_params = None;
+    if rest.starts_with('(') {
+        let (inner, end) = enclosed(rest, '(', ')')?;
+        inline_params = Some(inner);
+        rest = rest[end..].trim_start();
+    }
+    let body = rest.strip_prefix('{')?;
+
+    Some((inline_params, body))
+}
+
+fn help_block_before(src: &str) -> Option<&str> {
+    let src = src.trim_end().strip_suffix("#>")?;
+    let start = src.rfind("<#")?;
+    Some(&src[start + 2..])
+}
+
+fn help_block_at_start(body: &str) -> Option<(&str, &str)> {
+    let body = body.trim_start().strip_prefix("<#")?;
+    let end = body.find("#>")?;
+    Some((&body[..end], &body[end + 2..]))
+}
+
+fn param_block_at_start(body: &str) -> Option<&str> {
+    let mut rest = body.trim_start();
+    // Skip attributes like `[CmdletBinding()]` that precede the param block
+    while rest.starts_with('[') {
+        let (_, end) = enclosed(rest, '[', ']')?;
+        rest = rest[end..].trim_start();
+    }
+    let keyword = rest.get(..5)?;
+    if !keyword.eq_ignore_ascii_case("param") {
+        return None;
+    }
+    let rest = rest[5..].trim_start();
+    if !rest.starts_with('(') {
+        return None;
+    }
+
+    enclosed(rest, '(', ')').map(|(inner, _)| inner)
+}
+
+/// Parses comment-based help into the function's description (its `.SYNOPSIS` followed by its
+/// `.DESCRIPTION`) and the description of each `.PARAMETER`, keyed by lowercase name.
+fn parse_help(help: &str) -> (String, HashMap<String, String>) {
+    let mut sections: Vec<(String, Vec<&str>)> = vec![];
+    for line in help.lines() {
+        let line = line.trim();
+        if let Some(keyword) = line.strip_prefix('.')
+            && keyword.starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            sections.push((keyword.to_string(), vec![]));
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line);
+        }
+    }
+
+    let mut synopsis = String::new();
+    let mut description = String::new();
+    let mut params = HashMap::new();
+    for (keyword, lines) in sections {
+        let text = lines.join("\n").trim().to_string();
+        let mut words = keyword.split_whitespace();
+        match words.next().unwrap_or_default().to_uppercase().as_str() {
+            "SYNOPSIS" => synopsis = text,
+            "DESCRIPTION" => description = text,
+            "PARAMETER" => {
+                if let Some(name) = words.next() {
+                    params.insert(name.to_lowercase(), text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let description = [synopsis, description]
+        .into_iter()
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (description, params)
+}
+
+fn parse_params(block: &str) -> Vec<Param> {
+    let block = block
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    split_top_level(&block, ',')
+        .into_iter()
+        .filter_map(parse_param)
+        .collect()
+}
+
+fn parse_param(entry: &str) -> Option<Param> {
+    let mut param = Param::default();
+    let mut rest = entry.trim();
+    while rest.starts_with('[') {
+        let (attribute, end) = enclosed(rest, '[', ']')?;
+        apply_attribute(attribute.trim(), &mut param);
+        rest = rest[end..].trim_start();
+    }
+
+    let rest = rest.strip_prefix('$')?;
+    let (name, default) = match rest.split_once('=') {
+        Some((name, default)) => (name.trim(), parse_default(default.trim())),
+        None => (rest.trim(), None),
+    };
+    if name.is_empty() {
+        return None;
+    }
+    param.name = name.to_string();
+    param.default = default;
+
+    Some(param)
+}
+
+fn apply_attribute(attribute: &str, param: &mut Param) {
+    let Some((name, _)) = attribute.split_once('(') else {
+        param.ty = attribute.to_string();
+        return;
+    };
+    let Some((args, _)) = enclosed(&attribute[name.len()..], '(', ')') else {
+        return;
+    };
+    let args = split_top_level(args, ',');
+
+    match name.trim().to_lowercase().as_str() {
+        "parameter" => {
+            param.required = args.iter().any(|arg| {
+                let (key, value) = arg.split_once('=').unwrap_or((arg, "$true"));
+                key.trim().eq_ignore_ascii_case("mandatory")
+                    && value.trim().eq_ignore_ascii_case("$true")
+            });
+        }
+        "validateset" => {
+            param.enum_values = Some(
+                args.iter()
+                    .map(|v| v.trim().trim_matches(['"', '\'']).to_string())
+                    .collect(),
+            );
+        }
+        _ => {}
+    }
+}
+
+fn parse_default(value: &str) -> Option<Value> {
+    if value.len() >= 2 && value.starts_with(['"', '\'']) && value.ends_with(['"', '\'']) {
+        return Some(Value::from(&value[1..value.len() - 1]));
+    }
+    match value.to_lowercase().as_str() {
+        "$true" => Some(Value::Bool(true)),
+        "$false" => Some(Value::Bool(false)),
+        _ => serde_json::from_str::<Value>(value)
+            .ok()
+            .filter(Value::is_number),
+    }
+}
+
+/// Returns the text between `open` at the start of `s` and its matching `close`, along with the
+/// byte offset just past `close`.
+fn enclosed(s: &str, open: char, close: char) -> Option<(&str, usize)> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == open => depth += 1,
+            None if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((&s[open.len_utf8()..i], i + close.len_utf8()));
+                }
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+fn split_top_level(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                c if c == separator && depth == 0 => {
+                    parts.push(&s[start..i]);
+                    start = i + c.len_utf8();
+                }
+                _ => {}
+            },
+        }
+    }
+    parts.push(&s[start..]);
+    parts.into_iter().filter(|v| !v.trim().is_empty()).collect()
+}
+
+fn underscore(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .split('_')
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn build_parameters_schema(params: &[Param]) -> JsonSchema {
+    let mut props: IndexMap<String, JsonSchema> = IndexMap::new();
+    let mut req: Vec<String> = Vec::new();
+
+    for p in params {
+        let mut schema = JsonSchema::default();
+
+        if !p.description.is_empty() {
+            schema.description = Some(p.description.clone());
+        }
+
+        apply_type_to_schema(&p.ty, &mut schema);
+        if let Some(values) = &p.enum_values {
+            match schema.items.as_mut() {
+                Some(item) => item.enum_value = Some(values.clone()),
+                None => schema.enum_value = Some(values.clone()),
+            }
+        }
+        schema.default = p.default.clone();
+
+        if p.required {
+            req.push(p.name.clone());
+        }
+
+        props.insert(p.name.clone(), schema);
+    }
+
+    JsonSchema {
+        type_value: Some("object".into()),
+        description: None,
+        properties: Some(props),
+        items: None,
+        any_of: None,
+        enum_value: None,
+        default: None,
+        required: if req.is_empty() { None } else { Some(req) },
+    }
+}
+
+fn apply_type_to_schema(ty: &str, s: &mut JsonSchema) {
+    let t = ty.trim().to_lowercase();
+    let t = t.strip_prefix("system.").unwrap_or(&t);
+
+    if let Some(inner) = t.strip_suffix("[]") {
+        s.type_value = Some("array".into());
+        let mut item = JsonSchema::default();
+        apply_type_to_schema(inner, &mut item);
+        s.items = Some(Box::new(item));
+        return;
+    }
+
+    s.type_value = Some(
+        match t {
+            "bool" | "boolean" | "switch" => "boolean",
+            "int" | "int16" | "int32" | "int64" | "long" | "short" | "byte" | "uint16"
+            | "uint32" | "uint64" | "bigint" => "integer",
+            "double" | "float" | "single" | "decimal" => "number",
+            "hashtable" | "pscustomobject" => "object",
+            _ => "string",
+        }
+        .into(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_powershell_to_function_declarations() {
+        let src = r#"
+<#
+.SYNOPSIS
+Get the weather
+.DESCRIPTION
+Looks it up online.
+.PARAMETER Location
+The city
+.PARAMETER Units
+The units
+#>
+function Get-Weather {
+    [CmdletBinding()]
+    param(
+        # Where to look
+        [Parameter(Mandatory = $true)]
+        [string]$Location,
+        [ValidateSet("metric", "imperial")]
+        [string]$Units = "metric",
+        [int]$Days = 3,
+        [switch]$Alerts
+    )
+}
+
+function _helper {
+}
+"#;
+        let declarations = powershell_to_function_declarations("weather", src, false).unwrap();
+        assert_eq!(declarations.len(), 1);
+        let declaration = &declarations[0];
+        assert_eq!(declaration.name, "Get-Weather");
+        assert_eq!(
+            declaration.description,
+            "Get the weather\nLooks it up online."
+        );
+        assert!(declaration.agent);
+
+        let properties = declaration.parameters.properties.as_ref().unwrap();
+        let location = &properties["Location"];
+        assert_eq!(location.type_value.as_deref(), Some("string"));
+        assert_eq!(location.description.as_deref(), Some("The city"));
+        let units = &properties["Units"];
+        assert_eq!(
+            units.enum_value,
+            Some(vec!["metric".to_string(), "imperial".to_string()])
+        );
+        assert_eq!(units.default, Some(json!("metric")));
+        assert_eq!(units.description.as_deref(), Some("The units"));
+        assert_eq!(properties["Days"].type_value.as_deref(), Some("integer"));
+        assert_eq!(properties["Days"].default, Some(json!(3)));
+        assert_eq!(properties["Alerts"].type_value.as_deref(), Some("boolean"));
+        assert_eq!(
+            declaration.parameters.required,
+            Some(vec!["Location".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_powershell_tool_with_help_in_body() {
+        let src = r#"
+function Format-Path {
+    <#
+    .SYNOPSIS
+    Not a tool
+    #>
+}
+
+function run {
+    <#
+    .SYNOPSIS
+    Read a file
+    #>
+    param(
+        [Parameter(Mandatory)]
+        [string[]]$Paths
+    )
+}
+"#;
+        let declarations = powershell_to_function_declarations("read-file", src, true).unwrap();
+        assert_eq!(declarations.len(), 1);
+        let declaration = &declarations[0];
+        assert_eq!(declaration.name, "read_file");
+        assert_eq!(declaration.description, "Read a file");
+        assert!(!declaration.agent);
+        let paths = &declaration.parameters.properties.as_ref().unwrap()["Paths"];
+        assert_eq!(paths.type_value.as_deref(), Some("array"));
+        assert_eq!(
+            paths.items.as_ref().unwrap().type_value.as_deref(),
+            Some("string")
+        );
+        assert_eq!(
+            declaration.parameters.required,
+            Some(vec!["Paths".to_string()])
+        );
+
+        let err =
+            powershell_to_function_declarations("tool", "function Undocumented {\n}\n", false)
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing or empty description on function: Undocumented"
+        );
+    }
+
+    #[test]
+    fn test_split_top_level() {
+        assert_eq!(
+            split_top_level(r#"[ValidateSet("a,b", 'c')] $x, $y = @(1, 2)"#, ','),
+            vec![r#"[ValidateSet("a,b", 'c')] $x"#, " $y = @(1, 2)"]
+        );
+        assert_eq!(parse_default("$false"), Some(Value::Bool(false)));
+        assert_eq!(parse_default("'text'"), Some(json!("text")));
+        assert_eq!(parse_default("Get-Date"), None);
+    }
+}
@@ -1,3 +1,4 @@
+use super::is_cacheable;
 use crate::function::{FunctionDeclaration, JsonSchema};
 use anyhow::{Context, Result, bail};
 use ast::{Stmt, StmtFunctionDef};
@@ -40,6 +41,11 @@ pub fn generate_python_declarations(
             d.agent = true;
         }
     }
+    if is_cacheable(&src) {
+        for d in &mut declarations {
+            d.cacheable = true;
+        }
+    }
 
     Ok(declarations)
 }
@@ -95,6 +101,7 @@ fn python_to_function_declarations(
                 description: desc_trim,
                 parameters: schema,
                 agent: !is_tool,
+                cacheable: false,
             });
         }
     }
@@ -0,0 +1,243 @@
+use crate::function::{FunctionDeclaration, JsonSchema};
+use anyhow::{Result, bail};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+const MAX_FUNCTION_NAME_LEN: usize = 64;
+const SCHEMA_TYPES: [&str; 7] = [
+    "string", "number", "integer", "boolean", "array", "object", "null",
+];
+
+/// Checks that the declarations generated from a tool file are valid JSON Schema that providers
+/// will accept, so a mistake in a tool's comments is reported against the tool itself rather than
+/// as an opaque error from the provider. Each problem is reported with the line of `src` it most
+/// likely comes from.
+pub fn validate_declarations(
+    path: &Path,
+    src: Option<&str>,
+    declarations: &[FunctionDeclaration],
+) -> Result<()> {
+    let mut errors = vec![];
+    for declaration in declarations {
+        let function_line = src.and_then(|src| find_line(src, &declaration.name, None));
+        let mut problems = vec![];
+        if declaration.name.is_empty()
+            || declaration.name.len() > MAX_FUNCTION_NAME_LEN
+            || !declaration
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            problems.push((
+                None,
+                format!(
+                    "the name must be 1 to {MAX_FUNCTION_NAME_LEN} letters, digits, underscores, or dashes"
+                ),
+            ));
+        }
+        let parameters = &declaration.parameters;
+        if parameters.type_value.as_deref() != Some("object") {
+            problems.push((None, "the parameters must be an object".to_string()));
+        }
+        validate_schema(parameters, "", &mut problems);
+
+        for (param, problem) in problems {
+            let line = match (src, &param) {
+                (Some(src), Some(param)) => find_line(src, param, function_line).or(function_line),
+                _ => function_line,
+            };
+            let location = match line {
+                Some(line) => format!("{}:{}", path.display(), line + 1),
+                None => path.display().to_string(),
+            };
+            let subject = match param {
+                Some(param) => format!("parameter '{param}' of '{}'", declaration.name),
+                None => format!("'{}'", declaration.name),
+            };
+            errors.push(format!("{location}: invalid {subject}: {problem}"));
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("Invalid tool declarations:\n{}", errors.join("\n"));
+    }
+    Ok(())
+}
+
+/// Validates `schema`, recording each problem along with the top-level parameter it belongs to.
+fn validate_schema(schema: &JsonSchema, param: &str, problems: &mut Vec<(Option<String>, String)>) {
+    let mut problem = |message: String| {
+        let param = (!param.is_empty()).then(|| param.to_string());
+        problems.push((param, message));
+    };
+    let ty = schema.type_value.as_deref();
+
+    if let Some(ty) = ty
+        && !SCHEMA_TYPES.contains(&ty)
+    {
+        problem(format!(
+            "'{ty}' isn't a JSON Schema type (expected one of {})",
+            SCHEMA_TYPES.join(", ")
+        ));
+    }
+
+    if ty == Some("array") && schema.items.is_none() {
+        problem("arrays must declare the type of their items".to_string());
+    }
+
+    if let Some(required) = &schema.required {
+        let properties = schema.properties.as_ref();
+        let mut seen = HashSet::new();
+        for name in required {
+            if !properties.is_some_and(|v| v.contains_key(name)) {
+                problem(format!("'{name}' is required but isn't a property"));
+            }
+            if !seen.insert(name) {
+                problem(format!("'{name}' is required more than once"));
+            }
+        }
+    }
+
+    if let Some(values) = &schema.enum_value {
+        if values.is_empty() {
+            problem("the list of allowed values is empty".to_string());
+        }
+        if ty.is_some_and(|ty| ty != "string") {
+            problem(format!(
+                "only string values can be listed as allowed values, but the type is '{}'",
+                ty.unwrap_or_default()
+            ));
+        }
+        let mut seen = HashSet::new();
+        for value in values {
+            if !seen.insert(value) {
+                problem(format!(
+                    "the allowed value '{value}' is listed more than once"
+                ));
+            }
+        }
+    }
+
+    if let (Some(default), Some(ty)) = (&schema.default, ty)
+        && !default.is_null()
+    {
+        if !value_has_type(default, ty) {
+            problem(format!("the default value {default} isn't of type '{ty}'"));
+        } else if let (Some(values), Some(default)) = (&schema.enum_value, default.as_str())
+            && !values.iter().any(|v| v == default)
+        {
+            problem(format!(
+                "the default value '{default}' isn't one of the allowed values"
+            ));
+        }
+    }
+
+    if let Some(properties) = &schema.properties {
+        for (name, property) in properties {
+            let param = if param.is_empty() { name } else { param };
+            validate_schema(property, param, problems);
+        }
+    }
+    if let Some(items) = &schema.items {
+        validate_schema(items, param, problems);
+    }
+    for schema in schema.any_of.iter().flatten() {
+        validate_schema(schema, param, problems);
+    }
+}
+
+fn value_has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+/// Finds the line that mentions `name` as a whole word, as is (e.g. `def list_files`) or with
+/// dashes (e.g. `# @option --file-path`). Parameters are declared just above or below their
+/// function, so the mention closest to the line `near` is used.
+fn find_line(src: &str, name: &str, near: Option<usize>) -> Option<usize> {
+    let dashed = name.replace('_', "-");
+    let near = near.unwrap_or_default();
+    src.lines()
+        .enumerate()
+        .filter(|(_, line)| contains_word(line, name) || contains_word(line, &dashed))
+        .map(|(i, _)| i)
+        .min_by_key(|&i| (i.abs_diff(near), i))
+}
+
+fn contains_word(line: &str, word: &str) -> bool {
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    // A dash after the word means it's only the start of a longer option name
+    line.match_indices(word).any(|(i, _)| {
+        !line[..i].ends_with(is_word_char)
+            && !line[i + word.len()..].starts_with(|c| is_word_char(c) || c == '-')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+    use serde_json::json;
+
+    fn schema(value: Value) -> JsonSchema {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_validate_declarations() {
+        let src = "# @describe Count files\n# @option --file-path! The path\n# @option --count <INT>\ncount_files() {\n}\n";
+        let path = Path::new("tools/count_files.sh");
+        let mut properties = IndexMap::new();
+        properties.insert(
+            "file_path".to_string(),
+            schema(json!({"type": "string", "default": 1})),
+        );
+        properties.insert(
+            "count".to_string(),
+            schema(json!({"type": "integer", "enum": ["1", "2"]})),
+        );
+        properties.insert("tags".to_string(), schema(json!({"type": "array"})));
+        let declaration = FunctionDeclaration {
+            name: "count_files".to_string(),
+            description: "Count files".to_string(),
+            parameters: JsonSchema {
+                type_value: Some("object".to_string()),
+                properties: Some(properties),
+                required: Some(vec!["file_path".to_string(), "missing".to_string()]),
+                ..Default::default()
+            },
+            agent: false,
+            cacheable: false,
+        };
+
+        let err = validate_declarations(path, Some(src), std::slice::from_ref(&declaration))
+            .unwrap_err()
+            .to_string();
+        let errors = err.lines().skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "tools/count_files.sh:4: invalid 'count_files': 'missing' is required but isn't a property",
+                "tools/count_files.sh:2: invalid parameter 'file_path' of 'count_files': the default value 1 isn't of type 'string'",
+                "tools/count_files.sh:3: invalid parameter 'count' of 'count_files': only string values can be listed as allowed values, but the type is 'integer'",
+                "tools/count_files.sh:4: invalid parameter 'tags' of 'count_files': arrays must declare the type of their items",
+            ]
+        );
+
+        let mut declaration = declaration;
+        declaration.parameters.properties = Some(IndexMap::from([(
+            "file_path".to_string(),
+            schema(json!({"type": "string"})),
+        )]));
+        declaration.parameters.required = Some(vec!["file_path".to_string()]);
+        assert!(validate_declarations(path, Some(src), &[declaration]).is_ok());
+    }
+}
@@ -11,7 +11,12 @@ use anyhow::{Context, Result, anyhow, bail};
 use bm25::{Language, SearchEngine, SearchEngineBuilder};
 use hnsw_rs::prelude::*;
 use indexmap::{IndexMap, IndexSet};
-use inquire::{Confirm, Select, Text, required, validator::Validation};
+use inquire::{
+    Confirm, CustomUserError, Select, Text,
+    autocompletion::{Autocomplete, Replacement},
+    required,
+    validator::Validation,
+};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -61,6 +66,7 @@ impl Rag {
         name: &str,
         save_path: &Path,
         doc_paths: &[String],
+        exclude_paths: &[String],
         abort_signal: AbortSignal,
     ) -> Result<Self> {
         if !*IS_STDOUT_TERMINAL {
@@ -81,6 +87,7 @@ impl Rag {
             embedding_model.max_batch_size(),
         );
         let mut rag = Self::create(config, name, save_path, data)?;
+        rag.set_exclude_paths(exclude_paths);
         let mut paths = doc_paths.to_vec();
         if paths.is_empty() {
             paths = add_documents()?;
@@ -128,6 +135,11 @@ impl Rag {
         &self.data.document_paths
     }
 
+    /// Sets the glob patterns of local files to leave out when syncing documents.
+    pub fn set_exclude_paths(&mut self, exclude_paths: &[String]) {
+        self.data.exclude_paths = exclude_paths.to_vec();
+    }
+
     pub async fn refresh_document_paths(
         &mut self,
         document_paths: &[String],
@@ -354,6 +366,13 @@ impl Rag {
         }
         let (document_paths, mut recursive_urls, mut urls, mut protocol_paths, mut local_paths) =
             resolve_paths(&loaders, paths).await?;
+        local_paths.retain(|path| {
+            !self
+                .data
+                .exclude_paths
+                .iter()
+                .any(|pattern| glob_match(pattern, path))
+        });
         let mut to_deleted: IndexMap<String, Vec<FileId>> = Default::default();
         if refresh {
             for (file_id, file) in &self.data.files {
@@ -730,6 +749,8 @@ pub struct RagData {
     pub batch_size: Option<usize>,
     pub next_file_id: FileId,
     pub document_paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_paths: Vec<String>,
     pub files: IndexMap<FileId, RagFile>,
     #[serde(with = "serde_vectors")]
     pub vectors: IndexMap<DocumentId, Vec<f32>>,
@@ -746,6 +767,7 @@ impl Debug for RagData {
             .field("batch_size", &self.batch_size)
             .field("next_file_id", &self.next_file_id)
             .field("document_paths", &self.document_paths)
+            .field("exclude_paths", &self.exclude_paths)
             .field("files", &self.files)
             .finish()
     }
@@ -769,6 +791,7 @@ impl RagData {
             batch_size,
             next_file_id: 0,
             document_paths: Default::default(),
+            exclude_paths: Default::default(),
             files: Default::default(),
             vectors: Default::default(),
         }
@@ -952,6 +975,7 @@ fn add_documents() -> Result<Vec<String>> {
     let text = Text::new("Add documents:")
         .with_validator(required!("This field is required"))
         .with_help_message("e.g. file;dir/;dir/**/*.{md,mdx};loader:resource;url;website/**")
+        .with_autocomplete(DocumentPathCompleter)
         .prompt()?;
     let paths = text
         .split(';')
@@ -963,6 +987,38 @@ fn add_documents() -> Result<Vec<String>> {
     Ok(paths)
 }
 
+/// Tab-completes the filesystem path being typed after the last `;` of the documents prompt.
+#[derive(Debug, Clone)]
+struct DocumentPathCompleter;
+
+impl Autocomplete for DocumentPathCompleter {
+    fn get_suggestions(&mut self, input: &str) -> Result<Vec<String>, CustomUserError> {
+        let start = input.rfind(';').map_or(0, |i| i + 1);
+        let path = input[start..].trim_start();
+        let head = &input[..input.len() - path.len()];
+        Ok(complete_path(path)
+            .into_iter()
+            .map(|v| format!("{head}{v}"))
+            .collect())
+    }
+
+    fn get_completion(
+        &mut self,
+        input: &str,
+        highlighted_suggestion: Option<String>,
+    ) -> Result<Replacement, CustomUserError> {
+        if highlighted_suggestion.is_some() {
+            return Ok(highlighted_suggestion);
+        }
+        let mut suggestions = self.get_suggestions(input)?;
+        if suggestions.len() == 1 {
+            Ok(suggestions.pop())
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 async fn resolve_paths<T: AsRef<str>>(
     loaders: &HashMap<String, String>,
     paths: &[T],
@@ -1021,10 +1077,7 @@ fn reciprocal_rank_fusion(
 ) -> Vec<DocumentId> {
     let rrf_k = top_k * 2;
     let mut map: IndexMap<DocumentId, f32> = IndexMap::new();
-    for (document_ids, weight) in list_of_document_ids
-        .into_iter()
-        .zip(list_of_weights.into_iter())
-    {
+    for (document_ids, weight) in list_of_document_ids.into_iter().zip(list_of_weights) {
         for (index, &item) in document_ids.iter().enumerate() {
             *map.entry(item).or_default() += (1.0 / ((rrf_k + index + 1) as f32)) * weight;
         }
@@ -0,0 +1,325 @@
+use crate::utils::{CodeBlock, run_command_with_output, temp_file};
+
+use anyhow::{Context, Result, bail};
+use fancy_regex::Regex;
+use indexmap::IndexMap;
+use std::{fs, path::Path, sync::LazyLock};
+
+static MERMAID_LABELED_EDGE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:--|==|-\.)\s+([^>|]+?)\s+(-->|---|==>|\.->)").unwrap());
+static MERMAID_EDGE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\s*(<?(?:-{2,}|={2,}|-\.+-)[>xo]?|~~~)\s*(?:\|([^|]*)\|\s*)?").unwrap()
+});
+static MERMAID_NODE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^([\w.-]+)\s*(?:[\[({>]+\s*"?(.*?)"?\s*[\])}]+)?(?::::\w+)?$"#).unwrap()
+});
+static MERMAID_MESSAGE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^([^-\s]+?)\s*(-{1,2}(?:>>|>|x|\)))\s*[+-]?([^:]+?)\s*:\s*(.*)$").unwrap()
+});
+static DOT_LABEL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"label\s*=\s*(?:"((?:[^"\\]|\\.)*)"|([\w.]+))"#).unwrap());
+
+/// The diagram languages that can be rendered to an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramKind {
+    Mermaid,
+    Dot,
+}
+
+impl DiagramKind {
+    pub fn from_lang(lang: &str) -> Option<Self> {
+        match lang.to_ascii_lowercase().as_str() {
+            "mermaid" | "mmd" => Some(Self::Mermaid),
+            "dot" | "graphviz" | "gv" => Some(Self::Dot),
+            _ => None,
+        }
+    }
+
+    /// The command that renders this kind of diagram.
+    pub fn renderer(&self) -> &'static str {
+        match self {
+            Self::Mermaid => "mmdc",
+            Self::Dot => "dot",
+        }
+    }
+
+    pub fn is_renderer_installed(&self) -> bool {
+        which::which(self.renderer()).is_ok()
+    }
+}
+
+/// The Mermaid and Graphviz blocks among the code blocks of a response.
+pub fn diagram_blocks(blocks: Vec<CodeBlock>) -> Vec<(DiagramKind, CodeBlock)> {
+    blocks
+        .into_iter()
+        .filter_map(|block| DiagramKind::from_lang(&block.lang).map(|kind| (kind, block)))
+        .collect()
+}
+
+/// Renders a diagram to an image with `mmdc` or `dot`, in the format the path's extension names
+/// (e.g. `png` or `svg`).
+pub fn render_diagram(kind: DiagramKind, code: &str, output: &Path) -> Result<()> {
+    let format = output
+        .extension()
+        .and_then(|v| v.to_str())
+        .unwrap_or("png")
+        .to_ascii_lowercase();
+    let input = temp_file("-diagram-", ".txt");
+    fs::write(&input, code).context("Failed to write the diagram source")?;
+    let input = input.display().to_string();
+    let output = output.display().to_string();
+    let cmd = kind.renderer();
+    let args: Vec<String> = match kind {
+        DiagramKind::Mermaid => vec![
+            "-i".into(),
+            input.clone(),
+            "-o".into(),
+            output,
+            "-b".into(),
+            "white".into(),
+            "-q".into(),
+        ],
+        DiagramKind::Dot => vec![format!("-T{format}"), "-o".into(), output, input.clone()],
+    };
+    let ret = run_command_with_output(cmd, &args, None);
+    let _ = fs::remove_file(&input);
+    let (success, _, stderr) =
+        ret.with_context(|| format!("Unable to run '{cmd}'; is it installed?"))?;
+    if !success {
+        bail!("'{cmd}' failed to render the diagram: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Approximates a diagram as text, listing each node with the edges that leave it, for when
+/// there's nothing installed to render it with.
+pub fn ascii_diagram(kind: DiagramKind, code: &str) -> String {
+    let graph = match kind {
+        DiagramKind::Mermaid => parse_mermaid(code),
+        DiagramKind::Dot => parse_dot(code),
+    };
+    graph.render()
+}
+
+#[derive(Debug, Default)]
+struct Graph {
+    /// The label of each node, by ID, in the order they first appear.
+    nodes: IndexMap<String, String>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Debug)]
+struct Edge {
+    from: String,
+    to: String,
+    label: Option<String>,
+    directed: bool,
+}
+
+impl Graph {
+    fn add_node(&mut self, id: &str, label: Option<&str>) {
+        let label = label.map(str::trim).filter(|v| !v.is_empty());
+        match self.nodes.get_mut(id) {
+            Some(existing) => {
+                if let Some(label) = label
+                    && *existing == id
+                {
+                    *existing = label.to_string();
+                }
+            }
+            None => {
+                self.nodes
+                    .insert(id.to_string(), label.unwrap_or(id).to_string());
+            }
+        }
+    }
+
+    fn add_edge(&mut self, from: &str, to: &str, label: Option<&str>, directed: bool) {
+        self.add_node(from, None);
+        self.add_node(to, None);
+        self.edges.push(Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            label: label
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(String::from),
+            directed,
+        });
+    }
+
+    fn render(&self) -> String {
+        let mut output = vec![];
+        for (id, label) in &self.nodes {
+            let edges: Vec<&Edge> = self.edges.iter().filter(|e| &e.from == id).collect();
+            let is_connected = !edges.is_empty() || self.edges.iter().any(|e| &e.to == id);
+            if edges.is_empty() && is_connected {
+                continue;
+            }
+            output.push(label.clone());
+            for (i, edge) in edges.iter().enumerate() {
+                let branch = if i + 1 == edges.len() { "└" } else { "├" };
+                let head = if edge.directed { "▶" } else { "─" };
+                let arrow = match &edge.label {
+                    Some(label) => format!("─ {label} ─{head}"),
+                    None => format!("──{head}"),
+                };
+                output.push(format!("{branch}{arrow} {}", self.nodes[&edge.to]));
+            }
+        }
+        output.join("\n")
+    }
+}
+
+fn parse_mermaid(code: &str) -> Graph {
+    let mut graph = Graph::default();
+    let mut lines = code
+        .lines()
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && !v.starts_with("%%"));
+    let is_sequence = lines
+        .next()
+        .is_some_and(|v| v.starts_with("sequenceDiagram"));
+    for line in lines {
+        if is_sequence {
+            if let Ok(Some(captures)) = MERMAID_MESSAGE_RE.captures(line) {
+                graph.add_edge(&captures[1], &captures[3], Some(&captures[4]), true);
+            }
+            continue;
+        }
+        for statement in line.split(';') {
+            let statement = MERMAID_LABELED_EDGE_RE.replace_all(statement.trim(), "$2|$1|");
+            parse_mermaid_statement(&mut graph, &statement);
+        }
+    }
+    graph
+}
+
+/// Adds the nodes and edges of a statement like `A[Start] --> B & C -->|No| D`.
+fn parse_mermaid_statement(graph: &mut Graph, statement: &str) {
+    let mut sources: Vec<String> = vec![];
+    let mut pending: Option<(Option<String>, bool)> = None;
+    let mut rest = statement;
+    loop {
+        let (part, edge, next) = match MERMAID_EDGE_RE.captures(rest) {
+            Ok(Some(captures)) => {
+                let whole = captures.get(0).unwrap();
+                let arrow = &captures[1];
+                let label = captures.get(2).map(|v| v.as_str().to_string());
+                let directed = arrow.ends_with(['>', 'x', 'o']);
+                (
+                    &rest[..whole.start()],
+                    Some((label, directed)),
+                    &rest[whole.end()..],
+                )
+            }
+            _ => (rest, None, ""),
+        };
+        let mut targets = vec![];
+        for node in part.split('&').map(str::trim).filter(|v| !v.is_empty()) {
+            let Ok(Some(captures)) = MERMAID_NODE_RE.captures(node) else {
+                return;
+            };
+            graph.add_node(&captures[1], captures.get(2).map(|v| v.as_str()));
+            targets.push(captures[1].to_string());
+        }
+        if let Some((label, directed)) = pending.take() {
+            for from in &sources {
+                for to in &targets {
+                    graph.add_edge(from, to, label.as_deref(), directed);
+                }
+            }
+        }
+        if edge.is_none() {
+            break;
+        }
+        sources = targets;
+        pending = edge;
+        rest = next;
+    }
+}
+
+fn parse_dot(code: &str) -> Graph {
+    let mut graph = Graph::default();
+    let code = code.replace(['{', '}'], "\n");
+    for statement in code.split(['\n', ';']).map(str::trim) {
+        if statement.is_empty()
+            || statement.starts_with("//")
+            || statement.starts_with('#')
+            || statement.starts_with("digraph")
+            || statement.starts_with("graph")
+            || statement.starts_with("strict")
+            || statement.starts_with("subgraph")
+            || statement.starts_with("node")
+            || statement.starts_with("edge")
+            || (statement.contains('=') && !statement.contains('['))
+        {
+            continue;
+        }
+        let (body, attributes) = match statement.find('[') {
+            Some(i) => (&statement[..i], &statement[i..]),
+            None => (statement, ""),
+        };
+        let label = DOT_LABEL_RE
+            .captures(attributes)
+            .ok()
+            .flatten()
+            .and_then(|captures| captures.get(1).or(captures.get(2)))
+            .map(|v| v.as_str().replace("\\n", " ").replace("\\\"", "\""));
+        let directed = body.contains("->");
+        let ids: Vec<&str> = body
+            .split(if directed { "->" } else { "--" })
+            .map(|v| v.trim().trim_matches('"'))
+            .collect();
+        if ids.iter().any(|v| v.is_empty()) {
+            continue;
+        }
+        match ids.as_slice() {
+            [id] => graph.add_node(id, label.as_deref()),
+            _ => {
+                for pair in ids.windows(2) {
+                    graph.add_edge(pair[0], pair[1], label.as_deref(), directed);
+                }
+            }
+        }
+    }
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_mermaid() {
+        let code = r#"flowchart TD
+    A[Start] --> B{Is it working?}
+    B -- Yes --> C(Ship it)
+    B -->|No| D[Debug] --> B
+    %% a comment
+    E[Unused]"#;
+        assert_eq!(
+            ascii_diagram(DiagramKind::Mermaid, code),
+            "Start\n└──▶ Is it working?\nIs it working?\n├─ Yes ─▶ Ship it\n└─ No ─▶ Debug\nDebug\n└──▶ Is it working?\nUnused"
+        );
+        let code = "sequenceDiagram\n    Alice->>Bob: Hello\n    Bob-->>Alice: Hi";
+        assert_eq!(
+            ascii_diagram(DiagramKind::Mermaid, code),
+            "Alice\n└─ Hello ─▶ Bob\nBob\n└─ Hi ─▶ Alice"
+        );
+    }
+
+    #[test]
+    fn test_ascii_dot() {
+        let code = r#"digraph G {
+    rankdir=LR;
+    start [label="Start"];
+    start -> parse -> done [label="ok"];
+    "parse" -> error;
+}"#;
+        assert_eq!(
+            ascii_diagram(DiagramKind::Dot, code),
+            "Start\n└─ ok ─▶ parse\nparse\n├─ ok ─▶ done\n└──▶ error"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use super::markdown::escape_html;
+use super::{MarkdownRender, RenderOptions};
+
+use anyhow::Result;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd, html};
+use syntect::highlighting::Theme;
+
+const HTML_TEMPLATE: &str = include_str!("../../assets/export.html");
+
+/// Writes Markdown out as a standalone HTML page with its styles embedded, so it reads the same
+/// offline. Code blocks are highlighted with the theme, when there is one. Raw HTML is shown as
+/// text, except the `<details>` and `<summary>` tags that fold tool calls away. Since the model
+/// wrote the links, any that don't go to `http(s)` (or, for links, `mailto`) are dropped.
+pub fn render_html(title: &str, markdown: &str, theme: Option<Theme>) -> Result<String> {
+    let render = MarkdownRender::init(RenderOptions {
+        theme,
+        ..Default::default()
+    })?;
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+    let mut events = vec![];
+    let mut code_block: Option<(String, String)> = None;
+    for event in Parser::new_ext(markdown, options) {
+        if let Some((_, code)) = code_block.as_mut() {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => {
+                    let (lang, code) = code_block.take().unwrap_or_default();
+                    events.push(Event::Html(render.highlight_html(&code, &lang).into()));
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((lang, String::new()));
+            }
+            Event::Html(html) | Event::InlineHtml(html) if is_folding_tag(&html) => {
+                events.push(Event::Html(html))
+            }
+            Event::Html(html) | Event::InlineHtml(html) => events.push(Event::Text(html)),
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => events.push(Event::Start(Tag::Link {
+                link_type,
+                dest_url: safe_url(dest_url, true),
+                title,
+                id,
+            })),
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => events.push(Event::Start(Tag::Image {
+                link_type,
+                dest_url: safe_url(dest_url, false),
+                title,
+                id,
+            })),
+            event => events.push(event),
+        }
+    }
+    let mut body = String::new();
+    html::push_html(&mut body, events.into_iter());
+    Ok(HTML_TEMPLATE
+        .replace("{{title}}", &escape_html(title))
+        .replace("{{body}}", &body))
+}
+
+/// Replaces a destination that could run script or reach local files (e.g. `javascript:`,
+/// `data:`, `file:`) with `#`.
+fn safe_url(url: CowStr<'_>, allow_mailto: bool) -> CowStr<'_> {
+    let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme.trim().to_ascii_lowercase());
+    match scheme.as_deref() {
+        Some("http" | "https") => url,
+        Some("mailto") if allow_mailto => url,
+        _ => "#".into(),
+    }
+}
+
+fn is_folding_tag(html: &str) -> bool {
+    let html = html.trim();
+    matches!(html, "<details>" | "</details>")
+        || html
+            .strip_prefix("<summary>")
+            .and_then(|v| v.strip_suffix("</summary>"))
+            .is_some_and(|v| !v.contains('<'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_html() {
+        let markdown = "# Notes\n\nSee <script>alert(1)</script>.\n\n<details>\n<summary>Output</summary>\n\n```json\n{\"ok\": true}\n```\n\n</details>";
+        let output = render_html("A & B", markdown, None).unwrap();
+        assert!(output.contains("<title>A &amp; B - Loki</title>"));
+        assert!(output.contains("<h1>Notes</h1>"));
+        assert!(output.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(output.contains("<details>\n<summary>Output</summary>"));
+        assert!(output.contains("<pre><code>{&quot;ok&quot;: true}\n</code></pre>"));
+    }
+
+    #[test]
+    fn test_render_html_links() {
+        let markdown = "[docs](https://example.com) [mail](mailto:a@b.c) [x](javascript:alert(1)) \
+            [y](JavaScript:alert(1)) [z](file:///etc/passwd) <javascript:alert(1)> ![img](data:image/svg+xml,x)";
+        let output = render_html("Links", markdown, None).unwrap();
+        assert!(output.contains(r#"<a href="https://example.com">docs</a>"#));
+        assert!(output.contains(r#"<a href="mailto:a@b.c">mail</a>"#));
+        assert!(!output.contains("href=\"javascript:"));
+        assert!(!output.contains("href=\"JavaScript:"));
+        assert!(!output.contains("file:///"));
+        assert!(output.contains(r##"<img src="#" alt="img" />"##));
+    }
+
+    #[test]
+    fn test_is_folding_tag() {
+        assert!(is_folding_tag("<details>\n"));
+        assert!(is_folding_tag("<summary>Output</summary>"));
+        assert!(!is_folding_tag("<summary><img src=x></summary>"));
+        assert!(!is_folding_tag("<div>"));
+    }
+}
@@ -0,0 +1,362 @@
+use crate::config::GlobalConfig;
+use crate::function::ToolResult;
+use crate::utils::{IS_STDOUT_TERMINAL, dimmed_text, resolve_home_dir};
+
+use anyhow::{Result, bail};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use crossterm::terminal;
+use fancy_regex::Regex;
+use serde_json::Value;
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    io::{Write, stdout},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+pub const INLINE_IMAGE_MODES: [&str; 5] = ["auto", "kitty", "iterm", "sixel", "off"];
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+/// Kitty takes the image data in chunks of at most this many bytes.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// How wide (in pixels) an image may be drawn when the terminal doesn't report its size.
+const DEFAULT_MAX_WIDTH: u32 = 800;
+
+static MARKDOWN_IMAGE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"!\[[^\]]*\]\(\s*<?([^)\s>]+)>?(?:\s+"[^"]*")?\s*\)"#).unwrap());
+
+/// The terminal graphics protocols images can be drawn inline with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    Kitty,
+    Iterm,
+    Sixel,
+}
+
+impl ImageProtocol {
+    /// Resolves the `inline_images` setting, detecting what the terminal supports for `auto`.
+    pub fn from_setting(value: &str) -> Result<Option<Self>> {
+        match value {
+            "auto" => Ok(Self::detect()),
+            "kitty" => Ok(Some(Self::Kitty)),
+            "iterm" => Ok(Some(Self::Iterm)),
+            "sixel" => Ok(Some(Self::Sixel)),
+            "off" => Ok(None),
+            _ => bail!(
+                "Unknown inline image mode '{value}'; expected one of auto, kitty, iterm, sixel, or off"
+            ),
+        }
+    }
+
+    fn detect() -> Option<Self> {
+        let var = |name| env::var(name).unwrap_or_default();
+        let (term, term_program) = (var("TERM"), var("TERM_PROGRAM"));
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || matches!(term.as_str(), "xterm-kitty" | "xterm-ghostty")
+            || term_program == "ghostty"
+        {
+            Some(Self::Kitty)
+        } else if matches!(term_program.as_str(), "iTerm.app" | "WezTerm")
+            || var("LC_TERMINAL") == "iTerm2"
+        {
+            Some(Self::Iterm)
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Some(Self::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+/// Draws the local images a reply or its tool results point to below it, or prints their paths
+/// when the terminal can't show them.
+pub fn print_inline_images(
+    config: &GlobalConfig,
+    text: &str,
+    tool_results: &[ToolResult],
+) -> Result<()> {
+    let mode = config.read().inline_images.clone();
+    if !*IS_STDOUT_TERMINAL || mode == "off" {
+        return Ok(());
+    }
+    let paths = find_image_paths(text, tool_results);
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let protocol = ImageProtocol::from_setting(&mode)?;
+    for path in paths {
+        print_image(protocol, &path)?;
+    }
+    Ok(())
+}
+
+/// Draws an image file inline with the `inline_images` setting's protocol, or prints its path
+/// when it's off or the terminal can't show it.
+pub fn print_image_file(config: &GlobalConfig, path: &Path) -> Result<()> {
+    let mode = config.read().inline_images.clone();
+    let protocol = if *IS_STDOUT_TERMINAL {
+        ImageProtocol::from_setting(&mode)?
+    } else {
+        None
+    };
+    print_image(protocol, path)
+}
+
+fn print_image(protocol: Option<ImageProtocol>, path: &Path) -> Result<()> {
+    let mut stdout = stdout();
+    match protocol.and_then(|protocol| encode_image(protocol, path).ok()) {
+        Some(sequence) => {
+            stdout.write_all(sequence.as_bytes())?;
+            writeln!(stdout)?;
+        }
+        None => writeln!(
+            stdout,
+            "{}",
+            dimmed_text(&format!("Image: {}", path.display()))
+        )?,
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Collects the existing local image files referenced by Markdown images in the reply, and by
+/// any string in the tool results that is (or embeds) one.
+fn find_image_paths(text: &str, tool_results: &[ToolResult]) -> Vec<PathBuf> {
+    let mut candidates: Vec<String> = markdown_image_refs(text);
+    for result in tool_results {
+        collect_strings(&result.output, &mut candidates);
+    }
+    let mut paths: Vec<PathBuf> = vec![];
+    for candidate in candidates {
+        let candidate = candidate.trim();
+        let candidate = candidate.strip_prefix("file://").unwrap_or(candidate);
+        let path = PathBuf::from(resolve_home_dir(candidate));
+        if is_image_path(&path) && path.is_file() && !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+fn markdown_image_refs(text: &str) -> Vec<String> {
+    MARKDOWN_IMAGE_RE
+        .captures_iter(text)
+        .flatten()
+        .filter_map(|captures| captures.get(1).map(|v| v.as_str().to_string()))
+        .collect()
+}
+
+fn collect_strings(value: &Value, output: &mut Vec<String>) {
+    match value {
+        Value::String(text) => {
+            output.push(text.clone());
+            output.extend(markdown_image_refs(text));
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_strings(v, output)),
+        Value::Object(map) => map.values().for_each(|v| collect_strings(v, output)),
+        _ => {}
+    }
+}
+
+fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|v| v.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn encode_image(protocol: ImageProtocol, path: &Path) -> Result<String> {
+    let data = fs::read(path)?;
+    let max_width = max_image_width();
+    let sequence = match protocol {
+        ImageProtocol::Iterm => {
+            let name = path
+                .file_name()
+                .map(|v| STANDARD.encode(v.as_encoded_bytes()))
+                .unwrap_or_default();
+            format!(
+                "\x1b]1337;File=name={name};size={};inline=1;preserveAspectRatio=1:{}\x07",
+                data.len(),
+                STANDARD.encode(&data)
+            )
+        }
+        ImageProtocol::Kitty => encode_kitty(&internal::to_png(data, max_width)?),
+        ImageProtocol::Sixel => {
+            let (width, height, pixels) = internal::to_rgba(&data, max_width)?;
+            encode_sixel(width, height, &pixels)
+        }
+    };
+    Ok(sequence)
+}
+
+fn max_image_width() -> u32 {
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 => size.width as u32,
+        _ => DEFAULT_MAX_WIDTH,
+    }
+}
+
+/// Sends a PNG with Kitty's graphics protocol, asking the terminal not to reply.
+fn encode_kitty(png: &[u8]) -> String {
+    let encoded = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    let mut output = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = String::from_utf8_lossy(chunk);
+        if i == 0 {
+            let _ = write!(output, "\x1b_Ga=T,f=100,q=2,m={more};{chunk}\x1b\\");
+        } else {
+            let _ = write!(output, "\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    output
+}
+
+/// Encodes RGBA pixels as Sixel, with the colors reduced to a 6x6x6 cube and transparent pixels
+/// left unpainted.
+fn encode_sixel(width: usize, height: usize, pixels: &[u8]) -> String {
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let colors: Vec<Option<usize>> = pixels
+        .chunks_exact(4)
+        .map(|p| (p[3] >= 128).then(|| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])))
+        .collect();
+
+    let mut output = format!("\x1bP0;1;0q\"1;1;{width};{height}");
+    for i in 0..216 {
+        let _ = write!(
+            output,
+            "#{i};2;{};{};{}",
+            i / 36 * 20,
+            i / 6 % 6 * 20,
+            i % 6 * 20
+        );
+    }
+    for top in (0..height).step_by(6) {
+        let rows = (height - top).min(6);
+        let mut used = [false; 216];
+        for y in top..top + rows {
+            for color in colors[y * width..(y + 1) * width].iter().flatten() {
+                used[*color] = true;
+            }
+        }
+        let mut first = true;
+        for (color, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+            if !first {
+                output.push('$');
+            }
+            first = false;
+            let _ = write!(output, "#{color}");
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = (0..rows)
+                    .filter(|dy| colors[(top + dy) * width + x] == Some(color))
+                    .fold(0u8, |bits, dy| bits | 1 << dy);
+                let ch = (63 + bits) as char;
+                run = match run {
+                    Some((last, count)) if last == ch => Some((last, count + 1)),
+                    Some((last, count)) => {
+                        push_sixel_run(&mut output, last, count);
+                        Some((ch, 1))
+                    }
+                    None => Some((ch, 1)),
+                };
+            }
+            if let Some((last, count)) = run {
+                push_sixel_run(&mut output, last, count);
+            }
+        }
+        output.push('-');
+    }
+    output.push_str("\x1b\\");
+    output
+}
+
+fn push_sixel_run(output: &mut String, ch: char, count: usize) {
+    if count > 3 {
+        let _ = write!(output, "!{count}{ch}");
+    } else {
+        (0..count).for_each(|_| output.push(ch));
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "emscripten")))]
+mod internal {
+    use image::{DynamicImage, ImageFormat, imageops::FilterType};
+    use std::io::Cursor;
+
+    fn load(data: &[u8], max_width: u32) -> anyhow::Result<DynamicImage> {
+        let image = image::load_from_memory(data)?;
+        if image.width() > max_width {
+            let height = image.height() * max_width / image.width();
+            return Ok(image.resize(max_width, height.max(1), FilterType::Triangle));
+        }
+        Ok(image)
+    }
+
+    pub fn to_png(data: Vec<u8>, max_width: u32) -> anyhow::Result<Vec<u8>> {
+        let image = load(&data, max_width)?;
+        if image::guess_format(&data).ok() == Some(ImageFormat::Png) && image.width() <= max_width {
+            return Ok(data);
+        }
+        let mut output = Cursor::new(vec![]);
+        image.write_to(&mut output, ImageFormat::Png)?;
+        Ok(output.into_inner())
+    }
+
+    pub fn to_rgba(data: &[u8], max_width: u32) -> anyhow::Result<(usize, usize, Vec<u8>)> {
+        let image = load(data, max_width)?.into_rgba8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        Ok((width, height, image.into_raw()))
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "emscripten"))]
+mod internal {
+    pub fn to_png(data: Vec<u8>, _max_width: u32) -> anyhow::Result<Vec<u8>> {
+        if data.starts_with(b"\x89PNG") {
+            return Ok(data);
+        }
+        Err(anyhow::anyhow!("No image decoder available"))
+    }
+
+    pub fn to_rgba(_data: &[u8], _max_width: u32) -> anyhow::Result<(usize, usize, Vec<u8>)> {
+        Err(anyhow::anyhow!("No image decoder available"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_image_refs() {
+        assert_eq!(
+            markdown_image_refs(
+                "Here: ![chart](./out/chart.png) and ![logo](<~/logo.jpg> \"Logo\"), not [a](b.png)"
+            ),
+            vec!["./out/chart.png", "~/logo.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_encode_kitty() {
+        let output = encode_kitty(&[0; 4000]);
+        assert!(output.starts_with("\x1b_Ga=T,f=100,q=2,m=1;"));
+        assert!(output.contains("\x1b\\\x1b_Gm=0;"));
+        assert!(encode_kitty(&[0; 3]).starts_with("\x1b_Ga=T,f=100,q=2,m=0;AAAA"));
+    }
+
+    #[test]
+    fn test_encode_sixel() {
+        let red = [255, 0, 0, 255];
+        let clear = [0, 0, 0, 0];
+        let pixels = [red, red, red, red, clear, red].concat();
+        let output = encode_sixel(6, 1, &pixels);
+        assert!(output.starts_with("\x1bP0;1;0q\"1;1;6;1#0;2;0;0;0"));
+        assert!(output.ends_with("#180!4@?@-\x1b\\"));
+    }
+}
@@ -1,13 +1,17 @@
-use crate::utils::decode_bin;
+use super::math::render_math_line;
+
+use crate::utils::{decode_bin, display_width, grapheme_widths};
 
 use ansi_colours::AsRGB;
 use anyhow::{Context, Result, anyhow};
 use crossterm::style::{Color, Stylize};
 use crossterm::terminal;
 use std::collections::HashMap;
+use std::mem;
 use std::sync::LazyLock;
 use syntect::highlighting::{Color as SyntectColor, FontStyle, Style, Theme};
 use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
 use syntect::{easy::HighlightLines, parsing::SyntaxReference};
 
 /// Comes from <https://github.com/sharkdp/bat/raw/5e77ca37e89c873e4490b42ff556370dc5c6ba4f/assets/syntaxes.bin>
@@ -20,14 +24,24 @@ static LANG_MAPS: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
     m
 });
 
+/// Table columns are narrowed to fit the screen, but never below this width.
+const MIN_TABLE_COLUMN_WIDTH: usize = 5;
+
 pub struct MarkdownRender {
     options: RenderOptions,
     syntax_set: SyntaxSet,
     code_color: Option<Color>,
     md_syntax: SyntaxReference,
-    code_syntax: Option<SyntaxReference>,
+    /// The syntax of the code block being rendered, as an index into `syntax_set`; cloning the
+    /// `SyntaxReference` itself on every line is expensive while streaming.
+    code_syntax: Option<usize>,
     prev_line_type: LineType,
+    /// How many lines of the current code block have been rendered, for numbering them.
+    code_line: usize,
+    in_math_block: bool,
+    table_rows: Vec<String>,
     wrap_width: Option<u16>,
+    columns: Option<u16>,
 }
 
 impl MarkdownRender {
@@ -41,6 +55,7 @@ impl MarkdownRender {
             .map(|theme| get_code_color(theme, options.truecolor));
         let md_syntax = syntax_set.find_syntax_by_extension("md").unwrap().clone();
         let line_type = LineType::Normal;
+        let columns = terminal::size().ok().map(|(columns, _)| columns);
         let wrap_width = match options.wrap.as_deref() {
             None => None,
             Some(value) => match terminal::size() {
@@ -63,42 +78,186 @@ impl MarkdownRender {
             md_syntax,
             code_syntax: None,
             prev_line_type: line_type,
+            code_line: 0,
+            in_math_block: false,
+            table_rows: vec![],
             wrap_width,
+            columns,
             options,
         })
     }
 
     pub fn render(&mut self, text: &str) -> String {
-        text.split('\n')
-            .map(|line| self.render_line_mut(line))
-            .collect::<Vec<String>>()
-            .join("\n")
+        let mut lines = self.render_lines(text);
+        lines.extend(self.flush_table());
+        lines.join("\n")
+    }
+
+    /// Like `render`, for text that arrives a few lines at a time: a table is held back until a
+    /// line that isn't part of it comes in, so it can be laid out whole. Returns `None` when
+    /// every line was held back.
+    pub fn render_streamed(&mut self, text: &str) -> Option<String> {
+        let lines = self.render_lines(text);
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    pub fn has_pending_table(&self) -> bool {
+        !self.table_rows.is_empty()
     }
 
     pub fn render_line(&self, line: &str) -> String {
-        let (_, code_syntax, is_code) = self.check_line(line);
+        let (line_type, code_syntax, is_code) = self.check_line(line);
         if is_code {
-            self.highlight_code_line(line, &code_syntax)
+            self.highlight_code_line(line, code_syntax)
+        } else if let Some(output) = self.render_fence(line, line_type) {
+            output
         } else {
-            self.highlight_line(line, &self.md_syntax, false)
+            let (line, _) = render_math_line(line, self.in_math_block);
+            self.highlight_line(&line)
+        }
+    }
+
+    /// Highlights the text being typed at the REPL prompt as markdown, as styled pieces for the
+    /// line editor. Returns `None` when there's no theme to highlight with.
+    pub fn highlight_input(&self, text: &str) -> Option<Vec<(nu_ansi_term::Style, String)>> {
+        let theme = self.options.theme.as_ref()?;
+        let mut highlighter = HighlightLines::new(&self.md_syntax, theme);
+        let mut output = vec![];
+        for line in LinesWithEndings::from(text) {
+            let ranges = highlighter.highlight_line(line, &self.syntax_set).ok()?;
+            output.extend(ranges.into_iter().map(|(style, text)| {
+                (
+                    as_input_style(style, self.options.truecolor),
+                    text.to_string(),
+                )
+            }));
+        }
+        Some(output)
+    }
+
+    fn render_lines(&mut self, text: &str) -> Vec<String> {
+        let mut output = vec![];
+        for line in text.split('\n') {
+            let (_, _, is_code) = self.check_line(line);
+            if !is_code && is_table_row(line) {
+                // A row only starts a table when the next line is a delimiter row
+                if self.table_rows.len() == 1 && !is_table_delimiter(line) {
+                    output.extend(self.flush_table());
+                }
+                self.table_rows.push(line.to_string());
+            } else {
+                output.extend(self.flush_table());
+                output.push(self.render_line_mut(line));
+            }
         }
+        output
+    }
+
+    fn flush_table(&mut self) -> Vec<String> {
+        let rows = mem::take(&mut self.table_rows);
+        if rows.len() >= 2 && is_table_delimiter(&rows[1]) {
+            self.render_table(&rows)
+        } else {
+            rows.iter().map(|line| self.render_line_mut(line)).collect()
+        }
+    }
+
+    /// Draws a table with box borders, narrowing its columns to fit within the `wrap` width (or
+    /// the screen) and wrapping the cells that no longer fit on one line.
+    fn render_table(&self, rows: &[String]) -> Vec<String> {
+        let alignments: Vec<Alignment> = split_table_row(&rows[1])
+            .iter()
+            .map(|cell| Alignment::parse(cell))
+            .collect();
+        let mut cells: Vec<Vec<String>> = rows
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 1)
+            .map(|(_, row)| {
+                split_table_row(row)
+                    .iter()
+                    .map(|cell| render_math_line(cell, false).0)
+                    .collect()
+            })
+            .collect();
+        let num_columns = cells[0].len();
+        for row in &mut cells {
+            row.resize(num_columns, String::new());
+        }
+        let widths: Vec<usize> = (0..num_columns)
+            .map(|i| {
+                cells
+                    .iter()
+                    .map(|row| display_width(&row[i]))
+                    .max()
+                    .unwrap_or_default()
+                    .max(1)
+            })
+            .collect();
+        let max_width = self.wrap_width.or(self.columns).map(usize::from);
+        let widths = fit_column_widths(widths, max_width);
+
+        let border = |left: &str, middle: &str, right: &str| {
+            let lines: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{left}{}{right}", lines.join(middle))
+        };
+        let mut output = vec![border("┌", "┬", "┐")];
+        for (i, row) in cells.iter().enumerate() {
+            let wrapped: Vec<Vec<String>> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| wrap(cell, *width).split('\n').map(String::from).collect())
+                .collect();
+            let height = wrapped.iter().map(Vec::len).max().unwrap_or_default();
+            for line in 0..height.max(1) {
+                let parts: Vec<String> = wrapped
+                    .iter()
+                    .enumerate()
+                    .map(|(column, lines)| {
+                        let text = lines.get(line).map(String::as_str).unwrap_or_default();
+                        let alignment = alignments.get(column).copied().unwrap_or_default();
+                        let text = alignment.pad(text, widths[column]);
+                        match (i, &self.options.theme) {
+                            (0, Some(_)) => text.bold().to_string(),
+                            (0, None) => text,
+                            _ => self.highlight(&text, &self.md_syntax),
+                        }
+                    })
+                    .collect();
+                output.push(format!("│ {} │", parts.join(" │ ")));
+            }
+            if i == 0 {
+                output.push(border("├", "┼", "┤"));
+            }
+        }
+        output.push(border("└", "┴", "┘"));
+        output
     }
 
     fn render_line_mut(&mut self, line: &str) -> String {
         let (line_type, code_syntax, is_code) = self.check_line(line);
         let output = if is_code {
-            self.highlight_code_line(line, &code_syntax)
+            let output = self.highlight_code_line(line, code_syntax);
+            self.code_line += 1;
+            output
+        } else if let Some(output) = self.render_fence(line, line_type) {
+            output
         } else {
-            self.highlight_line(line, &self.md_syntax, false)
+            let (line, in_math_block) = render_math_line(line, self.in_math_block);
+            self.in_math_block = in_math_block;
+            self.highlight_line(&line)
         };
+        if line_type == LineType::CodeBegin && !is_code {
+            self.code_line = 0;
+        }
         self.prev_line_type = line_type;
         self.code_syntax = code_syntax;
         output
     }
 
-    fn check_line(&self, line: &str) -> (LineType, Option<SyntaxReference>, bool) {
+    fn check_line(&self, line: &str) -> (LineType, Option<usize>, bool) {
         let mut line_type = self.prev_line_type;
-        let mut code_syntax = self.code_syntax.clone();
+        let mut code_syntax = self.code_syntax;
         let mut is_code = false;
         if let Some(lang) = detect_code_block(line) {
             match line_type {
@@ -107,7 +266,7 @@ impl MarkdownRender {
                     code_syntax = if lang.is_empty() {
                         None
                     } else {
-                        self.find_syntax(&lang).cloned()
+                        self.find_syntax(&lang).and_then(|v| self.syntax_index(v))
                     };
                 }
                 LineType::CodeBegin | LineType::CodeInner => {
@@ -122,10 +281,13 @@ impl MarkdownRender {
                     line_type = LineType::Normal;
                 }
                 LineType::CodeBegin => {
-                    if code_syntax.is_none()
-                        && let Some(syntax) = self.syntax_set.find_syntax_by_first_line(line)
-                    {
-                        code_syntax = Some(syntax.clone());
+                    if code_syntax.is_none() {
+                        let syntax = if looks_like_diff(line) {
+                            self.syntax_set.find_syntax_by_extension("diff")
+                        } else {
+                            self.syntax_set.find_syntax_by_first_line(line)
+                        };
+                        code_syntax = syntax.and_then(|v| self.syntax_index(v));
                     }
                     line_type = LineType::CodeInner;
                     is_code = true;
@@ -138,7 +300,12 @@ impl MarkdownRender {
         (line_type, code_syntax, is_code)
     }
 
-    fn highlight_line(&self, line: &str, syntax: &SyntaxReference, is_code: bool) -> String {
+    fn highlight_line(&self, line: &str) -> String {
+        let line = self.highlight(line, &self.md_syntax);
+        self.wrap_line(line, false)
+    }
+
+    fn highlight(&self, line: &str, syntax: &SyntaxReference) -> String {
         let ws: String = line.chars().take_while(|c| c.is_whitespace()).collect();
         let trimmed_line: &str = &line[ws.len()..];
         let mut line_highlighted = None;
@@ -151,19 +318,68 @@ impl MarkdownRender {
                 ))
             }
         }
-        let line = line_highlighted.unwrap_or_else(|| line.into());
-        self.wrap_line(line, is_code)
+        line_highlighted.unwrap_or_else(|| line.into())
     }
 
-    fn highlight_code_line(&self, line: &str, code_syntax: &Option<SyntaxReference>) -> String {
-        if let Some(syntax) = code_syntax {
-            self.highlight_line(line, syntax, true)
-        } else {
-            let line = match self.code_color {
+    fn highlight_code_line(&self, line: &str, code_syntax: Option<usize>) -> String {
+        let output = match code_syntax.map(|i| &self.syntax_set.syntaxes()[i]) {
+            Some(syntax) if self.options.theme.is_some() && is_diff_syntax(syntax) => {
+                style_diff_line(line)
+            }
+            Some(syntax) => self.highlight(line, syntax),
+            None => match self.code_color {
                 Some(color) => line.with(color).to_string(),
                 None => line.to_string(),
-            };
-            self.wrap_line(line, true)
+            },
+        };
+        if !self.options.code_line_numbers {
+            return self.wrap_line(output, true);
+        }
+        let number = (self.code_line + 1).to_string();
+        let digits = number.len().max(3);
+        let gutter = |text: &str| {
+            let gutter = format!("{text:>digits$} │ ");
+            match self.options.theme {
+                Some(_) => gutter.dark_grey().to_string(),
+                None => gutter,
+            }
+        };
+        let lines = match self.wrap_width {
+            Some(width) if self.options.wrap_code => {
+                wrap(&output, (width as usize).saturating_sub(digits + 3).max(1))
+            }
+            _ => output,
+        };
+        lines
+            .split('\n')
+            .enumerate()
+            .map(|(i, line)| {
+                let number = if i == 0 { number.as_str() } else { "" };
+                format!("{}{line}", gutter(number))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// With `code_labels` on, the opening fence of a code block is shown as a label with its
+    /// language, and the closing fence as a blank line.
+    fn render_fence(&self, line: &str, line_type: LineType) -> Option<String> {
+        if !self.options.code_labels {
+            return None;
+        }
+        match line_type {
+            LineType::CodeBegin => {
+                let indent = &line[..line.len() - line.trim_start().len()];
+                let info = line.trim_start().trim_start_matches('`').trim();
+                let label = if info.is_empty() { "code" } else { info };
+                let label = match self.options.theme {
+                    Some(_) => format!(" {label} ").reverse().bold().to_string(),
+                    None => format!("[{label}]"),
+                };
+                Some(format!("{indent}{label}"))
+            }
+            LineType::CodeEnd => Some(String::new()),
+            _ => None,
         }
     }
 
@@ -178,6 +394,29 @@ impl MarkdownRender {
         }
     }
 
+    /// Highlights a code block as HTML, with the styles inline. Without a theme, the code is only
+    /// escaped.
+    pub(super) fn highlight_html(&self, code: &str, lang: &str) -> String {
+        let syntax = self
+            .find_syntax(lang)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        self.options
+            .theme
+            .as_ref()
+            .and_then(|theme| {
+                syntect::html::highlighted_html_for_string(code, &self.syntax_set, syntax, theme)
+                    .ok()
+            })
+            .unwrap_or_else(|| format!("<pre><code>{}</code></pre>\n", escape_html(code)))
+    }
+
+    fn syntax_index(&self, syntax: &SyntaxReference) -> Option<usize> {
+        self.syntax_set
+            .syntaxes()
+            .iter()
+            .position(|v| std::ptr::eq(v, syntax))
+    }
+
     fn find_syntax(&self, lang: &str) -> Option<&SyntaxReference> {
         if let Some(new_lang) = LANG_MAPS.get(&lang.to_ascii_lowercase()) {
             self.syntax_set.find_syntax_by_name(new_lang)
@@ -189,12 +428,126 @@ impl MarkdownRender {
     }
 }
 
+pub(super) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Wraps a line to a number of columns. It breaks at spaces, or between wide characters like
+/// CJK text, which doesn't use them, and never inside a grapheme cluster; a word too long for a
+/// line of its own is split. Leading spaces are kept on the first line.
 fn wrap(text: &str, width: usize) -> String {
-    let indent: usize = text.chars().take_while(|c| *c == ' ').count();
-    let wrap_options = textwrap::Options::new(width)
-        .wrap_algorithm(textwrap::WrapAlgorithm::FirstFit)
-        .initial_indent(&text[0..indent]);
-    textwrap::wrap(&text[indent..], wrap_options).join("\n")
+    let indent = text.len() - text.trim_start_matches(' ').len();
+    let mut lines = vec![];
+    let mut line = text[..indent].to_string();
+    let mut line_width = indent;
+    let mut start_width = indent;
+    // Where the current line can last be broken, as its length in bytes and columns
+    let mut breakpoint: Option<(usize, usize)> = None;
+    for (grapheme, grapheme_width) in grapheme_widths(&text[indent..]) {
+        if grapheme == " " || grapheme_width > 1 {
+            breakpoint = Some((line.len(), line_width));
+        }
+        line.push_str(grapheme);
+        line_width += grapheme_width;
+        if line_width > width && grapheme != " " {
+            let before = (line.len() - grapheme.len(), line_width - grapheme_width);
+            let at = breakpoint
+                .filter(|(_, at_width)| *at_width > start_width)
+                .or((before.1 > start_width).then_some(before));
+            if let Some((at, _)) = at {
+                let tail = line.split_off(at);
+                lines.push(line.trim_end_matches(' ').to_string());
+                line = tail.trim_start_matches(' ').to_string();
+                line_width = display_width(&line);
+                start_width = 0;
+                breakpoint = None;
+            }
+        }
+        if grapheme_width > 1 {
+            breakpoint = Some((line.len(), line_width));
+        }
+    }
+    lines.push(line);
+    lines.join("\n")
+}
+
+fn is_table_row(line: &str) -> bool {
+    line.trim_start().starts_with('|')
+}
+
+fn is_table_delimiter(line: &str) -> bool {
+    is_table_row(line)
+        && split_table_row(line).iter().all(|cell| {
+            let dashes = cell.trim_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+fn split_table_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    let mut cells = vec![];
+    let mut cell = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+/// Narrows the widest column one step at a time until the table, borders included, fits.
+fn fit_column_widths(mut widths: Vec<usize>, max_width: Option<usize>) -> Vec<usize> {
+    let Some(max_width) = max_width else {
+        return widths;
+    };
+    let available = max_width.saturating_sub(3 * widths.len() + 1);
+    while widths.iter().sum::<usize>() > available {
+        match widths.iter_mut().max() {
+            Some(widest) if *widest > MIN_TABLE_COLUMN_WIDTH => *widest -= 1,
+            _ => break,
+        }
+    }
+    widths
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum Alignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl Alignment {
+    fn parse(delimiter: &str) -> Self {
+        match (delimiter.starts_with(':'), delimiter.ends_with(':')) {
+            (true, true) => Self::Center,
+            (false, true) => Self::Right,
+            _ => Self::Left,
+        }
+    }
+
+    fn pad(&self, text: &str, width: usize) -> String {
+        let gap = width.saturating_sub(display_width(text));
+        let left = match self {
+            Self::Left => 0,
+            Self::Center => gap / 2,
+            Self::Right => gap,
+        };
+        format!("{}{text}{}", " ".repeat(left), " ".repeat(gap - left))
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -202,6 +555,8 @@ pub struct RenderOptions {
     pub theme: Option<Theme>,
     pub wrap: Option<String>,
     pub wrap_code: bool,
+    pub code_line_numbers: bool,
+    pub code_labels: bool,
     pub truecolor: bool,
 }
 
@@ -210,12 +565,16 @@ impl RenderOptions {
         theme: Option<Theme>,
         wrap: Option<String>,
         wrap_code: bool,
+        code_line_numbers: bool,
+        code_labels: bool,
         truecolor: bool,
     ) -> Self {
         Self {
             theme,
             wrap,
             wrap_code,
+            code_line_numbers,
+            code_labels,
             truecolor,
         }
     }
@@ -245,6 +604,23 @@ fn as_terminal_escaped(ranges: &[(Style, &str)], truecolor: bool) -> String {
     output
 }
 
+fn as_input_style(style: Style, truecolor: bool) -> nu_ansi_term::Style {
+    let fg = blend_fg_color(style.foreground, style.background);
+    let color = match convert_color(fg, truecolor) {
+        Color::Rgb { r, g, b } => nu_ansi_term::Color::Rgb(r, g, b),
+        Color::AnsiValue(value) => nu_ansi_term::Color::Fixed(value),
+        _ => nu_ansi_term::Color::Default,
+    };
+    let mut output = nu_ansi_term::Style::new().fg(color);
+    if style.font_style.contains(FontStyle::BOLD) {
+        output = output.bold();
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        output = output.underline();
+    }
+    output
+}
+
 fn convert_color(c: SyntectColor, truecolor: bool) -> Color {
     if truecolor {
         Color::Rgb {
@@ -292,6 +668,47 @@ fn detect_code_block(line: &str) -> Option<String> {
     Some(lang)
 }
 
+/// Whether the first line of a code block without a language is the start of a unified diff.
+fn looks_like_diff(line: &str) -> bool {
+    line.starts_with("diff --git ")
+        || line.starts_with("--- ")
+        || (line.starts_with("@@ -") && line[4..].contains(" @@"))
+}
+
+fn is_diff_syntax(syntax: &SyntaxReference) -> bool {
+    syntax
+        .file_extensions
+        .iter()
+        .any(|v| v == "diff" || v == "patch")
+}
+
+/// Colors a line of a unified diff by what it is, rather than leaving it to the theme, which
+/// often has no colors for diffs.
+fn style_diff_line(line: &str) -> String {
+    let is_header = [
+        "diff ",
+        "index ",
+        "--- ",
+        "+++ ",
+        "new file",
+        "deleted file",
+        "similarity",
+    ]
+    .iter()
+    .any(|v| line.starts_with(v));
+    if is_header {
+        line.bold().to_string()
+    } else if line.starts_with('+') {
+        line.with(Color::Green).to_string()
+    } else if line.starts_with('-') {
+        line.with(Color::Red).to_string()
+    } else if line.starts_with("@@") {
+        line.with(Color::Cyan).to_string()
+    } else {
+        line.to_string()
+    }
+}
+
 fn get_code_color(theme: &Theme, truecolor: bool) -> Color {
     let scope = theme.scopes.iter().find(|v| {
         v.scope
@@ -382,6 +799,90 @@ std::error::Error>> {
         assert_eq!(TEXT_WRAP_ALL, output);
     }
 
+    #[test]
+    fn code_line_numbers_and_labels() {
+        let options = RenderOptions {
+            wrap_code: true,
+            code_line_numbers: true,
+            code_labels: true,
+            ..Default::default()
+        };
+        let mut render = MarkdownRender::init(options).unwrap();
+        render.wrap_width = Some(20);
+        let output = render.render("Run:\n```sh\necho one two three four\nls\n```\ndone");
+        assert_eq!(
+            output,
+            "Run:\n[sh]\n  1 │ echo one two\n    │ three four\n  2 │ ls\n\ndone"
+        );
+    }
+
+    #[test]
+    fn wrap_wide_characters() {
+        assert_eq!(
+            wrap("  这是一个很长的中文句子，用来测试", 20),
+            "  这是一个很长的中文\n句子，用来测试"
+        );
+        assert_eq!(
+            wrap("emoji 👍🏽👍🏽👍🏽 and more", 10),
+            "emoji 👍🏽👍🏽\n👍🏽 and\nmore"
+        );
+    }
+
+    #[test]
+    fn test_render_table() {
+        let mut render = MarkdownRender::init(RenderOptions::default()).unwrap();
+        render.columns = None;
+        let output = render.render("| Name | Qty |\n|:-----|----:|\n| apple | 3 |\ndone");
+        assert_eq!(
+            output,
+            "┌───────┬─────┐\n│ Name  │ Qty │\n├───────┼─────┤\n│ apple │   3 │\n└───────┴─────┘\ndone"
+        );
+        assert_eq!(render.render_streamed("| a | b |\n|---|---|"), None);
+        assert!(render.has_pending_table());
+        assert_eq!(
+            render.render("| x | y |"),
+            "┌───┬───┐\n│ a │ b │\n├───┼───┤\n│ x │ y │\n└───┴───┘"
+        );
+        assert_eq!(
+            render.render("| not a table |\nplain"),
+            "| not a table |\nplain"
+        );
+    }
+
+    #[test]
+    fn test_fit_column_widths() {
+        assert_eq!(fit_column_widths(vec![10, 30, 4], None), vec![10, 30, 4]);
+        assert_eq!(fit_column_widths(vec![10, 30, 4], Some(30)), vec![8, 8, 4]);
+        assert_eq!(fit_column_widths(vec![10, 30], Some(10)), vec![5, 5]);
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("hello world", 20), "hello world");
+        assert_eq!(
+            wrap("这是一个没有空格的中文句子用于测试", 10),
+            "这是一个没\n有空格的中\n文句子用于\n测试"
+        );
+        let family = "👨\u{200d}👩\u{200d}👧\u{200d}👦";
+        assert_eq!(
+            wrap(&family.repeat(3), 4),
+            format!("{family}{family}\n{family}")
+        );
+        assert_eq!(
+            wrap(&format!("family {} emoji", family.repeat(3)), 8),
+            format!("family\n{}\nemoji", family.repeat(3))
+        );
+        assert_eq!(
+            wrap("a supercalifragilisticexpialidocious word", 10),
+            "a\nsupercalif\nragilistic\nexpialidoc\nious word"
+        );
+        assert_eq!(
+            wrap("    indented text that wraps around", 16),
+            "    indented\ntext that wraps\naround"
+        );
+        assert_eq!(wrap("  中文没有空格的句子", 8), "  中文没\n有空格的\n句子");
+    }
+
     #[test]
     fn test_detect_code_block() {
         assert_eq!(detect_code_block("```rust"), Some("rust".into()));
@@ -390,4 +891,13 @@ std::error::Error>> {
         assert_eq!(detect_code_block("```"), Some("".into()));
         assert_eq!(detect_code_block("``rust"), None);
     }
+
+    #[test]
+    fn test_looks_like_diff() {
+        assert!(looks_like_diff("diff --git a/src/main.rs b/src/main.rs"));
+        assert!(looks_like_diff("--- a/src/main.rs"));
+        assert!(looks_like_diff("@@ -1,4 +1,5 @@ fn main() {"));
+        assert!(!looks_like_diff("@@ not a hunk"));
+        assert!(!looks_like_diff("- a list item"));
+    }
 }
@@ -0,0 +1,512 @@
+const SUPERSCRIPTS: (&str, &str) = (
+    "0123456789+-=()abcdefghijklmnoprstuvwxyzT′∘−",
+    "⁰¹²³⁴⁵⁶⁷⁸⁹⁺⁻⁼⁽⁾ᵃᵇᶜᵈᵉᶠᵍʰⁱʲᵏˡᵐⁿᵒᵖʳˢᵗᵘᵛʷˣʸᶻᵀ′°⁻",
+);
+
+const SUBSCRIPTS: (&str, &str) = (
+    "0123456789+-=()aehijklmnoprstuvx−",
+    "₀₁₂₃₄₅₆₇₈₉₊₋₌₍₎ₐₑₕᵢⱼₖₗₘₙₒₚᵣₛₜᵤᵥₓ₋",
+);
+
+/// Replaces the TeX math in a line of Markdown with Unicode, e.g. `$\frac{1}{2} \leq x^2$`
+/// becomes `½ ≤ x²`. `in_block` says whether the line is inside a `$$`/`\[` display block
+/// opened on an earlier line; the second value says whether the next one is.
+pub fn render_math_line(line: &str, in_block: bool) -> (String, bool) {
+    let trimmed = line.trim();
+    if in_block {
+        for end in ["$$", "\\]"] {
+            if let Some(tex) = trimmed.strip_suffix(end) {
+                return (display_math(tex), false);
+            }
+        }
+        return (display_math(trimmed), true);
+    }
+    for (start, end) in [("$$", "$$"), ("\\[", "\\]")] {
+        if let Some(tex) = trimmed.strip_prefix(start)
+            && !tex.contains(end)
+        {
+            return (display_math(tex), true);
+        }
+    }
+    (render_inline_math(line), false)
+}
+
+fn display_math(tex: &str) -> String {
+    let output = latex_to_unicode(tex);
+    if output.is_empty() {
+        output
+    } else {
+        format!("    {output}")
+    }
+}
+
+/// Converts the `$...$`, `$$...$$`, `\(...\)`, and `\[...\]` spans in a line, leaving code
+/// spans, escaped dollars, and dollar amounts (`$5 and $10`) alone.
+fn render_inline_math(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut output = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let span = match (c, chars.get(i + 1)) {
+            ('`', _) => {
+                let ticks = chars[i..].iter().take_while(|v| **v == '`').count();
+                let fence = vec!['`'; ticks];
+                let end = find_seq(&chars, i + ticks, &fence).map_or(i + ticks, |v| v + ticks);
+                output.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            ('\\', Some('$')) => None,
+            ('\\', Some('(')) => find_seq(&chars, i + 2, &['\\', ')']).map(|end| (i + 2, end, 2)),
+            ('\\', Some('[')) => find_seq(&chars, i + 2, &['\\', ']']).map(|end| (i + 2, end, 2)),
+            ('$', Some('$')) => find_seq(&chars, i + 2, &['$', '$'])
+                .filter(|end| *end > i + 2)
+                .map(|end| (i + 2, end, 2)),
+            ('$', _) => find_closing_dollar(&chars, i + 1).map(|end| (i + 1, end, 1)),
+            _ => None,
+        };
+        match span {
+            Some((start, end, delimiter_len)) => {
+                let tex: String = chars[start..end].iter().collect();
+                output.push_str(&latex_to_unicode(&tex));
+                i = end + delimiter_len;
+            }
+            None if c == '\\' => {
+                output.extend(chars.get(i..i + 2).unwrap_or(&chars[i..]));
+                i += 2;
+            }
+            None => {
+                output.push(c);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+fn find_seq(chars: &[char], from: usize, pattern: &[char]) -> Option<usize> {
+    (from..chars.len()).find(|i| chars[*i..].starts_with(pattern))
+}
+
+/// Like Pandoc, an opening `$` must be followed by a non-space, and the closing one preceded by
+/// a non-space and not followed by a digit.
+fn find_closing_dollar(chars: &[char], start: usize) -> Option<usize> {
+    if chars.get(start).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '`' => return None,
+            '$' => {
+                let closes = !chars[i - 1].is_whitespace()
+                    && !chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+                return closes.then_some(i);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Renders TeX math as plain Unicode text. Anything it doesn't know is passed through
+/// (e.g. `\sin` becomes `sin`), so the output is always readable, if not always exact.
+pub fn latex_to_unicode(tex: &str) -> String {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        pos: 0,
+    };
+    let mut output = String::new();
+    while parser.pos < parser.chars.len() {
+        if parser.chars[parser.pos] == '}' {
+            parser.pos += 1;
+            continue;
+        }
+        output.push_str(&parser.parse_item());
+    }
+    output.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied();
+        self.pos += 1;
+        c
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Parses up to and past the `}` closing a group.
+    fn parse_group(&mut self) -> String {
+        let mut output = String::new();
+        while let Some(c) = self.peek() {
+            if c == '}' {
+                self.pos += 1;
+                break;
+            }
+            output.push_str(&self.parse_item());
+        }
+        output
+    }
+
+    /// Parses the argument of a command or script: a `{...}` group, or else a single item.
+    fn parse_argument(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.parse_group()
+            }
+            Some(_) => self.parse_item(),
+            None => String::new(),
+        }
+    }
+
+    fn parse_item(&mut self) -> String {
+        match self.next() {
+            Some('{') => self.parse_group(),
+            Some('\\') => self.parse_command(),
+            Some('^') => script(&self.parse_argument(), SUPERSCRIPTS, '^'),
+            Some('_') => script(&self.parse_argument(), SUBSCRIPTS, '_'),
+            Some('&') | None => String::new(),
+            Some('~') => " ".into(),
+            Some('\'') => "′".into(),
+            Some(c) => c.to_string(),
+        }
+    }
+
+    fn parse_command(&mut self) -> String {
+        let name: String = match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    self.pos += 1;
+                }
+                self.chars[start..self.pos].iter().collect()
+            }
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            None => return String::new(),
+        };
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_argument();
+                let denominator = self.parse_argument();
+                fraction(&numerator, &denominator)
+            }
+            "sqrt" => {
+                let mut index = String::new();
+                if self.peek() == Some('[') {
+                    self.pos += 1;
+                    while let Some(c) = self.next().filter(|c| *c != ']') {
+                        index.push(c);
+                    }
+                }
+                let radicand = self.parse_argument();
+                let root = match latex_to_unicode(&index).as_str() {
+                    "" | "2" => "√".to_string(),
+                    "3" => "∛".to_string(),
+                    "4" => "∜".to_string(),
+                    index => format!("{}√", script(index, SUPERSCRIPTS, '^')),
+                };
+                format!("{root}{}", parenthesize(&radicand))
+            }
+            "text" | "textrm" | "textbf" | "textit" | "mbox" | "mathrm" | "mathit" | "mathbf"
+            | "mathsf" | "mathtt" | "mathcal" | "boldsymbol" | "operatorname" => {
+                self.parse_argument()
+            }
+            "mathbb" => self.parse_argument().chars().map(double_struck).collect(),
+            "hat" | "widehat" => accent(&self.parse_argument(), '\u{0302}'),
+            "bar" | "overline" => accent(&self.parse_argument(), '\u{0304}'),
+            "tilde" | "widetilde" => accent(&self.parse_argument(), '\u{0303}'),
+            "vec" => accent(&self.parse_argument(), '\u{20D7}'),
+            "dot" => accent(&self.parse_argument(), '\u{0307}'),
+            "ddot" => accent(&self.parse_argument(), '\u{0308}'),
+            "left" | "right" | "bigl" | "bigr" | "Bigl" | "Bigr" | "big" | "Big" => {
+                if self.peek() == Some('.') {
+                    self.pos += 1;
+                }
+                String::new()
+            }
+            "begin" | "end" => {
+                self.parse_argument();
+                String::new()
+            }
+            "displaystyle" | "limits" | "nolimits" | "!" => String::new(),
+            "," | ":" | ";" | " " | "quad" => " ".into(),
+            "qquad" | "\\" => "  ".into(),
+            "{" | "}" | "$" | "%" | "&" | "#" | "_" => name,
+            "|" => "‖".into(),
+            _ => symbol(&name).map_or(name, str::to_string),
+        }
+    }
+}
+
+fn script(text: &str, (from, to): (&str, &str), marker: char) -> String {
+    let mapped: Option<String> = text
+        .chars()
+        .map(|c| {
+            from.chars()
+                .position(|v| v == c)
+                .and_then(|i| to.chars().nth(i))
+        })
+        .collect();
+    match mapped {
+        Some(mapped) if !mapped.is_empty() => mapped,
+        _ if text.chars().count() == 1 => format!("{marker}{text}"),
+        _ => format!("{marker}({text})"),
+    }
+}
+
+fn fraction(numerator: &str, denominator: &str) -> String {
+    let vulgar = match (numerator, denominator) {
+        ("1", "2") => Some("½"),
+        ("1", "3") => Some("⅓"),
+        ("2", "3") => Some("⅔"),
+        ("1", "4") => Some("¼"),
+        ("3", "4") => Some("¾"),
+        ("1", "5") => Some("⅕"),
+        ("1", "6") => Some("⅙"),
+        ("1", "8") => Some("⅛"),
+        _ => None,
+    };
+    match vulgar {
+        Some(vulgar) => vulgar.to_string(),
+        None => format!("{}/{}", parenthesize(numerator), parenthesize(denominator)),
+    }
+}
+
+fn parenthesize(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() <= 1 || text.chars().all(|c| c.is_alphanumeric() || c == '.') {
+        text.to_string()
+    } else {
+        format!("({text})")
+    }
+}
+
+fn accent(text: &str, mark: char) -> String {
+    text.chars()
+        .flat_map(|c| [Some(c), (!c.is_whitespace()).then_some(mark)])
+        .flatten()
+        .collect()
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        'A'..='Z' => char::from_u32(0x1D538 + (c as u32 - 'A' as u32)).unwrap_or(c),
+        '0'..='9' => char::from_u32(0x1D7D8 + (c as u32 - '0' as u32)).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn symbol(name: &str) -> Option<&'static str> {
+    let value = match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" | "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "rho" | "varrho" => "ρ",
+        "sigma" => "σ",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "times" => "×",
+        "div" => "÷",
+        "cdot" => "·",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "•",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "emptyset" | "varnothing" => "∅",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "neg" | "lnot" => "¬",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "iint" => "∬",
+        "iiint" => "∭",
+        "oint" => "∮",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "infty" => "∞",
+        "prime" => "′",
+        "degree" => "°",
+        "angle" => "∠",
+        "perp" => "⊥",
+        "parallel" => "∥",
+        "mid" => "∣",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "lvert" | "rvert" | "vert" => "|",
+        "lVert" | "rVert" | "Vert" => "‖",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        "therefore" => "∴",
+        "because" => "∵",
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_tables() {
+        for (from, to) in [SUPERSCRIPTS, SUBSCRIPTS] {
+            assert_eq!(from.chars().count(), to.chars().count());
+        }
+    }
+
+    #[test]
+    fn test_latex_to_unicode() {
+        assert_eq!(
+            latex_to_unicode(r"x^2 + y_{i+1} \leq \alpha"),
+            "x² + yᵢ₊₁ ≤ α"
+        );
+        assert_eq!(
+            latex_to_unicode(r"\frac{1}{2} + \frac{a+b}{c}"),
+            "½ + (a+b)/c"
+        );
+        assert_eq!(
+            latex_to_unicode(r"\sqrt{x^2 + 1} \cdot \sqrt[3]{8}"),
+            "√(x² + 1) · ∛8"
+        );
+        assert_eq!(
+            latex_to_unicode(r"\forall x \in \mathbb{R}, \sin x \neq \infty"),
+            "∀ x ∈ ℝ, sin x ≠ ∞"
+        );
+        assert_eq!(latex_to_unicode(r"e^{i\pi} = -1"), "e^(iπ) = -1");
+        assert_eq!(latex_to_unicode(r"\left( \vec{v} \right)"), "( v⃗ )");
+        assert_eq!(latex_to_unicode(r"\text{area} = \pi r^2"), "area = π r²");
+    }
+
+    #[test]
+    fn test_render_math_line() {
+        assert_eq!(
+            render_math_line(r"So $E = mc^2$ holds, and \(a \to b\).", false),
+            ("So E = mc² holds, and a → b.".to_string(), false)
+        );
+        assert_eq!(
+            render_math_line("It costs $5 and $10, or `$HOME` with \\$x$", false),
+            (
+                "It costs $5 and $10, or `$HOME` with \\$x$".to_string(),
+                false
+            )
+        );
+        assert_eq!(render_math_line("$$", false), (String::new(), true));
+        assert_eq!(
+            render_math_line(r"\sum_{k=1}^{n} k", true),
+            ("    ∑ₖ₌₁ⁿ k".to_string(), true)
+        );
+        assert_eq!(render_math_line("$$", true), (String::new(), false));
+        assert_eq!(
+            render_math_line(r"$$\int_0^1 f(x)\,dx$$", false),
+            ("∫₀¹ f(x) dx".to_string(), false)
+        );
+    }
+}
@@ -1,10 +1,21 @@
+mod diagram;
+mod html;
+mod inline_image;
 mod inquire;
 mod markdown;
+mod math;
+mod references;
 mod stream;
 
 pub use inquire::prompt_theme;
 
+pub use self::diagram::{DiagramKind, ascii_diagram, diagram_blocks, render_diagram};
+pub use self::html::render_html;
+pub use self::inline_image::{
+    INLINE_IMAGE_MODES, ImageProtocol, print_image_file, print_inline_images,
+};
 pub use self::markdown::{MarkdownRender, RenderOptions};
+pub use self::references::print_references;
 use self::stream::{markdown_stream, raw_stream};
 
 use crate::utils::{AbortSignal, IS_STDOUT_TERMINAL, error_text, pretty_error};
@@ -0,0 +1,169 @@
+use crate::config::GlobalConfig;
+use crate::utils::{IS_STDOUT_TERMINAL, NO_COLOR, dimmed_text};
+
+use anyhow::Result;
+use fancy_regex::Regex;
+use std::{env, sync::LazyLock};
+
+static INLINE_LINK_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?<!!)\[([^\[\]]+)\]\(\s*<?([^)\s>]+)>?(?:\s+"[^"]*")?\s*\)"#).unwrap()
+});
+static AUTOLINK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<((?:https?|ftp|mailto):[^>\s]+)>").unwrap());
+static DEFINITION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}\[([^\]]+)\]:\s*(.*)$").unwrap());
+static CODE_SPAN_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`+[^`]*`+").unwrap());
+
+/// A link or footnote in a reply, listed after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reference {
+    Link { text: String, url: String },
+    Footnote { label: String, text: String },
+}
+
+/// Lists the links and footnotes in a reply below it, so the URLs aren't lost in wrapped prose.
+/// Links are numbered in the order they appear, and are clickable in terminals that support it.
+pub fn print_references(config: &GlobalConfig, text: &str) -> Result<()> {
+    if !*IS_STDOUT_TERMINAL || !config.read().link_references {
+        return Ok(());
+    }
+    let references = collect_references(text);
+    if references.is_empty() {
+        return Ok(());
+    }
+    let hyperlinks = supports_hyperlinks();
+    let mut number = 0;
+    let mut output = vec![dimmed_text("References:")];
+    for reference in references {
+        let line = match reference {
+            Reference::Link { text, url } => {
+                number += 1;
+                let url = if hyperlinks {
+                    format!("\x1b]8;;{url}\x1b\\{url}\x1b]8;;\x1b\\")
+                } else {
+                    url
+                };
+                if text == url || text.is_empty() {
+                    format!("[{number}] {url}")
+                } else {
+                    format!("[{number}] {text}: {url}")
+                }
+            }
+            Reference::Footnote { label, text } => format!("[^{label}] {text}"),
+        };
+        output.push(line);
+    }
+    println!("\n{}\n", output.join("\n"));
+    Ok(())
+}
+
+/// Finds the inline links, autolinks, link reference definitions, and footnote definitions
+/// outside of code, leaving out repeated URLs.
+fn collect_references(text: &str) -> Vec<Reference> {
+    let mut references = vec![];
+    let mut in_code_block = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+        let line = CODE_SPAN_RE.replace_all(line, "");
+        if let Ok(Some(captures)) = DEFINITION_RE.captures(&line) {
+            let (label, value) = (&captures[1], captures[2].trim());
+            if let Some(label) = label.strip_prefix('^') {
+                references.push(Reference::Footnote {
+                    label: label.to_string(),
+                    text: value.to_string(),
+                });
+            } else if let Some(url) = value.split_whitespace().next() {
+                push_link(&mut references, label, url.trim_matches(['<', '>']));
+            }
+            continue;
+        }
+        for captures in INLINE_LINK_RE.captures_iter(&line).flatten() {
+            push_link(&mut references, &captures[1], &captures[2]);
+        }
+        for captures in AUTOLINK_RE.captures_iter(&line).flatten() {
+            push_link(&mut references, &captures[1], &captures[1]);
+        }
+    }
+    references
+}
+
+fn push_link(references: &mut Vec<Reference>, text: &str, url: &str) {
+    let is_new = !references
+        .iter()
+        .any(|v| matches!(v, Reference::Link { url: existing, .. } if existing == url));
+    if is_new && !url.starts_with('#') {
+        references.push(Reference::Link {
+            text: text.trim().to_string(),
+            url: url.to_string(),
+        });
+    }
+}
+
+/// Whether the terminal turns OSC 8 sequences into clickable links, as far as its environment
+/// tells.
+fn supports_hyperlinks() -> bool {
+    if *NO_COLOR {
+        return false;
+    }
+    let var = |name| env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    env::var_os("KITTY_WINDOW_ID").is_some()
+        || env::var_os("WT_SESSION").is_some()
+        || matches!(
+            var("TERM_PROGRAM").as_str(),
+            "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper"
+        )
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|v| v >= 5000)
+        || term == "xterm-kitty"
+        || term.starts_with("foot")
+        || term.starts_with("alacritty")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_references() {
+        let text = r#"See [the docs](https://example.com/docs "Docs") and <https://example.com>.
+Again, [the docs](https://example.com/docs), but not ![a chart](chart.png),
+`[code](https://example.com/code)`, or [a section](#setup).
+
+```md
+[in a block](https://example.com/block)
+```
+
+The spec[^1] covers it; see [rfc].
+
+[rfc]: <https://example.com/rfc> "RFC"
+[^1]: Section 4, page 12."#;
+        assert_eq!(
+            collect_references(text),
+            vec![
+                Reference::Link {
+                    text: "the docs".into(),
+                    url: "https://example.com/docs".into(),
+                },
+                Reference::Link {
+                    text: "https://example.com".into(),
+                    url: "https://example.com".into(),
+                },
+                Reference::Link {
+                    text: "rfc".into(),
+                    url: "https://example.com/rfc".into(),
+                },
+                Reference::Footnote {
+                    label: "1".into(),
+                    text: "Section 4, page 12.".into(),
+                },
+            ]
+        );
+        assert!(collect_references("No links here.").is_empty());
+    }
+}
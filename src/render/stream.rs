@@ -1,6 +1,6 @@
 use super::{MarkdownRender, SseEvent};
 
-use crate::utils::{AbortSignal, poll_abort_signal, spawn_spinner};
+use crate::utils::{AbortSignal, count_rows, display_width, poll_abort_signal, spawn_spinner};
 
 use anyhow::Result;
 use crossterm::{
@@ -11,7 +11,6 @@ use std::{
     io::{Stdout, Write, stdout},
     time::Duration,
 };
-use textwrap::core::display_width;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 pub async fn markdown_stream(
@@ -72,6 +71,8 @@ async fn markdown_stream_inner(
 ) -> Result<()> {
     let mut buffer = String::new();
     let mut buffer_rows = 1;
+    // The rendered unfinished line, as it's currently drawn
+    let mut buffer_output = String::new();
 
     let columns = terminal::size()?.0;
 
@@ -87,49 +88,28 @@ async fn markdown_stream_inner(
             }
 
             match reply_event {
-                SseEvent::Text(mut text) => {
+                SseEvent::Text(text) => {
                     // tab width hacking
-                    text = text.replace('\t', "    ");
-
-                    let mut attempts = 0;
-                    let (col, mut row) = loop {
-                        match cursor::position() {
-                            Ok(pos) => break pos,
-                            Err(_) if attempts < 3 => attempts += 1,
-                            Err(e) => return Err(e.into()),
-                        }
-                    };
+                    let text = format!("{buffer}{}", text.replace('\t', "    "));
 
-                    // Fix unexpected duplicate lines on kitty
-                    if col == 0 && row > 0 && display_width(&buffer) == columns as usize {
-                        row -= 1;
+                    // Finished lines are rendered once and never redrawn; only the unfinished
+                    // last line is re-rendered as text arrives
+                    let (finished, unfinished) = match text.rsplit_once('\n') {
+                        Some((head, tail)) => (render.render_streamed(head), tail),
+                        None => (None, text.as_str()),
+                    };
+                    let output = render.render_line(unfinished);
+                    if finished.is_none() && output == buffer_output {
+                        buffer = unfinished.to_string();
+                        continue;
                     }
 
-                    if row + 1 >= buffer_rows {
-                        queue!(writer, cursor::MoveTo(0, row + 1 - buffer_rows),)?;
-                    } else {
-                        let scroll_rows = buffer_rows - row - 1;
-                        queue!(
-                            writer,
-                            terminal::ScrollUp(scroll_rows),
-                            cursor::MoveTo(0, 0),
-                        )?;
+                    begin_redraw(writer, &buffer, buffer_rows, columns)?;
+                    if let Some(finished) = finished {
+                        print_block(writer, &finished, columns)?;
                     }
+                    buffer = unfinished.to_string();
 
-                    // No guarantee that text returned by render will not be re-layouted, so it is better to clear it.
-                    queue!(writer, terminal::Clear(terminal::ClearType::FromCursorDown))?;
-
-                    if text.contains('\n') {
-                        let text = format!("{buffer}{text}");
-                        let (head, tail) = split_line_tail(&text);
-                        let output = render.render(head);
-                        print_block(writer, &output, columns)?;
-                        buffer = tail.to_string();
-                    } else {
-                        buffer = format!("{buffer}{text}");
-                    }
-
-                    let output = render.render_line(&buffer);
                     if output.contains('\n') {
                         let (head, tail) = split_line_tail(&output);
                         buffer_rows = print_block(writer, head, columns)?;
@@ -142,8 +122,9 @@ async fn markdown_stream_inner(
                         queue!(writer, style::Print(&output))?;
                         buffer_rows = need_rows(&output, columns);
                     }
-
+                    queue!(writer, terminal::EndSynchronizedUpdate)?;
                     writer.flush()?;
+                    buffer_output = output;
                 }
                 SseEvent::Done => {
                     break 'outer;
@@ -156,12 +137,58 @@ async fn markdown_stream_inner(
         }
     }
 
+    // A table that ends the reply is still held back by the render
+    if render.has_pending_table() {
+        begin_redraw(writer, &buffer, buffer_rows, columns)?;
+        let output = render.render(&buffer);
+        let (head, tail) = split_line_tail(&output);
+        print_block(writer, head, columns)?;
+        queue!(writer, style::Print(tail), terminal::EndSynchronizedUpdate)?;
+        writer.flush()?;
+    }
+
     if let Some(spinner) = spinner.take() {
         spinner.stop();
     }
     Ok(())
 }
 
+/// Moves the cursor back to where the unfinished last line began, and clears from there down so
+/// it can be redrawn. The redraw is a synchronized update, so terminals that support it show it in
+/// one go rather than flashing the cleared lines; it ends with `EndSynchronizedUpdate`.
+fn begin_redraw(writer: &mut Stdout, buffer: &str, buffer_rows: u16, columns: u16) -> Result<()> {
+    let mut attempts = 0;
+    let (col, mut row) = loop {
+        match cursor::position() {
+            Ok(pos) => break pos,
+            Err(_) if attempts < 3 => attempts += 1,
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    // Fix unexpected duplicate lines on kitty
+    if col == 0 && row > 0 && display_width(buffer) == columns as usize {
+        row -= 1;
+    }
+
+    queue!(writer, terminal::BeginSynchronizedUpdate)?;
+
+    if row + 1 >= buffer_rows {
+        queue!(writer, cursor::MoveTo(0, row + 1 - buffer_rows),)?;
+    } else {
+        let scroll_rows = buffer_rows - row - 1;
+        queue!(
+            writer,
+            terminal::ScrollUp(scroll_rows),
+            cursor::MoveTo(0, 0),
+        )?;
+    }
+
+    // No guarantee that text returned by render will not be re-layouted, so it is better to clear it.
+    queue!(writer, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+    Ok(())
+}
+
 async fn gather_events(rx: &mut UnboundedReceiver<SseEvent>) -> Vec<SseEvent> {
     let mut texts = vec![];
     let mut done = false;
@@ -212,6 +239,5 @@ fn split_line_tail(text: &str) -> (&str, &str) {
 }
 
 fn need_rows(text: &str, columns: u16) -> u16 {
-    let buffer_width = display_width(text).max(1) as u16;
-    buffer_width.div_ceil(columns)
+    count_rows(text, columns as usize) as u16
 }
@@ -23,6 +23,32 @@ impl Completer for ReplCompleter {
         }
         let (cmd, cmd_start) = parts[0];
 
+        if cmd.starts_with('/') && parts_len == 1 {
+            if let Some(agent) = self.config.read().agent.as_ref() {
+                let span = Span::new(cmd_start, pos);
+                let commands = fuzzy_filter(agent.commands().to_vec(), |v| &v.name, &cmd[1..]);
+                suggestions.extend(
+                    commands
+                        .iter()
+                        .map(|v| create_suggestion(&format!("/{} ", v.name), &v.description, span)),
+                );
+            }
+            return suggestions;
+        }
+
+        if parts_len == 1 {
+            let span = Span::new(cmd_start, pos);
+            let aliases: Vec<_> = self.config.read().aliases.clone().into_iter().collect();
+            let aliases = fuzzy_filter(aliases, |(alias, _)| alias, cmd);
+            suggestions.extend(aliases.iter().map(|(alias, expansion)| {
+                create_suggestion(
+                    &format!("{alias} "),
+                    &format!("Alias for `{expansion}`"),
+                    span,
+                )
+            }));
+        }
+
         if !cmd.starts_with('.') {
             return suggestions;
         }
@@ -61,7 +87,7 @@ impl Completer for ReplCompleter {
             )
         }
 
-        if suggestions.is_empty() {
+        if parts_len == 1 || suggestions.is_empty() {
             let span = Span::new(cmd_start, pos);
             suggestions.extend(commands.iter().map(|cmd| {
                 let name = cmd.name;
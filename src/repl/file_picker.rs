@@ -0,0 +1,58 @@
+use crate::utils::{
+    estimate_token_length, expand_file_paths, is_loader_protocol, is_url, resolve_home_dir,
+};
+
+use anyhow::Result;
+use std::{collections::HashMap, fmt, fs};
+
+/// A local file that `.file` would attach, with an estimate of the tokens it adds.
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    pub path: String,
+    pub tokens: usize,
+}
+
+impl FileEntry {
+    /// Expands the local paths among those given to `.file` into the files they stand for.
+    pub fn list(loaders: &HashMap<String, String>, paths: &[String]) -> Result<Vec<Self>> {
+        let local_paths: Vec<String> = paths
+            .iter()
+            .filter(|path| is_local_path(loaders, path))
+            .map(|path| resolve_home_dir(path))
+            .collect();
+        let entries = expand_file_paths(&local_paths)?
+            .into_iter()
+            .map(|path| Self {
+                tokens: estimate_file_tokens(&path),
+                path,
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+impl fmt::Display for FileEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (~{} tokens)", self.path, self.tokens)
+    }
+}
+
+/// Whether a path given to `.file` is a local file or directory, rather than the last reply, a
+/// command, a URL, or a loader resource.
+pub fn is_local_path(loaders: &HashMap<String, String>, path: &str) -> bool {
+    path != "%%"
+        && !(path.starts_with('`') && path.len() > 2 && path.ends_with('`'))
+        && !is_url(path)
+        && !is_loader_protocol(loaders, path)
+}
+
+/// Files that can't be read as text (e.g. PDFs, which a loader converts) are guessed at from
+/// their size.
+fn estimate_file_tokens(path: &str) -> usize {
+    match fs::read_to_string(path) {
+        Ok(contents) => estimate_token_length(&contents),
+        Err(_) => fs::metadata(path)
+            .map(|v| v.len() as usize / 4)
+            .unwrap_or_default(),
+    }
+}
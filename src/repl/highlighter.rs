@@ -1,18 +1,138 @@
 use super::REPL_COMMANDS;
 
-use crate::{config::GlobalConfig, utils::NO_COLOR};
+use crate::config::{Config, GlobalConfig, list_agents};
+use crate::render::MarkdownRender;
+use crate::utils::NO_COLOR;
 
+use fancy_regex::Regex;
 use nu_ansi_term::{Color, Style};
+use parking_lot::RwLock;
 use reedline::{Highlighter, StyledText};
+use std::ops::Range;
+use std::sync::{Arc, LazyLock};
 
 const DEFAULT_COLOR: Color = Color::Default;
 const MATCH_COLOR: Color = Color::Green;
+const NAME_COLOR: Color = Color::Cyan;
+const VARIABLE_COLOR: Color = Color::Magenta;
+const CODE_COLOR: Color = Color::Yellow;
+const ERROR_COLOR: Color = Color::Red;
 
-pub struct ReplHighlighter;
+static VARIABLE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{[^{}\n]*\}\}").unwrap());
+static INLINE_CODE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`[^`\n]+`").unwrap());
+
+/// The role and agent names that `.role` and `.agent` are checked against as you type. Listing
+/// them reads the filesystem, so they're cached and only refreshed after a command has run.
+#[derive(Debug, Default)]
+pub struct KnownNames {
+    roles: RwLock<Vec<String>>,
+    agents: RwLock<Vec<String>>,
+}
+
+impl KnownNames {
+    pub fn load() -> Arc<Self> {
+        let names = Arc::new(Self::default());
+        names.refresh();
+        names
+    }
+
+    pub fn refresh(&self) {
+        *self.roles.write() = Config::list_roles(true);
+        *self.agents.write() = list_agents();
+    }
+
+    fn contains(&self, command: &str, name: &str) -> Option<bool> {
+        let names = match command {
+            ".role" => self.roles.read(),
+            ".agent" => self.agents.read(),
+            _ => return None,
+        };
+        Some(names.iter().any(|v| v == name))
+    }
+}
+
+pub struct ReplHighlighter {
+    render: Option<MarkdownRender>,
+    aliases: Vec<String>,
+    known_names: Arc<KnownNames>,
+}
 
 impl ReplHighlighter {
-    pub fn new(_config: &GlobalConfig) -> Self {
-        Self
+    pub fn new(config: &GlobalConfig, known_names: Arc<KnownNames>) -> Self {
+        let config = config.read();
+        let render = if config.highlight {
+            config.render_options().and_then(MarkdownRender::init).ok()
+        } else {
+            None
+        };
+        let aliases = config.aliases.keys().cloned().collect();
+        Self {
+            render,
+            aliases,
+            known_names,
+        }
+    }
+
+    /// The parts of the line styled on top of the markdown highlighting: the command or alias, the
+    /// role or agent it names, `{{variables}}`, and, without a theme, inline code.
+    fn overlays(&self, line: &str) -> Vec<(Range<usize>, Style)> {
+        let mut overlays = vec![];
+        let start = line.len() - line.trim_start().len();
+        let first_word = line[start..].split_whitespace().next().unwrap_or_default();
+        if self.aliases.iter().any(|alias| alias == first_word) {
+            overlays.push((
+                start..start + first_word.len(),
+                Style::new().fg(MATCH_COLOR),
+            ));
+            return overlays;
+        }
+        if line[start..].starts_with('.') {
+            let end = line[start..]
+                .find(char::is_whitespace)
+                .map_or(line.len(), |i| start + i);
+            let args = line[end..].trim_start();
+            let command = REPL_COMMANDS
+                .iter()
+                .map(|cmd| cmd.name)
+                .filter(|name| {
+                    line[start..].starts_with(name)
+                        && line[start + name.len()..]
+                            .chars()
+                            .next()
+                            .is_none_or(char::is_whitespace)
+                })
+                .max_by_key(|name| name.len());
+            let color = match command {
+                Some(_) => MATCH_COLOR,
+                None => ERROR_COLOR,
+            };
+            let command_end = command.map_or(end, |name| start + name.len());
+            overlays.push((start..command_end, Style::new().fg(color)));
+            if command.is_some() && command_end == end {
+                overlays.extend(name_overlay(
+                    &self.known_names,
+                    &line[start..end],
+                    args,
+                    line.len(),
+                ));
+            }
+            return overlays;
+        }
+        for captures in VARIABLE_RE.find_iter(line).flatten() {
+            overlays.push((captures.range(), Style::new().fg(VARIABLE_COLOR)));
+        }
+        if self.render.is_none() {
+            for captures in INLINE_CODE_RE.find_iter(line).flatten() {
+                if !overlays
+                    .iter()
+                    .any(|(range, _)| overlaps(range, &captures.range()))
+                {
+                    overlays.push((captures.range(), Style::new().fg(CODE_COLOR)));
+                }
+            }
+        }
+        overlays.sort_by_key(|(range, _)| range.start);
+        overlays
     }
 }
 
@@ -22,28 +142,107 @@ impl Highlighter for ReplHighlighter {
 
         if *NO_COLOR {
             styled_text.push((Style::default(), line.to_string()));
-        } else if REPL_COMMANDS.iter().any(|cmd| line.contains(cmd.name)) {
-            let matches: Vec<&str> = REPL_COMMANDS
-                .iter()
-                .filter(|cmd| line.contains(cmd.name))
-                .map(|cmd| cmd.name)
-                .collect();
-            let longest_match = matches.iter().fold(String::new(), |acc, &item| {
-                if item.len() > acc.len() {
-                    item.to_string()
-                } else {
-                    acc
-                }
-            });
-            let buffer_split: Vec<&str> = line.splitn(2, &longest_match).collect();
+            return styled_text;
+        }
 
-            styled_text.push((Style::new().fg(DEFAULT_COLOR), buffer_split[0].to_string()));
-            styled_text.push((Style::new().fg(MATCH_COLOR), longest_match));
-            styled_text.push((Style::new().fg(DEFAULT_COLOR), buffer_split[1].to_string()));
-        } else {
-            styled_text.push((Style::new().fg(DEFAULT_COLOR), line.to_string()));
+        let segments = self
+            .render
+            .as_ref()
+            .and_then(|render| render.highlight_input(line))
+            .unwrap_or_else(|| vec![(Style::new().fg(DEFAULT_COLOR), line.to_string())]);
+        for piece in apply_overlays(line, segments, &self.overlays(line)) {
+            styled_text.push(piece);
         }
 
         styled_text
     }
 }
+
+/// Styles the role or agent named by `.role` or `.agent`, in red when there's no agent by that
+/// name.
+fn name_overlay(
+    known_names: &KnownNames,
+    command: &str,
+    args: &str,
+    line_len: usize,
+) -> Option<(Range<usize>, Style)> {
+    let name = args.split_whitespace().next()?;
+    if command == ".agent" && name == "history" {
+        return None;
+    }
+    let start = line_len - args.len();
+    let range = start..start + name.len();
+    let known = known_names.contains(command, name)?;
+    match (command, known) {
+        (_, true) => Some((range, Style::new().fg(NAME_COLOR))),
+        (".agent", false) => Some((range, Style::new().fg(ERROR_COLOR))),
+        _ => None,
+    }
+}
+
+/// Splits the highlighted segments of `line` so that the overlays, which are sorted and don't
+/// overlap, take the place of the styles underneath them.
+fn apply_overlays(
+    line: &str,
+    segments: Vec<(Style, String)>,
+    overlays: &[(Range<usize>, Style)],
+) -> Vec<(Style, String)> {
+    let mut output = vec![];
+    let mut offset = 0;
+    for (style, text) in segments {
+        let end = offset + text.len();
+        let mut pos = offset;
+        while pos < end {
+            match overlays.iter().find(|(range, _)| range.contains(&pos)) {
+                Some((range, overlay_style)) => {
+                    let stop = range.end.min(end);
+                    output.push((*overlay_style, line[pos..stop].to_string()));
+                    pos = stop;
+                }
+                None => {
+                    let stop = overlays
+                        .iter()
+                        .map(|(range, _)| range.start)
+                        .filter(|start| *start > pos)
+                        .min()
+                        .unwrap_or(end)
+                        .min(end);
+                    output.push((style, line[pos..stop].to_string()));
+                    pos = stop;
+                }
+            }
+        }
+        offset = end;
+    }
+    output
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_overlays() {
+        let line = "say {{name}} twice";
+        let plain = Style::new().fg(DEFAULT_COLOR);
+        let bold = Style::new().bold();
+        let variable = Style::new().fg(VARIABLE_COLOR);
+        let segments = vec![
+            (plain, "say {{na".to_string()),
+            (bold, "me}} twice".to_string()),
+        ];
+        assert_eq!(
+            apply_overlays(line, segments, &[(4..12, variable)]),
+            vec![
+                (plain, "say ".to_string()),
+                (variable, "{{na".to_string()),
+                (variable, "me}}".to_string()),
+                (bold, " twice".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use crate::config::GlobalConfig;
+use crate::utils::NO_COLOR;
+
+use nu_ansi_term::{Color, Style};
+use reedline::{Hinter, History, SearchQuery};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Suggests the rest of the line, fish-style, from the latest input in the history that starts
+/// with it, or else from the active agent's conversation starters. Right-arrow accepts it.
+pub struct ReplHinter {
+    config: GlobalConfig,
+    style: Style,
+    current_hint: String,
+}
+
+impl ReplHinter {
+    pub fn new(config: &GlobalConfig) -> Self {
+        Self {
+            config: config.clone(),
+            style: Style::new().fg(Color::DarkGray),
+            current_hint: String::new(),
+        }
+    }
+
+    fn find_hint(&self, line: &str, history: &dyn History) -> Option<String> {
+        let from_history = history
+            .search(SearchQuery::last_with_prefix(
+                line.to_string(),
+                history.session(),
+            ))
+            .ok()
+            .and_then(|items| items.into_iter().next())
+            .map(|item| item.command_line);
+        let candidate = from_history.or_else(|| {
+            let config = self.config.read();
+            config
+                .agent
+                .as_ref()?
+                .conversation_starters()
+                .into_iter()
+                .find(|starter| starter.starts_with(line))
+        })?;
+        let hint = candidate.get(line.len()..)?;
+        (!hint.is_empty()).then(|| hint.to_string())
+    }
+}
+
+impl Hinter for ReplHinter {
+    fn handle(
+        &mut self,
+        line: &str,
+        pos: usize,
+        history: &dyn History,
+        use_ansi_coloring: bool,
+        _cwd: &str,
+    ) -> String {
+        self.current_hint =
+            if self.config.read().autosuggest && !line.trim().is_empty() && pos == line.len() {
+                self.find_hint(line, history).unwrap_or_default()
+            } else {
+                String::new()
+            };
+        if use_ansi_coloring && !*NO_COLOR && !self.current_hint.is_empty() {
+            self.style.paint(&self.current_hint).to_string()
+        } else {
+            self.current_hint.clone()
+        }
+    }
+
+    fn complete_hint(&self) -> String {
+        self.current_hint.clone()
+    }
+
+    fn next_hint_token(&self) -> String {
+        let mut reached_content = false;
+        self.current_hint
+            .split_word_bounds()
+            .take_while(|word| {
+                let is_whitespace = word.chars().all(char::is_whitespace);
+                match (is_whitespace, reached_content) {
+                    (_, true) => false,
+                    (true, false) => true,
+                    (false, false) => {
+                        reached_content = true;
+                        true
+                    }
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,193 @@
+use crate::client::Message;
+use crate::config::{Config, ensure_parent_exists};
+use crate::utils::dimmed_text;
+use crate::vault::Vault;
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use reedline::{FileBackedHistory, History, HistoryItem};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{OpenOptions, read_to_string},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// How many past inputs are loaded into the editor for browsing with the arrow keys.
+const EDITOR_HISTORY_LIMIT: usize = 1000;
+const SUMMARY_MAX_LEN: usize = 80;
+const PREVIEW_MAX_LINES: usize = 20;
+
+/// An input entered at the REPL prompt. Every input is appended to a single `repl-history.jsonl`
+/// shared by all sessions, so it can be searched for again later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub time: String,
+    pub input: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_file: Option<PathBuf>,
+}
+
+/// The part of a saved session that's needed to preview a conversation.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct SavedSession {
+    #[serde(default)]
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub branched_from: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(config: &Config, input: &str) -> Self {
+        let session = config.session.as_ref().map(|session| session.name());
+        Self {
+            time: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            input: input.to_string(),
+            session: session.map(|name| name.to_string()),
+            session_file: session.map(|name| config.session_file(name)),
+        }
+    }
+
+    /// Appends the input as a line of its own, encrypted with `vault` if it's given.
+    pub fn append(&self, path: &Path, vault: Option<&Vault>) -> Result<()> {
+        let mut line = serde_json::to_string(self)?;
+        if let Some(vault) = vault {
+            line = vault.encrypt_text(&format!("{line}\n"))?;
+        }
+        ensure_parent_exists(path)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open history file at '{}'", path.display()))?;
+        writeln!(file, "{line}")
+            .with_context(|| format!("Failed to write history file at '{}'", path.display()))
+    }
+
+    /// Loads every input, oldest first, decrypting any that were encrypted. Lines that can't be
+    /// parsed are skipped.
+    pub fn load(path: &Path, vault: &Vault) -> Vec<Self> {
+        let Ok(contents) = read_to_string(path) else {
+            return vec![];
+        };
+        let contents = match vault.decrypt_text(&contents) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Failed to decrypt the REPL history: {err}");
+                return vec![];
+            }
+        };
+        contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()
+    }
+
+    /// Loads the distinct inputs, most recent first, keeping the latest time each was entered.
+    pub fn load_distinct(path: &Path, vault: &Vault) -> Vec<Self> {
+        let mut entries: IndexMap<String, Self> = IndexMap::new();
+        for entry in Self::load(path, vault).into_iter().rev() {
+            entries.entry(entry.input.clone()).or_insert(entry);
+        }
+        entries.into_values().collect()
+    }
+
+    /// The editor's history, filled with the most recent inputs from previous runs.
+    pub fn editor_history(path: &Path, vault: &Vault) -> Result<Box<dyn History>> {
+        let mut history = FileBackedHistory::new(EDITOR_HISTORY_LIMIT)?;
+        let entries = Self::load(path, vault);
+        let skip = entries.len().saturating_sub(EDITOR_HISTORY_LIMIT);
+        for entry in entries.into_iter().skip(skip) {
+            history.save(HistoryItem::from_command_line(entry.input))?;
+        }
+        Ok(Box::new(history))
+    }
+
+    /// The input, followed by the reply it got, if it was entered in a session that was saved.
+    pub fn preview(&self, config: &Config) -> String {
+        let heading = match &self.session {
+            Some(session) => format!("{} · session: {session}", self.time),
+            None => self.time.clone(),
+        };
+        let mut lines = vec![dimmed_text(&format!("── {heading} ──")), self.input.clone()];
+        if let Some(reply) = self.find_reply(config) {
+            lines.push(dimmed_text("── reply ──"));
+            let mut reply_lines = reply.lines();
+            lines.extend(
+                reply_lines
+                    .by_ref()
+                    .take(PREVIEW_MAX_LINES)
+                    .map(String::from),
+            );
+            if reply_lines.next().is_some() {
+                lines.push(dimmed_text("…"));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn find_reply(&self, config: &Config) -> Option<String> {
+        let contents = config.read_session_file(self.session_file.as_ref()?).ok()?;
+        let session: SavedSession = serde_yaml::from_str(&contents).ok()?;
+        let messages = &session.messages;
+        let index = messages
+            .iter()
+            .rposition(|v| v.role.is_user() && v.content.to_text().trim() == self.input.trim())?;
+        messages[index + 1..]
+            .iter()
+            .take_while(|v| !v.role.is_user())
+            .filter(|v| v.role.is_assistant())
+            .map(|v| v.content.to_text())
+            .filter(|text| !text.is_empty())
+            .last()
+    }
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let input = self.input.split_whitespace().collect::<Vec<_>>().join(" ");
+        match input.char_indices().nth(SUMMARY_MAX_LEN) {
+            Some((i, _)) => write!(f, "{}…", &input[..i])?,
+            None => write!(f, "{input}")?,
+        }
+        if let Some(session) = &self.session {
+            write!(f, "  ({session})")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_distinct_history() {
+        let path = std::env::temp_dir().join(format!(
+            "loki-test-repl-history-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        for (input, session) in [
+            ("hello", None),
+            ("fix the bug", Some("work")),
+            ("hello", None),
+        ] {
+            HistoryEntry {
+                time: "2026-01-01 00:00".to_string(),
+                input: input.to_string(),
+                session: session.map(String::from),
+                session_file: None,
+            }
+            .append(&path, None)
+            .unwrap();
+        }
+        let entries = HistoryEntry::load_distinct(&path, &Vault::default());
+        let entries: Vec<String> = entries.iter().map(|v| v.to_string()).collect();
+        assert_eq!(entries, ["hello", "fix the bug  (work)"]);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,217 @@
+use super::{COMMAND_PALETTE_COMMAND, HISTORY_SEARCH_COMMAND, MENU_NAME, PASTE_IMAGE_COMMAND};
+
+use anyhow::{Context, Result, anyhow, bail};
+use crossterm::cursor::SetCursorStyle;
+use indexmap::IndexMap;
+use reedline::{CursorConfig, EditCommand, KeyCode, KeyModifiers, Keybindings, ReedlineEvent};
+
+/// Binds the key chords in the `keymap` config, replacing any default bindings for the same
+/// chords.
+pub fn apply_keymap(
+    keybindings: &mut Keybindings,
+    keymap: &IndexMap<String, String>,
+) -> Result<()> {
+    for (action, chord) in keymap {
+        let (modifiers, key_code) = parse_key_chord(chord)
+            .with_context(|| format!("Invalid key chord '{chord}' in the keymap"))?;
+        let event = action_event(action)?;
+        keybindings.add_binding(modifiers, key_code, event);
+    }
+    Ok(())
+}
+
+/// What an action in the keymap does: one of the named editor actions, or any REPL command
+/// (e.g. `.copy`), which is run as if it had been entered at the prompt.
+fn action_event(action: &str) -> Result<ReedlineEvent> {
+    let event = match action {
+        "history_search" => ReedlineEvent::ExecuteHostCommand(HISTORY_SEARCH_COMMAND.into()),
+        "command_palette" => ReedlineEvent::ExecuteHostCommand(COMMAND_PALETTE_COMMAND.into()),
+        "paste_image" => ReedlineEvent::ExecuteHostCommand(PASTE_IMAGE_COMMAND.into()),
+        "regenerate" => ReedlineEvent::ExecuteHostCommand(".regenerate".into()),
+        "open_editor" => ReedlineEvent::OpenEditor,
+        "insert_newline" => ReedlineEvent::Edit(vec![EditCommand::InsertNewline]),
+        "accept_suggestion" => ReedlineEvent::HistoryHintComplete,
+        "complete" => ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu(MENU_NAME.to_string()),
+            ReedlineEvent::MenuNext,
+        ]),
+        "clear_screen" => ReedlineEvent::ClearScreen,
+        _ if action.starts_with('.') => ReedlineEvent::ExecuteHostCommand(action.to_string()),
+        _ => bail!(
+            "Unknown keymap action '{action}'. Use one of history_search, command_palette, paste_image, regenerate, open_editor, insert_newline, accept_suggestion, complete, clear_screen, or a REPL command like .copy"
+        ),
+    };
+    Ok(event)
+}
+
+/// The cursor shape for each editing mode, starting from a blinking bar in vi insert mode and a
+/// steady block in vi normal mode, with the `cursor_shapes` config on top.
+pub fn cursor_config(cursor_shapes: &IndexMap<String, String>) -> Result<CursorConfig> {
+    let mut cursor_config = CursorConfig {
+        vi_insert: Some(SetCursorStyle::BlinkingBar),
+        vi_normal: Some(SetCursorStyle::SteadyBlock),
+        emacs: None,
+    };
+    for (mode, shape) in cursor_shapes {
+        let style = parse_cursor_shape(shape)
+            .with_context(|| format!("Invalid cursor shape for '{mode}'"))?;
+        match mode.as_str() {
+            "vi_insert" => cursor_config.vi_insert = style,
+            "vi_normal" => cursor_config.vi_normal = style,
+            "emacs" => cursor_config.emacs = style,
+            _ => bail!(
+                "Unknown editing mode '{mode}' in cursor_shapes. Use one of vi_insert, vi_normal, emacs"
+            ),
+        }
+    }
+    Ok(cursor_config)
+}
+
+/// Parses a cursor shape like `bar`, `blinking-block`, or `steady-underline`. `default` leaves the
+/// cursor as the terminal draws it.
+fn parse_cursor_shape(shape: &str) -> Result<Option<SetCursorStyle>> {
+    let shape = shape.trim().to_lowercase().replace('_', "-");
+    let style = match shape.as_str() {
+        "default" => return Ok(None),
+        "block" | "steady-block" => SetCursorStyle::SteadyBlock,
+        "blinking-block" => SetCursorStyle::BlinkingBlock,
+        "bar" | "steady-bar" => SetCursorStyle::SteadyBar,
+        "blinking-bar" => SetCursorStyle::BlinkingBar,
+        "underline" | "steady-underline" => SetCursorStyle::SteadyUnderScore,
+        "blinking-underline" => SetCursorStyle::BlinkingUnderScore,
+        _ => bail!(
+            "Unknown cursor shape '{shape}'. Use one of block, bar, underline (optionally prefixed with blinking- or steady-), or default"
+        ),
+    };
+    Ok(Some(style))
+}
+
+/// Parses a key chord like `ctrl-r`, `alt+v`, `ctrl-space`, or `f2`.
+fn parse_key_chord(chord: &str) -> Result<(KeyModifiers, KeyCode)> {
+    let chord = chord.trim().to_lowercase();
+    let mut parts: Vec<&str> = chord.split(['-', '+']).collect();
+    let key = parts
+        .pop()
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| anyhow!("No key given"))?;
+    let mut modifiers = KeyModifiers::NONE;
+    for part in parts {
+        modifiers |= match part {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" | "option" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => bail!("Unknown modifier '{part}'"),
+        };
+    }
+    let key_code = match key {
+        "space" => KeyCode::Char(' '),
+        "minus" => KeyCode::Char('-'),
+        "plus" => KeyCode::Char('+'),
+        "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+        "tab" => KeyCode::Tab,
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                    KeyCode::Char(c.to_ascii_uppercase())
+                }
+                (Some(c), None) => KeyCode::Char(c),
+                _ => match key.strip_prefix('f').and_then(|v| v.parse::<u8>().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => bail!("Unknown key '{key}'"),
+                },
+            }
+        }
+    };
+    Ok((modifiers, key_code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_chord() {
+        assert_eq!(
+            parse_key_chord("ctrl-r").unwrap(),
+            (KeyModifiers::CONTROL, KeyCode::Char('r'))
+        );
+        assert_eq!(
+            parse_key_chord("Ctrl+Space").unwrap(),
+            (KeyModifiers::CONTROL, KeyCode::Char(' '))
+        );
+        assert_eq!(
+            parse_key_chord("ctrl-alt-g").unwrap(),
+            (
+                KeyModifiers::CONTROL | KeyModifiers::ALT,
+                KeyCode::Char('g')
+            )
+        );
+        assert_eq!(
+            parse_key_chord("shift-tab").unwrap(),
+            (KeyModifiers::SHIFT, KeyCode::BackTab)
+        );
+        assert_eq!(
+            parse_key_chord("f5").unwrap(),
+            (KeyModifiers::NONE, KeyCode::F(5))
+        );
+        assert!(parse_key_chord("ctrl-").is_err());
+        assert!(parse_key_chord("hyper-x").is_err());
+        assert!(parse_key_chord("f13").is_err());
+    }
+
+    #[test]
+    fn test_action_event() {
+        assert_eq!(
+            action_event(".copy").unwrap(),
+            ReedlineEvent::ExecuteHostCommand(".copy".into())
+        );
+        assert_eq!(
+            action_event("history_search").unwrap(),
+            ReedlineEvent::ExecuteHostCommand(HISTORY_SEARCH_COMMAND.into())
+        );
+        assert!(action_event("launch_rockets").is_err());
+    }
+
+    #[test]
+    fn test_cursor_config() {
+        let shapes = IndexMap::from([
+            ("vi_insert".to_string(), "steady-underline".to_string()),
+            ("emacs".to_string(), "blinking_block".to_string()),
+        ]);
+        let cursor_config = cursor_config(&shapes).unwrap();
+        assert!(matches!(
+            cursor_config.vi_insert,
+            Some(SetCursorStyle::SteadyUnderScore)
+        ));
+        assert!(matches!(
+            cursor_config.vi_normal,
+            Some(SetCursorStyle::SteadyBlock)
+        ));
+        assert!(matches!(
+            cursor_config.emacs,
+            Some(SetCursorStyle::BlinkingBlock)
+        ));
+
+        let shapes = IndexMap::from([("vi_normal".to_string(), "default".to_string())]);
+        assert!(cursor_config(&shapes).unwrap().vi_normal.is_none());
+
+        let shapes = IndexMap::from([("vi_visual".to_string(), "bar".to_string())]);
+        assert!(cursor_config(&shapes).is_err());
+        let shapes = IndexMap::from([("emacs".to_string(), "triangle".to_string())]);
+        assert!(cursor_config(&shapes).is_err());
+    }
+}
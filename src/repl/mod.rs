@@ -1,39 +1,71 @@
 mod completer;
+mod file_picker;
 mod highlighter;
+mod hinter;
+mod history;
+mod keymap;
+mod palette;
 mod prompt;
+mod registers;
+mod search;
+mod session_picker;
 
 use self::completer::ReplCompleter;
-use self::highlighter::ReplHighlighter;
+use self::file_picker::FileEntry;
+use self::highlighter::{KnownNames, ReplHighlighter};
+use self::hinter::ReplHinter;
+use self::history::HistoryEntry;
+use self::keymap::{apply_keymap, cursor_config};
+use self::palette::PaletteEntry;
 use self::prompt::ReplPrompt;
+use self::registers::{RegisterVi, Registers};
+use self::search::SearchMatch;
+use self::session_picker::SessionEntry;
 
-use crate::client::{call_chat_completions, call_chat_completions_streaming, init_client, oauth};
+use crate::client::{
+    Model, ModelType, call_chat_completions, call_chat_completions_streaming, init_client,
+    list_models, oauth,
+};
+use crate::config::memory::{forget_memory, list_memories, remember};
 use crate::config::{
-    AgentVariables, AssertState, Config, GlobalConfig, Input, LastMessage, StateFlags,
-    macro_execute,
+    Agent, AgentVariables, AssertState, Config, ExportFormat, GlobalConfig, Input, LastMessage,
+    RoleLike, StateFlags, TEMP_SESSION_NAME, ensure_parent_exists, macro_execute,
+};
+use crate::function::display::{print_tool_call, print_tool_output};
+use crate::render::{
+    MarkdownRender, ascii_diagram, diagram_blocks, print_image_file, render_diagram, render_error,
 };
-use crate::render::render_error;
 use crate::utils::{
-    AbortSignal, abortable_run_with_spinner, create_abort_signal, dimmed_text, set_text, temp_file,
+    AbortSignal, CodeBlock, IS_STDOUT_TERMINAL, SHELL, abortable_run_with_spinner,
+    create_abort_signal, dimmed_text, edit_file, estimate_token_length, extract_code_blocks,
+    notify_when_done, read_focus_changes, render_word_diff, resolve_home_dir, save_image,
+    set_terminal_focused, set_text, strip_think_tag, temp_file, warning_text,
 };
 
 use crate::mcp::McpRegistry;
 use crate::resolve_oauth_client;
-use anyhow::{Context, Result, bail};
-use crossterm::cursor::SetCursorStyle;
+use anyhow::{Context, Result, anyhow, bail};
+use crossterm::event::{DisableFocusChange, EnableFocusChange};
+use crossterm::execute;
 use fancy_regex::Regex;
-use reedline::CursorConfig;
+use indexmap::IndexMap;
+use inquire::{Confirm, MultiSelect, Select, Text};
 use reedline::{
     ColumnarMenu, EditCommand, EditMode, Emacs, KeyCode, KeyModifiers, Keybindings, Reedline,
     ReedlineEvent, ReedlineMenu, ValidationResult, Validator, Vi, default_emacs_keybindings,
     default_vi_insert_keybindings, default_vi_normal_keybindings,
 };
-use reedline::{MenuBuilder, Signal};
-use std::sync::LazyLock;
-use std::{env, mem, process};
+use reedline::{HistoryItem, MenuBuilder, Signal};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use std::{env, fs, mem, process};
 
 const MENU_NAME: &str = "completion_menu";
+const DEFAULT_HISTORY_LIMIT: usize = 20;
 
-static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
+static REPL_COMMANDS: LazyLock<[ReplCommand; 63]> = LazyLock::new(|| {
     [
         ReplCommand::new(".help", "Show this help guide", AssertState::pass()),
         ReplCommand::new(".info", "Show system info", AssertState::pass()),
@@ -42,6 +74,11 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
             "Authenticate the current model client via OAuth (if configured)",
             AssertState::pass(),
         ),
+        ReplCommand::new(
+            ".edit",
+            "Compose the next message in an editor",
+            AssertState::pass(),
+        ),
         ReplCommand::new(
             ".edit config",
             "Modify configuration file",
@@ -51,7 +88,12 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
         ReplCommand::new(
             ".prompt",
             "Set a temporary role using a prompt",
-            AssertState::False(StateFlags::SESSION | StateFlags::AGENT),
+            AssertState::False(StateFlags::SESSION_EMPTY | StateFlags::SESSION | StateFlags::AGENT),
+        ),
+        ReplCommand::new(
+            ".prompt",
+            "Add a temporary instruction to the session",
+            AssertState::True(StateFlags::SESSION_EMPTY | StateFlags::SESSION),
         ),
         ReplCommand::new(
             ".role",
@@ -86,11 +128,26 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
             "Start or switch to a session",
             AssertState::False(StateFlags::SESSION_EMPTY | StateFlags::SESSION),
         ),
+        ReplCommand::new(
+            ".tokens",
+            "Show how the context window is being spent",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".search",
+            "Search saved sessions and past messages",
+            AssertState::pass(),
+        ),
         ReplCommand::new(
             ".empty session",
             "Clear session messages",
             AssertState::True(StateFlags::SESSION),
         ),
+        ReplCommand::new(
+            ".undo",
+            "Remove the last exchange from the session",
+            AssertState::True(StateFlags::SESSION),
+        ),
         ReplCommand::new(
             ".compress session",
             "Compress session messages",
@@ -111,11 +168,56 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
             "Save current session to file",
             AssertState::True(StateFlags::SESSION_EMPTY | StateFlags::SESSION),
         ),
+        ReplCommand::new(
+            ".pin",
+            "Keep a message word for word when the session is compressed",
+            AssertState::True(StateFlags::SESSION),
+        ),
+        ReplCommand::new(
+            ".unpin",
+            "Unpin a message, or all of them",
+            AssertState::True(StateFlags::SESSION),
+        ),
+        ReplCommand::new(
+            ".branch",
+            "Fork the session into a new named session",
+            AssertState::True(StateFlags::SESSION_EMPTY | StateFlags::SESSION),
+        ),
+        ReplCommand::new(
+            ".export",
+            "Export the session to a Markdown, HTML, or JSON document",
+            AssertState::True(StateFlags::SESSION),
+        ),
+        ReplCommand::new(
+            ".exit prompt",
+            "Drop the session's temporary instruction",
+            AssertState::True(StateFlags::SESSION_EMPTY | StateFlags::SESSION),
+        ),
         ReplCommand::new(
             ".exit session",
             "Exit active session",
             AssertState::True(StateFlags::SESSION_EMPTY | StateFlags::SESSION),
         ),
+        ReplCommand::new(
+            ".calls",
+            "Show the tool calls made in this session",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".remember",
+            "Save a fact to the global memory",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".memories",
+            "List or search the global memory",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".forget",
+            "Remove a fact from the global memory",
+            AssertState::pass(),
+        ),
         ReplCommand::new(".agent", "Use an agent", AssertState::bare()),
         ReplCommand::new(
             ".starter",
@@ -132,11 +234,21 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
             "Show agent info",
             AssertState::True(StateFlags::AGENT),
         ),
+        ReplCommand::new(
+            ".agent history",
+            "Show the agent's recent runs",
+            AssertState::True(StateFlags::AGENT),
+        ),
         ReplCommand::new(
             ".exit agent",
             "Leave agent",
             AssertState::True(StateFlags::AGENT),
         ),
+        ReplCommand::new(
+            ".todo",
+            "Show or update the todo list",
+            AssertState::True(StateFlags::AGENT),
+        ),
         ReplCommand::new(
             ".clear todo",
             "Clear the todo list and stop auto-continuation",
@@ -178,6 +290,11 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
             "Include files, directories, URLs or commands",
             AssertState::pass(),
         ),
+        ReplCommand::new(
+            ".paste-image",
+            "Attach the image in the clipboard to the next message",
+            AssertState::pass(),
+        ),
         ReplCommand::new(
             ".continue",
             "Continue previous response",
@@ -188,8 +305,47 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
             "Regenerate last response",
             AssertState::pass(),
         ),
-        ReplCommand::new(".copy", "Copy last response", AssertState::pass()),
+        ReplCommand::new(
+            ".retry",
+            "Retry the last prompt with another model",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".alternatives",
+            "Switch between the answers to the last prompt",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".copy",
+            "Copy the last response or a code block in it",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".save code",
+            "Save code blocks in the last response to files",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".diagram",
+            "Render a Mermaid or Graphviz diagram in the last response",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".diff",
+            "Show what changed between the last two responses",
+            AssertState::pass(),
+        ),
+        ReplCommand::new(
+            ".expand",
+            "Show the tool calls in the last response in full",
+            AssertState::pass(),
+        ),
         ReplCommand::new(".set", "Modify runtime settings", AssertState::pass()),
+        ReplCommand::new(
+            ".theme",
+            "Switch or preview color themes",
+            AssertState::pass(),
+        ),
         ReplCommand::new(
             ".delete",
             "Delete roles, sessions, RAGs, or agents",
@@ -203,6 +359,20 @@ static REPL_COMMANDS: LazyLock<[ReplCommand; 39]> = LazyLock::new(|| {
         ReplCommand::new(".exit", "Exit REPL", AssertState::pass()),
     ]
 });
+/// What the editor returns when the history search keybinding is pressed. It can't be typed.
+const HISTORY_SEARCH_COMMAND: &str = "\0history-search";
+/// What the editor returns when the command palette keybinding is pressed. It can't be typed.
+const COMMAND_PALETTE_COMMAND: &str = "\0command-palette";
+/// What the editor returns when the paste image keybinding is pressed. It can't be typed.
+const PASTE_IMAGE_COMMAND: &str = "\0paste-image";
+/// What the editor returns, followed by an action and a register name, to fill or paste a vi
+/// named register. It can't be typed.
+const REGISTER_COMMAND: &str = "\0register-";
+const PICKER_PAGE_SIZE: usize = 15;
+const THEME_PREVIEW: &str = include_str!("../../assets/theme-preview.md");
+/// How many characters of a message's first line are shown when listing messages.
+const FIRST_LINE_MAX_LEN: usize = 60;
+
 static COMMAND_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(\.\S*)\s*").unwrap());
 static MULTILINE_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(?s)^\s*:::\s*(.*)\s*:::\s*$").unwrap());
@@ -212,11 +382,14 @@ pub struct Repl {
     editor: Reedline,
     prompt: ReplPrompt,
     abort_signal: AbortSignal,
+    known_names: Arc<KnownNames>,
+    registers: Registers,
 }
 
 impl Repl {
     pub fn init(config: &GlobalConfig) -> Result<Self> {
-        let editor = Self::create_editor(config)?;
+        let known_names = KnownNames::load();
+        let editor = Self::create_editor(config, known_names.clone())?;
 
         let prompt = ReplPrompt::new(config);
         let abort_signal = create_abort_signal();
@@ -226,6 +399,8 @@ impl Repl {
             editor,
             prompt,
             abort_signal,
+            known_names,
+            registers: Registers::default(),
         })
     }
 
@@ -242,6 +417,10 @@ Type ".help" for additional help.
             )
         }
 
+        // Lets the spinner and the streamed output see when the terminal loses focus, for
+        // notifications. It's on for the whole session, since a turn that only runs tools or
+        // isn't streamed can take just as long.
+        execute!(io::stdout(), EnableFocusChange)?;
         loop {
             if self.abort_signal.aborted_ctrld() {
                 break;
@@ -249,14 +428,55 @@ Type ".help" for additional help.
             let sig = self.editor.read_line(&self.prompt);
             match sig {
                 Ok(Signal::Success(line)) => {
+                    let line = match line.as_str() {
+                        HISTORY_SEARCH_COMMAND => self.search_history(),
+                        COMMAND_PALETTE_COMMAND => self.open_command_palette().map(|_| None),
+                        PASTE_IMAGE_COMMAND => paste_image(&self.config).map(|_| None),
+                        command if command.starts_with(REGISTER_COMMAND) => {
+                            self.registers.run(&mut self.editor, command);
+                            Ok(None)
+                        }
+                        _ => Ok(Some(line)),
+                    };
+                    let line = match line {
+                        Ok(Some(line)) => line,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            render_error(err);
+                            println!();
+                            continue;
+                        }
+                    };
                     self.abort_signal.reset();
-                    match run_repl_command(&self.config, self.abort_signal.clone(), &line).await {
+                    self.record_history(&line);
+                    set_terminal_focused(true);
+                    let started = Instant::now();
+                    let ret =
+                        run_repl_command(&self.config, self.abort_signal.clone(), &line).await;
+                    // Commands like `.role`, `.agent`, or `.save role` can add the names the
+                    // highlighter checks against
+                    if line.trim_start().starts_with('.') {
+                        self.known_names.refresh();
+                    }
+                    let summary = match &ret {
+                        Ok(_) => "Loki finished",
+                        Err(_) => "Loki failed",
+                    };
+                    let notify_after_secs = self.config.read().notify_after_secs;
+                    if notify_after_secs
+                        .is_some_and(|secs| started.elapsed() >= Duration::from_secs(secs))
+                    {
+                        read_focus_changes();
+                    }
+                    notify_when_done(notify_after_secs, started, summary, line.trim());
+                    match ret {
                         Ok(exit) => {
                             if exit {
                                 break;
                             }
                         }
                         Err(err) => {
+                            self.config.write().finish_agent_run(Some(err.to_string()));
                             render_error(err);
                             println!()
                         }
@@ -273,23 +493,121 @@ Type ".help" for additional help.
                 _ => {}
             }
         }
+        execute!(io::stdout(), DisableFocusChange)?;
         self.config.write().exit_session()?;
         Ok(())
     }
 
-    fn create_editor(config: &GlobalConfig) -> Result<Reedline> {
+    /// Runs a line as if it had been entered at the prompt, e.g. an agent's prelude prompt.
+    pub async fn submit(&self, line: &str) {
+        println!("{}", dimmed_text(&format!(">> {line}")));
+        if let Err(err) = run_repl_command(&self.config, self.abort_signal.clone(), line).await {
+            self.config.write().finish_agent_run(Some(err.to_string()));
+            render_error(err);
+            println!()
+        }
+    }
+
+    fn record_history(&self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        let config = self.config.read();
+        let entry = HistoryEntry::new(&config, line);
+        let vault = config.encrypt_sessions.then_some(config.vault.as_ref());
+        if let Err(err) = entry.append(&Config::repl_history_file(), vault) {
+            warn!("Failed to save the REPL history: {err}");
+        }
+    }
+
+    /// Fuzzy searches the inputs from every previous REPL, previewing the chosen one before it's
+    /// run again or inserted at the prompt. Returns the input to run, if any.
+    fn search_history(&mut self) -> Result<Option<String>> {
+        let vault = self.config.read().vault.clone();
+        let entries = HistoryEntry::load_distinct(&Config::repl_history_file(), &vault);
+        if entries.is_empty() {
+            println!("No history yet.");
+            return Ok(None);
+        }
+        let query = self.editor.current_buffer_contents().to_string();
+        let Some(entry) = Select::new("Search history:", entries)
+            .with_starting_filter_input(&query)
+            .with_page_size(PICKER_PAGE_SIZE)
+            .prompt_skippable()?
+        else {
+            return Ok(None);
+        };
+        println!("{}\n", entry.preview(&self.config.read()));
+        match Select::new("Use this input?", vec!["Run", "Insert", "Cancel"]).prompt_skippable()? {
+            Some("Run") => {
+                self.editor.run_edit_commands(&[EditCommand::Clear]);
+                self.editor
+                    .history_mut()
+                    .save(HistoryItem::from_command_line(&entry.input))?;
+                println!("{}", dimmed_text(&format!(">> {}", entry.input)));
+                Ok(Some(entry.input))
+            }
+            Some("Insert") => {
+                self.editor.run_edit_commands(&[
+                    EditCommand::Clear,
+                    EditCommand::InsertString(entry.input),
+                ]);
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Fuzzy searches the commands that can be run right now, then inserts the chosen one at the
+    /// prompt, along with its argument if it takes one that can be picked from a list.
+    fn open_command_palette(&mut self) -> Result<()> {
+        let entries = PaletteEntry::list(&self.config.read());
+        let Some(entry) = Select::new("Command:", entries)
+            .with_page_size(PICKER_PAGE_SIZE)
+            .prompt_skippable()?
+        else {
+            return Ok(());
+        };
+        let arguments = entry.arguments(&self.config.read());
+        let argument = if arguments.is_empty() {
+            None
+        } else {
+            let options = arguments
+                .iter()
+                .map(|(value, description)| match description {
+                    Some(description) => format!("{} ({description})", value.trim()),
+                    None => value.trim().to_string(),
+                })
+                .collect();
+            Select::new(&format!("{}:", entry.name), options)
+                .with_help_message("Esc to leave out the argument")
+                .with_page_size(PICKER_PAGE_SIZE)
+                .raw_prompt_skippable()?
+        };
+        // Values that end with a space expect more to be typed after them, like `.set <key> `
+        let line = match argument {
+            Some(argument) => format!("{} {}", entry.name, arguments[argument.index].0),
+            None if !arguments.is_empty() => format!("{} ", entry.name),
+            None => entry.name,
+        };
+        self.editor
+            .run_edit_commands(&[EditCommand::Clear, EditCommand::InsertString(line)]);
+        Ok(())
+    }
+
+    fn create_editor(config: &GlobalConfig, known_names: Arc<KnownNames>) -> Result<Reedline> {
         let completer = ReplCompleter::new(config);
-        let highlighter = ReplHighlighter::new(config);
+        let highlighter = ReplHighlighter::new(config, known_names);
         let menu = Self::create_menu();
-        let edit_mode = Self::create_edit_mode(config);
-        let cursor_config = CursorConfig {
-            vi_insert: Some(SetCursorStyle::BlinkingBar),
-            vi_normal: Some(SetCursorStyle::SteadyBlock),
-            emacs: None,
-        };
+        let edit_mode = Self::create_edit_mode(config)?;
+        let cursor_config = cursor_config(&config.read().cursor_shapes)?;
+        let vault = config.read().vault.clone();
+        let history = HistoryEntry::editor_history(&Config::repl_history_file(), &vault)?;
         let mut editor = Reedline::create()
+            .with_history(history)
             .with_completer(Box::new(completer))
             .with_highlighter(Box::new(highlighter))
+            .with_hinter(Box::new(ReplHinter::new(config)))
             .with_menu(menu)
             .with_edit_mode(edit_mode)
             .with_cursor_config(cursor_config)
@@ -332,19 +650,43 @@ Type ".help" for additional help.
             KeyCode::Char('j'),
             ReedlineEvent::Edit(vec![EditCommand::InsertNewline]),
         );
+        keybindings.add_binding(
+            KeyModifiers::CONTROL,
+            KeyCode::Char('r'),
+            ReedlineEvent::ExecuteHostCommand(HISTORY_SEARCH_COMMAND.into()),
+        );
+        keybindings.add_binding(
+            KeyModifiers::CONTROL,
+            KeyCode::Char(' '),
+            ReedlineEvent::ExecuteHostCommand(COMMAND_PALETTE_COMMAND.into()),
+        );
+        keybindings.add_binding(
+            KeyModifiers::ALT,
+            KeyCode::Char('v'),
+            ReedlineEvent::ExecuteHostCommand(PASTE_IMAGE_COMMAND.into()),
+        );
     }
 
-    fn create_edit_mode(config: &GlobalConfig) -> Box<dyn EditMode> {
-        let edit_mode: Box<dyn EditMode> = if config.read().keybindings == "vi" {
+    fn create_edit_mode(config: &GlobalConfig) -> Result<Box<dyn EditMode>> {
+        let config = config.read();
+        let edit_mode: Box<dyn EditMode> = if config.keybindings == "vi" {
             let mut insert_keybindings = default_vi_insert_keybindings();
             Self::extra_keybindings(&mut insert_keybindings);
-            Box::new(Vi::new(insert_keybindings, default_vi_normal_keybindings()))
+            apply_keymap(&mut insert_keybindings, &config.keymap)?;
+            let mut normal_keybindings = default_vi_normal_keybindings();
+            Self::extra_keybindings(&mut normal_keybindings);
+            apply_keymap(&mut normal_keybindings, &config.keymap)?;
+            Box::new(RegisterVi::new(Vi::new(
+                insert_keybindings,
+                normal_keybindings,
+            )))
         } else {
             let mut keybindings = default_emacs_keybindings();
             Self::extra_keybindings(&mut keybindings);
+            apply_keymap(&mut keybindings, &config.keymap)?;
             Box::new(Emacs::new(keybindings))
         };
-        edit_mode
+        Ok(edit_mode)
     }
 
     fn create_menu() -> ReedlineMenu {
@@ -398,6 +740,13 @@ pub async fn run_repl_command(
     {
         line = text_match.as_str();
     }
+    let expanded = expand_alias(&config.read().aliases, line);
+    let line = expanded.as_deref().unwrap_or(line);
+    if let Some(cmd) = line.trim_start().strip_prefix('!') {
+        run_shell_command(config, cmd.trim())?;
+        println!();
+        return Ok(false);
+    }
     match parse_command(line) {
         Some((cmd, args)) => match cmd {
             ".help" => {
@@ -446,6 +795,9 @@ pub async fn run_repl_command(
                 oauth::run_oauth_flow(&*provider, &client_name).await?;
             }
             ".prompt" => match args {
+                Some(text) if config.read().session.is_some() => {
+                    config.write().set_session_prompt(Some(text))?;
+                }
                 Some(text) => {
                     config.write().use_prompt(text)?;
                 }
@@ -474,13 +826,88 @@ pub async fn run_repl_command(
                 ),
             },
             ".session" => {
-                Config::use_session_safely(config, args, abort_signal.clone()).await?;
+                let picked = match args {
+                    None if config.read().session.is_none() => Some(pick_session(config)?),
+                    _ => None,
+                };
+                let name = match &picked {
+                    Some(Some(name)) => Some(name.as_str()),
+                    Some(None) => return Ok(false),
+                    None => args,
+                };
+                Config::use_session_safely(config, name, abort_signal.clone()).await?;
                 Config::maybe_autoname_session(config.clone());
             }
+            ".tokens" => {
+                let mut input = Input::from_str(config, args.unwrap_or_default(), None);
+                let shell_output = config.read().shell_output.clone();
+                if let Some((cmd, output)) = shell_output {
+                    input.attach_command_output(&cmd, &output);
+                }
+                print!("{}", render_context_breakdown(&input)?);
+            }
+            ".search" => match args {
+                Some(query) => {
+                    let matches = search::search(&config.read(), query)?;
+                    if matches.is_empty() {
+                        println!("No matches for '{query}'");
+                        return Ok(false);
+                    }
+                    println!("{}\n", search::render_matches(&matches));
+                    if let Some(name) = pick_search_session(config, &matches)? {
+                        Config::use_session_safely(config, Some(&name), abort_signal.clone())
+                            .await?;
+                    }
+                }
+                None => println!("Usage: .search <text>"),
+            },
+            ".calls" => {
+                let limit = match args {
+                    Some(v) => v
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid number of calls '{v}'"))?,
+                    None => DEFAULT_HISTORY_LIMIT,
+                };
+                let calls = config.read().tool_calls(limit)?;
+                print!("{calls}");
+            }
+            ".remember" => match args {
+                Some(fact) => match remember(config, fact, vec![]).await? {
+                    Some(id) => println!("✓ Remembered as memory {id}."),
+                    None => println!("Already remembered."),
+                },
+                None => println!("Usage: .remember <fact>"),
+            },
+            ".memories" => {
+                print!("{}", list_memories(config, args).await?);
+            }
+            ".forget" => match args {
+                Some(id) => {
+                    let id = id
+                        .parse()
+                        .with_context(|| format!("Invalid memory id '{id}'"))?;
+                    forget_memory(config, id)?;
+                    println!("✓ Forgot memory {id}.");
+                }
+                None => {
+                    print!("{}", list_memories(config, None).await?);
+                    println!("Usage: .forget <id>");
+                }
+            },
             ".rag" => {
                 Config::use_rag(config, args, abort_signal.clone()).await?;
             }
             ".agent" => match split_first_arg(args) {
+                Some(("history", args)) if config.read().agent.is_some() => {
+                    let limit = match args {
+                        Some(v) => v
+                            .parse()
+                            .map_err(|_| anyhow!("Invalid number of runs '{v}'"))?,
+                        None => DEFAULT_HISTORY_LIMIT,
+                    };
+                    let history = config.read().agent_history(limit)?;
+                    print!("{history}");
+                }
                 Some((agent_name, args)) => {
                     let (new_args, _) = split_args_text(args.unwrap_or_default(), cfg!(windows));
                     let (session_name, variable_pairs) = match new_args.first() {
@@ -535,6 +962,38 @@ pub async fn run_repl_command(
                     config.read().print_markdown(&banner)?;
                 }
             },
+            ".pin" => match args {
+                Some(n) => {
+                    let n = n
+                        .parse()
+                        .with_context(|| format!("Invalid message number '{n}'"))?;
+                    config.write().pin_session_message(Some(n), true)?;
+                    println!("✓ Pinned message {n}.");
+                }
+                None => {
+                    print!("{}", render_pinnable_messages(config)?);
+                    println!("Usage: .pin <n>");
+                }
+            },
+            ".unpin" => {
+                let n = args
+                    .map(|n| {
+                        n.parse()
+                            .with_context(|| format!("Invalid message number '{n}'"))
+                    })
+                    .transpose()?;
+                config.write().pin_session_message(n, false)?;
+                match n {
+                    Some(n) => println!("✓ Unpinned message {n}."),
+                    None => println!("✓ Unpinned all messages."),
+                }
+            }
+            ".branch" => match args {
+                Some(name) => {
+                    config.write().branch_session(name)?;
+                }
+                None => println!("Usage: .branch <name>"),
+            },
             ".save" => match split_first_arg(args) {
                 Some(("role", name)) => {
                     config.write().save_role(name)?;
@@ -542,8 +1001,12 @@ pub async fn run_repl_command(
                 Some(("session", name)) => {
                     config.write().save_session(name)?;
                 }
+                Some(("code", n)) => save_code_blocks(config, n)?,
                 _ => {
-                    println!(r#"Usage: .save <role|session> [name]"#)
+                    println!(
+                        r#"Usage: .save <role|session> [name]
+       .save code [n]"#
+                    )
                 }
             },
             ".edit" => {
@@ -576,19 +1039,53 @@ pub async fn run_repl_command(
                     Some("agent-config") => {
                         config.write().edit_agent_config()?;
                     }
+                    None | Some("last") => {
+                        let text = match args {
+                            Some(_) => match config.read().last_message.as_ref() {
+                                Some(LastMessage { input, .. }) => input.raw_text().to_string(),
+                                None => bail!("No last input"),
+                            },
+                            None => String::new(),
+                        };
+                        let text = compose_message(config, &text)?;
+                        if text.is_empty() {
+                            println!("Empty message, nothing was sent.");
+                        } else {
+                            let input = prompt_input(config, &text).await?;
+                            ask(config, abort_signal.clone(), input, true).await?;
+                        }
+                    }
                     _ => {
-                        println!(r#"Usage: .edit <config|role|session|rag-docs|agent-config>"#)
+                        println!(
+                            r#"Usage:
+    .edit                           # Compose the next message in an editor
+    .edit last                      # Compose the next message starting from the last one
+    .edit <config|role|session|rag-docs|agent-config>"#
+                        )
                     }
                 }
             }
+            ".export" => match split_first_arg(args) {
+                Some((format, path)) => {
+                    let format = ExportFormat::parse(format)?;
+                    let path = config.read().export_session(format, path)?;
+                    println!("✓ Exported the session to '{}'", path.display());
+                }
+                None => println!("Usage: .export <md|html|json> [path]"),
+            },
             ".compress" => match args {
                 Some("session") => {
-                    abortable_run_with_spinner(
-                        Config::compress_session(config),
+                    let summary = abortable_run_with_spinner(
+                        Config::summarize_session(config),
                         "Compressing",
                         abort_signal.clone(),
                     )
                     .await?;
+                    if !confirm_session_summary(config, &summary)? {
+                        println!("The session was left as it was.");
+                        return Ok(false);
+                    }
+                    Config::apply_session_summary(config, &summary);
                     println!("✓ Successfully compressed the session.");
                 }
                 _ => {
@@ -603,6 +1100,10 @@ pub async fn run_repl_command(
                     println!(r#"Usage: .empty session"#)
                 }
             },
+            ".undo" => {
+                config.write().undo_session()?;
+                println!("✓ Removed the last exchange from the session.");
+            }
             ".rebuild" => match args {
                 Some("rag") => {
                     Config::rebuild_rag(config, abort_signal.clone()).await?;
@@ -633,6 +1134,9 @@ pub async fn run_repl_command(
             ".file" => match args {
                 Some(args) => {
                     let (files, text) = split_args_text(args, cfg!(windows));
+                    let Some(files) = review_files(config, files)? else {
+                        return Ok(false);
+                    };
                     let input = Input::from_files_with_spinner(
                         config,
                         text,
@@ -641,6 +1145,13 @@ pub async fn run_repl_command(
                         abort_signal.clone(),
                     )
                     .await?;
+                    println!(
+                        "{}",
+                        dimmed_text(&format!(
+                            "Sending about {} tokens",
+                            estimate_token_length(&input.text())
+                        ))
+                    );
                     ask(config, abort_signal.clone(), input, true).await?;
                 }
                 None => println!(
@@ -648,6 +1159,7 @@ pub async fn run_repl_command(
 
 .file /tmp/file.txt
 .file src/ Cargo.toml -- analyze
+.file 'src/**/*.rs' -- review
 .file https://example.com/file.txt -- summarize
 .file https://example.com/image.png -- recognize text
 .file `git diff` -- Generate git commit message
@@ -655,6 +1167,13 @@ pub async fn run_repl_command(
 .file %% -- translate last reply to english"#
                 ),
             },
+            ".paste-image" => {
+                paste_image(config)?;
+                if let Some(text) = args {
+                    let input = prompt_input(config, text).await?;
+                    ask(config, abort_signal.clone(), input, true).await?;
+                }
+            }
             ".continue" => {
                 let LastMessage {
                     mut input, output, ..
@@ -672,6 +1191,7 @@ pub async fn run_repl_command(
                 ask(config, abort_signal.clone(), input, true).await?;
             }
             ".regenerate" => {
+                let (model_id, temperature) = parse_regenerate_args(args)?;
                 let LastMessage { mut input, .. } = match config
                     .read()
                     .last_message
@@ -683,7 +1203,77 @@ pub async fn run_repl_command(
                     None => bail!("Unable to regenerate the response"),
                 };
                 input.set_regenerate();
+                if let Some(model_id) = model_id {
+                    let model = Model::retrieve_model(&config.read(), &model_id, ModelType::Chat)?;
+                    input.set_model(model);
+                }
+                if temperature.is_some() {
+                    input.set_temperature(temperature);
+                }
+                ask(config, abort_signal.clone(), input, true).await?;
+            }
+            ".retry" => {
+                let model_id = match args
+                    .map(|v| v.split_whitespace().collect::<Vec<_>>())
+                    .as_deref()
+                {
+                    None => pick_model(config)?,
+                    Some(["-m" | "--model", model_id]) => Some(model_id.to_string()),
+                    Some(_) => bail!("Usage: .retry [-m <model>]"),
+                };
+                let Some(model_id) = model_id else {
+                    return Ok(false);
+                };
+                let LastMessage { mut input, .. } = match config
+                    .read()
+                    .last_message
+                    .as_ref()
+                    .filter(|v| v.continuous)
+                    .cloned()
+                {
+                    Some(v) => v,
+                    None => bail!("Unable to retry the response"),
+                };
+                let model = Model::retrieve_model(&config.read(), &model_id, ModelType::Chat)?;
+                input.set_regenerate();
+                input.set_model(model);
                 ask(config, abort_signal.clone(), input, true).await?;
+                let count = config.read().alternatives.len();
+                if count > 0 {
+                    println!(
+                        "{}",
+                        dimmed_text(&format!(
+                            "{} other answer{} kept; switch with `.alternatives`",
+                            count,
+                            if count == 1 { "" } else { "s" }
+                        ))
+                    );
+                }
+            }
+            ".alternatives" => {
+                let alternatives = config.read().alternatives.clone();
+                if alternatives.is_empty() {
+                    println!("There are no other answers to the last prompt; try `.retry`");
+                    return Ok(false);
+                }
+                let index = match args {
+                    Some(n) => match n.parse::<usize>() {
+                        Ok(n) if n > 0 => n - 1,
+                        _ => bail!("Usage: .alternatives [number]"),
+                    },
+                    None => match pick_alternative(&alternatives)? {
+                        Some(index) => index,
+                        None => return Ok(false),
+                    },
+                };
+                config.write().use_alternative(index)?;
+                let output = config
+                    .read()
+                    .last_message
+                    .as_ref()
+                    .map(|v| v.output.clone())
+                    .unwrap_or_default();
+                config.read().print_markdown(&output)?;
             }
             ".set" => match args {
                 Some(args) => {
@@ -693,6 +1283,41 @@ pub async fn run_repl_command(
                     println!("Usage: .set <key> <value>...")
                 }
             },
+            ".theme" => {
+                let name = match split_first_arg(args) {
+                    Some(("preview", names)) => {
+                        let names = match names {
+                            Some(names) => names.split_whitespace().map(String::from).collect(),
+                            None => Config::list_themes(),
+                        };
+                        preview_themes(config, &names)?;
+                        return Ok(false);
+                    }
+                    Some((name, None)) => Some(name.to_string()),
+                    Some(_) => {
+                        println!(
+                            r#"Usage:
+    .theme                          # Pick a theme, previewing it first
+    .theme <name>                   # Switch to a theme
+    .theme preview [name]...        # Show how themes render a sample document"#
+                        );
+                        return Ok(false);
+                    }
+                    None => pick_theme(config)?,
+                };
+                if let Some(name) = name {
+                    config.write().switch_theme(&name)?;
+                    println!("✓ Switched to the {name} theme.");
+                    if config.read().theme() != Some(name.as_str()) {
+                        println!(
+                            "{}",
+                            dimmed_text(
+                                "The active session sets its own theme, which is used until it ends."
+                            )
+                        );
+                    }
+                }
+            }
             ".delete" => match args {
                 Some(args) => {
                     Config::delete(config, args)?;
@@ -701,6 +1326,45 @@ pub async fn run_repl_command(
                     println!("Usage: .delete <role|session|rag|macro|agent-data>")
                 }
             },
+            ".diagram" => show_diagram(config, args)?,
+            ".diff" => {
+                let (previous, last) = {
+                    let config = config.read();
+                    (config.previous_message.clone(), config.last_message.clone())
+                };
+                match (previous, last.filter(|v| !v.output.is_empty())) {
+                    (Some(previous), Some(last)) => {
+                        let (from, to) = (
+                            previous.input.role().model().id(),
+                            last.input.role().model().id(),
+                        );
+                        let title = if from == to {
+                            format!("── previous → last response ({to}) ──")
+                        } else {
+                            format!("── previous response ({from}) → last response ({to}) ──")
+                        };
+                        println!("{}", dimmed_text(&title));
+                        println!("{}", render_word_diff(&previous.output, &last.output));
+                    }
+                    _ => println!("There aren't two responses to compare yet"),
+                }
+            }
+            ".expand" => {
+                let tool_results = config
+                    .read()
+                    .last_message
+                    .as_ref()
+                    .and_then(|v| v.input.tool_calls().as_ref())
+                    .map(|v| v.tool_results.clone())
+                    .unwrap_or_default();
+                if tool_results.is_empty() {
+                    println!("The last response didn't call any tools");
+                }
+                for result in tool_results {
+                    print_tool_call(&result.call.name, &result.call.arguments, true);
+                    print_tool_output(&result.call.name, &result.output, true);
+                }
+            }
             ".copy" => {
                 let output = match config
                     .read()
@@ -712,7 +1376,17 @@ pub async fn run_repl_command(
                     Some(v) => v,
                     None => bail!("No chat response to copy"),
                 };
-                set_text(&output).context("Failed to copy the last chat response")?;
+                match args {
+                    Some(args) => {
+                        if let Some((n, block)) = pick_code_block(&output, args)? {
+                            set_text(&block.code).context("Failed to copy the code block")?;
+                            println!("✓ Copied code block {n}.");
+                        }
+                    }
+                    None => {
+                        set_text(&output).context("Failed to copy the last chat response")?;
+                    }
+                }
             }
             ".exit" => match args {
                 Some("role") => {
@@ -772,6 +1446,9 @@ pub async fn run_repl_command(
                         config.write().exit_session()?;
                     }
                 }
+                Some("prompt") => {
+                    config.write().set_session_prompt(None)?;
+                }
                 Some("rag") => {
                     config.write().exit_rag()?;
                 }
@@ -813,11 +1490,7 @@ pub async fn run_repl_command(
                     let mut cfg = config.write();
                     match cfg.agent.as_mut() {
                         Some(agent) => {
-                            if !agent.auto_continue_enabled() {
-                                bail!(
-                                    "The todo system is not enabled for this agent. Set 'auto_continue: true' in the agent's config.yaml to enable it."
-                                );
-                            }
+                            ensure_todo_enabled(agent)?;
                             if agent.todo_list().is_empty() {
                                 println!("Todo list is already empty.");
                             } else {
@@ -830,6 +1503,43 @@ pub async fn run_repl_command(
                 }
                 _ => unknown_command()?,
             },
+            ".todo" => {
+                let mut cfg = config.write();
+                let agent = match cfg.agent.as_mut() {
+                    Some(agent) => agent,
+                    None => bail!("No active agent"),
+                };
+                ensure_todo_enabled(agent)?;
+                match split_first_arg(args) {
+                    Some(("list", None)) => {
+                        if agent.todo_list().is_empty() {
+                            println!("Todo list is empty.");
+                        } else {
+                            println!("{}", agent.todo_list().render_for_model());
+                        }
+                    }
+                    Some(("add", Some(task))) => {
+                        let id = agent.add_todo(task);
+                        println!("✓ Added todo {id}.");
+                    }
+                    Some(("done", Some(id))) => {
+                        let id: usize = id
+                            .parse()
+                            .with_context(|| format!("Invalid todo id '{id}'"))?;
+                        if agent.mark_todo_done(id) {
+                            println!("✓ Marked todo {id} as done.");
+                        } else {
+                            bail!("Todo {id} not found");
+                        }
+                    }
+                    _ => println!(
+                        r#"Usage:
+    .todo list                      # Show the todo list
+    .todo add <task>                # Add a task to the todo list
+    .todo done <id>                 # Mark a task as done"#
+                    ),
+                }
+            }
             ".vault" => match split_first_arg(args) {
                 Some(("add", name)) => {
                     if let Some(name) = name {
@@ -877,7 +1587,20 @@ pub async fn run_repl_command(
             {
                 config.write().agent.as_mut().unwrap().reset_continuation();
             }
-            let input = Input::from_str(config, line, None);
+            let command = config
+                .read()
+                .agent
+                .as_ref()
+                .and_then(|agent| agent.expand_command(line));
+            let text = match command {
+                Some(text) => {
+                    let text = text?;
+                    println!("{}", dimmed_text(&format!(">> {text}")));
+                    text
+                }
+                None => line.to_string(),
+            };
+            let input = prompt_input(config, &text).await?;
             ask(config, abort_signal.clone(), input, true).await?;
         }
     }
@@ -908,10 +1631,23 @@ async fn ask(
 
     let client = input.create_client()?;
     config.write().before_chat_completion(&input)?;
-    let (output, tool_results) = if input.stream() {
-        call_chat_completions_streaming(&input, client.as_ref(), abort_signal.clone()).await?
+    let ret = if input.stream() {
+        call_chat_completions_streaming(&input, client.as_ref(), abort_signal.clone()).await
     } else {
-        call_chat_completions(&input, true, false, client.as_ref(), abort_signal.clone()).await?
+        call_chat_completions(&input, true, false, client.as_ref(), abort_signal.clone()).await
+    };
+    let (output, tool_results) = match ret {
+        Ok(ret) => ret,
+        Err(err) => {
+            let fallback = Config::use_agent_fallback_model(config, &input, &err)?;
+            return match fallback {
+                Some(model) => {
+                    input.set_model(model);
+                    ask(config, abort_signal, input, false).await
+                }
+                None => Err(err),
+            };
+        }
     };
     config
         .write()
@@ -925,6 +1661,12 @@ async fn ask(
         )
         .await
     } else {
+        if !diagram_blocks(extract_code_blocks(&strip_think_tag(&output))).is_empty() {
+            println!(
+                "{}",
+                dimmed_text("Render the diagram in this response with `.diagram`.")
+            );
+        }
         let should_continue = {
             let cfg = config.read();
             if let Some(agent) = &cfg.agent {
@@ -975,6 +1717,7 @@ async fn ask(
                 config.write().agent.as_mut().unwrap().reset_continuation();
             }
             Config::maybe_autoname_session(config.clone());
+            Config::maybe_extract_memories(config.clone());
 
             let needs_compression = {
                 let cfg = config.read();
@@ -1053,6 +1796,360 @@ async fn ask(
     }
 }
 
+/// Creates the input for a prompt, attaching any images pasted and shell output kept since the
+/// last one.
+async fn prompt_input(config: &GlobalConfig, text: &str) -> Result<Input> {
+    let images = mem::take(&mut config.write().pasted_images);
+    let mut input = if images.is_empty() {
+        Input::from_str(config, text, None)
+    } else {
+        Input::from_files(config, text, images, None).await?
+    };
+    let shell_output = config.write().shell_output.take();
+    if let Some((cmd, output)) = shell_output {
+        input.attach_command_output(&cmd, &output);
+    }
+    Ok(input)
+}
+
+/// Shows the summary that would replace the session's history, and how many tokens it saves, and
+/// asks whether to go ahead. Macros and piped output don't ask.
+fn confirm_session_summary(config: &GlobalConfig, summary: &str) -> Result<bool> {
+    if config.read().macro_flag || !*IS_STDOUT_TERMINAL {
+        return Ok(true);
+    }
+    let tokens = config
+        .read()
+        .session
+        .as_ref()
+        .map(|session| session.tokens())
+        .unwrap_or_default();
+    println!("{}", dimmed_text("── summary ──"));
+    config.read().print_markdown(summary)?;
+    println!(
+        "{}",
+        dimmed_text(&format!(
+            "── about {tokens} tokens of history → about {} ──",
+            estimate_token_length(summary)
+        ))
+    );
+    let confirmed = Confirm::new("Replace the session history with this summary?")
+        .with_default(true)
+        .prompt()?;
+    Ok(confirmed)
+}
+
+/// The first non-blank line of a message, cut short if it's long.
+fn first_line_summary(text: &str) -> String {
+    let first_line = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .trim();
+    match first_line.char_indices().nth(FIRST_LINE_MAX_LEN) {
+        Some((end, _)) => format!("{}…", &first_line[..end]),
+        None => first_line.to_string(),
+    }
+}
+
+/// Numbers the session's messages that can be pinned, each shown by its first line, with the
+/// pinned ones marked.
+fn render_pinnable_messages(config: &GlobalConfig) -> Result<String> {
+    let config = config.read();
+    let Some(session) = &config.session else {
+        bail!("No session")
+    };
+    let mut output = String::new();
+    for (index, message) in session.messages().iter().enumerate() {
+        let role = if message.role.is_user() {
+            "you"
+        } else if message.role.is_assistant() {
+            "reply"
+        } else {
+            continue;
+        };
+        let summary = first_line_summary(&message.content.to_text());
+        let marker = if session.pinned().contains(&index) {
+            "📌"
+        } else {
+            "  "
+        };
+        output.push_str(&format!(
+            "{marker} {:>3}  {}  {summary}\n",
+            index + 1,
+            dimmed_text(&format!("{role:<5}"))
+        ));
+    }
+    Ok(output)
+}
+
+/// Fuzzy searches the chat models. Returns the ID of the chosen one, if any.
+fn pick_model(config: &GlobalConfig) -> Result<Option<String>> {
+    let ids: Vec<String> = list_models(&config.read(), ModelType::Chat)
+        .into_iter()
+        .map(|v| v.id())
+        .collect();
+    let model_id = Select::new("Retry with model:", ids)
+        .with_page_size(PICKER_PAGE_SIZE)
+        .prompt_skippable()?;
+    Ok(model_id)
+}
+
+/// Lists the other answers to the last prompt by model and first line. Returns the index of the
+/// chosen one, if any.
+fn pick_alternative(alternatives: &[LastMessage]) -> Result<Option<usize>> {
+    let options: Vec<String> = alternatives
+        .iter()
+        .enumerate()
+        .map(|(i, alternative)| {
+            format!(
+                "{}. {} — {}",
+                i + 1,
+                alternative.input.role().model().id(),
+                first_line_summary(&alternative.output)
+            )
+        })
+        .collect();
+    let picked = Select::new("Switch to answer:", options)
+        .with_page_size(PICKER_PAGE_SIZE)
+        .raw_prompt_skippable()?;
+    Ok(picked.map(|v| v.index))
+}
+
+/// Fuzzy searches the saved sessions, previewing the chosen one before it's opened. Returns the
+/// name of the session to open, which is a new temporary one when there are none to pick from.
+fn pick_session(config: &GlobalConfig) -> Result<Option<String>> {
+    let entries = SessionEntry::list(&config.read());
+    if entries.len() == 1 || config.read().macro_flag || !*IS_STDOUT_TERMINAL {
+        return Ok(Some(TEMP_SESSION_NAME.to_string()));
+    }
+    let Some(entry) = Select::new("Session:", entries)
+        .with_page_size(PICKER_PAGE_SIZE)
+        .prompt_skippable()?
+    else {
+        return Ok(None);
+    };
+    if !entry.is_saved() {
+        return Ok(Some(entry.name));
+    }
+    println!("{}\n", entry.preview());
+    match Select::new("Open this session?", vec!["Open", "Cancel"]).prompt_skippable()? {
+        Some("Open") => Ok(Some(entry.name)),
+        _ => Ok(None),
+    }
+}
+
+/// Renders the sample document with each of the given themes.
+fn preview_themes(config: &GlobalConfig, names: &[String]) -> Result<()> {
+    let mut options = config.read().render_options()?;
+    for name in names {
+        options.theme = Some(Config::load_theme(name)?);
+        let mut render = MarkdownRender::init(options.clone())?;
+        println!("{}\n", dimmed_text(&format!("── {name} ──")));
+        println!("{}\n", render.render(THEME_PREVIEW));
+    }
+    Ok(())
+}
+
+/// Lists the themes, starting at the current one, and previews the chosen one before switching
+/// to it. Returns the name of the theme to switch to, if any.
+fn pick_theme(config: &GlobalConfig) -> Result<Option<String>> {
+    let names = Config::list_themes();
+    let current = config.read().theme().unwrap_or("dark").to_string();
+    let Some(name) = Select::new("Theme:", names.clone())
+        .with_starting_cursor(names.iter().position(|v| *v == current).unwrap_or_default())
+        .with_page_size(PICKER_PAGE_SIZE)
+        .prompt_skippable()?
+    else {
+        return Ok(None);
+    };
+    preview_themes(config, std::slice::from_ref(&name))?;
+    let confirmed = Confirm::new(&format!("Switch to the {name} theme?"))
+        .with_default(true)
+        .prompt_skippable()?
+        .unwrap_or_default();
+    Ok(confirmed.then_some(name))
+}
+
+/// Lists the tokens taken up by each part of the context, and how much of the model's context
+/// window they fill.
+fn render_context_breakdown(input: &Input) -> Result<String> {
+    let parts = input.context_breakdown()?;
+    let total: usize = parts.iter().map(|(_, tokens)| tokens).sum();
+    let mut output = String::new();
+    for (name, tokens) in &parts {
+        let percent = *tokens as f64 * 100.0 / total.max(1) as f64;
+        output.push_str(&format!("{name:<24}{tokens:>8}  {percent:>5.1}%\n"));
+    }
+    let limit = match input.role().model().max_input_tokens() {
+        Some(max) if max > 0 => format!(
+            " / {max} ({:.1}% of the context window)",
+            total as f64 * 100.0 / max as f64
+        ),
+        _ => String::new(),
+    };
+    output.push_str(&format!("{:<24}{total:>8}{limit}\n", "Total"));
+    Ok(output)
+}
+
+/// Expands the local paths given to `.file` into the files they stand for, and checks that those
+/// fit in the model's context window. When they don't, the files to attach are picked from a
+/// list. Returns `None` when that's cancelled.
+fn review_files(config: &GlobalConfig, paths: Vec<String>) -> Result<Option<Vec<String>>> {
+    let (loaders, model) = {
+        let config = config.read();
+        (
+            config.document_loaders.clone(),
+            config.extract_role().model().clone(),
+        )
+    };
+    let entries = FileEntry::list(&loaders, &paths)?;
+    let mut files: Vec<String> = paths
+        .into_iter()
+        .filter(|path| !file_picker::is_local_path(&loaders, path))
+        .collect();
+    let total: usize = entries.iter().map(|v| v.tokens).sum();
+    let max_input_tokens = match model.max_input_tokens() {
+        Some(max_input_tokens)
+            if total > max_input_tokens
+                && entries.len() > 1
+                && !config.read().macro_flag
+                && *IS_STDOUT_TERMINAL =>
+        {
+            max_input_tokens
+        }
+        _ => {
+            files.extend(entries.into_iter().map(|v| v.path));
+            return Ok(Some(files));
+        }
+    };
+    println!(
+        "{}",
+        warning_text(&format!(
+            "These {} files come to about {total} tokens, more than the {max_input_tokens} {} takes in",
+            entries.len(),
+            model.id()
+        ))
+    );
+    let Some(picked) = MultiSelect::new("Files to attach:", entries)
+        .with_all_selected_by_default()
+        .with_page_size(PICKER_PAGE_SIZE)
+        .with_formatter(&|picked| {
+            let tokens: usize = picked.iter().map(|v| v.value.tokens).sum();
+            format!("{} files, about {tokens} tokens", picked.len())
+        })
+        .prompt_skippable()?
+    else {
+        return Ok(None);
+    };
+    files.extend(picked.into_iter().map(|v| v.path));
+    Ok(Some(files))
+}
+
+/// Offers to open one of the sessions with a match, when no session is active.
+fn pick_search_session(config: &GlobalConfig, matches: &[SearchMatch]) -> Result<Option<String>> {
+    {
+        let config = config.read();
+        if config.session.is_some() || config.macro_flag || !*IS_STDOUT_TERMINAL {
+            return Ok(None);
+        }
+    }
+    let mut names: Vec<&str> = matches
+        .iter()
+        .filter_map(|item| item.session.as_deref())
+        .collect();
+    names.dedup();
+    if names.is_empty() {
+        return Ok(None);
+    }
+    let name = Select::new("Open a matching session? (Esc to skip)", names)
+        .with_page_size(PICKER_PAGE_SIZE)
+        .prompt_skippable()?;
+    Ok(name.map(String::from))
+}
+
+/// Opens the editor on a temporary file holding `text`, and returns what it holds once the
+/// editor exits.
+fn compose_message(config: &GlobalConfig, text: &str) -> Result<String> {
+    let path = temp_file("-message-", ".md");
+    fs::write(&path, text).with_context(|| format!("Failed to write to '{}'", path.display()))?;
+    let editor = config.read().editor()?;
+    edit_file(&editor, &path)?;
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let _ = fs::remove_file(&path);
+    Ok(text.trim().to_string())
+}
+
+/// Saves the image in the clipboard to a temporary file to attach it to the next prompt.
+fn paste_image(config: &GlobalConfig) -> Result<()> {
+    let path = temp_file("-clipboard-", ".png");
+    let (width, height) = save_image(&path)?;
+    let mut config = config.write();
+    config.pasted_images.push(path.display().to_string());
+    let count = config.pasted_images.len();
+    println!(
+        "{}",
+        dimmed_text(&format!(
+            "Pasted a {width}x{height} image; {count} image(s) will be attached to the next message"
+        ))
+    );
+    Ok(())
+}
+
+/// Runs a command entered with `!` in the user's shell, showing its output as it comes, then
+/// offers to attach that output to the next prompt.
+fn run_shell_command(config: &GlobalConfig, cmd: &str) -> Result<()> {
+    if cmd.is_empty() {
+        println!("Usage: !<command>");
+        return Ok(());
+    }
+    let mut reader = duct::cmd(&SHELL.cmd, [SHELL.arg.as_str(), cmd])
+        .stderr_to_stdout()
+        .unchecked()
+        .reader()
+        .with_context(|| format!("Failed to run `{cmd}`"))?;
+    let mut stdout = io::stdout();
+    let mut output = vec![];
+    let mut buf = [0; 4096];
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        stdout.write_all(&buf[..len])?;
+        stdout.flush()?;
+        output.extend_from_slice(&buf[..len]);
+    }
+    if let Some(code) = reader
+        .try_wait()?
+        .and_then(|ret| ret.status.code())
+        .filter(|code| *code != 0)
+    {
+        println!("{}", dimmed_text(&format!("Exited with code {code}")));
+    }
+    let output = String::from_utf8_lossy(&output).trim_end().to_string();
+    if output.is_empty() {
+        return Ok(());
+    }
+    let attach = Confirm::new("Attach the output to the next prompt?")
+        .with_default(false)
+        .prompt()?;
+    if attach {
+        config.write().shell_output = Some((cmd.to_string(), output));
+    }
+    Ok(())
+}
+
+fn ensure_todo_enabled(agent: &Agent) -> Result<()> {
+    if !agent.auto_continue_enabled() {
+        bail!(
+            "The todo system is not enabled for this agent. Set 'auto_continue: true' in the agent's config.yaml to enable it."
+        );
+    }
+    Ok(())
+}
+
 fn unknown_command() -> Result<()> {
     bail!(r#"Unknown command. Type ".help" for additional help."#);
 }
@@ -1067,11 +2164,23 @@ fn dump_repl_help() {
         r###"{head}
 
 Type ::: to start multi-line editing, type ::: to finish it.
+Type !<command> to run a shell command and optionally attach its output to the next prompt.
 Press Ctrl+O to open an editor for editing the input buffer.
+Press Ctrl+R to search the history, Ctrl+Space to open the command palette.
+Press Alt+V to attach the image in the clipboard to the next message.
 Press Ctrl+C to cancel the response, Ctrl+D to exit the REPL."###,
     );
 }
 
+/// Replaces an alias at the start of the line with what it stands for. Aliases aren't expanded
+/// again, so one can't refer to another.
+fn expand_alias(aliases: &IndexMap<String, String>, line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    let expansion = aliases.get(&trimmed[..end])?;
+    Some(format!("{expansion}{}", &trimmed[end..]))
+}
+
 fn parse_command(line: &str) -> Option<(&str, Option<&str>)> {
     match COMMAND_RE.captures(line) {
         Ok(Some(captures)) => {
@@ -1084,6 +2193,240 @@ fn parse_command(line: &str) -> Option<(&str, Option<&str>)> {
     }
 }
 
+/// Picks the code block in `output` to copy, as `.copy code` or `.copy <n>`. Returns it along
+/// with its number, or `None` if nothing was picked.
+fn pick_code_block(output: &str, args: &str) -> Result<Option<(usize, CodeBlock)>> {
+    let mut blocks = extract_code_blocks(&strip_think_tag(output));
+    if blocks.is_empty() {
+        bail!("No code blocks in the last chat response");
+    }
+    let index = match args {
+        "code" if blocks.len() == 1 => Some(0),
+        "code" => {
+            let options = blocks
+                .iter()
+                .enumerate()
+                .map(|(i, block)| format!("{}. {}", i + 1, block.summary()))
+                .collect();
+            Select::new("Copy code block:", options)
+                .raw_prompt_skippable()?
+                .map(|option| option.index)
+        }
+        _ => match args.parse::<usize>() {
+            Ok(n) if (1..=blocks.len()).contains(&n) => Some(n - 1),
+            Ok(n) => bail!(
+                "Code block {n} not found; the last chat response has {}",
+                blocks.len()
+            ),
+            Err(_) => {
+                println!(
+                    r#"Usage:
+    .copy                           # Copy the last response
+    .copy code                      # Pick a code block in the last response to copy
+    .copy <n>                       # Copy the nth code block in the last response"#
+                );
+                None
+            }
+        },
+    };
+    Ok(index.map(|i| (i + 1, blocks.swap_remove(i))))
+}
+
+/// Writes code blocks in the last response to files, asking where each one goes (starting from
+/// the filename the response gave it, if any) and, when the file exists, showing what would
+/// change before overwriting it.
+/// Renders a Mermaid or Graphviz block in the last response to an image with `mmdc` or `dot`,
+/// shows it, and offers to save it. Without the renderer installed, the diagram is approximated
+/// as text instead.
+fn show_diagram(config: &GlobalConfig, args: Option<&str>) -> Result<()> {
+    let output = match config
+        .read()
+        .last_message
+        .as_ref()
+        .filter(|v| !v.output.is_empty())
+        .map(|v| v.output.clone())
+    {
+        Some(v) => v,
+        None => bail!("No chat response to render a diagram from"),
+    };
+    let mut diagrams = diagram_blocks(extract_code_blocks(&strip_think_tag(&output)));
+    if diagrams.is_empty() {
+        bail!("No Mermaid or Graphviz diagrams in the last chat response");
+    }
+    let index = match args {
+        Some(args) => match args.parse::<usize>() {
+            Ok(n) if (1..=diagrams.len()).contains(&n) => n - 1,
+            Ok(n) => bail!(
+                "Diagram {n} not found; the last chat response has {}",
+                diagrams.len()
+            ),
+            Err(_) => {
+                println!(
+                    r#"Usage:
+    .diagram                        # Render a diagram in the last response
+    .diagram <n>                    # Render the nth diagram in the last response"#
+                );
+                return Ok(());
+            }
+        },
+        None if diagrams.len() == 1 => 0,
+        None => {
+            let options = diagrams
+                .iter()
+                .enumerate()
+                .map(|(i, (_, block))| format!("{}. {}", i + 1, block.summary()))
+                .collect();
+            match Select::new("Render diagram:", options).raw_prompt_skippable()? {
+                Some(option) => option.index,
+                None => return Ok(()),
+            }
+        }
+    };
+    let (kind, block) = diagrams.swap_remove(index);
+    if !kind.is_renderer_installed() {
+        println!(
+            "{}\n",
+            dimmed_text(&format!(
+                "'{}' isn't installed, so this is an approximation of the diagram:",
+                kind.renderer()
+            ))
+        );
+        println!("{}", ascii_diagram(kind, &block.code));
+        return Ok(());
+    }
+
+    let preview = temp_file("-diagram-", ".png");
+    render_diagram(kind, &block.code, &preview)?;
+    print_image_file(config, &preview)?;
+    let path = Text::new("Save the diagram to (Esc to skip):")
+        .with_initial_value("diagram.png")
+        .prompt_skippable()?;
+    let path = match path {
+        Some(path) if !path.trim().is_empty() => PathBuf::from(resolve_home_dir(path.trim())),
+        _ => return Ok(()),
+    };
+    ensure_parent_exists(&path)?;
+    if path
+        .extension()
+        .is_some_and(|v| v.eq_ignore_ascii_case("png"))
+    {
+        fs::copy(&preview, &path)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    } else {
+        render_diagram(kind, &block.code, &path)?;
+    }
+    println!("✓ Saved the diagram to {}.", path.display());
+    Ok(())
+}
+
+fn save_code_blocks(config: &GlobalConfig, args: Option<&str>) -> Result<()> {
+    let output = match config
+        .read()
+        .last_message
+        .as_ref()
+        .filter(|v| !v.output.is_empty())
+        .map(|v| v.output.clone())
+    {
+        Some(v) => v,
+        None => bail!("No chat response to save code from"),
+    };
+    let blocks = extract_code_blocks(&strip_think_tag(&output));
+    if blocks.is_empty() {
+        bail!("No code blocks in the last chat response");
+    }
+    let indexes: Vec<usize> = match args {
+        Some(args) => match args.parse::<usize>() {
+            Ok(n) if (1..=blocks.len()).contains(&n) => vec![n - 1],
+            Ok(n) => bail!(
+                "Code block {n} not found; the last chat response has {}",
+                blocks.len()
+            ),
+            Err(_) => {
+                println!(
+                    r#"Usage:
+    .save code                      # Pick code blocks in the last response to save
+    .save code <n>                  # Save the nth code block in the last response"#
+                );
+                return Ok(());
+            }
+        },
+        None if blocks.len() == 1 => vec![0],
+        None => {
+            let options = blocks
+                .iter()
+                .enumerate()
+                .map(|(i, block)| format!("{}. {}", i + 1, block.summary()))
+                .collect();
+            MultiSelect::new("Save code blocks:", options)
+                .with_page_size(PICKER_PAGE_SIZE)
+                .raw_prompt_skippable()?
+                .map(|options| options.into_iter().map(|v| v.index).collect())
+                .unwrap_or_default()
+        }
+    };
+
+    for i in indexes {
+        let block = &blocks[i];
+        let message = format!("Save code block {} to:", i + 1);
+        let mut prompt = Text::new(&message);
+        if let Some(filename) = &block.filename {
+            prompt = prompt.with_initial_value(filename);
+        }
+        let path = match prompt.prompt_skippable()? {
+            Some(path) if !path.trim().is_empty() => PathBuf::from(resolve_home_dir(path.trim())),
+            _ => continue,
+        };
+        if path.exists() {
+            let existing = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read '{}'", path.display()))?;
+            if existing == block.code {
+                println!("{} is already up to date.", path.display());
+                continue;
+            }
+            println!("{}", render_word_diff(&existing, &block.code));
+            let overwrite = Confirm::new(&format!("Overwrite {}?", path.display()))
+                .with_default(false)
+                .prompt()?;
+            if !overwrite {
+                continue;
+            }
+        }
+        ensure_parent_exists(&path)?;
+        fs::write(&path, &block.code)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+        println!("✓ Saved code block {} to {}.", i + 1, path.display());
+    }
+    Ok(())
+}
+
+/// Parses the `--model <model>` and `--temperature <value>` overrides of `.regenerate`.
+fn parse_regenerate_args(args: Option<&str>) -> Result<(Option<String>, Option<f64>)> {
+    const USAGE: &str = "Usage: .regenerate [--model <model>] [--temperature <value>]";
+    let (mut model_id, mut temperature) = (None, None);
+    let mut args = args.unwrap_or_default().split_whitespace();
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, args.next()),
+        };
+        let Some(value) = value else {
+            bail!("Missing value for '{name}'\n{USAGE}")
+        };
+        match name {
+            "--model" => model_id = Some(value.to_string()),
+            "--temperature" => {
+                temperature = Some(
+                    value
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid temperature '{value}'"))?,
+                )
+            }
+            _ => bail!("Unknown argument '{name}'\n{USAGE}"),
+        }
+    }
+    Ok((model_id, temperature))
+}
+
 fn split_first_arg(args: Option<&str>) -> Option<(&str, Option<&str>)> {
     args.map(|v| match v.split_once(' ') {
         Some((subcmd, args)) => (subcmd, Some(args.trim())),
@@ -1167,6 +2510,36 @@ pub fn split_args_text(line: &str, is_win: bool) -> (Vec<String>, &str) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_regenerate_args() {
+        assert_eq!(parse_regenerate_args(None).unwrap(), (None, None));
+        assert_eq!(
+            parse_regenerate_args(Some("--model openai:gpt-4o --temperature=0.2")).unwrap(),
+            (Some("openai:gpt-4o".to_string()), Some(0.2))
+        );
+        assert!(parse_regenerate_args(Some("--temperature")).is_err());
+        assert!(parse_regenerate_args(Some("--temperature hot")).is_err());
+        assert!(parse_regenerate_args(Some("--top-p 0.5")).is_err());
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let aliases = IndexMap::from([
+            (".rs".to_string(), ".role shell".to_string()),
+            (".gg".to_string(), ".agent git-helper".to_string()),
+        ]);
+        assert_eq!(
+            expand_alias(&aliases, " .rs list files"),
+            Some(".role shell list files".to_string())
+        );
+        assert_eq!(
+            expand_alias(&aliases, ".gg"),
+            Some(".agent git-helper".to_string())
+        );
+        assert_eq!(expand_alias(&aliases, ".rsx"), None);
+        assert_eq!(expand_alias(&aliases, "what is .rs"), None);
+    }
+
     #[test]
     fn test_process_command_line() {
         assert_eq!(parse_command(" ."), Some((".", None)));
@@ -0,0 +1,80 @@
+use super::REPL_COMMANDS;
+
+use crate::config::Config;
+
+use std::fmt;
+
+const NAME_WIDTH: usize = 20;
+/// How many of a command's argument values are shown as a hint of what it takes.
+const HINT_MAX_VALUES: usize = 3;
+
+/// A command in the command palette: one of the REPL's dot-commands, or one of the active
+/// agent's slash commands.
+#[derive(Debug, Clone)]
+pub struct PaletteEntry {
+    pub name: String,
+    description: String,
+    hint: Option<String>,
+}
+
+impl PaletteEntry {
+    /// The commands that can be run right now, with hints drawn from their completions.
+    pub fn list(config: &Config) -> Vec<Self> {
+        let state = config.state();
+        let mut entries: Vec<Self> = REPL_COMMANDS
+            .iter()
+            .filter(|cmd| cmd.is_valid(state))
+            .map(|cmd| Self {
+                name: cmd.name.to_string(),
+                description: cmd.description.to_string(),
+                hint: Self::hint(config, cmd.name),
+            })
+            .collect();
+        if let Some(agent) = config.agent.as_ref() {
+            entries.extend(agent.commands().iter().map(|cmd| Self {
+                name: format!("/{}", cmd.name),
+                description: cmd.description.clone(),
+                hint: None,
+            }));
+        }
+        entries
+    }
+
+    /// The values the command's argument can be completed with, to pick one from.
+    pub fn arguments(&self, config: &Config) -> Vec<(String, Option<String>)> {
+        if self.name.contains(' ') || !self.name.starts_with('.') {
+            return vec![];
+        }
+        config.repl_complete(&self.name, &[""], "")
+    }
+
+    fn hint(config: &Config, name: &str) -> Option<String> {
+        if name.contains(' ') {
+            return None;
+        }
+        let values = config.repl_complete(name, &[""], "");
+        let mut hint = values
+            .iter()
+            .take(HINT_MAX_VALUES)
+            .map(|(value, _)| value.trim())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if hint.is_empty() {
+            return None;
+        }
+        if values.len() > HINT_MAX_VALUES {
+            hint.push_str(", …");
+        }
+        Some(hint)
+    }
+}
+
+impl fmt::Display for PaletteEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<NAME_WIDTH$} {}", self.name, self.description)?;
+        if let Some(hint) = &self.hint {
+            write!(f, " ({hint})")?;
+        }
+        Ok(())
+    }
+}
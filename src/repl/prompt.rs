@@ -1,5 +1,7 @@
 use crate::config::GlobalConfig;
+use crate::utils::{display_width, truncate_to_width};
 
+use crossterm::terminal;
 use reedline::{Prompt, PromptHistorySearch, PromptHistorySearchStatus};
 use std::borrow::Cow;
 
@@ -21,8 +23,18 @@ impl Prompt for ReplPrompt {
         Cow::Owned(self.config.read().render_prompt_left())
     }
 
+    /// The right prompt is shortened to fit beside the left one, measuring both by the columns
+    /// they take up, since the line editor leaves it out altogether when it doesn't fit.
     fn render_prompt_right(&self) -> Cow<'_, str> {
-        Cow::Owned(self.config.read().render_prompt_right())
+        let config = self.config.read();
+        let right_prompt = config.render_prompt_right();
+        let Ok((columns, _)) = terminal::size() else {
+            return Cow::Owned(right_prompt);
+        };
+        let left_prompt = config.render_prompt_left();
+        let left_width = left_prompt.lines().last().map_or(0, display_width);
+        let available = (columns as usize).saturating_sub(left_width + 1);
+        Cow::Owned(truncate_to_width(&right_prompt, available))
     }
 
     fn render_prompt_indicator(&self, _prompt_mode: reedline::PromptEditMode) -> Cow<'_, str> {
@@ -0,0 +1,357 @@
+use super::REGISTER_COMMAND;
+
+use crossterm::event::{Event, KeyEvent};
+use reedline::{
+    EditCommand, EditMode, KeyCode, KeyModifiers, PromptEditMode, PromptViMode, Reedline,
+    ReedlineEvent, ReedlineRawEvent, Vi,
+};
+use std::collections::HashMap;
+use std::mem;
+
+/// The keys that start a command which puts text in the unnamed register.
+const YANKING_KEYS: [char; 8] = ['y', 'd', 'c', 'x', 's', 'D', 'C', 'S'];
+
+/// Reedline's vi mode with vim's named registers on top. `"a` to `"z` before a yank, delete, or
+/// change also keeps the text in that register, an uppercase name appends to it, and `"ap` or
+/// `"aP` pastes it.
+pub struct RegisterVi {
+    vi: Vi,
+    state: RegisterState,
+}
+
+enum RegisterState {
+    Idle,
+    /// `"` was typed, so the next key names the register.
+    Naming,
+    /// A register was named, and these keys of the command have been typed since.
+    Named {
+        name: char,
+        keys: String,
+    },
+}
+
+impl RegisterVi {
+    pub fn new(vi: Vi) -> Self {
+        Self {
+            vi,
+            state: RegisterState::Idle,
+        }
+    }
+
+    fn forward(&mut self, event: Event) -> ReedlineEvent {
+        match ReedlineRawEvent::try_from(event) {
+            Ok(event) => self.vi.parse_event(event),
+            Err(_) => ReedlineEvent::None,
+        }
+    }
+}
+
+impl EditMode for RegisterVi {
+    fn parse_event(&mut self, event: ReedlineRawEvent) -> ReedlineEvent {
+        let event = Event::from(event);
+        if !matches!(
+            self.vi.edit_mode(),
+            PromptEditMode::Vi(PromptViMode::Normal)
+        ) {
+            self.state = RegisterState::Idle;
+            return self.forward(event);
+        }
+        match (
+            mem::replace(&mut self.state, RegisterState::Idle),
+            typed_char(&event),
+        ) {
+            // Reedline has no use for `"`, so when it throws the key away rather than finishing
+            // a pending command with it (e.g. `f"`), the next key is a register name
+            (RegisterState::Idle, Some('"')) => {
+                let parsed = self.forward(event);
+                if matches!(parsed, ReedlineEvent::None) {
+                    self.state = RegisterState::Naming;
+                }
+                parsed
+            }
+            (RegisterState::Naming, Some(name)) => {
+                if name.is_ascii_alphabetic() {
+                    self.state = RegisterState::Named {
+                        name,
+                        keys: String::new(),
+                    };
+                }
+                ReedlineEvent::None
+            }
+            (RegisterState::Named { name, keys }, Some(key @ ('p' | 'P'))) if keys.is_empty() => {
+                let action = if key == 'p' {
+                    "put-after"
+                } else {
+                    "put-before"
+                };
+                register_command(action, name)
+            }
+            (RegisterState::Named { name, mut keys }, Some(key)) => {
+                keys.push(key);
+                let parsed = self.forward(event);
+                if matches!(parsed, ReedlineEvent::None) {
+                    self.state = RegisterState::Named { name, keys };
+                    parsed
+                } else if keys
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .starts_with(YANKING_KEYS)
+                {
+                    ReedlineEvent::Multiple(vec![parsed, register_command("yank", name)])
+                } else {
+                    parsed
+                }
+            }
+            (_, _) => self.forward(event),
+        }
+    }
+
+    fn edit_mode(&self) -> PromptEditMode {
+        self.vi.edit_mode()
+    }
+}
+
+/// The character a key types in vi normal mode, if it's a plain or shifted character key.
+fn typed_char(event: &Event) -> Option<char> {
+    match event {
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::NONE,
+            ..
+        }) => Some(*c),
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::SHIFT,
+            ..
+        }) => Some(c.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+fn register_command(action: &str, name: char) -> ReedlineEvent {
+    ReedlineEvent::ExecuteHostCommand(format!("{REGISTER_COMMAND}{action}:{name}"))
+}
+
+/// The contents of the vi named registers, filled and pasted through the host commands
+/// [`RegisterVi`] returns.
+#[derive(Debug, Default)]
+pub struct Registers(HashMap<char, Register>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+impl Registers {
+    /// Runs a host command [`RegisterVi`] returned on the editor's buffer.
+    pub fn run(&mut self, editor: &mut Reedline, command: &str) {
+        let Some((action, name)) = command
+            .strip_prefix(REGISTER_COMMAND)
+            .and_then(|command| command.split_once(':'))
+        else {
+            return;
+        };
+        let Some(name) = name.chars().next() else {
+            return;
+        };
+        match action {
+            "yank" => self.yank(editor, name),
+            "put-after" => self.put(editor, name, false),
+            "put-before" => self.put(editor, name, true),
+            _ => {}
+        }
+    }
+
+    /// Copies the unnamed register into the named one. Reedline doesn't expose its cut buffer,
+    /// so it's pasted at the start of the buffer, read back, and undone.
+    fn yank(&mut self, editor: &mut Reedline, name: char) {
+        let position = editor.current_insertion_point();
+        let len = editor.current_buffer_contents().len();
+        editor.run_edit_commands(&[
+            EditCommand::MoveToStart { select: false },
+            EditCommand::PasteCutBufferBefore,
+        ]);
+        let buffer = editor.current_buffer_contents();
+        let text = buffer[..buffer.len() - len].to_string();
+        editor.run_edit_commands(&[
+            EditCommand::Undo,
+            EditCommand::MoveToPosition {
+                position,
+                select: false,
+            },
+        ]);
+        let linewise = text.ends_with('\n');
+        let register = Register { text, linewise };
+        if name.is_ascii_uppercase() {
+            self.0
+                .entry(name.to_ascii_lowercase())
+                .and_modify(|existing| {
+                    existing.text.push_str(&register.text);
+                    existing.linewise |= register.linewise;
+                })
+                .or_insert(register);
+        } else {
+            self.0.insert(name, register);
+        }
+    }
+
+    /// Pastes a named register after or before the cursor, or below or above the current line
+    /// if it holds whole lines.
+    fn put(&self, editor: &mut Reedline, name: char, before: bool) {
+        let Some(Register { text, linewise }) = self.0.get(&name.to_ascii_lowercase()) else {
+            return;
+        };
+        let buffer = editor.current_buffer_contents();
+        let position = editor.current_insertion_point();
+        let (at, text, cursor) = if *linewise {
+            let line_start = buffer[..position].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = buffer[position..]
+                .find('\n')
+                .map_or(buffer.len(), |i| position + i);
+            if before {
+                (line_start, text.clone(), line_start)
+            } else {
+                let text = format!("\n{}", text.trim_end_matches('\n'));
+                (line_end, text, line_end + 1)
+            }
+        } else {
+            let at = if before {
+                position
+            } else {
+                buffer[position..]
+                    .chars()
+                    .next()
+                    .map_or(position, |c| position + c.len_utf8())
+            };
+            let last_char_len = text.chars().next_back().map_or(0, char::len_utf8);
+            (at, text.clone(), at + text.len() - last_char_len)
+        };
+        editor.run_edit_commands(&[
+            EditCommand::MoveToPosition {
+                position: at,
+                select: false,
+            },
+            EditCommand::InsertString(text),
+            EditCommand::MoveToPosition {
+                position: cursor,
+                select: false,
+            },
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reedline::{default_vi_insert_keybindings, default_vi_normal_keybindings};
+
+    fn press(vi: &mut RegisterVi, keys: &str) -> Vec<ReedlineEvent> {
+        keys.chars()
+            .map(|c| {
+                let code = if c == '\x1b' {
+                    KeyCode::Esc
+                } else {
+                    KeyCode::Char(c)
+                };
+                let event = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+                vi.parse_event(ReedlineRawEvent::try_from(event).unwrap())
+            })
+            .filter(|event| !matches!(event, ReedlineEvent::None))
+            .collect()
+    }
+
+    fn editor_with(buffer: &str, position: usize) -> Reedline {
+        let mut editor = Reedline::create();
+        editor.run_edit_commands(&[
+            EditCommand::InsertString(buffer.into()),
+            EditCommand::MoveToPosition {
+                position,
+                select: false,
+            },
+        ]);
+        editor
+    }
+
+    fn host_commands(events: &[ReedlineEvent]) -> Vec<String> {
+        events
+            .iter()
+            .flat_map(|event| match event {
+                ReedlineEvent::Multiple(events) => events.clone(),
+                event => vec![event.clone()],
+            })
+            .filter_map(|event| match event {
+                ReedlineEvent::ExecuteHostCommand(command) => Some(command),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_register_vi() {
+        let mut vi = RegisterVi::new(Vi::new(
+            default_vi_insert_keybindings(),
+            default_vi_normal_keybindings(),
+        ));
+        assert_eq!(
+            host_commands(&press(&mut vi, "\x1b\"ayiw")),
+            ["\0register-yank:a"]
+        );
+        assert_eq!(
+            host_commands(&press(&mut vi, "\"Bdw")),
+            ["\0register-yank:B"]
+        );
+        assert_eq!(
+            host_commands(&press(&mut vi, "\"ap\"aP")),
+            ["\0register-put-after:a", "\0register-put-before:a"]
+        );
+        // A register isn't kept for motions, and `"` is still a valid target for `f`
+        assert!(host_commands(&press(&mut vi, "\"aw")).is_empty());
+        assert!(host_commands(&press(&mut vi, "f\"yiw")).is_empty());
+        // Insert mode types `"` as is
+        assert!(host_commands(&press(&mut vi, "i\"ap")).is_empty());
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut registers = Registers::default();
+        let mut editor = editor_with("one two three", 4);
+        editor.run_edit_commands(&[EditCommand::CutWordRightToNext]);
+        registers.run(&mut editor, "\0register-yank:a");
+        assert_eq!(editor.current_buffer_contents(), "one three");
+        assert_eq!(editor.current_insertion_point(), 4);
+        registers.run(&mut editor, "\0register-put-before:a");
+        assert_eq!(editor.current_buffer_contents(), "one two three");
+
+        editor.run_edit_commands(&[EditCommand::CutCurrentLine]);
+        registers.run(&mut editor, "\0register-yank:A");
+        assert_eq!(
+            registers.0.get(&'a'),
+            Some(&Register {
+                text: "two one two three\n".into(),
+                linewise: true,
+            })
+        );
+
+        let mut editor = editor_with("first\nsecond", 2);
+        registers.0.insert(
+            'b',
+            Register {
+                text: "line\n".into(),
+                linewise: true,
+            },
+        );
+        registers.run(&mut editor, "\0register-put-after:b");
+        assert_eq!(editor.current_buffer_contents(), "first\nline\nsecond");
+        assert_eq!(editor.current_insertion_point(), 6);
+        registers.run(&mut editor, "\0register-put-before:b");
+        assert_eq!(
+            editor.current_buffer_contents(),
+            "first\nline\nline\nsecond"
+        );
+        registers.run(&mut editor, "\0register-put-after:z");
+        assert_eq!(
+            editor.current_buffer_contents(),
+            "first\nline\nline\nsecond"
+        );
+    }
+}
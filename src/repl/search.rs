@@ -0,0 +1,156 @@
+use crate::config::Config;
+use crate::utils::{color_text, dimmed_text};
+
+use anyhow::{Context, Result};
+use fancy_regex::Regex;
+use nu_ansi_term::Color;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// How many characters of a matching line are kept on either side of the match.
+const SNIPPET_CONTEXT: usize = 40;
+
+/// A line matching a `.search` query, in a saved session or in `messages.md`.
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    pub session: Option<String>,
+    pub path: PathBuf,
+    pub line_number: usize,
+    line: String,
+    range: Range<usize>,
+}
+
+impl SearchMatch {
+    /// The matching line, cut down to the text around the match, with the match highlighted.
+    pub fn snippet(&self) -> String {
+        let start = char_boundary_before(&self.line, self.range.start, SNIPPET_CONTEXT);
+        let end = char_boundary_after(&self.line, self.range.end, SNIPPET_CONTEXT);
+        format!(
+            "{}{}{}{}{}",
+            if start > 0 { "…" } else { "" },
+            &self.line[start..self.range.start],
+            color_text(&self.line[self.range.clone()], Color::Red),
+            &self.line[self.range.end..end],
+            if end < self.line.len() { "…" } else { "" },
+        )
+    }
+}
+
+/// Searches the saved sessions and `messages.md` for lines containing `query`. Like ripgrep, the
+/// search ignores case unless the query has an uppercase letter.
+pub fn search(config: &Config, query: &str) -> Result<Vec<SearchMatch>> {
+    let case = if query.chars().any(char::is_uppercase) {
+        ""
+    } else {
+        "(?i)"
+    };
+    let re = Regex::new(&format!("{case}{}", fancy_regex::escape(query)))
+        .with_context(|| format!("Invalid search '{query}'"))?;
+    let autoname_sessions = config
+        .list_autoname_sessions()
+        .into_iter()
+        .map(|name| format!("_/{name}"));
+    let mut matches = vec![];
+    for name in config.list_sessions().into_iter().chain(autoname_sessions) {
+        let path = config.session_file(&name);
+        matches.extend(search_file(config, &re, &path, Some(&name)));
+    }
+    matches.extend(search_file(config, &re, &config.messages_file(), None));
+    Ok(matches)
+}
+
+/// Lists the matches grouped by file, each line prefixed with its line number.
+pub fn render_matches(matches: &[SearchMatch]) -> String {
+    let mut output = vec![];
+    let mut current: Option<&Path> = None;
+    for item in matches {
+        if current != Some(item.path.as_path()) {
+            if current.is_some() {
+                output.push(String::new());
+            }
+            let title = match &item.session {
+                Some(name) => format!("session {name} ({})", item.path.display()),
+                None => item.path.display().to_string(),
+            };
+            output.push(color_text(&title, Color::Magenta));
+            current = Some(item.path.as_path());
+        }
+        output.push(format!(
+            "{}{}{}",
+            color_text(&item.line_number.to_string(), Color::Green),
+            dimmed_text(":"),
+            item.snippet()
+        ));
+    }
+    output.join("\n")
+}
+
+fn search_file(
+    config: &Config,
+    re: &Regex,
+    path: &Path,
+    session: Option<&str>,
+) -> Vec<SearchMatch> {
+    let Ok(contents) = config.read_session_file(path) else {
+        return vec![];
+    };
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let found = re.find(line).ok().flatten()?;
+            Some(SearchMatch {
+                session: session.map(String::from),
+                path: path.to_path_buf(),
+                line_number: index + 1,
+                line: line.trim_end().to_string(),
+                range: found.range(),
+            })
+        })
+        .collect()
+}
+
+fn char_boundary_before(text: &str, index: usize, chars: usize) -> usize {
+    text[..index]
+        .char_indices()
+        .rev()
+        .nth(chars.saturating_sub(1))
+        .map_or(0, |(i, _)| i)
+}
+
+fn char_boundary_after(text: &str, index: usize, chars: usize) -> usize {
+    text[index..]
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| index + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippet() {
+        let line = format!("{}needle{}", "a".repeat(50), "b".repeat(50));
+        let item = SearchMatch {
+            session: None,
+            path: PathBuf::new(),
+            line_number: 1,
+            range: 50..56,
+            line,
+        };
+        let snippet = item.snippet();
+        assert!(snippet.starts_with(&format!("…{}", "a".repeat(SNIPPET_CONTEXT))));
+        assert!(snippet.ends_with(&format!("{}…", "b".repeat(SNIPPET_CONTEXT))));
+
+        let item = SearchMatch {
+            line: "é needle ü".to_string(),
+            range: 3..9,
+            ..item
+        };
+        assert!(item.snippet().starts_with("é "));
+        assert!(item.snippet().ends_with(" ü"));
+    }
+}
@@ -0,0 +1,155 @@
+use super::history::SavedSession;
+
+use crate::client::Message;
+use crate::config::{Config, TEMP_SESSION_NAME};
+use crate::utils::dimmed_text;
+
+use chrono::{DateTime, Local};
+use std::{fmt, fs::metadata, path::Path, time::SystemTime};
+
+const LABEL_WIDTH: usize = 36;
+const PREVIEW_MAX_LINES: usize = 20;
+
+/// A saved session to pick with `.session`, or a new temporary one.
+#[derive(Debug, Clone)]
+pub struct SessionEntry {
+    pub name: String,
+    label: String,
+    modified: Option<SystemTime>,
+    tokens: usize,
+    last_exchange: Option<(String, String)>,
+    branched_from: Option<String>,
+}
+
+impl SessionEntry {
+    /// A new temporary session, followed by the saved sessions, most recently active first.
+    pub fn list(config: &Config) -> Vec<Self> {
+        let autoname_sessions = config
+            .list_autoname_sessions()
+            .into_iter()
+            .map(|name| format!("_/{name}"));
+        let mut entries: Vec<Self> = config
+            .list_sessions()
+            .into_iter()
+            .chain(autoname_sessions)
+            .map(|name| Self::load(config, name))
+            .collect();
+        entries.sort_by(|a, b| b.modified.cmp(&a.modified));
+        entries.insert(
+            0,
+            Self {
+                name: TEMP_SESSION_NAME.to_string(),
+                label: "(new temporary session)".to_string(),
+                modified: None,
+                tokens: 0,
+                last_exchange: None,
+                branched_from: None,
+            },
+        );
+        entries
+    }
+
+    fn load(config: &Config, name: String) -> Self {
+        let path = config.session_file(&name);
+        // Autonamed sessions are saved as `_/<YYYYMMDDTHHMMSS>-<autoname>`
+        let label = match name.strip_prefix("_/") {
+            Some(autoname) => autoname.get(16..).unwrap_or(autoname).to_string(),
+            None => name.clone(),
+        };
+        let session = load_session(config, &path);
+        Self {
+            label,
+            modified: metadata(&path).and_then(|v| v.modified()).ok(),
+            tokens: config.model.total_tokens(&session.messages),
+            last_exchange: last_exchange(&session.messages),
+            branched_from: session.branched_from,
+            name,
+        }
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.modified.is_some()
+    }
+
+    /// The last message sent in the session and the reply to it.
+    pub fn preview(&self) -> String {
+        let branch = self
+            .branched_from
+            .as_ref()
+            .map(|parent| format!(" · branch of {parent}"))
+            .unwrap_or_default();
+        let mut lines = vec![dimmed_text(&format!(
+            "── {}{branch} · {} · {} tokens ──",
+            self.label,
+            self.last_activity(),
+            self.tokens
+        ))];
+        match &self.last_exchange {
+            Some((message, reply)) => {
+                lines.push(message.clone());
+                lines.push(dimmed_text("── reply ──"));
+                let mut reply_lines = reply.lines();
+                lines.extend(
+                    reply_lines
+                        .by_ref()
+                        .take(PREVIEW_MAX_LINES)
+                        .map(String::from),
+                );
+                if reply_lines.next().is_some() {
+                    lines.push(dimmed_text("…"));
+                }
+            }
+            None => lines.push(dimmed_text("(no messages)")),
+        }
+        lines.join("\n")
+    }
+
+    fn last_activity(&self) -> String {
+        self.modified
+            .map(|v| {
+                DateTime::<Local>::from(v)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for SessionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_saved() {
+            return write!(f, "{}", self.label);
+        }
+        write!(
+            f,
+            "{:<LABEL_WIDTH$} {}  {} tokens",
+            self.label,
+            self.last_activity(),
+            self.tokens
+        )?;
+        if let Some(parent) = &self.branched_from {
+            write!(f, "  ⑂ {parent}")?;
+        }
+        Ok(())
+    }
+}
+
+fn load_session(config: &Config, path: &Path) -> SavedSession {
+    config
+        .read_session_file(path)
+        .ok()
+        .and_then(|contents| serde_yaml::from_str::<SavedSession>(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn last_exchange(messages: &[Message]) -> Option<(String, String)> {
+    let index = messages.iter().rposition(|v| v.role.is_user())?;
+    let reply = messages[index + 1..]
+        .iter()
+        .rev()
+        .filter(|v| v.role.is_assistant())
+        .map(|v| v.content.to_text())
+        .find(|text| !text.is_empty())
+        .unwrap_or_default();
+    Some((messages[index].content.to_text(), reply))
+}
@@ -1,3 +1,5 @@
+use super::set_terminal_focused;
+
 use anyhow::Result;
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
 use std::{
@@ -69,18 +71,21 @@ pub async fn wait_abort_signal(abort_signal: &AbortSignal) {
 }
 
 pub fn poll_abort_signal(abort_signal: &AbortSignal) -> Result<bool> {
-    if event::poll(Duration::from_millis(25))?
-        && let Event::Key(key) = event::read()?
-    {
-        match key.code {
-            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
-                abort_signal.set_ctrlc();
-                return Ok(true);
-            }
-            KeyCode::Char('d') if key.modifiers == KeyModifiers::CONTROL => {
-                abort_signal.set_ctrld();
-                return Ok(true);
-            }
+    if event::poll(Duration::from_millis(25))? {
+        match event::read()? {
+            Event::Key(key) => match key.code {
+                KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
+                    abort_signal.set_ctrlc();
+                    return Ok(true);
+                }
+                KeyCode::Char('d') if key.modifiers == KeyModifiers::CONTROL => {
+                    abort_signal.set_ctrld();
+                    return Ok(true);
+                }
+                _ => {}
+            },
+            Event::FocusGained => set_terminal_focused(true),
+            Event::FocusLost => set_terminal_focused(false),
             _ => {}
         }
     }
@@ -1,9 +1,12 @@
 use anyhow::Context;
+use std::path::Path;
 
 #[cfg(not(any(target_os = "android", target_os = "emscripten")))]
 mod internal {
     use arboard::Clipboard;
     use base64::{Engine as _, engine::general_purpose::STANDARD};
+    use image::{ImageFormat, RgbaImage};
+    use std::path::Path;
     use std::sync::{LazyLock, Mutex};
 
     static CLIPBOARD: LazyLock<Mutex<Option<Clipboard>>> =
@@ -22,6 +25,37 @@ mod internal {
         }
     }
 
+    pub fn get_text() -> anyhow::Result<Option<String>> {
+        let mut clipboard = CLIPBOARD.lock().unwrap();
+        let clipboard = clipboard
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("No clipboard available"))?;
+        match clipboard.get_text() {
+            Ok(text) if !text.trim().is_empty() => Ok(Some(text)),
+            Ok(_) | Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save_image(path: &Path) -> anyhow::Result<(usize, usize)> {
+        let mut clipboard = CLIPBOARD.lock().unwrap();
+        let clipboard = clipboard
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("No clipboard available"))?;
+        let image = match clipboard.get_image() {
+            Ok(image) => image,
+            Err(arboard::Error::ContentNotAvailable) => {
+                anyhow::bail!("No image in the clipboard")
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (width, height) = (image.width, image.height);
+        let buffer = RgbaImage::from_raw(width as u32, height as u32, image.bytes.into_owned())
+            .ok_or_else(|| anyhow::anyhow!("Invalid image data in the clipboard"))?;
+        buffer.save_with_format(path, ImageFormat::Png)?;
+        Ok((width, height))
+    }
+
     /// Attempts to set text to clipboard with OSC52 escape sequence
     /// Works in many modern terminals, including over SSH.
     fn set_text_osc52(text: &str) -> anyhow::Result<()> {
@@ -42,8 +76,26 @@ mod internal {
     pub fn set_text(_text: &str) -> anyhow::Result<()> {
         Err(anyhow::anyhow!("No clipboard available"))
     }
+
+    pub fn get_text() -> anyhow::Result<Option<String>> {
+        Err(anyhow::anyhow!("No clipboard available"))
+    }
+
+    pub fn save_image(_path: &std::path::Path) -> anyhow::Result<(usize, usize)> {
+        Err(anyhow::anyhow!("No clipboard available"))
+    }
 }
 
 pub fn set_text(text: &str) -> anyhow::Result<()> {
     internal::set_text(text).context("Failed to copy")
 }
+
+/// The text in the clipboard, or `None` if it holds something else, like an image.
+pub fn get_text() -> anyhow::Result<Option<String>> {
+    internal::get_text().context("Failed to paste")
+}
+
+/// Saves the image in the clipboard to `path` as a PNG, returning its width and height.
+pub fn save_image(path: &Path) -> anyhow::Result<(usize, usize)> {
+    internal::save_image(path).context("Failed to paste the image")
+}
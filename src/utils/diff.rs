@@ -0,0 +1,135 @@
+use super::NO_COLOR;
+
+use nu_ansi_term::{Color, Style};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Above this many comparisons, the changed middle of the texts is shown as removed and re-added
+/// whole, rather than diffed word by word.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal(String),
+    Delete(String),
+    Insert(String),
+}
+
+/// Diffs two texts word by word, keeping the longest run of words they have in common.
+pub fn word_diff(old: &str, new: &str) -> Vec<DiffOp> {
+    let old: Vec<&str> = old.split_word_bounds().collect();
+    let new: Vec<&str> = new.split_word_bounds().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut ops = vec![];
+    push_op(&mut ops, DiffOp::Equal(old[..prefix].concat()));
+    if a.len() * b.len() > MAX_DIFF_CELLS {
+        push_op(&mut ops, DiffOp::Delete(a.concat()));
+        push_op(&mut ops, DiffOp::Insert(b.concat()));
+    } else {
+        // lcs[i * width + j] is the length of the longest common subsequence of a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                push_op(&mut ops, DiffOp::Equal(a[i].to_string()));
+                i += 1;
+                j += 1;
+            } else if j == b.len()
+                || (i < a.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                push_op(&mut ops, DiffOp::Delete(a[i].to_string()));
+                i += 1;
+            } else {
+                push_op(&mut ops, DiffOp::Insert(b[j].to_string()));
+                j += 1;
+            }
+        }
+    }
+    push_op(&mut ops, DiffOp::Equal(old[old.len() - suffix..].concat()));
+    ops
+}
+
+/// Shows the word diff of two texts, with removed words struck through in red and added words in
+/// green, or marked `[-like this-]` and `{+like this+}` without colors.
+pub fn render_word_diff(old: &str, new: &str) -> String {
+    word_diff(old, new)
+        .into_iter()
+        .map(|op| match (op, *NO_COLOR) {
+            (DiffOp::Equal(text), _) => text,
+            (DiffOp::Delete(text), true) => format!("[-{text}-]"),
+            (DiffOp::Insert(text), true) => format!("{{+{text}+}}"),
+            (DiffOp::Delete(text), false) => Style::new()
+                .fg(Color::Red)
+                .strikethrough()
+                .paint(text)
+                .to_string(),
+            (DiffOp::Insert(text), false) => Style::new().fg(Color::Green).paint(text).to_string(),
+        })
+        .collect()
+}
+
+/// Appends an op, merging it into the last one when they're the same kind.
+fn push_op(ops: &mut Vec<DiffOp>, op: DiffOp) {
+    let (DiffOp::Equal(text) | DiffOp::Delete(text) | DiffOp::Insert(text)) = &op;
+    if text.is_empty() {
+        return;
+    }
+    match (ops.last_mut(), &op) {
+        (Some(DiffOp::Equal(last)), DiffOp::Equal(text))
+        | (Some(DiffOp::Delete(last)), DiffOp::Delete(text))
+        | (Some(DiffOp::Insert(last)), DiffOp::Insert(text)) => last.push_str(text),
+        _ => ops.push(op),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_diff() {
+        assert_eq!(
+            word_diff("the quick brown fox", "the slow brown fox"),
+            vec![
+                DiffOp::Equal("the ".into()),
+                DiffOp::Delete("quick".into()),
+                DiffOp::Insert("slow".into()),
+                DiffOp::Equal(" brown fox".into()),
+            ]
+        );
+        assert_eq!(
+            word_diff("use a map here", "use a sorted map here, or a set"),
+            vec![
+                DiffOp::Equal("use a ".into()),
+                DiffOp::Insert("sorted ".into()),
+                DiffOp::Equal("map here".into()),
+                DiffOp::Insert(", or a set".into()),
+            ]
+        );
+        assert_eq!(
+            word_diff("same", "same"),
+            vec![DiffOp::Equal("same".into())]
+        );
+        assert_eq!(word_diff("", "new"), vec![DiffOp::Insert("new".into())]);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{error::Error, fmt};
+
+/// The status `loki` exits with when it fails, so scripts and CI can tell why. Usage errors exit
+/// with `2`, from clap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Anything not covered below
+    Error = 1,
+    /// The config, or a model, role, agent, or session named on the command line, is invalid
+    Config = 3,
+    /// A model provider couldn't be reached or returned an error
+    Network = 4,
+    /// A tool call couldn't be run
+    Tool = 5,
+    /// The user pressed Ctrl-C or Ctrl-D
+    Aborted = 130,
+}
+
+impl ExitCode {
+    /// Marks an error as having this exit code. The error prints the same as before.
+    pub fn tag(self, error: anyhow::Error) -> anyhow::Error {
+        TaggedError { code: self, error }.into()
+    }
+
+    /// The exit code for an error: aborting and network errors are recognized wherever they
+    /// happen, otherwise the innermost tag decides.
+    pub fn of(error: &anyhow::Error) -> Self {
+        if error.root_cause().to_string().starts_with("Aborted") {
+            return Self::Aborted;
+        }
+        let mut code = Self::Error;
+        for cause in error.chain() {
+            if cause.is::<reqwest::Error>() || cause.is::<reqwest_eventsource::Error>() {
+                return Self::Network;
+            }
+            // A tagged error's source skips the error it wraps, so that's checked on its own
+            if let Some(tagged) = cause.downcast_ref::<TaggedError>() {
+                match Self::of(&tagged.error) {
+                    Self::Error => code = tagged.code,
+                    inner => return inner,
+                }
+            }
+        }
+        code
+    }
+}
+
+/// An error with an exit code, which displays as the error it wraps.
+#[derive(Debug)]
+struct TaggedError {
+    code: ExitCode,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for TaggedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl Error for TaggedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn test_exit_code() {
+        let err = ExitCode::Config.tag(anyhow!("Unknown chat model 'foo'"));
+        assert_eq!(ExitCode::of(&err), ExitCode::Config);
+        assert_eq!(err.to_string(), "Unknown chat model 'foo'");
+
+        let err = ExitCode::Tool.tag(ExitCode::Config.tag(anyhow!("Invalid tool config")));
+        assert_eq!(ExitCode::of(&err), ExitCode::Config);
+        let err = ExitCode::Tool
+            .tag(anyhow!("Failed to run the tool"))
+            .context("Failed to call the model");
+        assert_eq!(ExitCode::of(&err), ExitCode::Tool);
+
+        let err = Err::<(), _>(anyhow!("Aborted!"))
+            .context("Failed to call the model")
+            .unwrap_err();
+        assert_eq!(ExitCode::of(&ExitCode::Tool.tag(err)), ExitCode::Aborted);
+        assert_eq!(ExitCode::of(&anyhow!("Something broke")), ExitCode::Error);
+    }
+}
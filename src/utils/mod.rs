@@ -2,38 +2,54 @@ mod abort_signal;
 mod clipboard;
 mod command;
 mod crypto;
+mod diff;
+mod exit_code;
 mod html_to_md;
 mod input;
 mod loader;
 mod logs;
 pub mod native;
+mod notification;
 mod path;
 mod render_prompt;
 mod request;
 mod spinner;
 mod variables;
+mod width;
 
 pub use self::abort_signal::*;
-pub use self::clipboard::set_text;
+pub use self::clipboard::{get_text, save_image, set_text};
 pub use self::command::*;
 pub use self::crypto::*;
+pub use self::diff::*;
+pub use self::exit_code::*;
 pub use self::html_to_md::*;
 pub use self::input::*;
 pub use self::loader::*;
 pub use self::logs::*;
+pub use self::notification::*;
 pub use self::path::*;
 pub use self::render_prompt::render_prompt;
 pub use self::request::*;
 pub use self::spinner::*;
 pub use self::variables::*;
+pub use self::width::*;
 
 use anyhow::{Context, Result};
 use fancy_regex::Regex;
 use fuzzy_matcher::{FuzzyMatcher, skim::SkimMatcherV2};
 use is_terminal::IsTerminal;
+use parking_lot::Mutex;
 use std::borrow::Cow;
 use std::sync::LazyLock;
-use std::{env, path::PathBuf, process};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    env,
+    fs::{DirBuilder, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    process,
+};
 use unicode_segmentation::UnicodeSegmentation;
 
 pub static CODE_BLOCK_RE: LazyLock<Regex> =
@@ -49,6 +65,19 @@ pub static NO_COLOR: LazyLock<bool> = LazyLock::new(|| {
         || !*IS_STDOUT_TERMINAL
 });
 
+static QUIET: AtomicBool = AtomicBool::new(false);
+static KEPT_TEMP_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Sets whether only the answer and errors are printed, without spinners, tool call summaries,
+/// or progress bars, for `--quiet`.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
 pub fn now() -> String {
     chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
 }
@@ -103,6 +132,135 @@ pub fn extract_code_block(text: &str) -> &str {
         .unwrap_or(text)
 }
 
+/// A fenced code block in a response, e.g. one the user wants to copy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    pub lang: String,
+    pub code: String,
+    /// The file the block is meant for, when the fence (e.g. ```` ```rust src/main.rs ````) or
+    /// the line before it (e.g. ``Create `src/main.rs`:``) names one.
+    pub filename: Option<String>,
+}
+
+impl CodeBlock {
+    /// A one-line description of the block, to pick it by.
+    pub fn summary(&self) -> String {
+        let first_line = self.code.lines().find(|v| !v.trim().is_empty());
+        let first_line = first_line.unwrap_or_default().trim();
+        let lines = match self.code.lines().count() {
+            1 => "1 line".to_string(),
+            n => format!("{n} lines"),
+        };
+        let summary = match self.lang.as_str() {
+            "" => format!("{first_line} ({lines})"),
+            lang => format!("[{lang}] {first_line} ({lines})"),
+        };
+        match &self.filename {
+            Some(filename) => format!("{summary} → {filename}"),
+            None => summary,
+        }
+    }
+}
+
+/// Extracts every fenced (```` ``` ```` or `~~~`) code block in `text`, in order. A block that's
+/// never closed, like one in a response that was cut off, runs to the end of the text.
+pub fn extract_code_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = vec![];
+    // The fence that opened the current block, how far it was indented, and the block so far
+    let mut current: Option<(&str, usize, CodeBlock)> = None;
+    // The last line of prose, which may name the file the next block is for
+    let mut prose_line = "";
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match current.as_mut() {
+            Some((fence, indent, block)) => {
+                let closing = trimmed.trim_end();
+                if closing.starts_with(*fence) && closing.chars().all(|c| fence.starts_with(c)) {
+                    blocks.extend(current.take().map(|(_, _, block)| block));
+                    prose_line = "";
+                    continue;
+                }
+                let strip = line.len() - line.trim_start_matches(' ').len();
+                block.code.push_str(&line[strip.min(*indent)..]);
+                block.code.push('\n');
+            }
+            None => {
+                let fence_len = ['`', '~']
+                    .iter()
+                    .map(|&c| trimmed.chars().take_while(|&v| v == c).count())
+                    .max()
+                    .unwrap_or_default();
+                if fence_len >= 3 {
+                    let (lang, fence_filename) = parse_fence_info(&trimmed[fence_len..]);
+                    let block = CodeBlock {
+                        lang,
+                        code: String::new(),
+                        filename: fence_filename.or_else(|| prose_filename(prose_line)),
+                    };
+                    current = Some((&trimmed[..fence_len], line.len() - trimmed.len(), block));
+                } else if !trimmed.is_empty() {
+                    prose_line = trimmed;
+                }
+            }
+        }
+    }
+    blocks.extend(current.map(|(_, _, block)| block));
+    blocks
+}
+
+/// Splits a fence's info string into the language and any filename it gives, as in
+/// `rust src/main.rs`, `rust:src/main.rs`, or `rust title="main.rs"`.
+fn parse_fence_info(info: &str) -> (String, Option<String>) {
+    let mut words = info.split_whitespace();
+    let first = words.next().unwrap_or_default();
+    let (lang, mut filename) = match first.split_once(':') {
+        Some((lang, path)) if looks_like_filename(path) => (lang, Some(path.to_string())),
+        _ => (first, None),
+    };
+    for word in words {
+        if filename.is_some() {
+            break;
+        }
+        let value = match word.split_once('=') {
+            Some(("title" | "file" | "filename" | "path", value)) => value.trim_matches('"'),
+            Some(_) => continue,
+            None => word,
+        };
+        if looks_like_filename(value) {
+            filename = Some(value.to_string());
+        }
+    }
+    (lang.to_string(), filename)
+}
+
+/// Finds a filename in the line introducing a code block, either in backticks or as the whole
+/// line (e.g. `**src/main.rs**` or `File: src/main.rs`).
+fn prose_filename(line: &str) -> Option<String> {
+    let quoted = line.split('`').skip(1).step_by(2);
+    let whole = line
+        .trim_start_matches(['#', '*', '_', ' '])
+        .trim_end_matches([':', '*', '_', ' ']);
+    let whole = ["File:", "Filename:", "Path:"]
+        .iter()
+        .find_map(|prefix| whole.strip_prefix(prefix))
+        .unwrap_or(whole)
+        .trim();
+    quoted
+        .chain([whole])
+        .find(|v| looks_like_filename(v))
+        .map(|v| v.to_string())
+}
+
+fn looks_like_filename(value: &str) -> bool {
+    let is_path_like = value
+        .chars()
+        .all(|c| c.is_alphanumeric() || "._-/~".contains(c));
+    let has_extension = value
+        .rsplit_once('.')
+        .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty() && !ext.contains('/'));
+    is_path_like && !value.ends_with('/') && (has_extension || value.contains('/'))
+}
+
 pub fn convert_option_string(value: &str) -> Option<String> {
     if value.is_empty() {
         None
@@ -123,10 +281,35 @@ where
             Some((v, score))
         })
         .collect();
-    list.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+    list.sort_unstable_by_key(|v| std::cmp::Reverse(v.1));
     list.into_iter().map(|(v, _)| v).collect()
 }
 
+/// Matches `text` against a shell-style pattern where `*` matches any run of
+/// characters and `?` matches a single character.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 pub fn pretty_error(err: &anyhow::Error) -> String {
     let mut output = vec![];
     output.push(format!("Error: {err}"));
@@ -203,6 +386,48 @@ pub fn temp_file(prefix: &str, suffix: &str) -> PathBuf {
     ))
 }
 
+/// Writes `contents` to a new file in a new directory under the temp dir, both of which only the
+/// current user can open, for plain copies of files that are kept encrypted. Returns the file's
+/// path; remove its parent directory once you're done with it.
+pub fn write_private_temp_file(file_name: &str, contents: &str) -> Result<PathBuf> {
+    let dir = temp_file("-private-", "");
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(&dir)
+        .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    let path = dir.join(file_name);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    Ok(path)
+}
+
+/// Keeps a temp file around until the session or run ends, for files that something (e.g.
+/// the model) may still read after they're written.
+pub fn keep_temp_file(path: PathBuf) {
+    KEPT_TEMP_FILES.lock().push(path);
+}
+
+/// Removes the temp files kept with [`keep_temp_file`].
+pub fn remove_kept_temp_files() {
+    for path in KEPT_TEMP_FILES.lock().drain(..) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
 pub fn is_url(path: &str) -> bool {
     path.starts_with("http://") || path.starts_with("https://")
 }
@@ -239,6 +464,60 @@ mod tests {
         assert!(safe_join_path("/home/user/dir1", "../file1").is_none());
     }
 
+    #[test]
+    fn test_extract_code_blocks() {
+        let text = "Run this:\n```bash\necho hi\n```\n\n1. Then:\n   ~~~\n   ls\n     -la\n   ~~~\n````md\n```\nnested\n";
+        assert_eq!(
+            extract_code_blocks(text),
+            [
+                CodeBlock {
+                    lang: "bash".into(),
+                    code: "echo hi\n".into(),
+                    filename: None,
+                },
+                CodeBlock {
+                    lang: "".into(),
+                    code: "ls\n  -la\n".into(),
+                    filename: None,
+                },
+                CodeBlock {
+                    lang: "md".into(),
+                    code: "```\nnested\n".into(),
+                    filename: None,
+                },
+            ]
+        );
+        let text = "Create `src/main.rs`:\n```rust\nfn main() {}\n```\n```toml Cargo.toml\n```\n```py:app/cli.py\n```\n**File: notes.md**\n\n```\n```\nRun `cargo build`:\n```sh\n```";
+        let filenames: Vec<_> = extract_code_blocks(text)
+            .into_iter()
+            .map(|v| (v.lang, v.filename))
+            .collect();
+        assert_eq!(
+            filenames,
+            [
+                ("rust".into(), Some("src/main.rs".into())),
+                ("toml".into(), Some("Cargo.toml".into())),
+                ("py".into(), Some("app/cli.py".into())),
+                ("".into(), Some("notes.md".into())),
+                ("sh".into(), None),
+            ]
+        );
+        assert!(extract_code_blocks("no code here").is_empty());
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "execute_command"));
+        assert!(wildcard_match("fs_*", "fs_write"));
+        assert!(wildcard_match("*_prod", "deploy_prod"));
+        assert!(wildcard_match("fs_?rite", "fs_write"));
+        assert!(wildcard_match("git*push*", "git push --force"));
+        assert!(!wildcard_match("fs_*", "web_search"));
+        assert!(!wildcard_match("fs_read", "fs_read_all"));
+        assert!(wildcard_match("", ""));
+        assert!(!wildcard_match("", "fs_read"));
+    }
+
     #[test]
     #[cfg(target_os = "windows")]
     fn test_safe_join_path() {
@@ -1,6 +1,6 @@
 #[cfg(windows)]
 pub mod runtime {
-    use std::path::Path;
+    use std::{fs, io, path::Path};
 
     pub fn bash_path() -> Option<String> {
         let bash_path = "C:\\Program Files\\Git\\bin\\bash.exe";
@@ -20,6 +20,23 @@ pub mod runtime {
         None
     }
 
+    /// Canonicalizes `path` without the `\\?\` prefix Windows adds, which `cmd.exe` and most
+    /// interpreters don't understand.
+    pub fn canonicalize(path: &Path) -> io::Result<String> {
+        Ok(simplify_path(&fs::canonicalize(path)?))
+    }
+
+    pub fn simplify_path(path: &Path) -> String {
+        let path = path.to_string_lossy();
+        if let Some(path) = path.strip_prefix(r"\\?\UNC\") {
+            format!(r"\\{path}")
+        } else if let Some(path) = path.strip_prefix(r"\\?\") {
+            path.to_string()
+        } else {
+            path.into_owned()
+        }
+    }
+
     fn exist_path(path: &str) -> bool {
         Path::new(path).exists()
     }
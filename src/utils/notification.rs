@@ -0,0 +1,62 @@
+use crossterm::{
+    event::{self, Event},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Whether the terminal has focus, as last reported by the terminal. Terminals that don't report
+/// focus changes are always considered focused.
+static TERMINAL_FOCUSED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_terminal_focused(focused: bool) {
+    TERMINAL_FOCUSED.store(focused, Ordering::SeqCst);
+}
+
+pub fn is_terminal_focused() -> bool {
+    TERMINAL_FOCUSED.load(Ordering::SeqCst)
+}
+
+/// Catches up on the focus changes the terminal reported while nothing was reading its input,
+/// e.g. while tools ran. They can only be read once the terminal is in raw mode, and anything
+/// else that was typed in the meantime is dropped along with them.
+pub fn read_focus_changes() {
+    if enable_raw_mode().is_err() {
+        return;
+    }
+    while event::poll(Duration::ZERO).unwrap_or_default() {
+        match event::read() {
+            Ok(Event::FocusGained) => set_terminal_focused(true),
+            Ok(Event::FocusLost) => set_terminal_focused(false),
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    let _ = disable_raw_mode();
+}
+
+/// Shows a desktop notification for something that's finished, if it ran for at least
+/// `after_secs` while the terminal wasn't focused. Does nothing when `after_secs` isn't set.
+pub fn notify_when_done(after_secs: Option<u64>, started: Instant, summary: &str, body: &str) {
+    let Some(after_secs) = after_secs else {
+        return;
+    };
+    if started.elapsed() < Duration::from_secs(after_secs) || is_terminal_focused() {
+        return;
+    }
+    let summary = summary.to_string();
+    let body = body.to_string();
+    // Showing a notification can block on the desktop's notification service
+    std::thread::spawn(move || {
+        if let Err(err) = notify_rust::Notification::new()
+            .appname(env!("CARGO_CRATE_NAME"))
+            .summary(&summary)
+            .body(&body)
+            .show()
+        {
+            debug!("Failed to show a desktop notification: {err}");
+        }
+    });
+}
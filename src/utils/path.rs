@@ -1,8 +1,12 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 
+use super::render_prompt;
+
 use anyhow::{Result, bail};
 use fancy_regex::Regex;
+use ignore::WalkBuilder;
 use indexmap::IndexSet;
 use path_absolutize::Absolutize;
 
@@ -54,6 +58,75 @@ pub async fn expand_glob_paths<T: AsRef<str>>(
     Ok(new_paths)
 }
 
+/// Expands the paths given to `.file`. Directories are walked recursively, and a path with glob
+/// characters (`*`, `?`, `{a,b}`) matches the files under the directory it starts from. Walking
+/// skips `.git` and whatever the repository's ignore files (`.gitignore`, `.git/info/exclude`,
+/// and the global one) ignore.
+pub fn expand_file_paths<T: AsRef<str>>(paths: &[T]) -> Result<IndexSet<String>> {
+    let mut files = IndexSet::new();
+    for path in paths {
+        let path = path.as_ref();
+        let Some(glob_start) = path.find(['*', '?', '{']) else {
+            let entry_path = Path::new(path);
+            if entry_path.is_dir() {
+                for file in walk_files(entry_path, None) {
+                    files.insert(file.display().to_string());
+                }
+            } else if entry_path.exists() {
+                files.insert(path.to_string());
+            } else {
+                bail!("Not found '{path}'");
+            }
+            continue;
+        };
+        let base = path[..glob_start]
+            .rfind(['/', '\\'])
+            .map_or("", |i| &path[..i + 1]);
+        let walk_root = if base.is_empty() { "." } else { base };
+        // Only `**` matches across directories, so otherwise there's no need to walk deeper than
+        // the pattern goes (e.g. `*.rs` only looks at the files in `.`)
+        let pattern = &path[base.len()..];
+        let max_depth = match pattern.contains("**") {
+            true => None,
+            false => Some(pattern.matches(['/', '\\']).count() + 1),
+        };
+        let Some(re) = glob_regex(path) else {
+            bail!("Invalid glob '{path}'");
+        };
+        let mut matched = false;
+        for file in walk_files(Path::new(walk_root), max_depth) {
+            let file = file.display().to_string();
+            let relative = if base.is_empty() {
+                file.trim_start_matches("./").to_string()
+            } else {
+                file
+            };
+            if re.is_match(&relative).unwrap_or_default() {
+                matched = true;
+                files.insert(relative);
+            }
+        }
+        if !matched {
+            bail!("No files match '{path}'");
+        }
+    }
+    Ok(files)
+}
+
+/// The files under `dir`, sorted, up to `max_depth` levels down.
+fn walk_files(dir: &Path, max_depth: Option<usize>) -> Vec<PathBuf> {
+    WalkBuilder::new(dir)
+        .hidden(false)
+        .max_depth(max_depth)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|v| v.is_file()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
 pub fn clear_dir(dir: &Path) -> Result<()> {
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
@@ -106,6 +179,105 @@ pub fn resolve_home_dir(path: &str) -> String {
     path
 }
 
+/// Fills in the `{name}` placeholders of a path template, as in `render_prompt` (so
+/// `{?session ...}` blocks work too). Characters that can't go in a file name, like the `:` of a
+/// model ID, are replaced in the values with `-`.
+pub fn render_path_template(template: &str, variables: &[(&str, String)]) -> PathBuf {
+    let variables: HashMap<&str, String> = variables
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .chars()
+                .map(|c| match c {
+                    '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+                    c => c,
+                })
+                .collect();
+            (*name, value)
+        })
+        .collect();
+    PathBuf::from(resolve_home_dir(&render_prompt(template, &variables)))
+}
+
+/// Completes a partly typed filesystem path, expanding a leading `~`. Directories end with a `/`
+/// so completion can carry on into them, and hidden entries are only offered once a `.` is typed.
+pub fn complete_path(word: &str) -> Vec<String> {
+    if word == "~" {
+        return vec!["~/".to_string()];
+    }
+    let (dir, prefix) = match word.rfind(std::path::is_separator) {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let search_dir = if dir.is_empty() {
+        ".".to_string()
+    } else {
+        resolve_home_dir(dir)
+    };
+    let Ok(entries) = fs::read_dir(search_dir) else {
+        return vec![];
+    };
+    let mut values: Vec<String> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            if entry.path().is_dir() {
+                Some(format!("{dir}{name}/"))
+            } else {
+                Some(format!("{dir}{name}"))
+            }
+        })
+        .collect();
+    values.sort_unstable();
+    values
+}
+
+/// Matches a path against a glob pattern, where `**` matches across directories, `*` and
+/// `?` match within a single path segment, and `{a,b}` matches any of the alternatives.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    glob_regex(pattern)
+        .and_then(|re| re.is_match(path).ok())
+        .unwrap_or_default()
+}
+
+/// Compiles a glob pattern (see [`glob_match`]) to a regex, for matching it against many paths.
+pub fn glob_regex(pattern: &str) -> Option<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    let mut in_braces = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if matches!(chars.peek(), Some('/' | '\\')) {
+                    chars.next();
+                    regex.push_str(r"(?:.*[/\\])?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str(r"[^/\\]*"),
+            '?' => regex.push_str(r"[^/\\]"),
+            '/' | '\\' => regex.push_str(r"[/\\]"),
+            '{' => {
+                in_braces = true;
+                regex.push_str("(?:");
+            }
+            '}' if in_braces => {
+                in_braces = false;
+                regex.push(')');
+            }
+            ',' if in_braces => regex.push('|'),
+            _ => regex.push_str(&fancy_regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).ok()
+}
+
 fn parse_glob(path_str: &str) -> Result<ParseGlobResult> {
     let globbed_single_subdir_regex = Regex::new(r"\*/[^/]+\.[^/]+$").expect("invalid regex");
     let globbed_recursive_subdir_regex = Regex::new(r"\*\*/[^/]+\.[^/]+$").expect("invalid regex");
@@ -264,6 +436,97 @@ fn is_valid_extension(suffixes: Option<&Vec<String>>, path: &Path) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("docs/**/*.md", "docs/guide.md"));
+        assert!(glob_match("docs/**/*.md", "docs/a/b/guide.md"));
+        assert!(!glob_match("docs/**/*.md", "docs/guide.txt"));
+        assert!(glob_match("docs/drafts/**", "docs/drafts/a/b.md"));
+        assert!(!glob_match("docs/*.md", "docs/a/guide.md"));
+        assert!(glob_match("**/README.md", "/agents/demo/README.md"));
+        assert!(glob_match("docs/*.{md,mdx}", "docs/page.mdx"));
+        assert!(glob_match("docs/page-?.md", "docs/page-1.md"));
+        assert!(!glob_match("docs/page-?.md", "docs/page-10.md"));
+    }
+
+    #[test]
+    fn test_render_path_template() {
+        let variables = [
+            ("date", "2025-01-01".to_string()),
+            ("session", String::new()),
+            ("model", "openai:gpt-4o".to_string()),
+        ];
+        assert_eq!(
+            render_path_template("answers/{date}-{model}.md", &variables),
+            PathBuf::from("answers/2025-01-01-openai-gpt-4o.md")
+        );
+        assert_eq!(
+            render_path_template(
+                "{?session {session}-}{!session scratch-}{date}.md",
+                &variables
+            ),
+            PathBuf::from("scratch-2025-01-01.md")
+        );
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let dir = std::env::temp_dir().join("loki-test-complete-path");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("draft.md"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+
+        let base = format!("{}/", dir.display());
+        assert_eq!(
+            complete_path(&format!("{base}d")),
+            vec![format!("{base}docs/"), format!("{base}draft.md")]
+        );
+        assert_eq!(complete_path(&base).len(), 2);
+        assert_eq!(
+            complete_path(&format!("{base}.")),
+            vec![format!("{base}.hidden")]
+        );
+        assert_eq!(complete_path("~"), vec!["~/"]);
+    }
+
+    #[test]
+    fn test_expand_file_paths() {
+        let dir = std::env::temp_dir().join(format!("loki-test-expand-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n!keep.log\n").unwrap();
+        for file in ["a.rs", "b.log", "keep.log", "src/c.rs", "src/nested/d.rs"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let root = dir.display().to_string();
+        let relative = |files: IndexSet<String>| -> Vec<String> {
+            files
+                .iter()
+                .map(|v| v[root.len() + 1..].replace('\\', "/"))
+                .collect()
+        };
+
+        let files = expand_file_paths(&[&root]).unwrap();
+        assert_eq!(
+            relative(files),
+            [
+                ".gitignore",
+                "a.rs",
+                "keep.log",
+                "src/c.rs",
+                "src/nested/d.rs"
+            ]
+        );
+        let files = expand_file_paths(&[format!("{root}/*.rs")]).unwrap();
+        assert_eq!(relative(files), ["a.rs"]);
+        let files = expand_file_paths(&[format!("{root}/**/*.rs")]).unwrap();
+        assert_eq!(relative(files), ["a.rs", "src/c.rs", "src/nested/d.rs"]);
+        assert!(expand_file_paths(&[format!("{root}/*.py")]).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn test_parse_glob() {
         assert_eq!(
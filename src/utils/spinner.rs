@@ -1,11 +1,13 @@
-use super::{AbortSignal, IS_STDOUT_TERMINAL, poll_abort_signal, wait_abort_signal};
+use super::{AbortSignal, IS_STDOUT_TERMINAL, is_quiet, poll_abort_signal, wait_abort_signal};
 
 use anyhow::{Result, bail};
 use crossterm::{cursor, queue, style, terminal};
+use parking_lot::RwLock;
 use std::{
     future::Future,
     io::{Write, stdout},
-    time::Duration,
+    sync::LazyLock,
+    time::{Duration, Instant},
 };
 use tokio::{
     sync::{
@@ -15,23 +17,95 @@ use tokio::{
     time::interval,
 };
 
+pub const SPINNER_STYLES: [&str; 5] = ["dots", "line", "arc", "circle", "bounce"];
+
+/// How long an operation runs before the spinner starts showing how long it's taken.
+const SHOW_ELAPSED_AFTER: Duration = Duration::from_secs(1);
+
+static SPINNER_SETTINGS: LazyLock<RwLock<SpinnerSettings>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Copy)]
+struct SpinnerSettings {
+    enabled: bool,
+    frames: &'static [&'static str],
+    show_text: bool,
+}
+
+impl Default for SpinnerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frames: spinner_frames("dots").unwrap_or_default(),
+            show_text: true,
+        }
+    }
+}
+
+/// Sets whether spinners are shown, which animation they use, and whether they say what they're
+/// waiting on (e.g. "Loading MCP servers") or only spin.
+pub fn configure_spinner(enabled: bool, style: &str, show_text: bool) -> Result<()> {
+    let Some(frames) = spinner_frames(style) else {
+        bail!(
+            "Unknown spinner style '{style}'; expected one of {}",
+            SPINNER_STYLES.join(", ")
+        )
+    };
+    *SPINNER_SETTINGS.write() = SpinnerSettings {
+        enabled,
+        frames,
+        show_text,
+    };
+    Ok(())
+}
+
+fn spinner_frames(style: &str) -> Option<&'static [&'static str]> {
+    let frames: &'static [&'static str] = match style {
+        "dots" => &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"],
+        "line" => &["-", "\\", "|", "/"],
+        "arc" => &["◜", "◠", "◝", "◞", "◡", "◟"],
+        "circle" => &["◐", "◓", "◑", "◒"],
+        "bounce" => &["⠁", "⠂", "⠄", "⠂"],
+        _ => return None,
+    };
+    Some(frames)
+}
+
+/// Formats how long an operation has been running, e.g. `4s` or `2m 05s`.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs < 60 {
+        format!("{secs}s")
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SpinnerInner {
     index: usize,
     message: String,
+    started: Option<Instant>,
 }
 
 impl SpinnerInner {
-    const DATA: [&'static str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
-
     fn step(&mut self) -> Result<()> {
-        if !*IS_STDOUT_TERMINAL || self.message.is_empty() {
+        let settings = *SPINNER_SETTINGS.read();
+        if !*IS_STDOUT_TERMINAL || !settings.enabled || is_quiet() || self.message.is_empty() {
             return Ok(());
         }
+        let started = *self.started.get_or_insert_with(Instant::now);
         let mut writer = stdout();
-        let frame = Self::DATA[self.index % Self::DATA.len()];
-        let dots = ".".repeat((self.index / 5) % 4);
-        let line = format!("{frame}{}{:<3}", self.message, dots);
+        let frame = settings.frames[self.index % settings.frames.len()];
+        let mut line = if settings.show_text {
+            let dots = ".".repeat((self.index / 5) % 4);
+            format!("{frame}{}{:<3}", self.message, dots)
+        } else {
+            frame.to_string()
+        };
+        let elapsed = started.elapsed();
+        if elapsed >= SHOW_ELAPSED_AFTER {
+            line.push_str(&format!(" {}", format_elapsed(elapsed)));
+        }
         queue!(writer, cursor::MoveToColumn(0), style::Print(line),)?;
         if self.index == 0 {
             queue!(writer, cursor::Hide)?;
@@ -54,6 +128,10 @@ impl SpinnerInner {
             return Ok(());
         }
         self.message.clear();
+        // Nothing was drawn if the spinner is turned off, so there's nothing to clear
+        if self.index == 0 {
+            return Ok(());
+        }
         let mut writer = stdout();
         queue!(
             writer,
@@ -215,3 +293,22 @@ async fn run_abortable_spinner(
     spinner.clear_message()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::from_millis(4_500)), "4s");
+        assert_eq!(format_elapsed(Duration::from_secs(125)), "2m 05s");
+    }
+
+    #[test]
+    fn test_spinner_frames() {
+        for style in SPINNER_STYLES {
+            assert!(spinner_frames(style).is_some_and(|frames| !frames.is_empty()));
+        }
+        assert!(spinner_frames("unknown").is_none());
+    }
+}
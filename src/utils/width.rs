@@ -0,0 +1,130 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Splits text into what a terminal draws as a unit: grapheme clusters, with the number of
+/// columns each takes up, and ANSI escape sequences, which take up none.
+///
+/// Widths are measured per cluster rather than per char, so emoji sequences (e.g. `👍🏽` or `❤️`)
+/// count as the two columns they're drawn in.
+pub fn grapheme_widths(text: &str) -> Vec<(&str, usize)> {
+    let mut output = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (plain, tail) = rest.split_at(rest.find('\x1b').unwrap_or(rest.len()));
+        output.extend(plain.graphemes(true).map(|v| (v, v.width())));
+        if tail.is_empty() {
+            break;
+        }
+        let (escape, tail) = tail.split_at(escape_len(tail));
+        output.push((escape, 0));
+        rest = tail;
+    }
+    output
+}
+
+/// The number of terminal columns text takes up, leaving out ANSI escape sequences.
+pub fn display_width(text: &str) -> usize {
+    grapheme_widths(text).iter().map(|(_, width)| width).sum()
+}
+
+/// Shortens text to fit in a number of columns, ending it with `…` when anything is cut. Escape
+/// sequences are kept, so a color is still reset after the cut.
+pub fn truncate_to_width(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let mut output = String::new();
+    let mut used = 0;
+    let mut is_cut = false;
+    for (grapheme, grapheme_width) in grapheme_widths(text) {
+        if grapheme_width == 0 {
+            if !is_cut || grapheme.starts_with('\x1b') {
+                output.push_str(grapheme);
+            }
+        } else if !is_cut && used + grapheme_width < width {
+            output.push_str(grapheme);
+            used += grapheme_width;
+        } else if !is_cut {
+            if width > 0 {
+                output.push('…');
+            }
+            is_cut = true;
+        }
+    }
+    output
+}
+
+/// The number of rows a line of text takes up once the terminal wraps it. A wide character that
+/// doesn't fit at the end of a row moves to the next one, leaving a gap.
+pub fn count_rows(text: &str, columns: usize) -> usize {
+    let columns = columns.max(1);
+    let mut rows = 1;
+    let mut column = 0;
+    for (_, width) in grapheme_widths(text) {
+        if column + width > columns {
+            rows += 1;
+            column = 0;
+        }
+        column += width;
+    }
+    rows
+}
+
+/// The length of the escape sequence at the start of text: a CSI sequence (`ESC [`) ends with a
+/// final byte, an OSC sequence (`ESC ]`, e.g. a hyperlink) with BEL or `ESC \`.
+fn escape_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    match bytes.get(1) {
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|v| (0x40..=0x7e).contains(v))
+            .map_or(bytes.len(), |i| i + 3),
+        Some(b']') => (2..bytes.len())
+            .find_map(|i| match bytes[i] {
+                0x07 => Some(i + 1),
+                0x1b if bytes.get(i + 1) == Some(&b'\\') => Some(i + 2),
+                _ => None,
+            })
+            .unwrap_or(bytes.len()),
+        Some(_) => 1 + text[1..].chars().next().map_or(0, char::len_utf8),
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("中文"), 4);
+        assert_eq!(display_width("👍🏽 ❤️ 👨‍🦰"), 8);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("\x1b[31m中\x1b[0m"), 2);
+        assert_eq!(
+            display_width("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"),
+            4
+        );
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        assert_eq!(truncate_to_width("abc", 3), "abc");
+        assert_eq!(truncate_to_width("abcdef", 4), "abc…");
+        assert_eq!(truncate_to_width("中文字符", 5), "中文…");
+        assert_eq!(
+            truncate_to_width("\x1b[35m中文字符\x1b[0m", 4),
+            "\x1b[35m中…\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_count_rows() {
+        assert_eq!(count_rows("", 10), 1);
+        assert_eq!(count_rows("abcdefghij", 10), 1);
+        assert_eq!(count_rows("abcdefghijk", 10), 2);
+        assert_eq!(count_rows("abcdefghi中", 10), 2);
+        assert_eq!(count_rows("\x1b[31m中文中文中\x1b[0m", 10), 1);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::utils::{base64_decode, base64_encode};
+
+use anyhow::{Context, Result, anyhow, bail};
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::num::NonZeroU32;
+
+/// Starts each line of a file written by `Vault::encrypt_text`, so encrypted files can be told
+/// apart from plain ones and lines appended to a file can be decrypted one at a time.
+const ENCRYPTED_PREFIX: &str = "$loki-encrypted$v2$";
+/// Starts the lines encrypted before each install had a salt of its own. They're still decrypted
+/// with the fixed salt they were written with.
+const LEGACY_ENCRYPTED_PREFIX: &str = "$loki-encrypted$v1$";
+pub(super) const LEGACY_KEY_SALT: &[u8] = b"loki-encrypted-sessions";
+const KEY_SALT_LEN: usize = 16;
+const KEY_ITERATIONS: NonZeroU32 = NonZeroU32::new(600_000).unwrap();
+
+/// The key sessions, `messages.md`, the REPL history, and memories are encrypted with, derived
+/// from the vault password and a salt.
+pub(super) struct TextKey([u8; 32]);
+
+impl fmt::Debug for TextKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TextKey(..)")
+    }
+}
+
+impl TextKey {
+    pub(super) fn derive(password: &str, salt: &[u8]) -> Self {
+        let mut key = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            KEY_ITERATIONS,
+            salt,
+            password.as_bytes(),
+            &mut key,
+        );
+        Self(key)
+    }
+
+    fn aead_key(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.0).expect("a 256-bit key"))
+    }
+}
+
+/// A random salt for an install's key.
+pub(super) fn generate_salt() -> Result<Vec<u8>> {
+    let mut salt = vec![0; KEY_SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| anyhow!("Failed to generate a salt"))?;
+    Ok(salt)
+}
+
+pub(super) fn is_encrypted(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| line.starts_with(ENCRYPTED_PREFIX) || line.starts_with(LEGACY_ENCRYPTED_PREFIX))
+}
+
+pub(super) fn has_legacy_lines(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| line.starts_with(LEGACY_ENCRYPTED_PREFIX))
+}
+
+/// Encrypts text into a single line, with a random nonce of its own.
+pub(super) fn encrypt(key: &TextKey, text: &str) -> Result<String> {
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow!("Failed to generate a nonce"))?;
+    let mut data = text.as_bytes().to_vec();
+    key.aead_key()
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Failed to encrypt"))?;
+    let mut payload = nonce.to_vec();
+    payload.extend(data);
+    Ok(format!("{ENCRYPTED_PREFIX}{}", base64_encode(payload)))
+}
+
+/// Decrypts each encrypted line of a file, keeping any lines written before encryption was
+/// turned on as they are. `legacy_key` is needed for lines written with the fixed salt.
+pub(super) fn decrypt(
+    key: &TextKey,
+    legacy_key: Option<&TextKey>,
+    content: &str,
+) -> Result<String> {
+    let mut output = String::new();
+    for line in content.lines() {
+        let (key, payload) = if let Some(payload) = line.strip_prefix(ENCRYPTED_PREFIX) {
+            (key, payload)
+        } else if let Some(payload) = line.strip_prefix(LEGACY_ENCRYPTED_PREFIX) {
+            let key = legacy_key.context("No key for text encrypted with the fixed salt")?;
+            (key, payload)
+        } else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+        let mut data = base64_decode(payload.trim()).context("Corrupted encrypted text")?;
+        if data.len() < NONCE_LEN {
+            bail!("Corrupted encrypted text");
+        }
+        let nonce = Nonce::try_assume_unique_for_key(&data[..NONCE_LEN])
+            .map_err(|_| anyhow!("Corrupted encrypted text"))?;
+        let text = key
+            .aead_key()
+            .open_in_place(nonce, Aad::empty(), &mut data[NONCE_LEN..])
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to decrypt; the vault password may have changed since it was written"
+                )
+            })?;
+        output.push_str(std::str::from_utf8(text).context("Corrupted encrypted text")?);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let salt = generate_salt().unwrap();
+        let key = TextKey::derive("correct horse battery staple", &salt);
+        let text = "messages:\n- role: user\n  content: my password is hunter2\n";
+        let line = encrypt(&key, text).unwrap();
+        assert!(is_encrypted(&line) && !line.contains("hunter2") && !line.contains('\n'));
+        assert_ne!(line, encrypt(&key, text).unwrap());
+
+        let appended = format!(
+            "# CHAT: plain\n{line}\n{}\n",
+            encrypt(&key, "more\n").unwrap()
+        );
+        assert_eq!(
+            decrypt(&key, None, &appended).unwrap(),
+            format!("# CHAT: plain\n{text}more\n")
+        );
+
+        let other_key = TextKey::derive("a different password", &salt);
+        assert!(decrypt(&other_key, None, &line).is_err());
+        let other_salt = generate_salt().unwrap();
+        assert_ne!(salt, other_salt);
+        let other_key = TextKey::derive("correct horse battery staple", &other_salt);
+        assert!(decrypt(&other_key, None, &line).is_err());
+        assert!(!is_encrypted("messages: []\n"));
+    }
+
+    #[test]
+    fn test_decrypt_legacy_lines() {
+        let key = TextKey::derive("correct horse battery staple", &generate_salt().unwrap());
+        let legacy_key = TextKey::derive("correct horse battery staple", LEGACY_KEY_SALT);
+        let legacy_line = encrypt(&legacy_key, "old\n").unwrap().replacen(
+            ENCRYPTED_PREFIX,
+            LEGACY_ENCRYPTED_PREFIX,
+            1,
+        );
+        let content = format!("{legacy_line}\n{}\n", encrypt(&key, "new\n").unwrap());
+        assert!(is_encrypted(&legacy_line) && has_legacy_lines(&content));
+        assert!(decrypt(&key, None, &content).is_err());
+        assert_eq!(
+            decrypt(&key, Some(&legacy_key), &content).unwrap(),
+            "old\nnew\n"
+        );
+    }
+}
@@ -1,25 +1,38 @@
+mod encryption;
 mod utils;
 
 use std::path::PathBuf;
+pub use utils::InjectedSecrets;
 pub use utils::create_vault_password_file;
 pub use utils::interpolate_secrets;
 
 use crate::cli::Cli;
-use crate::config::Config;
+use crate::config::{Config, ensure_parent_exists};
+use crate::utils::{base64_decode, base64_encode};
+use crate::vault::encryption::{
+    LEGACY_KEY_SALT, TextKey, decrypt, encrypt, generate_salt, has_legacy_lines, is_encrypted,
+};
 use crate::vault::utils::ensure_password_file_initialized;
 use anyhow::{Context, Result};
 use fancy_regex::Regex;
 use gman::providers::SecretProvider;
 use gman::providers::local::LocalProvider;
 use inquire::{Password, PasswordDisplayMode, required};
-use std::sync::{Arc, LazyLock};
+use std::fs::{OpenOptions, read_to_string};
+use std::io::{ErrorKind, Write};
+use std::sync::{Arc, LazyLock, OnceLock};
 use tokio::runtime::Handle;
 
 pub static SECRET_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{(.+)}}").unwrap());
 
+/// Holds the random salt the key for encrypted sessions is derived with, created on first use.
+const KEY_SALT_FILE_NAME: &str = "encryption-salt";
+
 #[derive(Debug, Default, Clone)]
 pub struct Vault {
     local_provider: LocalProvider,
+    text_key: Arc<OnceLock<TextKey>>,
+    legacy_text_key: Arc<OnceLock<TextKey>>,
 }
 
 pub type GlobalVault = Arc<Vault>;
@@ -33,7 +46,11 @@ impl Vault {
             ..LocalProvider::default()
         };
 
-        Self { local_provider }
+        Self {
+            local_provider,
+            text_key: Default::default(),
+            legacy_text_key: Default::default(),
+        }
     }
 
     pub fn init(config: &Config) -> Self {
@@ -47,7 +64,11 @@ impl Vault {
         ensure_password_file_initialized(&mut local_provider)
             .expect("Failed to initialize password file");
 
-        Self { local_provider }
+        Self {
+            local_provider,
+            text_key: Default::default(),
+            legacy_text_key: Default::default(),
+        }
     }
 
     pub fn password_file(&self) -> Result<PathBuf> {
@@ -57,6 +78,57 @@ impl Vault {
             .with_context(|| "A password file is required for the local provider")
     }
 
+    /// Encrypts text to keep at rest, like sessions and `messages.md`, into a single line. The key
+    /// is derived from the vault password and this install's salt, once per run.
+    pub fn encrypt_text(&self, text: &str) -> Result<String> {
+        encrypt(self.text_key()?, text)
+    }
+
+    /// Decrypts a file written with `encrypt_text`. Files that aren't encrypted are returned as
+    /// they are, so they can still be read once encryption is turned on or off.
+    pub fn decrypt_text(&self, content: &str) -> Result<String> {
+        if !is_encrypted(content) {
+            return Ok(content.to_string());
+        }
+        let legacy_key = match has_legacy_lines(content) {
+            true => Some(self.legacy_text_key()?),
+            false => None,
+        };
+        decrypt(self.text_key()?, legacy_key, content)
+    }
+
+    fn text_key(&self) -> Result<&TextKey> {
+        if let Some(key) = self.text_key.get() {
+            return Ok(key);
+        }
+        let (password, salt) = (self.password()?, key_salt()?);
+        Ok(self
+            .text_key
+            .get_or_init(|| TextKey::derive(&password, &salt)))
+    }
+
+    /// The key for text encrypted before each install had a salt of its own.
+    fn legacy_text_key(&self) -> Result<&TextKey> {
+        if let Some(key) = self.legacy_text_key.get() {
+            return Ok(key);
+        }
+        let password = self.password()?;
+        Ok(self
+            .legacy_text_key
+            .get_or_init(|| TextKey::derive(&password, LEGACY_KEY_SALT)))
+    }
+
+    fn password(&self) -> Result<String> {
+        let password_file = self.password_file()?;
+        let password = read_to_string(&password_file).with_context(|| {
+            format!(
+                "Failed to read the vault password file at '{}'",
+                password_file.display()
+            )
+        })?;
+        Ok(password.trim().to_string())
+    }
+
     pub fn add_secret(&self, secret_name: &str) -> Result<()> {
         let secret_value = Password::new("Enter the secret value:")
             .with_validator(required!())
@@ -154,3 +226,29 @@ impl Vault {
         Ok(())
     }
 }
+
+/// This install's salt for the key of encrypted sessions. It's created the first time it's
+/// needed, and never replaced, since text encrypted with it can't be read without it.
+fn key_salt() -> Result<Vec<u8>> {
+    let path = Config::local_path(KEY_SALT_FILE_NAME);
+    ensure_parent_exists(&path)?;
+    let salt = generate_salt()?;
+    match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(mut file) => {
+            file.write_all(base64_encode(&salt).as_bytes())
+                .with_context(|| format!("Failed to write the salt to '{}'", path.display()))?;
+            Ok(salt)
+        }
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            let content = read_to_string(&path)
+                .with_context(|| format!("Failed to read the salt at '{}'", path.display()))?;
+            base64_decode(content.trim())
+                .ok()
+                .filter(|salt| !salt.is_empty())
+                .with_context(|| format!("Invalid salt at '{}'", path.display()))
+        }
+        Err(err) => {
+            Err(err).with_context(|| format!("Failed to create the salt at '{}'", path.display()))
+        }
+    }
+}
@@ -2,11 +2,17 @@ use crate::config::ensure_parent_exists;
 use crate::vault::{SECRET_RE, Vault};
 use anyhow::Result;
 use anyhow::anyhow;
+use fancy_regex::{Captures, Regex};
 use gman::providers::local::LocalProvider;
 use indoc::formatdoc;
 use inquire::validator::Validation;
 use inquire::{Confirm, Password, PasswordDisplayMode, Text, min_length, required};
+use serde_json::Value;
 use std::path::PathBuf;
+use std::sync::LazyLock;
+
+static SECRET_PLACEHOLDER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([\w.-]+)\s*}}").unwrap());
 
 pub fn ensure_password_file_initialized(local_provider: &mut LocalProvider) -> Result<()> {
     let vault_password_file = local_provider
@@ -192,3 +198,106 @@ pub fn interpolate_secrets(content: &str, vault: &Vault) -> (String, Vec<String>
 
     (parsed_content, missing_secrets)
 }
+
+/// The secrets injected into tool calls just before they run, so their values can be kept out of
+/// what tools return to the model.
+#[derive(Debug, Clone, Default)]
+pub struct InjectedSecrets {
+    /// The names of the secrets in the vault, loaded the first time a placeholder is seen.
+    names: Option<Vec<String>>,
+    values: Vec<(String, String)>,
+}
+
+impl InjectedSecrets {
+    /// Replaces the `{{SECRET}}` placeholders in `content` that name a secret in the vault. Any
+    /// other placeholders are left as they are, since tools are often given templates to write.
+    pub fn inject(&mut self, content: &str, vault: &Vault) -> String {
+        if !SECRET_PLACEHOLDER_RE.is_match(content).unwrap_or_default() {
+            return content.to_string();
+        }
+        let names = self
+            .names
+            .get_or_insert_with(|| vault.list_secrets(false).unwrap_or_default());
+        let values = &mut self.values;
+        SECRET_PLACEHOLDER_RE
+            .replace_all(content, |caps: &Captures<'_>| {
+                let name = &caps[1];
+                if !names.iter().any(|v| v == name) {
+                    return caps[0].to_string();
+                }
+                match vault.get_secret(name, false) {
+                    Ok(value) => {
+                        if !values.iter().any(|(v, _)| v == name) {
+                            values.push((name.to_string(), value.clone()));
+                        }
+                        value
+                    }
+                    Err(_) => caps[0].to_string(),
+                }
+            })
+            .to_string()
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        for (name, value) in other.values {
+            if !self.values.iter().any(|(v, _)| *v == name) {
+                self.values.push((name, value));
+            }
+        }
+    }
+
+    /// Gets the secret `name` from the vault to inject as is, if it's there.
+    pub fn get(&mut self, name: &str, vault: &Vault) -> Option<String> {
+        let value = vault.get_secret(name, false).ok()?;
+        if !self.values.iter().any(|(v, _)| v == name) {
+            self.values.push((name.to_string(), value.clone()));
+        }
+        Some(value)
+    }
+
+    /// Puts the placeholders back in place of any injected secrets that show up in `content`.
+    pub fn redact(&self, content: &str) -> String {
+        self.values
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .fold(content.to_string(), |content, (name, value)| {
+                content.replace(value, &format!("{{{{{name}}}}}"))
+            })
+    }
+
+    pub fn redact_json(&self, value: Value) -> Value {
+        if self.values.is_empty() {
+            return value;
+        }
+        match value {
+            Value::String(content) => Value::String(self.redact(&content)),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|v| self.redact_json(v)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, v)| (key, self.redact_json(v)))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_injected_secrets() {
+        let secrets = InjectedSecrets {
+            names: None,
+            values: vec![("API_TOKEN".to_string(), "s3cr3t".to_string())],
+        };
+        assert_eq!(
+            secrets.redact_json(json!({"output": "curl -H 'Token: s3cr3t'", "lines": ["s3cr3t"]})),
+            json!({"output": "curl -H 'Token: {{API_TOKEN}}'", "lines": ["{{API_TOKEN}}"]})
+        );
+    }
+}